@@ -7,6 +7,7 @@ use noli::net::lookup_host;
 use noli::net::SocketAddr;
 use noli::net::TcpStream;
 use saba_core::error::Error;
+use saba_core::http::Header;
 use saba_core::http::HttpResponse;
 
 /// HTTP client implementation.
@@ -16,6 +17,27 @@ impl HttpClient {
         Self {}
     }
     pub fn get(&self, host: String, port: u16, path: String) -> Result<HttpResponse, Error> {
+        self.send("GET", host, port, path, Vec::new(), String::new())
+    }
+    pub fn post(
+        &self,
+        host: String,
+        port: u16,
+        path: String,
+        headers: Vec<Header>,
+        body: String,
+    ) -> Result<HttpResponse, Error> {
+        self.send("POST", host, port, path, headers, body)
+    }
+    pub fn send(
+        &self,
+        method: &str,
+        host: String,
+        port: u16,
+        path: String,
+        headers: Vec<Header>,
+        body: String,
+    ) -> Result<HttpResponse, Error> {
         let ips = match lookup_host(&host) {
             Ok(ips) => ips,
             Err(e) => {
@@ -37,7 +59,8 @@ impl HttpClient {
                 ))
             }
         };
-        let mut request = String::from("GET /");
+        let mut request = String::from(method);
+        request.push_str(" /");
         request.push_str(&path);
         request.push_str(" HTTP/1.1\n");
         request.push_str("Host: ");
@@ -45,7 +68,17 @@ impl HttpClient {
         request.push('\n');
         request.push_str("Accept: text/html\n");
         request.push_str("Connection: close\n");
+        for header in headers {
+            request.push_str(&header.name());
+            request.push_str(": ");
+            request.push_str(&header.value());
+            request.push('\n');
+        }
+        if !body.is_empty() {
+            request.push_str(&format!("Content-Length: {}\n", body.len()));
+        }
         request.push('\n');
+        request.push_str(&body);
         let _bytes_written = match stream.write(request.as_bytes()) {
             Ok(bytes) => bytes,
             Err(_) => {
@@ -70,9 +103,6 @@ impl HttpClient {
             }
             received.extend_from_slice(&buf[..bytes_read]);
         }
-        match core::str::from_utf8(&received) {
-            Ok(response) => HttpResponse::new(response.to_string()),
-            Err(e) => Err(Error::Network(format!("Invalid received response: {}", e))),
-        }
+        HttpResponse::from_bytes(&received)
     }
 }
@@ -1,3 +1,5 @@
+use crate::fetch::Fetcher;
+use crate::fetch::RequestSender;
 use crate::renderer::page::Page;
 use alloc::rc::Rc;
 use alloc::vec::Vec;
@@ -8,6 +10,7 @@ use core::cell::RefCell;
 pub struct Browser {
     active_page_index: usize,
     pages: Vec<Rc<RefCell<Page>>>,
+    fetcher: Option<Fetcher>,
 }
 
 impl Browser {
@@ -16,6 +19,7 @@ impl Browser {
         let browser = Rc::new(RefCell::new(Self {
             active_page_index: 0,
             pages: Vec::new(),
+            fetcher: None,
         }));
         page.set_browser(Rc::downgrade(&browser));
         browser.borrow_mut().pages.push(Rc::new(RefCell::new(page)));
@@ -24,4 +28,12 @@ impl Browser {
     pub fn current_page(&self) -> Rc<RefCell<Page>> {
         self.pages[self.active_page_index].clone()
     }
+    /// Sets the function that sends requests over the network. Every page shares the fetcher
+    /// built on it, and with it the cookies.
+    pub fn set_request_sender(&mut self, send: RequestSender) {
+        self.fetcher = Some(Fetcher::new(send));
+    }
+    pub fn fetcher(&self) -> Option<Fetcher> {
+        self.fetcher.clone()
+    }
 }
@@ -4,6 +4,7 @@ pub static GRAY: u32 = 0x808080;
 pub static DARKGRAY: u32 = 0x5a5a5a;
 pub static BLACK: u32 = 0x000000;
 pub static ADDRESS_BAR_HEIGHT: i64 = 20;
+pub static TAB_LABEL_WIDTH: i64 = 160;
 //pub static WINDOW_INIT_X_POS: i64 = 30;
 //pub static WINDOW_INIT_Y_POS: i64 = 400;
 pub static WINDOW_WIDTH: i64 = 600;
@@ -0,0 +1,170 @@
+use crate::url::Url;
+use alloc::string::String;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+
+/// Struct representing a cookie stored by the browser.
+/// # References:
+/// - <https://www.rfc-editor.org/rfc/rfc6265#section-5.3>
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cookie {
+    name: String,
+    value: String,
+    host: String,
+    path: String,
+}
+impl Cookie {
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+    pub fn value(&self) -> String {
+        self.value.clone()
+    }
+    pub fn host(&self) -> String {
+        self.host.clone()
+    }
+    pub fn path(&self) -> String {
+        self.path.clone()
+    }
+}
+
+/// Struct representing the cookies shared by every request the browser makes.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CookieJar {
+    cookies: Vec<Cookie>,
+}
+impl CookieJar {
+    pub fn new() -> Self {
+        Self {
+            cookies: Vec::new(),
+        }
+    }
+    pub fn cookies(&self) -> Vec<Cookie> {
+        self.cookies.clone()
+    }
+    /// Stores a cookie received in a `Set-Cookie` header.
+    /// Cookies are host-only: the `Domain` attribute is ignored.
+    /// # Parameters
+    /// - `url`: The URL of the response.
+    /// - `set_cookie`: The value of the `Set-Cookie` header.
+    /// # References:
+    /// - <https://www.rfc-editor.org/rfc/rfc6265#section-5.2>
+    pub fn store(&mut self, url: &Url, set_cookie: &str) {
+        let mut parts = set_cookie.split(';');
+        let (name, value) = match parts.next().and_then(|p| p.split_once('=')) {
+            Some((name, value)) => (name.trim(), value.trim()),
+            None => return,
+        };
+        if name.is_empty() {
+            return;
+        }
+        let mut path = default_path(url);
+        let mut expired = false;
+        for attribute in parts {
+            let (key, val) = match attribute.split_once('=') {
+                Some((k, v)) => (k.trim(), v.trim()),
+                None => (attribute.trim(), ""),
+            };
+            if key.eq_ignore_ascii_case("path") && val.starts_with('/') {
+                path = val.to_string();
+            } else if key.eq_ignore_ascii_case("max-age") {
+                // A non-positive Max-Age removes the cookie.
+                expired = val.starts_with('-') || val.parse::<u64>() == Ok(0);
+            }
+        }
+        let host = url.host();
+        self.cookies
+            .retain(|c| !(c.name == name && c.host == host && c.path == path));
+        if !expired {
+            self.cookies.push(Cookie {
+                name: name.to_string(),
+                value: value.to_string(),
+                host,
+                path,
+            });
+        }
+    }
+    /// Returns the value of the `Cookie` header for a request.
+    /// # Parameters
+    /// - `url`: The URL of the request.
+    /// # Returns
+    /// - The serialized cookies, or `None` if no cookie matches.
+    /// # References:
+    /// - <https://www.rfc-editor.org/rfc/rfc6265#section-5.4>
+    pub fn cookie_header(&self, url: &Url) -> Option<String> {
+        let request_path = format_path(&url.path());
+        let pairs: Vec<String> = self
+            .cookies
+            .iter()
+            .filter(|c| c.host == url.host() && path_matches(&request_path, &c.path))
+            .map(|c| c.name.clone() + "=" + &c.value)
+            .collect();
+        if pairs.is_empty() {
+            return None;
+        }
+        Some(pairs.join("; "))
+    }
+}
+
+fn format_path(path: &str) -> String {
+    let mut s = String::from("/");
+    s.push_str(path);
+    s
+}
+
+/// # References:
+/// - <https://www.rfc-editor.org/rfc/rfc6265#section-5.1.4>
+fn default_path(url: &Url) -> String {
+    let path = format_path(&url.path());
+    match path.rfind('/') {
+        Some(0) | None => "/".to_string(),
+        Some(i) => path[..i].to_string(),
+    }
+}
+
+fn path_matches(request_path: &str, cookie_path: &str) -> bool {
+    if request_path == cookie_path {
+        return true;
+    }
+    match request_path.strip_prefix(cookie_path) {
+        Some(rest) => cookie_path.ends_with('/') || rest.starts_with('/'),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    fn url(s: &str) -> Url {
+        Url::new(s.to_string())
+            .parse()
+            .expect("failed to parse url")
+    }
+    #[test]
+    fn test_store_and_send() {
+        let mut jar = CookieJar::new();
+        jar.store(&url("http://example.com/a/b"), "id=1; Path=/");
+        jar.store(&url("http://example.com/a/b"), "scoped=2");
+        assert_eq!(
+            jar.cookie_header(&url("http://example.com/a/c")),
+            Some("id=1; scoped=2".to_string())
+        );
+        assert_eq!(
+            jar.cookie_header(&url("http://example.com/other")),
+            Some("id=1".to_string())
+        );
+        assert_eq!(jar.cookie_header(&url("http://other.com/")), None);
+    }
+    #[test]
+    fn test_replace_and_expire() {
+        let mut jar = CookieJar::new();
+        jar.store(&url("http://example.com/"), "id=1");
+        jar.store(&url("http://example.com/"), "id=2");
+        assert_eq!(
+            jar.cookie_header(&url("http://example.com/")),
+            Some("id=2".to_string())
+        );
+        jar.store(&url("http://example.com/"), "id=; Max-Age=0");
+        assert_eq!(jar.cookie_header(&url("http://example.com/")), None);
+    }
+}
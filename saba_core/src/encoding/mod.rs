@@ -0,0 +1,974 @@
+pub mod index;
+
+use crate::encoding::index::JIS0208;
+use crate::encoding::index::JIS0212;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// The labels of each encoding.
+/// # References:
+/// - <https://encoding.spec.whatwg.org/#names-and-labels>
+static LABELS: [(Encoding, &[&str]); 7] = [
+    (
+        Encoding::Utf8,
+        &[
+            "unicode-1-1-utf-8",
+            "unicode11utf8",
+            "unicode20utf8",
+            "utf-8",
+            "utf8",
+            "x-unicode20utf8",
+        ],
+    ),
+    (Encoding::Utf16Be, &["unicodefffe", "utf-16be"]),
+    (
+        Encoding::Utf16Le,
+        &[
+            "csunicode",
+            "iso-10646-ucs-2",
+            "ucs-2",
+            "unicode",
+            "unicodefeff",
+            "utf-16",
+            "utf-16le",
+        ],
+    ),
+    (
+        Encoding::ShiftJis,
+        &[
+            "csshiftjis",
+            "ms932",
+            "ms_kanji",
+            "shift-jis",
+            "shift_jis",
+            "sjis",
+            "windows-31j",
+            "x-sjis",
+        ],
+    ),
+    (
+        Encoding::EucJp,
+        &["cseucpkdfmtjapanese", "euc-jp", "x-euc-jp"],
+    ),
+    (Encoding::Iso2022Jp, &["csiso2022jp", "iso-2022-jp"]),
+    (
+        Encoding::Windows1252,
+        &[
+            "ansi_x3.4-1968",
+            "ascii",
+            "cp1252",
+            "cp819",
+            "csisolatin1",
+            "ibm819",
+            "iso-8859-1",
+            "iso-ir-100",
+            "iso8859-1",
+            "iso88591",
+            "iso_8859-1",
+            "iso_8859-1:1987",
+            "l1",
+            "latin1",
+            "us-ascii",
+            "windows-1252",
+            "x-cp1252",
+        ],
+    ),
+];
+
+/// The code points of the bytes 0x80 to 0x9F in windows-1252. The other bytes are the same as
+/// their code points.
+/// # References:
+/// - <https://encoding.spec.whatwg.org/index-windows-1252.txt>
+static WINDOWS_1252_C1: [u16; 32] = [
+    0x20AC, 0x0081, 0x201A, 0x0192, 0x201E, 0x2026, 0x2020, 0x2021, 0x02C6, 0x2030, 0x0160, 0x2039,
+    0x0152, 0x008D, 0x017D, 0x008F, 0x0090, 0x2018, 0x2019, 0x201C, 0x201D, 0x2022, 0x2013, 0x2014,
+    0x02DC, 0x2122, 0x0161, 0x203A, 0x0153, 0x009D, 0x017E, 0x0178,
+];
+
+/// The number of bytes that the prescan looks at.
+static PRESCAN_LENGTH: usize = 1024;
+
+/// Enum representing a character encoding.
+/// # References:
+/// - <https://encoding.spec.whatwg.org/#names-and-labels>
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Encoding {
+    #[default]
+    Utf8,
+    Utf16Be,
+    Utf16Le,
+    ShiftJis,
+    EucJp,
+    Iso2022Jp,
+    Windows1252,
+}
+impl Encoding {
+    /// Returns the encoding for a label, such as `sjis` or ` UTF-8 `.
+    /// # References:
+    /// - <https://encoding.spec.whatwg.org/#concept-encoding-get>
+    pub fn for_label(label: &str) -> Option<Self> {
+        let label = label
+            .trim_matches(|c| matches!(c, '\t' | '\n' | '\x0C' | '\r' | ' '))
+            .to_ascii_lowercase();
+        LABELS
+            .iter()
+            .find(|(_, labels)| labels.contains(&label.as_str()))
+            .map(|(encoding, _)| *encoding)
+    }
+    /// Returns the name of the encoding, which is used by `document.characterSet`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "UTF-8",
+            Encoding::Utf16Be => "UTF-16BE",
+            Encoding::Utf16Le => "UTF-16LE",
+            Encoding::ShiftJis => "Shift_JIS",
+            Encoding::EucJp => "EUC-JP",
+            Encoding::Iso2022Jp => "ISO-2022-JP",
+            Encoding::Windows1252 => "windows-1252",
+        }
+    }
+}
+
+/// Enum representing how reliable the encoding chosen for a document is.
+/// # References:
+/// - <https://html.spec.whatwg.org/multipage/parsing.html#concept-encoding-confidence>
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Confidence {
+    Tentative,
+    Certain,
+}
+
+/// Determines the encoding of an HTML document.
+/// # Parameters
+/// - `bytes`: The beginning of the document.
+/// - `content_type`: The `Content-Type` header of the response, if any.
+/// # Returns
+/// - The encoding from the BOM, the `charset` parameter of `content_type` or a `meta` element,
+///   in this order. Without any of them, UTF-8 if `bytes` is valid UTF-8, and windows-1252
+///   otherwise.
+/// # References:
+/// - <https://html.spec.whatwg.org/multipage/parsing.html#encoding-sniffing-algorithm>
+pub fn sniff_encoding(bytes: &[u8], content_type: Option<&str>) -> (Encoding, Confidence) {
+    if let Some(encoding) = bom_sniff(bytes) {
+        return (encoding, Confidence::Certain);
+    }
+    if let Some(encoding) = content_type.and_then(charset_from_content_type) {
+        return (encoding, Confidence::Certain);
+    }
+    if let Some(encoding) = prescan(bytes) {
+        return (encoding, Confidence::Tentative);
+    }
+    let encoding = match core::str::from_utf8(bytes) {
+        Ok(_) => Encoding::Utf8,
+        // A sequence that is cut at the end of `bytes` is not an error.
+        Err(e) if e.error_len().is_none() => Encoding::Utf8,
+        Err(_) => Encoding::Windows1252,
+    };
+    (encoding, Confidence::Tentative)
+}
+
+/// Returns the encoding indicated by the byte order mark at the beginning of `bytes`, if any.
+/// # References:
+/// - <https://encoding.spec.whatwg.org/#bom-sniff>
+pub fn bom_sniff(bytes: &[u8]) -> Option<Encoding> {
+    if bytes.starts_with(&[0xEF, 0xBB, 0xBF]) {
+        return Some(Encoding::Utf8);
+    }
+    if bytes.starts_with(&[0xFE, 0xFF]) {
+        return Some(Encoding::Utf16Be);
+    }
+    if bytes.starts_with(&[0xFF, 0xFE]) {
+        return Some(Encoding::Utf16Le);
+    }
+    None
+}
+
+/// Returns the encoding of the `charset` parameter of a `Content-Type` header value, such as
+/// `text/html; charset=Shift_JIS`.
+/// # References:
+/// - <https://mimesniff.spec.whatwg.org/#parsing-a-mime-type>
+pub fn charset_from_content_type(content_type: &str) -> Option<Encoding> {
+    for parameter in content_type.split(';').skip(1) {
+        let (name, value) = match parameter.split_once('=') {
+            Some(pair) => pair,
+            None => continue,
+        };
+        if name.trim().eq_ignore_ascii_case("charset") {
+            return Encoding::for_label(value.trim().trim_matches('"'));
+        }
+    }
+    None
+}
+
+fn is_whitespace_byte(b: u8) -> bool {
+    matches!(b, 0x09 | 0x0A | 0x0C | 0x0D | 0x20)
+}
+
+/// Struct representing the position of the prescan in a byte stream.
+struct Prescanner<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+impl<'a> Prescanner<'a> {
+    fn starts_with(&self, prefix: &[u8]) -> bool {
+        self.bytes.len() >= self.pos + prefix.len()
+            && self.bytes[self.pos..self.pos + prefix.len()].eq_ignore_ascii_case(prefix)
+    }
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+    fn skip_while(&mut self, f: impl Fn(u8) -> bool) {
+        while self.peek().is_some_and(&f) {
+            self.pos += 1;
+        }
+    }
+    /// Reads an attribute of a tag.
+    /// # Returns
+    /// - The name and the value in lowercase, or `None` at the end of the tag or the input.
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/parsing.html#concept-get-attributes-when-sniffing>
+    fn get_attribute(&mut self) -> Option<(Vec<u8>, Vec<u8>)> {
+        self.skip_while(|b| is_whitespace_byte(b) || b == b'/');
+        let mut name = Vec::new();
+        let mut value = Vec::new();
+        loop {
+            match self.peek()? {
+                b'>' if name.is_empty() => return None,
+                b'=' if !name.is_empty() => {
+                    self.pos += 1;
+                    break;
+                }
+                b if is_whitespace_byte(b) => {
+                    self.skip_while(is_whitespace_byte);
+                    if self.peek()? != b'=' {
+                        return Some((name, value));
+                    }
+                    self.pos += 1;
+                    break;
+                }
+                b'/' | b'>' => return Some((name, value)),
+                b => name.push(b.to_ascii_lowercase()),
+            }
+            self.pos += 1;
+        }
+        self.skip_while(is_whitespace_byte);
+        match self.peek()? {
+            quote @ (b'"' | b'\'') => {
+                self.pos += 1;
+                loop {
+                    let b = self.peek()?;
+                    self.pos += 1;
+                    if b == quote {
+                        return Some((name, value));
+                    }
+                    value.push(b.to_ascii_lowercase());
+                }
+            }
+            b'>' => Some((name, value)),
+            _ => {
+                loop {
+                    match self.peek() {
+                        Some(b) if !is_whitespace_byte(b) && b != b'>' => {
+                            value.push(b.to_ascii_lowercase());
+                            self.pos += 1;
+                        }
+                        _ => break,
+                    }
+                }
+                Some((name, value))
+            }
+        }
+    }
+    /// Reads the attributes of a `meta` element.
+    /// # Returns
+    /// - The encoding that the element declares, if any.
+    fn meta_encoding(&mut self) -> Option<Encoding> {
+        let mut names: Vec<Vec<u8>> = Vec::new();
+        let mut got_pragma = false;
+        let mut need_pragma = None;
+        let mut charset = None;
+        while let Some((name, value)) = self.get_attribute() {
+            if names.contains(&name) {
+                continue;
+            }
+            let value: String = value.iter().map(|b| char::from(*b)).collect();
+            match name.as_slice() {
+                b"http-equiv" => got_pragma |= value == "content-type",
+                b"content" if charset.is_none() => {
+                    if let Some(encoding) = extract_encoding_from_meta(&value) {
+                        charset = Some(encoding);
+                        need_pragma = Some(true);
+                    }
+                }
+                b"charset" => {
+                    charset = Encoding::for_label(&value);
+                    need_pragma = Some(false);
+                }
+                _ => {}
+            }
+            names.push(name);
+        }
+        match need_pragma {
+            None => return None,
+            Some(true) if !got_pragma => return None,
+            _ => {}
+        }
+        // A document that can be prescanned as ASCII is not in UTF-16.
+        match charset {
+            Some(Encoding::Utf16Be | Encoding::Utf16Le) => Some(Encoding::Utf8),
+            charset => charset,
+        }
+    }
+}
+
+/// Looks for a `meta` element that declares the encoding in the first 1024 bytes of a document.
+/// # References:
+/// - <https://html.spec.whatwg.org/multipage/parsing.html#prescan-a-byte-stream-to-determine-its-encoding>
+pub fn prescan(bytes: &[u8]) -> Option<Encoding> {
+    let mut s = Prescanner {
+        bytes: &bytes[..bytes.len().min(PRESCAN_LENGTH)],
+        pos: 0,
+    };
+    while s.pos < s.bytes.len() {
+        if s.starts_with(b"<!--") {
+            // `-->` may share the dashes of `<!--`.
+            s.pos += 2;
+            while s.pos < s.bytes.len() && !s.starts_with(b"-->") {
+                s.pos += 1;
+            }
+            s.pos += 2;
+        } else if s.starts_with(b"<meta")
+            && s.bytes
+                .get(s.pos + 5)
+                .is_some_and(|b| is_whitespace_byte(*b) || *b == b'/')
+        {
+            s.pos += 6;
+            if let Some(encoding) = s.meta_encoding() {
+                return Some(encoding);
+            }
+        } else if s.starts_with(b"<")
+            && (s.bytes.get(s.pos + 1).is_some_and(u8::is_ascii_alphabetic)
+                || (s.bytes.get(s.pos + 1) == Some(&b'/')
+                    && s.bytes.get(s.pos + 2).is_some_and(u8::is_ascii_alphabetic)))
+        {
+            s.skip_while(|b| !is_whitespace_byte(b) && b != b'>');
+            while s.get_attribute().is_some() {}
+        } else if s.starts_with(b"<!") || s.starts_with(b"</") || s.starts_with(b"<?") {
+            s.skip_while(|b| b != b'>');
+        }
+        s.pos += 1;
+    }
+    None
+}
+
+/// Returns the encoding of the `charset` in the `content` attribute of a `meta` element, such as
+/// `text/html; charset=EUC-JP`.
+/// # References:
+/// - <https://html.spec.whatwg.org/multipage/urls-and-fetching.html#algorithm-for-extracting-a-character-encoding-from-a-meta-element>
+pub fn extract_encoding_from_meta(content: &str) -> Option<Encoding> {
+    let lower = content.to_ascii_lowercase();
+    let mut rest = lower.as_str();
+    loop {
+        let start = rest.find("charset")?;
+        rest = rest[start + "charset".len()..].trim_start_matches(is_whitespace_char);
+        let value = match rest.strip_prefix('=') {
+            Some(value) => value.trim_start_matches(is_whitespace_char),
+            None => continue,
+        };
+        let label = match value.chars().next()? {
+            quote @ ('"' | '\'') => {
+                let end = value[1..].find(quote)?;
+                &value[1..end + 1]
+            }
+            _ => value
+                .split(|c| is_whitespace_char(c) || c == ';')
+                .next()
+                .unwrap_or_default(),
+        };
+        return Encoding::for_label(label);
+    }
+}
+
+fn is_whitespace_char(c: char) -> bool {
+    matches!(c, '\t' | '\n' | '\x0C' | '\r' | ' ')
+}
+
+/// Enum representing the state of an ISO-2022-JP decoder.
+/// # References:
+/// - <https://encoding.spec.whatwg.org/#iso-2022-jp-decoder>
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Iso2022JpState {
+    Ascii,
+    Roman,
+    Katakana,
+    LeadByte,
+    TrailByte,
+    EscapeStart,
+    Escape,
+}
+
+/// Struct representing a decoder that converts bytes in an encoding to characters. The bytes can
+/// be given in chunks, and a sequence that is split between chunks is decoded when the rest of it
+/// arrives. Invalid sequences are replaced with U+FFFD.
+/// # References:
+/// - <https://encoding.spec.whatwg.org/#decoders>
+#[derive(Debug, Clone)]
+pub struct Decoder {
+    encoding: Encoding,
+    /// Bytes to process before the next input byte, in reverse order.
+    prepended: Vec<u8>,
+    /// Whether any character has been decoded, to skip the byte order mark.
+    started: bool,
+    /// The number of invalid sequences that were replaced.
+    errors: usize,
+    lead: u8,
+    code_point: u32,
+    bytes_seen: u8,
+    bytes_needed: u8,
+    lower_boundary: u8,
+    upper_boundary: u8,
+    lead_surrogate: Option<u16>,
+    jis0212: bool,
+    iso2022jp_state: Iso2022JpState,
+    iso2022jp_output_state: Iso2022JpState,
+    iso2022jp_output: bool,
+}
+impl Decoder {
+    pub fn new(encoding: Encoding) -> Self {
+        Self {
+            encoding,
+            prepended: Vec::new(),
+            started: false,
+            errors: 0,
+            lead: 0,
+            code_point: 0,
+            bytes_seen: 0,
+            bytes_needed: 0,
+            lower_boundary: 0x80,
+            upper_boundary: 0xBF,
+            lead_surrogate: None,
+            jis0212: false,
+            iso2022jp_state: Iso2022JpState::Ascii,
+            iso2022jp_output_state: Iso2022JpState::Ascii,
+            iso2022jp_output: false,
+        }
+    }
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+    /// Returns the number of invalid sequences that were replaced with U+FFFD so far.
+    pub fn errors(&self) -> usize {
+        self.errors
+    }
+    /// Decodes a chunk of bytes.
+    /// # Parameters
+    /// - `bytes`: The chunk.
+    /// - `last`: Whether this is the last chunk. An incomplete sequence at the end of the last
+    ///   chunk is an error.
+    /// # Returns
+    /// - The characters that are complete so far.
+    pub fn decode(&mut self, bytes: &[u8], last: bool) -> String {
+        let mut out = String::new();
+        let mut i = 0;
+        loop {
+            let byte = match self.prepended.pop() {
+                Some(b) => Some(b),
+                None if i < bytes.len() => {
+                    i += 1;
+                    Some(bytes[i - 1])
+                }
+                None if last => None,
+                None => break,
+            };
+            self.handle(byte, &mut out);
+            if byte.is_none() && self.prepended.is_empty() {
+                break;
+            }
+        }
+        out
+    }
+    fn emit(&mut self, code_point: u32, out: &mut String) {
+        let c = char::from_u32(code_point).unwrap_or('\u{FFFD}');
+        let started = self.started;
+        self.started = true;
+        // The byte order mark is not a part of the text.
+        if !started
+            && c == '\u{FEFF}'
+            && matches!(
+                self.encoding,
+                Encoding::Utf8 | Encoding::Utf16Be | Encoding::Utf16Le
+            )
+        {
+            return;
+        }
+        out.push(c);
+    }
+    fn error(&mut self, out: &mut String) {
+        self.errors += 1;
+        self.emit(0xFFFD, out);
+    }
+    /// Processes a byte, or the end of the input if `byte` is `None`.
+    fn handle(&mut self, byte: Option<u8>, out: &mut String) {
+        match self.encoding {
+            Encoding::Utf8 => self.handle_utf8(byte, out),
+            Encoding::Utf16Be | Encoding::Utf16Le => self.handle_utf16(byte, out),
+            Encoding::ShiftJis => self.handle_shift_jis(byte, out),
+            Encoding::EucJp => self.handle_euc_jp(byte, out),
+            Encoding::Iso2022Jp => self.handle_iso2022jp(byte, out),
+            Encoding::Windows1252 => match byte {
+                Some(b @ 0x80..=0x9F) => self.emit(WINDOWS_1252_C1[b as usize - 0x80] as u32, out),
+                Some(b) => self.emit(b as u32, out),
+                None => {}
+            },
+        }
+    }
+    /// # References:
+    /// - <https://encoding.spec.whatwg.org/#utf-8-decoder>
+    fn handle_utf8(&mut self, byte: Option<u8>, out: &mut String) {
+        let b = match byte {
+            Some(b) => b,
+            None => {
+                if self.bytes_needed != 0 {
+                    self.bytes_needed = 0;
+                    self.error(out);
+                }
+                return;
+            }
+        };
+        if self.bytes_needed == 0 {
+            match b {
+                0x00..=0x7F => self.emit(b as u32, out),
+                0xC2..=0xDF => {
+                    self.bytes_needed = 1;
+                    self.code_point = (b & 0x1F) as u32;
+                }
+                0xE0..=0xEF => {
+                    if b == 0xE0 {
+                        self.lower_boundary = 0xA0;
+                    }
+                    if b == 0xED {
+                        self.upper_boundary = 0x9F;
+                    }
+                    self.bytes_needed = 2;
+                    self.code_point = (b & 0x0F) as u32;
+                }
+                0xF0..=0xF4 => {
+                    if b == 0xF0 {
+                        self.lower_boundary = 0x90;
+                    }
+                    if b == 0xF4 {
+                        self.upper_boundary = 0x8F;
+                    }
+                    self.bytes_needed = 3;
+                    self.code_point = (b & 0x07) as u32;
+                }
+                _ => self.error(out),
+            }
+            return;
+        }
+        if !(self.lower_boundary..=self.upper_boundary).contains(&b) {
+            self.code_point = 0;
+            self.bytes_needed = 0;
+            self.bytes_seen = 0;
+            self.lower_boundary = 0x80;
+            self.upper_boundary = 0xBF;
+            self.prepended.push(b);
+            self.error(out);
+            return;
+        }
+        self.lower_boundary = 0x80;
+        self.upper_boundary = 0xBF;
+        self.code_point = (self.code_point << 6) | (b & 0x3F) as u32;
+        self.bytes_seen += 1;
+        if self.bytes_seen == self.bytes_needed {
+            let code_point = self.code_point;
+            self.code_point = 0;
+            self.bytes_needed = 0;
+            self.bytes_seen = 0;
+            self.emit(code_point, out);
+        }
+    }
+    /// # References:
+    /// - <https://encoding.spec.whatwg.org/#shared-utf-16-decoder>
+    fn handle_utf16(&mut self, byte: Option<u8>, out: &mut String) {
+        let b = match byte {
+            Some(b) => b,
+            None => {
+                if self.bytes_seen != 0 || self.lead_surrogate.is_some() {
+                    self.bytes_seen = 0;
+                    self.lead_surrogate = None;
+                    self.error(out);
+                }
+                return;
+            }
+        };
+        if self.bytes_seen == 0 {
+            self.lead = b;
+            self.bytes_seen = 1;
+            return;
+        }
+        self.bytes_seen = 0;
+        let big_endian = self.encoding == Encoding::Utf16Be;
+        let code_unit = match big_endian {
+            true => u16::from_be_bytes([self.lead, b]),
+            false => u16::from_le_bytes([self.lead, b]),
+        };
+        if let Some(lead_surrogate) = self.lead_surrogate.take() {
+            if (0xDC00..=0xDFFF).contains(&code_unit) {
+                let code_point = 0x10000
+                    + (((lead_surrogate - 0xD800) as u32) << 10)
+                    + (code_unit - 0xDC00) as u32;
+                self.emit(code_point, out);
+                return;
+            }
+            // The code unit is processed again on its own.
+            self.prepended.push(b);
+            self.prepended.push(self.lead);
+            self.error(out);
+            return;
+        }
+        match code_unit {
+            0xD800..=0xDBFF => self.lead_surrogate = Some(code_unit),
+            0xDC00..=0xDFFF => self.error(out),
+            _ => self.emit(code_unit as u32, out),
+        }
+    }
+    /// # References:
+    /// - <https://encoding.spec.whatwg.org/#shift_jis-decoder>
+    fn handle_shift_jis(&mut self, byte: Option<u8>, out: &mut String) {
+        let b = match byte {
+            Some(b) => b,
+            None => {
+                if self.lead != 0 {
+                    self.lead = 0;
+                    self.error(out);
+                }
+                return;
+            }
+        };
+        if self.lead != 0 {
+            let lead = self.lead as usize;
+            self.lead = 0;
+            let offset = if b < 0x7F { 0x40 } else { 0x41 };
+            let lead_offset = if lead < 0xA0 { 0x81 } else { 0xC1 };
+            let mut code_point = None;
+            if matches!(b, 0x40..=0x7E | 0x80..=0xFC) {
+                let pointer = (lead - lead_offset) * 188 + b as usize - offset;
+                code_point = match pointer {
+                    8836..=10715 => Some(0xE000 - 8836 + pointer as u32),
+                    _ => index_code_point(&JIS0208, pointer),
+                };
+            }
+            match code_point {
+                Some(c) => self.emit(c, out),
+                None => {
+                    if b.is_ascii() {
+                        self.prepended.push(b);
+                    }
+                    self.error(out);
+                }
+            }
+            return;
+        }
+        match b {
+            0x00..=0x80 => self.emit(b as u32, out),
+            0xA1..=0xDF => self.emit(0xFF61 - 0xA1 + b as u32, out),
+            0x81..=0x9F | 0xE0..=0xFC => self.lead = b,
+            _ => self.error(out),
+        }
+    }
+    /// # References:
+    /// - <https://encoding.spec.whatwg.org/#euc-jp-decoder>
+    fn handle_euc_jp(&mut self, byte: Option<u8>, out: &mut String) {
+        let b = match byte {
+            Some(b) => b,
+            None => {
+                if self.lead != 0 {
+                    self.lead = 0;
+                    self.error(out);
+                }
+                return;
+            }
+        };
+        if self.lead == 0x8E && (0xA1..=0xDF).contains(&b) {
+            self.lead = 0;
+            self.emit(0xFF61 - 0xA1 + b as u32, out);
+            return;
+        }
+        if self.lead == 0x8F && (0xA1..=0xFE).contains(&b) {
+            self.jis0212 = true;
+            self.lead = b;
+            return;
+        }
+        if self.lead != 0 {
+            let lead = self.lead as usize;
+            self.lead = 0;
+            let mut code_point = None;
+            if (0xA1..=0xFE).contains(&lead) && (0xA1..=0xFE).contains(&b) {
+                let pointer = (lead - 0xA1) * 94 + b as usize - 0xA1;
+                code_point = match self.jis0212 {
+                    true => index_code_point(&JIS0212, pointer),
+                    false => index_code_point(&JIS0208, pointer),
+                };
+            }
+            self.jis0212 = false;
+            match code_point {
+                Some(c) => self.emit(c, out),
+                None => {
+                    if b.is_ascii() {
+                        self.prepended.push(b);
+                    }
+                    self.error(out);
+                }
+            }
+            return;
+        }
+        match b {
+            0x00..=0x7F => self.emit(b as u32, out),
+            0x8E | 0x8F | 0xA1..=0xFE => self.lead = b,
+            _ => self.error(out),
+        }
+    }
+    /// # References:
+    /// - <https://encoding.spec.whatwg.org/#iso-2022-jp-decoder>
+    fn handle_iso2022jp(&mut self, byte: Option<u8>, out: &mut String) {
+        match self.iso2022jp_state {
+            Iso2022JpState::Ascii
+            | Iso2022JpState::Roman
+            | Iso2022JpState::Katakana
+            | Iso2022JpState::LeadByte => {
+                let b = match byte {
+                    Some(b) => b,
+                    None => return,
+                };
+                if b == 0x1B {
+                    self.iso2022jp_state = Iso2022JpState::EscapeStart;
+                    return;
+                }
+                self.iso2022jp_output = false;
+                let code_point = match (self.iso2022jp_state, b) {
+                    (_, 0x0E | 0x0F) => None,
+                    (Iso2022JpState::Ascii, 0x00..=0x7F) => Some(b as u32),
+                    (Iso2022JpState::Roman, 0x5C) => Some(0xA5),
+                    (Iso2022JpState::Roman, 0x7E) => Some(0x203E),
+                    (Iso2022JpState::Roman, 0x00..=0x7F) => Some(b as u32),
+                    (Iso2022JpState::Katakana, 0x21..=0x5F) => Some(0xFF61 - 0x21 + b as u32),
+                    (Iso2022JpState::LeadByte, 0x21..=0x7E) => {
+                        self.lead = b;
+                        self.iso2022jp_state = Iso2022JpState::TrailByte;
+                        return;
+                    }
+                    _ => None,
+                };
+                match code_point {
+                    Some(c) => self.emit(c, out),
+                    None => self.error(out),
+                }
+            }
+            Iso2022JpState::TrailByte => match byte {
+                Some(0x1B) => {
+                    self.iso2022jp_state = Iso2022JpState::EscapeStart;
+                    self.error(out);
+                }
+                Some(b @ 0x21..=0x7E) => {
+                    self.iso2022jp_state = Iso2022JpState::LeadByte;
+                    let pointer = (self.lead as usize - 0x21) * 94 + b as usize - 0x21;
+                    match index_code_point(&JIS0208, pointer) {
+                        Some(c) => self.emit(c, out),
+                        None => self.error(out),
+                    }
+                }
+                // The end of the input is processed again in the lead byte state.
+                _ => {
+                    self.iso2022jp_state = Iso2022JpState::LeadByte;
+                    self.error(out);
+                }
+            },
+            Iso2022JpState::EscapeStart => match byte {
+                Some(b @ (0x24 | 0x28)) => {
+                    self.lead = b;
+                    self.iso2022jp_state = Iso2022JpState::Escape;
+                }
+                _ => {
+                    if let Some(b) = byte {
+                        self.prepended.push(b);
+                    }
+                    self.iso2022jp_output = false;
+                    self.iso2022jp_state = self.iso2022jp_output_state;
+                    self.error(out);
+                }
+            },
+            Iso2022JpState::Escape => {
+                let lead = self.lead;
+                self.lead = 0;
+                let state = match (lead, byte) {
+                    (0x28, Some(0x42)) => Some(Iso2022JpState::Ascii),
+                    (0x28, Some(0x4A)) => Some(Iso2022JpState::Roman),
+                    (0x28, Some(0x49)) => Some(Iso2022JpState::Katakana),
+                    (0x24, Some(0x40 | 0x42)) => Some(Iso2022JpState::LeadByte),
+                    _ => None,
+                };
+                match state {
+                    Some(state) => {
+                        self.iso2022jp_state = state;
+                        self.iso2022jp_output_state = state;
+                        // Two escape sequences in a row without any character is an error.
+                        let output = self.iso2022jp_output;
+                        self.iso2022jp_output = true;
+                        if output {
+                            self.error(out);
+                        }
+                    }
+                    None => {
+                        if let Some(b) = byte {
+                            self.prepended.push(b);
+                        }
+                        self.prepended.push(lead);
+                        self.iso2022jp_output = false;
+                        self.iso2022jp_state = self.iso2022jp_output_state;
+                        self.error(out);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Returns the code point for a pointer in an index, if any.
+fn index_code_point(index: &[u16], pointer: usize) -> Option<u32> {
+    match index.get(pointer) {
+        Some(0) | None => None,
+        Some(c) => Some(*c as u32),
+    }
+}
+
+/// Decodes a whole byte sequence. A byte order mark overrides `encoding`.
+/// # References:
+/// - <https://encoding.spec.whatwg.org/#decode>
+pub fn decode(bytes: &[u8], encoding: Encoding) -> String {
+    let encoding = bom_sniff(bytes).unwrap_or(encoding);
+    Decoder::new(encoding).decode(bytes, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+    #[test]
+    fn test_labels() {
+        assert_eq!(Some(Encoding::ShiftJis), Encoding::for_label(" SJIS\n"));
+        assert_eq!(
+            Some(Encoding::Windows1252),
+            Encoding::for_label("iso-8859-1")
+        );
+        assert_eq!(Some(Encoding::Utf16Le), Encoding::for_label("utf-16"));
+        assert_eq!(None, Encoding::for_label("utf-7"));
+        assert_eq!("EUC-JP", Encoding::EucJp.name());
+    }
+    #[test]
+    fn test_sniff_encoding() {
+        for (bytes, content_type, expected) in [
+            (
+                &b"\xEF\xBB\xBF<meta charset=sjis>"[..],
+                Some("text/html; charset=euc-jp"),
+                (Encoding::Utf8, Confidence::Certain),
+            ),
+            (
+                &b"<meta charset=sjis>"[..],
+                Some("text/html; charset=\"EUC-JP\""),
+                (Encoding::EucJp, Confidence::Certain),
+            ),
+            (
+                &b"<!-- <meta charset=utf-8> --><html lang=ja><META CHARSET='Shift_JIS'>"[..],
+                None,
+                (Encoding::ShiftJis, Confidence::Tentative),
+            ),
+            (
+                &b"<meta http-equiv=\"Content-Type\" content=\"text/html; charset=euc-jp\">"[..],
+                Some("text/html"),
+                (Encoding::EucJp, Confidence::Tentative),
+            ),
+            (
+                &b"<meta content=\"text/html; charset=euc-jp\">\xA4\xA2"[..],
+                None,
+                (Encoding::Windows1252, Confidence::Tentative),
+            ),
+            (
+                &b"<meta charset=utf-16>"[..],
+                None,
+                (Encoding::Utf8, Confidence::Tentative),
+            ),
+            (
+                "<p>\u{3042}".as_bytes(),
+                None,
+                (Encoding::Utf8, Confidence::Tentative),
+            ),
+        ] {
+            assert_eq!(expected, sniff_encoding(bytes, content_type));
+        }
+    }
+    #[test]
+    fn test_decode() {
+        for (bytes, encoding, expected) in [
+            (
+                &b"\xEF\xBB\xBFa\xF0\x9F\x92b\xC0"[..],
+                Encoding::Utf8,
+                "a\u{FFFD}b\u{FFFD}",
+            ),
+            (
+                &b"\xFF\xFE\xE5\x65\x2C\x67\x3D\xD8\x00\xDE\x00\xD8a\x00"[..],
+                Encoding::Utf16Le,
+                "\u{65E5}\u{672C}\u{1F600}\u{FFFD}a",
+            ),
+            (&b"\x65\xE5\x00"[..], Encoding::Utf16Be, "\u{65E5}\u{FFFD}"),
+            (
+                &b"\x93\xFA\x96\x7B\x8C\xEA\xB1\x81 \x81"[..],
+                Encoding::ShiftJis,
+                "日本語ｱ\u{FFFD} \u{FFFD}",
+            ),
+            (
+                &b"\xC6\xFC\xCB\xDC\xB8\xEC\x8E\xB1\x8F\xB0\xA1\xA1a"[..],
+                Encoding::EucJp,
+                "日本語ｱ丂\u{FFFD}a",
+            ),
+            (
+                &b"a\x1B$BF|K\\8l\x1B(Bb\x1B(J\\\x1B(I1\x1B(B\x1B$"[..],
+                Encoding::Iso2022Jp,
+                "a日本語b\u{A5}ｱ\u{FFFD}$",
+            ),
+            (
+                &b"\x80\xE9\x81"[..],
+                Encoding::Windows1252,
+                "\u{20AC}\u{E9}\u{81}",
+            ),
+        ] {
+            assert_eq!(
+                expected.to_string(),
+                decode(bytes, encoding),
+                "{:?}",
+                encoding
+            );
+        }
+    }
+    #[test]
+    fn test_decode_in_chunks() {
+        for (bytes, encoding) in [
+            ("a\u{3042}\u{1F600}".as_bytes(), Encoding::Utf8),
+            (&b"\x93\xFA\x96\x7B\x8C\xEA"[..], Encoding::ShiftJis),
+            (&b"\xC6\xFC\x8F\xB0\xA1"[..], Encoding::EucJp),
+            (&b"\x1B$BF|K\\\x1B(Ba"[..], Encoding::Iso2022Jp),
+        ] {
+            let expected = decode(bytes, encoding);
+            for size in 1..bytes.len() {
+                let mut decoder = Decoder::new(encoding);
+                let mut text = String::new();
+                for chunk in bytes.chunks(size) {
+                    text.push_str(&decoder.decode(chunk, false));
+                }
+                text.push_str(&decoder.decode(&[], true));
+                assert_eq!(expected, text, "{:?} in chunks of {}", encoding, size);
+                assert_eq!(0, decoder.errors());
+            }
+        }
+    }
+}
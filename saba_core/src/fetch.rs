@@ -0,0 +1,156 @@
+use crate::cookie::CookieJar;
+use crate::error::Error;
+use crate::http::Header;
+use crate::http::HttpRequest;
+use crate::http::HttpResponse;
+use crate::url::Url;
+use alloc::format;
+use alloc::rc::Rc;
+use alloc::string::ToString;
+use core::cell::RefCell;
+
+/// The maximum number of redirects followed for one request.
+static MAX_REDIRECTS: usize = 20;
+
+/// Type of a function that sends one HTTP request over the network.
+pub type RequestSender = fn(HttpRequest) -> Result<HttpResponse, Error>;
+
+/// Struct representing the request pipeline shared by navigations and scripts.
+/// It attaches cookies, stores the cookies a response sets, and follows redirects.
+#[derive(Debug, Clone)]
+pub struct Fetcher {
+    send: RequestSender,
+    cookie_jar: Rc<RefCell<CookieJar>>,
+}
+impl Fetcher {
+    pub fn new(send: RequestSender) -> Self {
+        Self {
+            send,
+            cookie_jar: Rc::new(RefCell::new(CookieJar::new())),
+        }
+    }
+    pub fn cookie_jar(&self) -> Rc<RefCell<CookieJar>> {
+        self.cookie_jar.clone()
+    }
+    /// Sends a request and follows the redirects of the responses.
+    /// # Parameters
+    /// - `request`: The request to send.
+    /// - `include_cookies`: Whether to attach cookies. Responses always update the cookie jar.
+    /// # Returns
+    /// - The last response, whose `url()` is the URL it was fetched from.
+    /// # References:
+    /// - <https://fetch.spec.whatwg.org/#http-redirect-fetch>
+    pub fn fetch(
+        &self,
+        request: HttpRequest,
+        include_cookies: bool,
+    ) -> Result<HttpResponse, Error> {
+        let mut request = request;
+        for _ in 0..=MAX_REDIRECTS {
+            let url = Url::new(request.url())
+                .parse()
+                .map_err(Error::UnexpectedInput)?;
+            let mut outgoing = request.clone();
+            if include_cookies {
+                if let Some(cookie) = self.cookie_jar.borrow().cookie_header(&url) {
+                    outgoing.add_header(Header::new("Cookie".to_string(), cookie));
+                }
+            }
+            let mut response = (self.send)(outgoing)?;
+            for set_cookie in response.header_values("Set-Cookie") {
+                self.cookie_jar.borrow_mut().store(&url, &set_cookie);
+            }
+            response.set_url(url.serialize());
+            let status = response.status_code();
+            if !matches!(status, 301 | 302 | 303 | 307 | 308) {
+                return Ok(response);
+            }
+            let location = match response.header_values("Location").first() {
+                Some(location) => location.clone(),
+                None => return Ok(response),
+            };
+            let next_url = url.resolve(&location).map_err(Error::UnexpectedInput)?;
+            // 303, and 301/302 after a POST, are followed with a GET request.
+            request = if status == 303
+                || ((status == 301 || status == 302) && request.method() == "POST")
+            {
+                HttpRequest::get(next_url.serialize())
+            } else {
+                request.with_url(next_url.serialize())
+            };
+        }
+        Err(Error::Network(format!(
+            "Too many redirects while fetching {}",
+            request.url()
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    fn send(request: HttpRequest) -> Result<HttpResponse, Error> {
+        let cookie = request
+            .headers()
+            .iter()
+            .find(|h| h.name() == "Cookie")
+            .map(|h| h.value())
+            .unwrap_or_default();
+        let raw = match request.url().as_str() {
+            "http://example.com/login" => {
+                "HTTP/1.1 302 Found\nLocation: /home\nSet-Cookie: session=abc\n\n".to_string()
+            }
+            "http://example.com/home" => format!(
+                "HTTP/1.1 200 OK\nContent-Type: text/plain\n\ncookie:{}",
+                cookie
+            ),
+            "http://example.com/loop" => "HTTP/1.1 302 Found\nLocation: /loop\n\n".to_string(),
+            _ => "HTTP/1.1 404 Not Found\n\n".to_string(),
+        };
+        HttpResponse::new(raw)
+    }
+    #[test]
+    fn test_redirect_with_cookie() {
+        let fetcher = Fetcher::new(send);
+        let response = fetcher
+            .fetch(
+                HttpRequest::post(
+                    "http://example.com/login".to_string(),
+                    "text/plain",
+                    "x".to_string(),
+                ),
+                true,
+            )
+            .expect("failed to fetch");
+        assert_eq!(response.status_code(), 200);
+        assert_eq!(response.url(), "http://example.com/home".to_string());
+        assert_eq!(response.body(), "cookie:session=abc".to_string());
+    }
+    #[test]
+    fn test_without_cookies() {
+        let fetcher = Fetcher::new(send);
+        assert!(fetcher
+            .fetch(
+                HttpRequest::get("http://example.com/login".to_string()),
+                false
+            )
+            .is_ok());
+        let response = fetcher
+            .fetch(
+                HttpRequest::get("http://example.com/home".to_string()),
+                false,
+            )
+            .expect("failed to fetch");
+        assert_eq!(response.body(), "cookie:".to_string());
+    }
+    #[test]
+    fn test_redirect_loop() {
+        let fetcher = Fetcher::new(send);
+        assert!(fetcher
+            .fetch(
+                HttpRequest::get("http://example.com/loop".to_string()),
+                true
+            )
+            .is_err());
+    }
+}
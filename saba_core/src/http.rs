@@ -1,4 +1,6 @@
 use crate::alloc::string::ToString;
+use crate::encoding::decode;
+use crate::encoding::Encoding;
 use crate::error::Error;
 use alloc::format;
 use alloc::string::String;
@@ -12,8 +14,10 @@ pub struct HttpResponse {
     reason: String,
     headers: Vec<Header>,
     body: String,
+    body_bytes: Vec<u8>,
+    url: String,
 }
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Header {
     name: String,
     value: String,
@@ -22,6 +26,70 @@ impl Header {
     pub fn new(name: String, value: String) -> Self {
         Self { name, value }
     }
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+    pub fn value(&self) -> String {
+        self.value.clone()
+    }
+}
+
+/// Struct representing an HTTP request issued by a navigation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpRequest {
+    method: String,
+    url: String,
+    headers: Vec<Header>,
+    body: String,
+}
+impl HttpRequest {
+    pub fn new(method: &str, url: String) -> Self {
+        Self {
+            method: method.to_string(),
+            url,
+            headers: Vec::new(),
+            body: String::new(),
+        }
+    }
+    pub fn get(url: String) -> Self {
+        Self::new("GET", url)
+    }
+    pub fn post(url: String, content_type: &str, body: String) -> Self {
+        let mut request = Self::new("POST", url);
+        request.add_header(Header::new(
+            "Content-Type".to_string(),
+            content_type.to_string(),
+        ));
+        request.body = body;
+        request
+    }
+    /// Returns the same request sent to another URL, e.g. when following a redirect.
+    pub fn with_url(&self, url: String) -> Self {
+        let mut request = self.clone();
+        request.url = url;
+        request
+    }
+    /// Returns the same request with another body.
+    pub fn with_body(&self, body: String) -> Self {
+        let mut request = self.clone();
+        request.body = body;
+        request
+    }
+    pub fn add_header(&mut self, header: Header) {
+        self.headers.push(header);
+    }
+    pub fn method(&self) -> String {
+        self.method.clone()
+    }
+    pub fn url(&self) -> String {
+        self.url.clone()
+    }
+    pub fn headers(&self) -> Vec<Header> {
+        self.headers.clone()
+    }
+    pub fn body(&self) -> String {
+        self.body.clone()
+    }
 }
 impl HttpResponse {
     pub fn new(raw_response: String) -> Result<Self, Error> {
@@ -56,8 +124,30 @@ impl HttpResponse {
             reason: statuses[2].to_string(),
             headers,
             body: body.to_string(),
+            body_bytes: body.as_bytes().to_vec(),
+            url: String::new(),
         })
     }
+    /// Parses a response whose body may not be UTF-8. The body is kept as bytes so that a
+    /// document can be decoded in its own encoding, and `body()` decodes it as UTF-8.
+    pub fn from_bytes(raw: &[u8]) -> Result<Self, Error> {
+        let body_start = (0..raw.len())
+            .find_map(|i| {
+                if raw[i..].starts_with(b"\r\n\r\n") {
+                    Some(i + 4)
+                } else if raw[i..].starts_with(b"\n\n") {
+                    Some(i + 2)
+                } else {
+                    None
+                }
+            })
+            .unwrap_or(raw.len());
+        let (head, body) = raw.split_at(body_start);
+        let mut response = Self::new(decode(head, Encoding::Utf8))?;
+        response.body = decode(body, Encoding::Utf8);
+        response.body_bytes = body.to_vec();
+        Ok(response)
+    }
     pub fn version(&self) -> String {
         self.version.clone()
     }
@@ -73,6 +163,9 @@ impl HttpResponse {
     pub fn body(&self) -> String {
         self.body.clone()
     }
+    pub fn body_bytes(&self) -> Vec<u8> {
+        self.body_bytes.clone()
+    }
     pub fn header_value(&self, name: &str) -> Result<String, String> {
         for h in &self.headers {
             if h.name == name {
@@ -81,6 +174,21 @@ impl HttpResponse {
         }
         Err(format!("failed to find {} in headers", name))
     }
+    /// Returns the values of all headers with the name, compared case-insensitively.
+    pub fn header_values(&self, name: &str) -> Vec<String> {
+        self.headers
+            .iter()
+            .filter(|h| h.name.eq_ignore_ascii_case(name))
+            .map(|h| h.value.clone())
+            .collect()
+    }
+    /// Returns the URL the response was fetched from, after redirects were followed.
+    pub fn url(&self) -> String {
+        self.url.clone()
+    }
+    pub fn set_url(&mut self, url: String) {
+        self.url = url;
+    }
 }
 
 #[cfg(test)]
@@ -124,6 +232,46 @@ mod tests {
         assert_eq!(res.body(), "body message".to_string());
     }
     #[test]
+    fn test_header_values() {
+        let raw = "HTTP/1.1 200 OK\nSet-Cookie: a=1\nset-cookie: b=2\n\n".to_string();
+        let res = HttpResponse::new(raw).expect("failed to parse http response");
+        assert_eq!(
+            res.header_values("Set-Cookie"),
+            ["a=1".to_string(), "b=2".to_string()].to_vec()
+        );
+    }
+    #[test]
+    fn test_post_request() {
+        let req = HttpRequest::post(
+            "http://example.com/submit".to_string(),
+            "application/x-www-form-urlencoded",
+            "a=1".to_string(),
+        );
+        assert_eq!(req.method(), "POST");
+        assert_eq!(
+            req.headers(),
+            [Header::new(
+                "Content-Type".to_string(),
+                "application/x-www-form-urlencoded".to_string()
+            )]
+            .to_vec()
+        );
+        assert_eq!(req.body(), "a=1".to_string());
+    }
+    #[test]
+    fn test_from_bytes() {
+        let mut raw =
+            b"HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=Shift_JIS\r\n\r\n".to_vec();
+        raw.extend_from_slice(&[0x93, 0xfa, 0x96, 0x7b]);
+        let res = HttpResponse::from_bytes(&raw).expect("failed to parse http response");
+        assert_eq!(res.status_code(), 200);
+        assert_eq!(
+            res.header_value("Content-Type"),
+            Ok("text/html; charset=Shift_JIS".to_string())
+        );
+        assert_eq!(res.body_bytes(), [0x93, 0xfa, 0x96, 0x7b].to_vec());
+    }
+    #[test]
     fn test_invalid() {
         let raw = "HTTP/1.1 200 OK".to_string();
         assert!(HttpResponse::new(raw).is_err());
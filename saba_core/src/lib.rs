@@ -2,9 +2,13 @@
 extern crate alloc;
 pub mod browser;
 pub mod constants;
+pub mod cookie;
 pub mod display_item;
+pub mod encoding;
 pub mod error;
+pub mod fetch;
 pub mod http;
 pub mod renderer;
 pub mod url;
 pub mod utils;
+pub mod websocket;
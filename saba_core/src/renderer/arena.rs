@@ -0,0 +1,545 @@
+use alloc::vec::Vec;
+use core::fmt::Debug;
+use core::fmt::Formatter;
+use core::hash::Hash;
+use core::hash::Hasher;
+use core::marker::PhantomData;
+use core::ops::Index;
+use core::ops::IndexMut;
+
+/// Struct representing a handle to a value in an `Arena<T>`. It is a typed index, so it is cheap
+/// to copy and compare, and a handle to a DOM node can't be used for a layout object by mistake.
+/// The generation tells the handles of the values that share a reused slot apart, so a handle
+/// to a freed value can't refer to the value allocated after it.
+pub struct Id<T> {
+    index: usize,
+    generation: u32,
+    _marker: PhantomData<fn() -> T>,
+}
+impl<T> Id<T> {
+    pub fn index(&self) -> usize {
+        self.index
+    }
+}
+impl<T> Clone for Id<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+impl<T> Copy for Id<T> {}
+impl<T> PartialEq for Id<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index && self.generation == other.generation
+    }
+}
+impl<T> Eq for Id<T> {}
+impl<T> Hash for Id<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.index.hash(state);
+        self.generation.hash(state);
+    }
+}
+impl<T> Debug for Id<T> {
+    fn fmt(&self, f: &mut Formatter) -> core::fmt::Result {
+        write!(f, "Id({}v{})", self.index, self.generation)
+    }
+}
+
+/// Struct representing a value in an arena and its links to the other values in the tree. The
+/// value of a freed slot is `None`.
+#[derive(Debug, Clone)]
+struct Slot<T> {
+    value: Option<T>,
+    /// The generation of the value in the slot, which is incremented when the value is freed.
+    generation: u32,
+    parent: Option<Id<T>>,
+    first_child: Option<Id<T>>,
+    last_child: Option<Id<T>>,
+    previous_sibling: Option<Id<T>>,
+    next_sibling: Option<Id<T>>,
+}
+
+/// Struct representing a tree whose nodes are stored in a vector and refer to each other by
+/// `Id`. An `Id` stays valid after the node is removed from the tree, so a detached node can be
+/// inserted again, until the node is freed. The slots of freed nodes are reused by `alloc`, and
+/// using the `Id` of a freed node panics.
+#[derive(Debug, Clone)]
+pub struct Arena<T> {
+    slots: Vec<Slot<T>>,
+    /// The indices of the freed slots.
+    free_slots: Vec<usize>,
+}
+impl<T> Default for Arena<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl<T> Arena<T> {
+    pub fn new() -> Self {
+        Self {
+            slots: Vec::new(),
+            free_slots: Vec::new(),
+        }
+    }
+    /// Adds `value` to the arena as a node that is not in any tree.
+    pub fn alloc(&mut self, value: T) -> Id<T> {
+        let index = match self.free_slots.pop() {
+            Some(index) => {
+                // The links were cleared when the slot was freed.
+                self.slots[index].value = Some(value);
+                index
+            }
+            None => {
+                self.slots.push(Slot {
+                    value: Some(value),
+                    generation: 0,
+                    parent: None,
+                    first_child: None,
+                    last_child: None,
+                    previous_sibling: None,
+                    next_sibling: None,
+                });
+                self.slots.len() - 1
+            }
+        };
+        Id {
+            index,
+            generation: self.slots[index].generation,
+            _marker: PhantomData,
+        }
+    }
+    /// Frees a node and drops its value. The caller must make sure that nothing refers to the
+    /// node anymore, including the other nodes of its tree, because its slot will be reused.
+    pub fn free(&mut self, id: Id<T>) {
+        let slot = &mut self.slots[id.index];
+        if slot.generation != id.generation || slot.value.take().is_none() {
+            return;
+        }
+        slot.generation = slot.generation.wrapping_add(1);
+        slot.parent = None;
+        slot.first_child = None;
+        slot.last_child = None;
+        slot.previous_sibling = None;
+        slot.next_sibling = None;
+        self.free_slots.push(id.index);
+    }
+    /// Returns the number of nodes, including the ones that are not in any tree, but not the
+    /// freed ones.
+    pub fn len(&self) -> usize {
+        self.slots.len() - self.free_slots.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Returns the nodes that are not freed, in the order of their slots.
+    pub fn ids(&self) -> impl Iterator<Item = Id<T>> + '_ {
+        self.slots
+            .iter()
+            .enumerate()
+            .filter(|(_, slot)| slot.value.is_some())
+            .map(|(index, slot)| Id {
+                index,
+                generation: slot.generation,
+                _marker: PhantomData,
+            })
+    }
+    /// Returns the slot of `id`.
+    /// # Panics
+    /// - If the node of `id` was freed, even if its slot holds another node now.
+    fn slot(&self, id: Id<T>) -> &Slot<T> {
+        let slot = &self.slots[id.index];
+        assert!(
+            slot.generation == id.generation && slot.value.is_some(),
+            "the node was freed"
+        );
+        slot
+    }
+    fn slot_mut(&mut self, id: Id<T>) -> &mut Slot<T> {
+        let slot = &mut self.slots[id.index];
+        assert!(
+            slot.generation == id.generation && slot.value.is_some(),
+            "the node was freed"
+        );
+        slot
+    }
+    pub fn parent(&self, id: Id<T>) -> Option<Id<T>> {
+        self.slot(id).parent
+    }
+    pub fn first_child(&self, id: Id<T>) -> Option<Id<T>> {
+        self.slot(id).first_child
+    }
+    pub fn last_child(&self, id: Id<T>) -> Option<Id<T>> {
+        self.slot(id).last_child
+    }
+    pub fn previous_sibling(&self, id: Id<T>) -> Option<Id<T>> {
+        self.slot(id).previous_sibling
+    }
+    pub fn next_sibling(&self, id: Id<T>) -> Option<Id<T>> {
+        self.slot(id).next_sibling
+    }
+    /// Appends `child` to the children of `parent`. See `insert_before`.
+    pub fn append(&mut self, parent: Id<T>, child: Id<T>) {
+        self.insert_before(parent, child, None);
+    }
+    /// Inserts `node` as a child of `parent` before `reference`, or as the last child if
+    /// `reference` is `None`. The node is detached from its old parent first. The caller must
+    /// make sure that `node` is not an ancestor of `parent` and that `reference` is a child of
+    /// `parent`.
+    pub fn insert_before(&mut self, parent: Id<T>, node: Id<T>, reference: Option<Id<T>>) {
+        self.detach(node);
+        let previous = match reference {
+            Some(r) => self.slot(r).previous_sibling,
+            None => self.slot(parent).last_child,
+        };
+        match previous {
+            Some(p) => self.slot_mut(p).next_sibling = Some(node),
+            None => self.slot_mut(parent).first_child = Some(node),
+        }
+        match reference {
+            Some(r) => self.slot_mut(r).previous_sibling = Some(node),
+            None => self.slot_mut(parent).last_child = Some(node),
+        }
+        let slot = self.slot_mut(node);
+        slot.parent = Some(parent);
+        slot.previous_sibling = previous;
+        slot.next_sibling = reference;
+    }
+    /// Removes `node` from its parent, if any. The node keeps its children.
+    pub fn detach(&mut self, node: Id<T>) {
+        let slot = self.slot(node);
+        let (parent, previous, next) = match slot.parent {
+            Some(parent) => (parent, slot.previous_sibling, slot.next_sibling),
+            None => return,
+        };
+        match previous {
+            Some(p) => self.slot_mut(p).next_sibling = next,
+            None => self.slot_mut(parent).first_child = next,
+        }
+        match next {
+            Some(n) => self.slot_mut(n).previous_sibling = previous,
+            None => self.slot_mut(parent).last_child = previous,
+        }
+        let slot = self.slot_mut(node);
+        slot.parent = None;
+        slot.previous_sibling = None;
+        slot.next_sibling = None;
+    }
+    /// Returns the children of `id` in order.
+    pub fn children(&self, id: Id<T>) -> Children<'_, T> {
+        Children {
+            arena: self,
+            next: self.first_child(id),
+        }
+    }
+    /// Returns the descendants of `root` in tree order, which don't include `root` itself.
+    pub fn descendants(&self, root: Id<T>) -> Descendants<'_, T> {
+        Descendants {
+            arena: self,
+            root,
+            next: self.first_child(root),
+        }
+    }
+    /// Returns `root` and its descendants in tree order.
+    pub fn inclusive_descendants(&self, root: Id<T>) -> Descendants<'_, T> {
+        Descendants {
+            arena: self,
+            root,
+            next: Some(root),
+        }
+    }
+    /// Returns `root` and its descendants in post-order, where the children of a node come
+    /// before the node, so it ends with `root`.
+    pub fn post_order(&self, root: Id<T>) -> PostOrder<'_, T> {
+        PostOrder {
+            arena: self,
+            root,
+            next: Some(self.first_leaf(root)),
+        }
+    }
+    /// Returns the ancestors of `id` from its parent to the root.
+    pub fn ancestors(&self, id: Id<T>) -> Ancestors<'_, T> {
+        Ancestors {
+            arena: self,
+            next: self.parent(id),
+        }
+    }
+    /// Returns `id` and its ancestors from `id` to the root.
+    pub fn inclusive_ancestors(&self, id: Id<T>) -> Ancestors<'_, T> {
+        Ancestors {
+            arena: self,
+            next: Some(id),
+        }
+    }
+    /// Returns the root of the tree that `id` belongs to.
+    /// # References:
+    /// - <https://dom.spec.whatwg.org/#concept-tree-root>
+    pub fn root_of(&self, id: Id<T>) -> Id<T> {
+        self.inclusive_ancestors(id)
+            .last()
+            .expect("a node is its own inclusive ancestor")
+    }
+    /// Returns true if `ancestor` is `id` or one of its ancestors.
+    pub fn is_inclusive_ancestor(&self, ancestor: Id<T>, id: Id<T>) -> bool {
+        self.inclusive_ancestors(id).any(|n| n == ancestor)
+    }
+    /// Returns the node after `id` in tree order that is a descendant of `root`.
+    pub fn following(&self, id: Id<T>, root: Id<T>) -> Option<Id<T>> {
+        match self.first_child(id) {
+            Some(child) => Some(child),
+            None => self.following_skipping_children(id, root),
+        }
+    }
+    /// Returns the node after the descendants of `id` in tree order that is a descendant of
+    /// `root`.
+    pub fn following_skipping_children(&self, id: Id<T>, root: Id<T>) -> Option<Id<T>> {
+        let mut node = id;
+        loop {
+            if node == root {
+                return None;
+            }
+            if let Some(sibling) = self.next_sibling(node) {
+                return Some(sibling);
+            }
+            node = self.parent(node)?;
+        }
+    }
+    /// Returns the node before `id` in tree order that is an inclusive descendant of `root`.
+    pub fn preceding(&self, id: Id<T>, root: Id<T>) -> Option<Id<T>> {
+        if id == root {
+            return None;
+        }
+        match self.previous_sibling(id) {
+            Some(sibling) => Some(self.last_inclusive_descendant(sibling)),
+            None => self.parent(id),
+        }
+    }
+    /// Returns the last inclusive descendant of `id` in tree order.
+    pub fn last_inclusive_descendant(&self, id: Id<T>) -> Id<T> {
+        let mut node = id;
+        while let Some(child) = self.last_child(node) {
+            node = child;
+        }
+        node
+    }
+    /// Returns the first leaf of `id` in tree order, which is the first node of a post-order walk.
+    fn first_leaf(&self, id: Id<T>) -> Id<T> {
+        let mut node = id;
+        while let Some(child) = self.first_child(node) {
+            node = child;
+        }
+        node
+    }
+}
+impl<T> Index<Id<T>> for Arena<T> {
+    type Output = T;
+    fn index(&self, id: Id<T>) -> &T {
+        self.slot(id)
+            .value
+            .as_ref()
+            .expect("the slot of a node has a value")
+    }
+}
+impl<T> IndexMut<Id<T>> for Arena<T> {
+    fn index_mut(&mut self, id: Id<T>) -> &mut T {
+        self.slot_mut(id)
+            .value
+            .as_mut()
+            .expect("the slot of a node has a value")
+    }
+}
+
+/// Iterator over the children of a node.
+pub struct Children<'a, T> {
+    arena: &'a Arena<T>,
+    next: Option<Id<T>>,
+}
+impl<'a, T> Iterator for Children<'a, T> {
+    type Item = Id<T>;
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.next?;
+        self.next = self.arena.next_sibling(node);
+        Some(node)
+    }
+}
+
+/// Iterator over nodes in tree order, which is the pre-order, depth-first order. It follows the
+/// links between the nodes instead of recursing, so it doesn't overflow the stack on deep trees
+/// or long sibling lists.
+/// # References:
+/// - <https://dom.spec.whatwg.org/#concept-tree-order>
+pub struct Descendants<'a, T> {
+    arena: &'a Arena<T>,
+    root: Id<T>,
+    next: Option<Id<T>>,
+}
+impl<'a, T> Iterator for Descendants<'a, T> {
+    type Item = Id<T>;
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.next?;
+        self.next = self.arena.following(node, self.root);
+        Some(node)
+    }
+}
+
+/// Iterator over nodes in post-order. Like `Descendants`, it doesn't recurse.
+pub struct PostOrder<'a, T> {
+    arena: &'a Arena<T>,
+    root: Id<T>,
+    next: Option<Id<T>>,
+}
+impl<'a, T> Iterator for PostOrder<'a, T> {
+    type Item = Id<T>;
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.next.take()?;
+        if node != self.root {
+            self.next = match self.arena.next_sibling(node) {
+                Some(sibling) => Some(self.arena.first_leaf(sibling)),
+                None => self.arena.parent(node),
+            };
+        }
+        Some(node)
+    }
+}
+
+/// Iterator over the ancestors of a node, from the nearest to the root.
+pub struct Ancestors<'a, T> {
+    arena: &'a Arena<T>,
+    next: Option<Id<T>>,
+}
+impl<'a, T> Iterator for Ancestors<'a, T> {
+    type Item = Id<T>;
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.next?;
+        self.next = self.arena.parent(node);
+        Some(node)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+    #[test]
+    fn test_insert_and_detach() {
+        let mut arena = Arena::new();
+        let root = arena.alloc("root");
+        let a = arena.alloc("a");
+        let b = arena.alloc("b");
+        let c = arena.alloc("c");
+        arena.append(root, a);
+        arena.append(root, c);
+        arena.insert_before(root, b, Some(c));
+        assert_eq!(vec![a, b, c], arena.children(root).collect::<Vec<_>>());
+        assert_eq!(Some(root), arena.parent(b));
+        assert_eq!(Some(a), arena.previous_sibling(b));
+        // Inserting a node that is in the tree moves it.
+        arena.insert_before(root, c, Some(a));
+        assert_eq!(vec![c, a, b], arena.children(root).collect::<Vec<_>>());
+        assert_eq!(Some(b), arena.last_child(root));
+        arena.detach(a);
+        assert_eq!(vec![c, b], arena.children(root).collect::<Vec<_>>());
+        assert_eq!(None, arena.parent(a));
+        assert_eq!(None, arena.next_sibling(a));
+        assert_eq!("a", arena[a]);
+        arena[a] = "A";
+        assert_eq!("A", arena[a]);
+    }
+    #[test]
+    fn test_iterators() {
+        let mut arena = Arena::new();
+        let r = arena.alloc("r");
+        let a = arena.alloc("a");
+        let a1 = arena.alloc("a1");
+        let a2 = arena.alloc("a2");
+        let b = arena.alloc("b");
+        arena.append(r, a);
+        arena.append(a, a1);
+        arena.append(a, a2);
+        arena.append(r, b);
+        let values = |ids: &mut dyn Iterator<Item = Id<&'static str>>| {
+            ids.map(|id| arena[id]).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            vec!["a", "a1", "a2", "b"],
+            values(&mut arena.descendants(r))
+        );
+        assert_eq!(
+            vec!["a1", "a2", "b"],
+            values(&mut arena.descendants(r).skip(1))
+        );
+        assert_eq!(
+            vec!["r", "a", "a1", "a2", "b"],
+            values(&mut arena.inclusive_descendants(r))
+        );
+        assert_eq!(vec!["a1", "a2"], values(&mut arena.descendants(a)));
+        assert_eq!(
+            vec!["a1", "a2", "a", "b", "r"],
+            values(&mut arena.post_order(r))
+        );
+        assert_eq!(vec!["a", "r"], values(&mut arena.ancestors(a2)));
+        assert_eq!(
+            vec!["a2", "a", "r"],
+            values(&mut arena.inclusive_ancestors(a2))
+        );
+        assert_eq!(r, arena.root_of(a2));
+        assert!(arena.is_inclusive_ancestor(a, a2));
+        assert!(!arena.is_inclusive_ancestor(b, a2));
+        assert_eq!(Some(a2), arena.preceding(b, r));
+        assert_eq!(Some(b), arena.following(a2, r));
+        assert_eq!(None, arena.following(a2, a));
+    }
+    #[test]
+    fn test_free() {
+        let mut arena = Arena::new();
+        let root = arena.alloc("root");
+        let a = arena.alloc("a");
+        let b = arena.alloc("b");
+        arena.append(root, a);
+        arena.append(root, b);
+        arena.detach(a);
+        arena.free(a);
+        // Freeing a node twice doesn't free its slot twice.
+        arena.free(a);
+        assert_eq!(2, arena.len());
+        assert_eq!(vec![root, b], arena.ids().collect::<Vec<_>>());
+        // The slot of the freed node is reused.
+        let c = arena.alloc("c");
+        assert_eq!(a.index(), c.index());
+        assert_ne!(a, c);
+        // The stale `Id` doesn't free the node that reuses its slot.
+        arena.free(a);
+        assert_eq!(None, arena.parent(c));
+        assert_eq!("c", arena[c]);
+        assert_eq!(3, arena.len());
+        let d = arena.alloc("d");
+        assert_eq!(3, d.index());
+    }
+    #[test]
+    #[should_panic(expected = "the node was freed")]
+    fn test_freed_id() {
+        let mut arena = Arena::new();
+        let a = arena.alloc("a");
+        arena.free(a);
+        arena.alloc("b");
+        let _ = arena[a];
+    }
+    #[test]
+    fn test_deep_and_wide_trees() {
+        let mut arena = Arena::new();
+        let root = arena.alloc(0);
+        let mut parent = root;
+        for i in 0..100_000 {
+            let child = arena.alloc(i);
+            arena.append(parent, child);
+            parent = child;
+        }
+        for i in 0..100_000 {
+            let child = arena.alloc(i);
+            arena.append(root, child);
+        }
+        assert_eq!(200_000, arena.descendants(root).count());
+        assert_eq!(200_001, arena.post_order(root).count());
+        assert_eq!(100_000, arena.ancestors(parent).count());
+        assert_eq!(100_001, arena.children(root).count());
+    }
+}
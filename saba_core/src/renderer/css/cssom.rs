@@ -2,24 +2,56 @@ use crate::alloc::string::String;
 use crate::alloc::string::ToString;
 use crate::renderer::css::token::CssToken;
 use crate::renderer::css::token::CssTokenizer;
+use crate::renderer::diagnostics::ParseError;
+use crate::renderer::diagnostics::SourceLanguage;
+use crate::renderer::diagnostics::TokenStream;
+use crate::renderer::dom::node::Element;
+use crate::renderer::dom::node::Namespace;
 use alloc::vec::Vec;
-use core::iter::Peekable;
-use core::panic;
 
 /// Struct representing a CSS parser.
 #[derive(Debug, Clone)]
 pub struct CssParser {
-    t: Peekable<CssTokenizer>,
+    t: TokenStream<CssTokenizer>,
+    errors: Vec<ParseError>,
 }
 impl CssParser {
     pub fn new(t: CssTokenizer) -> Self {
-        Self { t: t.peekable() }
+        Self {
+            t: TokenStream::new(t),
+            errors: Vec::new(),
+        }
+    }
+    /// Returns the parse errors of the tokenizer and the parser, in source order.
+    pub fn errors(&self) -> Vec<ParseError> {
+        let mut errors = self.t.tokenizer().errors();
+        errors.extend(self.errors.iter().cloned());
+        errors.sort_by_key(|e| e.span().start());
+        errors
+    }
+    /// Records a parse error at the token that was consumed last.
+    fn parse_error(&mut self, code: &str) {
+        self.errors
+            .push(ParseError::new(SourceLanguage::Css, code, self.t.span()));
+    }
+    /// Skips tokens until the next `{`, so that the block is parsed as the rule's declarations.
+    fn skip_to_block(&mut self) {
+        while !matches!(self.t.peek(), Some(CssToken::OpenCurly) | None) {
+            self.t.next();
+        }
     }
     pub fn parse_stylesheet(&mut self) -> StyleSheet {
         let mut sheet = StyleSheet::new();
         sheet.set_rules(self.consume_list_of_rules());
         sheet
     }
+    /// Parses the declarations of a `style` attribute, which are not enclosed by braces.
+    /// # References:
+    /// - <https://drafts.csswg.org/css-syntax/#parse-a-list-of-declarations>
+    /// - <https://drafts.csswg.org/css-style-attr/#syntax>
+    pub fn parse_declaration_list(&mut self) -> Vec<Declaration> {
+        self.consume_list_of_declarations()
+    }
     pub fn consume_list_of_rules(&mut self) -> Vec<QualifiedRule> {
         let mut rules = Vec::new();
         loop {
@@ -46,7 +78,10 @@ impl CssParser {
         loop {
             let token = match self.t.peek() {
                 Some(t) => t,
-                None => return None,
+                None => {
+                    self.parse_error("eof-in-qualified-rule");
+                    return None;
+                }
             };
             match token {
                 CssToken::OpenCurly => {
@@ -63,28 +98,32 @@ impl CssParser {
     fn consume_selector(&mut self) -> Selector {
         let token = match self.t.next() {
             Some(t) => t,
-            None => panic!("Should have a token but get None."),
+            None => {
+                self.parse_error("eof-in-selector");
+                return Selector::UnknownSelector;
+            }
         };
         match token {
             CssToken::HashToken(value) => Selector::IdSelector(value[1..].to_string()),
             CssToken::Delim(delim) => {
                 if delim == '.' {
-                    return Selector::ClassSelector(self.consume_ident());
+                    if let Some(ident) = self.consume_ident() {
+                        return Selector::ClassSelector(ident);
+                    }
+                } else {
+                    self.parse_error("unexpected-token-in-selector");
                 }
-                panic!("Parse error: {:?} is an unexpect token.", token);
+                self.skip_to_block();
+                Selector::UnknownSelector
             }
             CssToken::Ident(ident) => {
                 if self.t.peek() == Some(&CssToken::Colon) {
-                    while self.t.peek() != Some(&CssToken::OpenCurly) {
-                        self.t.next();
-                    }
+                    self.skip_to_block();
                 }
                 Selector::TypeSelector(ident.to_string())
             }
             CssToken::AtKeyword(_keyword) => {
-                while self.t.peek() != Some(&CssToken::OpenCurly) {
-                    self.t.next();
-                }
+                self.skip_to_block();
                 Selector::UnknownSelector
             }
             _ => {
@@ -93,6 +132,33 @@ impl CssParser {
             }
         }
     }
+    /// Parses a selector list, such as the argument of `querySelector()`. Each selector of the
+    /// list is a single type, class or ID selector, because compound selectors and combinators are
+    /// not supported.
+    /// # Returns
+    /// - The selectors, or `None` if the input is not such a selector list.
+    /// # References:
+    /// - <https://drafts.csswg.org/selectors-4/#parse-a-selector>
+    pub fn parse_selector_list(&mut self) -> Option<Vec<Selector>> {
+        let mut selectors = Vec::new();
+        loop {
+            let selector = match self.t.next()? {
+                CssToken::HashToken(value) => Selector::IdSelector(value[1..].to_string()),
+                CssToken::Delim('.') => match self.t.next()? {
+                    CssToken::Ident(ident) => Selector::ClassSelector(ident),
+                    _ => return None,
+                },
+                CssToken::Ident(ident) => Selector::TypeSelector(ident),
+                _ => return None,
+            };
+            selectors.push(selector);
+            match self.t.next() {
+                None => return Some(selectors),
+                Some(CssToken::Delim(',')) => {}
+                Some(_) => return None,
+            }
+        }
+    }
     fn consume_list_of_declarations(&mut self) -> Vec<Declaration> {
         let mut declarations = Vec::new();
         loop {
@@ -124,33 +190,45 @@ impl CssParser {
             return None;
         }
         let mut declaration = Declaration::new();
-        declaration.set_property(self.consume_ident());
+        declaration.set_property(self.consume_ident()?);
         match self.t.next() {
             Some(token) => match token {
                 CssToken::Colon => {}
-                _ => return None,
+                _ => {
+                    self.parse_error("missing-colon-in-declaration");
+                    return None;
+                }
             },
-            None => return None,
+            None => {
+                self.parse_error("eof-in-declaration");
+                return None;
+            }
         }
-        declaration.set_value(self.consume_component_value());
+        declaration.set_value(self.consume_component_value()?);
         Some(declaration)
     }
-    fn consume_ident(&mut self) -> String {
-        let token = match self.t.next() {
-            Some(t) => t,
-            None => panic!("Should have a token but got None."),
-        };
-        match token {
-            CssToken::Ident(ref ident) => ident.to_string(),
-            _ => {
-                panic!("Parse error: {:?} is an unexpected token.", token)
+    /// Consumes an ident token.
+    /// # Returns
+    /// - The ident, or `None` after recording a parse error if the next token is not an ident.
+    fn consume_ident(&mut self) -> Option<String> {
+        match self.t.next() {
+            Some(CssToken::Ident(ident)) => Some(ident),
+            Some(_) => {
+                self.parse_error("expected-ident");
+                None
+            }
+            None => {
+                self.parse_error("eof-before-ident");
+                None
             }
         }
     }
-    fn consume_component_value(&mut self) -> ComponentValue {
-        self.t
-            .next()
-            .expect("Should have a token in consume_component_value.")
+    fn consume_component_value(&mut self) -> Option<ComponentValue> {
+        let value = self.t.next();
+        if value.is_none() {
+            self.parse_error("eof-in-declaration");
+        }
+        value
     }
 }
 
@@ -168,6 +246,11 @@ impl StyleSheet {
     pub fn set_rules(&mut self, rules: Vec<QualifiedRule>) {
         self.rules = rules;
     }
+    /// Appends the rules of `sheet`, which come after the rules of this style sheet in the
+    /// cascade.
+    pub fn append(&mut self, sheet: StyleSheet) {
+        self.rules.extend(sheet.rules);
+    }
 }
 
 /// Struct representing a qualified rule.
@@ -199,6 +282,31 @@ pub enum Selector {
     IdSelector(String),
     UnknownSelector,
 }
+impl Selector {
+    /// Returns true if `element` matches the selector. Type selectors match HTML elements ASCII
+    /// case-insensitively, and class selectors match any class in the space-separated `class`
+    /// attribute.
+    /// # References:
+    /// - <https://drafts.csswg.org/selectors-4/#match-a-selector-against-an-element>
+    pub fn matches(&self, element: &Element) -> bool {
+        match self {
+            Selector::TypeSelector(type_name) => {
+                if element.namespace() == Namespace::Html {
+                    element.local_name().eq_ignore_ascii_case(type_name)
+                } else {
+                    element.local_name() == *type_name
+                }
+            }
+            Selector::ClassSelector(class_name) => element
+                .get_attribute("class")
+                .is_some_and(|c| c.split_ascii_whitespace().any(|c| c == class_name)),
+            Selector::IdSelector(id_name) => {
+                element.get_attribute("id").is_some_and(|id| id == *id_name)
+            }
+            Selector::UnknownSelector => false,
+        }
+    }
+}
 
 /// Struct representing a CSS declaration.
 #[derive(Debug, Clone, PartialEq)]
@@ -225,6 +333,7 @@ pub type ComponentValue = CssToken;
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::renderer::html::attribute::Attribute;
     use alloc::vec;
     #[test]
     fn test_empty() {
@@ -300,4 +409,64 @@ mod tests {
             i += 1;
         }
     }
+    #[test]
+    fn test_parse_errors() {
+        let style =
+            "p { color: red; } .? { color: blue; }\n/* comment */ h1 { content: \"a".to_string();
+        let t = CssTokenizer::new(style);
+        let mut parser = CssParser::new(t);
+        let cssom = parser.parse_stylesheet();
+        assert_eq!(cssom.rules.len(), 3);
+        assert_eq!(
+            cssom.rules[2].selector,
+            Selector::TypeSelector("h1".to_string())
+        );
+        let errors: Vec<(String, usize)> = parser
+            .errors()
+            .iter()
+            .map(|e| (e.code(), e.span().start().line()))
+            .collect();
+        assert_eq!(
+            vec![
+                ("expected-ident".to_string(), 1),
+                ("eof-in-string".to_string(), 2),
+            ],
+            errors
+        );
+    }
+    #[test]
+    fn test_parse_selector_list() {
+        let parse = |input: &str| {
+            CssParser::new(CssTokenizer::new(input.to_string())).parse_selector_list()
+        };
+        assert_eq!(
+            Some(vec![
+                Selector::TypeSelector("p".to_string()),
+                Selector::ClassSelector("note".to_string()),
+                Selector::IdSelector("main".to_string()),
+            ]),
+            parse("p, .note ,#main")
+        );
+        for input in ["", "p,", "div p", "a:hover", "*"] {
+            assert_eq!(None, parse(input), "{}", input);
+        }
+    }
+    #[test]
+    fn test_selector_matches() {
+        let attribute = |name: &str, value: &str| {
+            let mut attribute = Attribute::new();
+            name.chars().for_each(|c| attribute.add_char(c, true));
+            value.chars().for_each(|c| attribute.add_char(c, false));
+            attribute
+        };
+        let element = Element::new(
+            "div",
+            vec![attribute("class", " a\tb "), attribute("id", "x")],
+        );
+        assert!(Selector::TypeSelector("DIV".to_string()).matches(&element));
+        assert!(Selector::ClassSelector("b".to_string()).matches(&element));
+        assert!(!Selector::ClassSelector("a b".to_string()).matches(&element));
+        assert!(Selector::IdSelector("x".to_string()).matches(&element));
+        assert!(!Selector::IdSelector("X".to_string()).matches(&element));
+    }
 }
@@ -1,3 +1,8 @@
+use crate::renderer::diagnostics::LineIndex;
+use crate::renderer::diagnostics::ParseError;
+use crate::renderer::diagnostics::SourceLanguage;
+use crate::renderer::diagnostics::Span;
+use crate::renderer::diagnostics::SpannedTokenizer;
 use alloc::string::String;
 use alloc::vec::Vec;
 
@@ -26,29 +31,75 @@ pub enum CssToken {
 pub struct CssTokenizer {
     pos: usize,
     input: Vec<char>,
+    line_index: LineIndex,
+    token_start: usize,
+    errors: Vec<ParseError>,
 }
 impl CssTokenizer {
     pub fn new(css: String) -> Self {
+        let input: Vec<char> = css.chars().collect();
         Self {
             pos: 0,
-            input: css.chars().collect(),
+            line_index: LineIndex::new(&input),
+            token_start: 0,
+            errors: Vec::new(),
+            input,
         }
     }
+    /// Returns the parse errors found so far.
+    pub fn errors(&self) -> Vec<ParseError> {
+        self.errors.clone()
+    }
+    /// Records a parse error at the current input character.
+    fn parse_error(&mut self, code: &str) {
+        let start = self.pos.min(self.input.len());
+        let span = self
+            .line_index
+            .span(start, (start + 1).min(self.input.len()));
+        self.errors
+            .push(ParseError::new(SourceLanguage::Css, code, span));
+    }
+    /// Consumes a string token. The current input character is the opening quote.
+    /// # References:
+    /// - <https://www.w3.org/TR/css-syntax-3/#consume-string-token>
     fn consume_string_token(&mut self) -> String {
+        let quote = self.input[self.pos];
         let mut s = String::new();
         loop {
-            if self.pos >= self.input.len() {
-                return s;
-            }
             self.pos += 1;
-            let c = self.input[self.pos];
-            match c {
-                '"' | '/' => break,
-                _ => s.push(c),
+            let c = match self.input.get(self.pos) {
+                Some(c) => *c,
+                None => {
+                    self.parse_error("eof-in-string");
+                    return s;
+                }
+            };
+            if c == quote {
+                break;
             }
+            s.push(c);
         }
         s
     }
+    /// Consumes a comment. The current input character is the `/` of `/*`.
+    /// # References:
+    /// - <https://www.w3.org/TR/css-syntax-3/#consume-comments>
+    fn consume_comment(&mut self) {
+        self.pos += 2;
+        loop {
+            match self.input.get(self.pos) {
+                Some('*') if self.input.get(self.pos + 1) == Some(&'/') => {
+                    self.pos += 2;
+                    return;
+                }
+                Some(_) => self.pos += 1,
+                None => {
+                    self.parse_error("eof-in-comment");
+                    return;
+                }
+            }
+        }
+    }
     fn consume_numeric_token(&mut self) -> f64 {
         let mut num = 0f64;
         let mut floating = false;
@@ -82,10 +133,9 @@ impl CssTokenizer {
         s.push(self.input[self.pos]);
         loop {
             self.pos += 1;
-            let c = self.input[self.pos];
-            match c {
-                'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' => {
-                    s.push(c);
+            match self.input.get(self.pos) {
+                Some(c @ ('a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_')) => {
+                    s.push(*c);
                 }
                 _ => break,
             }
@@ -100,6 +150,7 @@ impl Iterator for CssTokenizer {
             if self.pos >= self.input.len() {
                 return None;
             }
+            self.token_start = self.pos;
             let c = self.input[self.pos];
             let token = match c {
                 '(' => CssToken::OpenParenthesis,
@@ -110,10 +161,14 @@ impl Iterator for CssTokenizer {
                 ';' => CssToken::SemiColon,
                 '{' => CssToken::OpenCurly,
                 '}' => CssToken::CloseCurly,
-                ' ' | '\n' => {
+                ' ' | '\n' | '\t' | '\r' | '\x0C' => {
                     self.pos += 1;
                     continue;
                 }
+                '/' if self.input.get(self.pos + 1) == Some(&'*') => {
+                    self.consume_comment();
+                    continue;
+                }
                 '"' | '\'' => {
                     let value = self.consume_string_token();
                     CssToken::StringToken(value)
@@ -134,9 +189,10 @@ impl Iterator for CssTokenizer {
                     t
                 }
                 '@' => {
-                    if self.input[self.pos + 1].is_ascii_alphabetic()
-                        && self.input[self.pos + 2].is_alphanumeric()
-                        && self.input[self.pos + 3].is_alphanumeric()
+                    let lookahead = |i: usize| self.input.get(self.pos + i).copied();
+                    if lookahead(1).is_some_and(|c| c.is_ascii_alphabetic())
+                        && lookahead(2).is_some_and(|c| c.is_alphanumeric())
+                        && lookahead(3).is_some_and(|c| c.is_alphanumeric())
                     {
                         self.pos += 1;
                         let t = CssToken::AtKeyword(self.consume_ident_token());
@@ -150,9 +206,8 @@ impl Iterator for CssTokenizer {
                     self.pos -= 1;
                     t
                 }
-                _ => {
-                    unimplemented!("Char {} is not supported yet.", c);
-                }
+                // Any other character is a delimiter.
+                _ => CssToken::Delim(c),
             };
             self.pos += 1;
             return Some(token);
@@ -160,6 +215,13 @@ impl Iterator for CssTokenizer {
     }
 }
 
+impl SpannedTokenizer for CssTokenizer {
+    fn span(&self) -> Span {
+        let end = self.pos.min(self.input.len());
+        self.line_index.span(self.token_start.min(end), end)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,213 @@
+use alloc::string::String;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use core::fmt::Display;
+use core::fmt::Formatter;
+
+/// Struct representing a position in a source text. Lines and columns start at 1, and columns
+/// count characters.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SourcePosition {
+    line: usize,
+    column: usize,
+}
+impl SourcePosition {
+    pub fn new(line: usize, column: usize) -> Self {
+        Self { line, column }
+    }
+    pub fn line(&self) -> usize {
+        self.line
+    }
+    pub fn column(&self) -> usize {
+        self.column
+    }
+}
+impl Default for SourcePosition {
+    fn default() -> Self {
+        Self::new(1, 1)
+    }
+}
+
+/// Struct representing the range of a token or a node in a source text. `end` is the position
+/// just after the last character.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct Span {
+    start: SourcePosition,
+    end: SourcePosition,
+}
+impl Span {
+    pub fn new(start: SourcePosition, end: SourcePosition) -> Self {
+        Self { start, end }
+    }
+    pub fn start(&self) -> SourcePosition {
+        self.start
+    }
+    pub fn end(&self) -> SourcePosition {
+        self.end
+    }
+}
+
+/// Struct mapping character offsets of a source text to lines and columns.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineIndex {
+    /// The offsets where each line starts.
+    line_starts: Vec<usize>,
+}
+impl LineIndex {
+    pub fn new(input: &[char]) -> Self {
+        let mut index = Self {
+            line_starts: Vec::new(),
+        };
+        index.line_starts.push(0);
+        index.extend(0, input);
+        index
+    }
+    /// Adds the lines of text that was appended to the source.
+    /// # Parameters
+    /// - `offset`: The offset of the first character of `input` in the source.
+    /// - `input`: The appended characters.
+    pub fn extend(&mut self, offset: usize, input: &[char]) {
+        for (i, c) in input.iter().enumerate() {
+            if *c == '\n' {
+                self.line_starts.push(offset + i + 1);
+            }
+        }
+    }
+    pub fn position(&self, offset: usize) -> SourcePosition {
+        let line = match self.line_starts.binary_search(&offset) {
+            Ok(i) => i,
+            Err(i) => i - 1,
+        };
+        SourcePosition::new(line + 1, offset - self.line_starts[line] + 1)
+    }
+    /// Returns the span between two character offsets.
+    pub fn span(&self, start: usize, end: usize) -> Span {
+        Span::new(self.position(start), self.position(end.max(start)))
+    }
+}
+
+/// Enum representing the language of the source text where a parse error occurred.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SourceLanguage {
+    Html,
+    Css,
+    JavaScript,
+}
+
+/// Struct representing a parse error. Spans are relative to the source text that was parsed,
+/// e.g. the content of a `style` element for CSS.
+/// # References:
+/// - <https://html.spec.whatwg.org/multipage/parsing.html#parse-errors>
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    language: SourceLanguage,
+    code: String,
+    span: Span,
+}
+impl ParseError {
+    pub fn new(language: SourceLanguage, code: &str, span: Span) -> Self {
+        Self {
+            language,
+            code: code.to_string(),
+            span,
+        }
+    }
+    pub fn language(&self) -> SourceLanguage {
+        self.language
+    }
+    /// Returns the name of the error, e.g. `eof-in-tag`. HTML uses the error codes of the spec.
+    pub fn code(&self) -> String {
+        self.code.clone()
+    }
+    pub fn span(&self) -> Span {
+        self.span
+    }
+}
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter) -> core::fmt::Result {
+        write!(
+            f,
+            "{:?}:{}:{}: {}",
+            self.language, self.span.start.line, self.span.start.column, self.code
+        )
+    }
+}
+
+/// Trait for tokenizers that report where the last token came from.
+pub trait SpannedTokenizer: Iterator {
+    /// Returns the span of the token that `next()` returned last.
+    fn span(&self) -> Span;
+}
+
+/// Struct wrapping a tokenizer like `Peekable`, while keeping the span of each token.
+#[derive(Debug, Clone)]
+pub struct TokenStream<T: SpannedTokenizer> {
+    tokenizer: T,
+    peeked: Option<Option<(T::Item, Span)>>,
+    span: Span,
+}
+impl<T: SpannedTokenizer> TokenStream<T> {
+    pub fn new(tokenizer: T) -> Self {
+        Self {
+            tokenizer,
+            peeked: None,
+            span: Span::default(),
+        }
+    }
+    fn fill(&mut self) {
+        if self.peeked.is_none() {
+            let next = self.tokenizer.next().map(|t| (t, self.tokenizer.span()));
+            self.peeked = Some(next);
+        }
+    }
+    pub fn peek(&mut self) -> Option<&T::Item> {
+        self.fill();
+        match self.peeked {
+            Some(Some((ref t, _))) => Some(t),
+            _ => None,
+        }
+    }
+    /// Returns the span of the next token, or of the last token at the end of the input.
+    pub fn peek_span(&mut self) -> Span {
+        self.fill();
+        match self.peeked {
+            Some(Some((_, span))) => span,
+            _ => Span::new(self.span.end, self.span.end),
+        }
+    }
+    /// Returns the span of the token that `next()` returned last.
+    pub fn span(&self) -> Span {
+        self.span
+    }
+    pub fn tokenizer(&self) -> &T {
+        &self.tokenizer
+    }
+}
+impl<T: SpannedTokenizer> Iterator for TokenStream<T> {
+    type Item = T::Item;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.fill();
+        match self.peeked.take() {
+            Some(Some((t, span))) => {
+                self.span = span;
+                Some(t)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_line_index() {
+        let input: Vec<char> = "ab\ncd\n\ne".chars().collect();
+        let index = LineIndex::new(&input);
+        assert_eq!(SourcePosition::new(1, 1), index.position(0));
+        assert_eq!(SourcePosition::new(1, 3), index.position(2));
+        assert_eq!(SourcePosition::new(2, 2), index.position(4));
+        assert_eq!(SourcePosition::new(3, 1), index.position(6));
+        assert_eq!(SourcePosition::new(4, 2), index.position(8));
+    }
+}
@@ -1,111 +1,1281 @@
+use crate::error::Error;
+use crate::renderer::css::cssom::CssParser;
+use crate::renderer::css::cssom::Selector;
+use crate::renderer::css::token::CssTokenizer;
+use crate::renderer::dom::document::Document;
+use crate::renderer::dom::node::ClassList;
+use crate::renderer::dom::node::Dom;
 use crate::renderer::dom::node::Element;
 use crate::renderer::dom::node::ElementKind;
+use crate::renderer::dom::node::Namespace;
 use crate::renderer::dom::node::Node;
+use crate::renderer::dom::node::NodeId;
 use crate::renderer::dom::node::NodeKind;
-use alloc::rc::Rc;
+use crate::renderer::html::parser::HtmlParser;
+use alloc::format;
 use alloc::string::String;
 use alloc::string::ToString;
 use alloc::vec::Vec;
-use core::cell::RefCell;
 
 /// Return the target element node based on the provided element kind.
 /// # Parameters
-/// - `node`: The current node in the DOM tree.
+/// - `dom`: The nodes of the document.
+/// - `root`: The root node of the tree to search, which is included.
 /// - `element_kind`: The kind of element to find.
 /// # Returns
-/// - The target element node if found, or `None` if not found.
+/// - The first target element node in tree order if found, or `None` if not found.
 pub fn get_target_element_node(
-    node: Option<Rc<RefCell<Node>>>,
+    dom: &Dom,
+    root: NodeId,
     element_kind: ElementKind,
-) -> Option<Rc<RefCell<Node>>> {
-    match node {
-        Some(n) => {
-            if n.borrow().kind()
-                == NodeKind::Element(Element::new(&element_kind.to_string(), Vec::new()))
-            {
-                return Some(n.clone());
-            }
-            let result1 = get_target_element_node(n.borrow().first_child(), element_kind);
-            let result2 = get_target_element_node(n.borrow().next_sibling(), element_kind);
-            if result1.is_none() && result2.is_none() {
-                return None;
-            }
-            if result1.is_none() {
-                return result2;
-            }
-            result1
+) -> Option<NodeId> {
+    dom.inclusive_descendants(root)
+        .find(|&n| dom[n].element_kind() == Some(element_kind))
+}
+
+/// Returns the elements of the given kind in tree order.
+/// # Parameters
+/// - `dom`: The nodes of the document.
+/// - `root`: The root node of the DOM tree.
+/// - `element_kind`: The kind of elements to find.
+/// # Returns
+/// - The elements in tree order, which is the order of their start tags in the source.
+/// # References:
+/// - <https://dom.spec.whatwg.org/#concept-tree-order>
+pub fn get_elements_by_kind(dom: &Dom, root: NodeId, element_kind: ElementKind) -> Vec<NodeId> {
+    get_elements_matching(dom, root, |node| node.element_kind() == Some(element_kind))
+}
+
+fn get_elements_matching(
+    dom: &Dom,
+    root: NodeId,
+    predicate: impl Fn(&Node) -> bool,
+) -> Vec<NodeId> {
+    dom.inclusive_descendants(root)
+        .filter(|&node| predicate(&dom[node]))
+        .collect()
+}
+
+/// Returns the descendant elements of `root` with the given qualified name in tree order, which
+/// is `getElementsByTagName()`. The result is a snapshot rather than a live collection.
+/// # Parameters
+/// - `root`: The document or the element to search in, which is not included.
+/// - `qualified_name`: The tag name, which matches HTML elements ASCII case-insensitively, or `*`
+///   for all elements.
+/// # References:
+/// - <https://dom.spec.whatwg.org/#concept-getelementsbytagname>
+pub fn get_elements_by_tag_name(dom: &Dom, root: NodeId, qualified_name: &str) -> Vec<NodeId> {
+    let selector = Selector::TypeSelector(qualified_name.to_string());
+    get_descendants_matching(dom, root, |node| {
+        node.get_element()
+            .is_some_and(|e| qualified_name == "*" || selector.matches(e))
+    })
+}
+
+/// Returns the descendant elements of `root` that have all the given classes in tree order,
+/// which is `getElementsByClassName()`.
+/// # Parameters
+/// - `root`: The document or the element to search in, which is not included.
+/// - `class_names`: The space-separated classes. No element matches if it has no class.
+/// # References:
+/// - <https://dom.spec.whatwg.org/#concept-getelementsbyclassname>
+pub fn get_elements_by_class_name(dom: &Dom, root: NodeId, class_names: &str) -> Vec<NodeId> {
+    let selectors: Vec<Selector> = class_names
+        .split_ascii_whitespace()
+        .map(|c| Selector::ClassSelector(c.to_string()))
+        .collect();
+    if selectors.is_empty() {
+        return Vec::new();
+    }
+    get_descendants_matching(dom, root, |node| {
+        node.get_element()
+            .is_some_and(|e| selectors.iter().all(|s| s.matches(e)))
+    })
+}
+
+/// Returns the descendant elements of `root` that match any of `selectors` in tree order, which
+/// is `querySelectorAll()`.
+/// # Parameters
+/// - `root`: The document or the element to search in, which is not included.
+/// - `selectors`: The selector list, such as `p, .note`.
+/// # Returns
+/// - The elements, or an error if `selectors` is not a selector list that the CSS parser
+///   supports.
+/// # References:
+/// - <https://dom.spec.whatwg.org/#scope-match-a-selectors-string>
+pub fn query_selector_all(dom: &Dom, root: NodeId, selectors: &str) -> Result<Vec<NodeId>, Error> {
+    let mut parser = CssParser::new(CssTokenizer::new(selectors.to_string()));
+    let selectors = match parser.parse_selector_list() {
+        Some(selectors) => selectors,
+        None => {
+            return Err(Error::Other(format!(
+                "SyntaxError: '{}' is not a valid selector",
+                selectors
+            )))
+        }
+    };
+    Ok(get_descendants_matching(dom, root, |node| {
+        node.get_element()
+            .is_some_and(|e| selectors.iter().any(|s| s.matches(e)))
+    }))
+}
+
+/// Returns the first descendant element of `root` that matches any of `selectors`, which is
+/// `querySelector()`.
+/// # Returns
+/// - The element, `None` if no element matches, or an error if `selectors` is not a selector
+///   list that the CSS parser supports.
+/// # References:
+/// - <https://dom.spec.whatwg.org/#dom-parentnode-queryselector>
+pub fn query_selector(dom: &Dom, root: NodeId, selectors: &str) -> Result<Option<NodeId>, Error> {
+    Ok(query_selector_all(dom, root, selectors)?.into_iter().next())
+}
+
+fn get_descendants_matching(
+    dom: &Dom,
+    root: NodeId,
+    predicate: impl Fn(&Node) -> bool,
+) -> Vec<NodeId> {
+    dom.descendants(root)
+        .filter(|&node| predicate(&dom[node]))
+        .collect()
+}
+
+/// Returns the concatenated data of the Text children of `node`.
+/// # References:
+/// - <https://dom.spec.whatwg.org/#concept-child-text-content>
+pub fn child_text_content(dom: &Dom, node: NodeId) -> String {
+    let mut content = String::new();
+    for child in dom.children(node) {
+        if let NodeKind::Text(ref s) = dom[child].kind {
+            content.push_str(s);
         }
-        None => None,
     }
+    content
 }
 
-/// Return the content of the style tag.
+/// Returns the contents of the style elements whose type is CSS, in tree order.
 /// # Parameters
+/// - `dom`: The nodes of the document.
 /// - `root`: The root node of the DOM tree.
 /// # Returns
-/// - Content of the style tag as a string.
-pub fn get_style_content(root: Rc<RefCell<Node>>) -> String {
-    let style_node = match get_target_element_node(Some(root), ElementKind::Style) {
-        Some(node) => node,
-        None => return "".to_string(),
+/// - The CSS of each style element.
+/// # References:
+/// - <https://html.spec.whatwg.org/multipage/semantics.html#update-a-style-block>
+pub fn get_style_contents(dom: &Dom, root: NodeId) -> Vec<String> {
+    get_elements_by_kind(dom, root, ElementKind::Style)
+        .into_iter()
+        .filter(|&node| dom[node].get_element().is_some_and(is_css_style))
+        .map(|node| child_text_content(dom, node))
+        .collect()
+}
+
+/// Returns true if the type of the `style` element is absent, empty or CSS.
+fn is_css_style(element: &Element) -> bool {
+    match element.get_attribute("type") {
+        Some(t) => t.is_empty() || t.eq_ignore_ascii_case("text/css"),
+        None => true,
+    }
+}
+
+/// Returns true if `element` is a `link` element that loads a style sheet, whose `rel` attribute
+/// has the `stylesheet` keyword and whose `href` attribute is not empty.
+/// # References:
+/// - <https://html.spec.whatwg.org/multipage/links.html#link-type-stylesheet>
+pub fn is_style_sheet_link(element: &Element) -> bool {
+    let has_keyword = element.get_attribute("rel").is_some_and(|rel| {
+        rel.split_ascii_whitespace()
+            .any(|keyword| keyword.eq_ignore_ascii_case("stylesheet"))
+    });
+    has_keyword
+        && element
+            .get_attribute("href")
+            .is_some_and(|href| !href.is_empty())
+}
+
+/// Returns the `style` elements whose type is CSS and the `link` elements that load a style
+/// sheet, in tree order, which is the order of their style sheets in the cascade.
+/// # Parameters
+/// - `dom`: The nodes of the document.
+/// - `root`: The root node of the DOM tree.
+/// # Returns
+/// - The elements that own a style sheet.
+/// # References:
+/// - <https://drafts.csswg.org/cssom/#documentorshadowroot-document-or-shadow-root-css-style-sheets>
+pub fn get_style_sheet_owners(dom: &Dom, root: NodeId) -> Vec<NodeId> {
+    get_elements_matching(dom, root, |node| match node.element_kind() {
+        Some(ElementKind::Style) => node.get_element().is_some_and(is_css_style),
+        Some(ElementKind::Link) => node.get_element().is_some_and(is_style_sheet_link),
+        _ => false,
+    })
+}
+
+/// Returns true if `node` is in a document, not in a detached subtree or in the contents of a
+/// `template` element.
+/// # References:
+/// - <https://dom.spec.whatwg.org/#connected>
+pub fn is_connected(dom: &Dom, node: NodeId) -> bool {
+    matches!(dom[dom.root_of(node)].kind, NodeKind::Document)
+}
+
+/// Returns the element with the given id.
+/// # Parameters
+/// - `dom`: The nodes of the document.
+/// - `root`: The root node of the tree to search, which is included.
+/// - `id_name`: The ID of the element.
+/// # Returns
+/// - The first target element node in tree order if found, or `None` if not found.
+pub fn get_element_by_id(dom: &Dom, root: NodeId, id_name: &str) -> Option<NodeId> {
+    dom.inclusive_descendants(root).find(|&n| {
+        dom[n]
+            .get_element()
+            .is_some_and(|e| e.get_attribute("id").as_deref() == Some(id_name))
+    })
+}
+
+/// The MIME types of JavaScript, which make a script element a classic script.
+/// # References:
+/// - <https://mimesniff.spec.whatwg.org/#javascript-mime-type>
+static JAVASCRIPT_MIME_TYPES: [&str; 16] = [
+    "application/ecmascript",
+    "application/javascript",
+    "application/x-ecmascript",
+    "application/x-javascript",
+    "text/ecmascript",
+    "text/javascript",
+    "text/javascript1.0",
+    "text/javascript1.1",
+    "text/javascript1.2",
+    "text/javascript1.3",
+    "text/javascript1.4",
+    "text/javascript1.5",
+    "text/jscript",
+    "text/livescript",
+    "text/x-ecmascript",
+    "text/x-javascript",
+];
+
+/// Returns true if `element` is a classic script, which is a script element without a type or
+/// with a JavaScript MIME type. Module scripts and data blocks are not run.
+/// # References:
+/// - <https://html.spec.whatwg.org/multipage/scripting.html#prepare-the-script-element>
+pub fn is_classic_script(element: &Element) -> bool {
+    match element.get_attribute("type") {
+        Some(t) => {
+            let t = t.trim_matches(|c: char| c.is_ascii_whitespace());
+            t.is_empty()
+                || JAVASCRIPT_MIME_TYPES
+                    .iter()
+                    .any(|mime| mime.eq_ignore_ascii_case(t))
+        }
+        None => true,
+    }
+}
+
+/// Inserts `node` as a child of `parent` before `child`, or as the last child if `child` is
+/// `None`, without checking the hierarchy. The node is removed from its old parent first, and the
+/// children of a document fragment are inserted instead of the fragment itself. The HTML parser
+/// builds the tree with this, because it only inserts nodes where they are allowed.
+/// # Parameters
+/// - `dom`: The nodes of the document.
+/// - `parent`: The new parent of the node.
+/// - `node`: The node to insert.
+/// - `child`: The child of `parent` that follows the node.
+/// # References:
+/// - <https://dom.spec.whatwg.org/#concept-node-insert>
+pub fn insert(dom: &mut Dom, parent: NodeId, node: NodeId, child: Option<NodeId>) {
+    if matches!(dom[node].kind, NodeKind::DocumentFragment) {
+        for c in children(dom, node) {
+            dom.insert_before(parent, c, child);
+        }
+        return;
+    }
+    dom.insert_before(parent, node, child);
+}
+
+/// Removes `node` from its parent, if any. The node keeps its children.
+/// # References:
+/// - <https://dom.spec.whatwg.org/#concept-node-remove>
+pub fn remove(dom: &mut Dom, node: NodeId) {
+    dom.detach(node);
+}
+
+/// Returns the children of `node` in order, as a snapshot that stays valid while the tree is
+/// modified.
+pub fn children(dom: &Dom, node: NodeId) -> Vec<NodeId> {
+    dom.children(node).collect()
+}
+
+/// Frees the nodes that can't be reached from `roots` anymore, such as the children that
+/// `innerHTML` replaced. A node keeps the whole tree that it belongs to alive, because the other
+/// nodes can be reached through its parent, and an element keeps its template contents alive.
+/// # Parameters
+/// - `dom`: The nodes of the document.
+/// - `roots`: The nodes that are still referred to, such as the `Document` node and the nodes
+///   that scripts hold. No other `NodeId` may be used after this.
+/// # Returns
+/// - The number of freed nodes.
+pub fn collect_garbage(dom: &mut Dom, roots: &[NodeId]) -> usize {
+    let mut reachable = Vec::new();
+    let mut pending: Vec<NodeId> = roots.iter().map(|root| dom.root_of(*root)).collect();
+    pending.sort_unstable_by_key(|root| root.index());
+    pending.dedup();
+    while let Some(root) = pending.pop() {
+        for node in dom.inclusive_descendants(root) {
+            reachable.push(node.index());
+            if let Some(contents) = dom[node].template_contents() {
+                pending.push(contents);
+            }
+        }
+    }
+    reachable.sort_unstable();
+    let unreachable: Vec<NodeId> = dom
+        .ids()
+        .filter(|id| reachable.binary_search(&id.index()).is_err())
+        .collect();
+    for node in &unreachable {
+        dom.free(*node);
+    }
+    unreachable.len()
+}
+
+fn hierarchy_request_error(message: &str) -> Error {
+    Error::Other(format!("HierarchyRequestError: {}", message))
+}
+
+fn not_found_error(message: &str) -> Error {
+    Error::Other(format!("NotFoundError: {}", message))
+}
+
+/// Checks that inserting `node` into `parent` before `child`, or in place of `child` when
+/// `replaced` is true, keeps the tree valid. A document can have at most one element and one
+/// DOCTYPE, which comes before the element, and no text.
+/// # References:
+/// - <https://dom.spec.whatwg.org/#concept-node-ensure-pre-insertion-validity>
+/// - <https://dom.spec.whatwg.org/#concept-node-replace>
+fn ensure_insertion_validity(
+    dom: &Dom,
+    parent: NodeId,
+    node: NodeId,
+    child: Option<NodeId>,
+    replaced: bool,
+) -> Result<(), Error> {
+    let parent_kind = dom[parent].kind();
+    if !matches!(
+        parent_kind,
+        NodeKind::Document | NodeKind::DocumentFragment | NodeKind::Element(_)
+    ) {
+        return Err(hierarchy_request_error("the parent can't have children"));
+    }
+    if dom.is_inclusive_ancestor(node, parent) {
+        return Err(hierarchy_request_error(
+            "a node can't be inserted into itself or its descendant",
+        ));
+    }
+    if let Some(child) = child {
+        if dom.parent(child) != Some(parent) {
+            return Err(not_found_error("the child is not a child of the parent"));
+        }
+    }
+    let node_kind = dom[node].kind();
+    match node_kind {
+        NodeKind::Document => return Err(hierarchy_request_error("a document can't be inserted")),
+        NodeKind::Text(_) if parent_kind == NodeKind::Document => {
+            return Err(hierarchy_request_error("a document can't have text"))
+        }
+        NodeKind::DocumentType { .. } if parent_kind != NodeKind::Document => {
+            return Err(hierarchy_request_error(
+                "a DOCTYPE can only be a child of a document",
+            ))
+        }
+        _ => {}
+    }
+    if parent_kind != NodeKind::Document {
+        return Ok(());
+    }
+    let is_element = |n: NodeId| matches!(dom[n].kind, NodeKind::Element(_));
+    let is_doctype = |n: NodeId| matches!(dom[n].kind, NodeKind::DocumentType { .. });
+    // The children of the document other than the replaced child.
+    let siblings: Vec<NodeId> = dom
+        .children(parent)
+        .filter(|&c| !(replaced && child == Some(c)))
+        .collect();
+    let has_element = siblings.iter().any(|&n| is_element(n));
+    let has_doctype = siblings.iter().any(|&n| is_doctype(n));
+    // Whether a DOCTYPE follows the position of the node.
+    let doctype_follows = match child {
+        Some(child) => {
+            let mut current = match replaced {
+                true => dom.next_sibling(child),
+                false => Some(child),
+            };
+            let mut found = false;
+            while let Some(c) = current {
+                found |= is_doctype(c);
+                current = dom.next_sibling(c);
+            }
+            found
+        }
+        None => false,
     };
-    let text_node = match style_node.borrow().first_child() {
-        Some(node) => node,
-        None => return "".to_string(),
+    // Whether an element precedes the position of the node.
+    let element_precedes = match child {
+        Some(child) => {
+            let mut current = dom.previous_sibling(child);
+            let mut found = false;
+            while let Some(c) = current {
+                found |= is_element(c);
+                current = dom.previous_sibling(c);
+            }
+            found
+        }
+        None => has_element,
     };
-    let content = match &text_node.borrow().kind() {
-        NodeKind::Text(ref s) => s.clone(),
-        _ => "".to_string(),
+    match node_kind {
+        NodeKind::DocumentFragment => {
+            let elements = dom.children(node).filter(|&c| is_element(c)).count();
+            let has_text = dom
+                .children(node)
+                .any(|c| matches!(dom[c].kind, NodeKind::Text(_)));
+            if elements > 1 || has_text {
+                return Err(hierarchy_request_error(
+                    "a document can have only one element and no text",
+                ));
+            }
+            if elements == 1 && (has_element || doctype_follows) {
+                return Err(hierarchy_request_error(
+                    "a document can have only one element, after the DOCTYPE",
+                ));
+            }
+        }
+        NodeKind::Element(_) if has_element || doctype_follows => {
+            return Err(hierarchy_request_error(
+                "a document can have only one element, after the DOCTYPE",
+            ));
+        }
+        NodeKind::DocumentType { .. } if has_doctype || element_precedes => {
+            return Err(hierarchy_request_error(
+                "a document can have only one DOCTYPE, before the element",
+            ));
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Inserts `node` into `parent` before `child`, or as the last child if `child` is `None`, which
+/// is `Node.insertBefore()`.
+/// # Parameters
+/// - `dom`: The nodes of the document.
+/// - `parent`: The new parent of the node.
+/// - `node`: The node to insert. A node in a tree is moved.
+/// - `child`: The child of `parent` that follows the node.
+/// # Returns
+/// - The inserted node, or an error if the insertion makes the tree invalid or `child` is not a
+///   child of `parent`.
+/// # References:
+/// - <https://dom.spec.whatwg.org/#concept-node-pre-insert>
+pub fn insert_before(
+    dom: &mut Dom,
+    parent: NodeId,
+    node: NodeId,
+    child: Option<NodeId>,
+) -> Result<NodeId, Error> {
+    ensure_insertion_validity(dom, parent, node, child, false)?;
+    let reference = match child {
+        Some(c) if c == node => dom.next_sibling(node),
+        _ => child,
     };
-    content
+    insert(dom, parent, node, reference);
+    Ok(node)
 }
-/// Returns the element with the given id.
+
+/// Appends `node` as the last child of `parent`, which is `Node.appendChild()`.
+/// # Returns
+/// - The appended node, or an error if the insertion makes the tree invalid.
+/// # References:
+/// - <https://dom.spec.whatwg.org/#concept-node-append>
+pub fn append_child(dom: &mut Dom, parent: NodeId, node: NodeId) -> Result<NodeId, Error> {
+    insert_before(dom, parent, node, None)
+}
+
+/// Removes `child` from `parent`, which is `Node.removeChild()`.
+/// # Returns
+/// - The removed node, or an error if `child` is not a child of `parent`.
+/// # References:
+/// - <https://dom.spec.whatwg.org/#concept-node-pre-remove>
+pub fn remove_child(dom: &mut Dom, parent: NodeId, child: NodeId) -> Result<NodeId, Error> {
+    if dom.parent(child) != Some(parent) {
+        return Err(not_found_error("the child is not a child of the parent"));
+    }
+    remove(dom, child);
+    Ok(child)
+}
+
+/// Replaces `child` of `parent` with `node`, which is `Node.replaceChild()`.
 /// # Parameters
-/// - `node`: The node of the DOM tree.
-/// - `id_name`: The ID of the element.
+/// - `dom`: The nodes of the document.
+/// - `parent`: The parent of the child.
+/// - `node`: The new node. A node in a tree is moved.
+/// - `child`: The child to replace.
 /// # Returns
-/// - The target element node if found, or `None` if not found.
-pub fn get_element_by_id(
-    node: Option<Rc<RefCell<Node>>>,
-    id_name: &String,
-) -> Option<Rc<RefCell<Node>>> {
-    match node {
-        Some(n) => {
-            if let NodeKind::Element(e) = n.borrow().kind() {
-                for attr in &e.attributes() {
-                    if attr.name() == "id" && attr.value() == *id_name {
-                        return Some(n.clone());
-                    }
+/// - The replaced child, or an error if the replacement makes the tree invalid or `child` is not
+///   a child of `parent`.
+/// # References:
+/// - <https://dom.spec.whatwg.org/#concept-node-replace>
+pub fn replace_child(
+    dom: &mut Dom,
+    parent: NodeId,
+    node: NodeId,
+    child: NodeId,
+) -> Result<NodeId, Error> {
+    ensure_insertion_validity(dom, parent, node, Some(child), true)?;
+    let mut reference = dom.next_sibling(child);
+    if reference == Some(node) {
+        reference = dom.next_sibling(node);
+    }
+    if child != node {
+        remove(dom, child);
+    }
+    insert(dom, parent, node, reference);
+    Ok(child)
+}
+
+/// Returns a copy of `node` that is not in any tree, which is `Node.cloneNode()`. The copy of a
+/// `template` element has its own template contents.
+/// # Parameters
+/// - `dom`: The nodes of the document, where the copy is allocated.
+/// - `node`: The node to copy.
+/// - `deep`: Whether the descendants, including the template contents, are copied too.
+/// # References:
+/// - <https://dom.spec.whatwg.org/#concept-node-clone>
+pub fn clone_node(dom: &mut Dom, node: NodeId, deep: bool) -> NodeId {
+    let copy = clone_single_node(dom, node, deep);
+    if !deep {
+        return copy;
+    }
+    // Pairs of an original node whose children are still to be copied and its copy.
+    let mut pending = Vec::from([(node, copy)]);
+    while let Some((original, copy)) = pending.pop() {
+        for child in children(dom, original) {
+            let child_copy = clone_single_node(dom, child, true);
+            dom.append(copy, child_copy);
+            pending.push((child, child_copy));
+        }
+    }
+    copy
+}
+
+/// Returns a copy of `node` without its children. The template contents of a `template` element
+/// are copied deeply if `deep` is true.
+fn clone_single_node(dom: &mut Dom, node: NodeId, deep: bool) -> NodeId {
+    let mut copy = dom[node].clone();
+    copy.set_template_contents(None);
+    let copy = dom.alloc(copy);
+    if let Some(contents) = dom[node].template_contents() {
+        let contents_copy = match deep {
+            true => clone_node(dom, contents, true),
+            false => dom.alloc(Node::new(NodeKind::DocumentFragment)),
+        };
+        dom[copy].set_template_contents(Some(contents_copy));
+    }
+    copy
+}
+
+/// Removes the empty Text nodes among the descendants of `node` and merges adjacent Text nodes
+/// into the first one, which is `Node.normalize()`.
+/// # References:
+/// - <https://dom.spec.whatwg.org/#dom-node-normalize>
+pub fn normalize(dom: &mut Dom, node: NodeId) {
+    let parents: Vec<NodeId> = dom
+        .inclusive_descendants(node)
+        .filter(|&n| dom.first_child(n).is_some())
+        .collect();
+    for parent in parents {
+        normalize_children(dom, parent);
+    }
+}
+
+/// Removes the empty Text children of `node` and merges adjacent Text children.
+fn normalize_children(dom: &mut Dom, node: NodeId) {
+    let text = |dom: &Dom, n: NodeId| match dom[n].kind {
+        NodeKind::Text(ref data) => Some(data.clone()),
+        _ => None,
+    };
+    let mut child = dom.first_child(node);
+    while let Some(c) = child {
+        let mut data = match text(dom, c) {
+            Some(data) => data,
+            None => {
+                child = dom.next_sibling(c);
+                continue;
+            }
+        };
+        let mut next = dom.next_sibling(c);
+        while let Some(n) = next {
+            match text(dom, n) {
+                Some(d) => {
+                    data.push_str(&d);
+                    next = dom.next_sibling(n);
+                    remove(dom, n);
                 }
+                None => break,
+            }
+        }
+        if data.is_empty() {
+            remove(dom, c);
+        } else {
+            dom[c].kind = NodeKind::Text(data);
+        }
+        child = next;
+    }
+}
+
+/// Returns the text of `node`, which is the getter of `Node.textContent`.
+/// # Returns
+/// - The concatenated data of the descendant Text nodes of an element or a document fragment,
+///   the data of a Text or Comment node, or `None` for a document and a DOCTYPE.
+/// # References:
+/// - <https://dom.spec.whatwg.org/#dom-node-textcontent>
+pub fn text_content(dom: &Dom, node: NodeId) -> Option<String> {
+    match dom[node].kind {
+        NodeKind::Text(ref data) | NodeKind::Comment(ref data) => return Some(data.clone()),
+        NodeKind::Document | NodeKind::DocumentType { .. } => return None,
+        _ => {}
+    }
+    let mut content = String::new();
+    for n in dom.descendants(node) {
+        if let NodeKind::Text(ref data) = dom[n].kind {
+            content.push_str(data);
+        }
+    }
+    Some(content)
+}
+
+/// Sets the text of `node`, which is the setter of `Node.textContent`. The children of an element
+/// or a document fragment are replaced with a Text node, or removed if `text` is empty.
+/// # References:
+/// - <https://dom.spec.whatwg.org/#dom-node-textcontent>
+pub fn set_text_content(dom: &mut Dom, node: NodeId, text: &str) {
+    match dom[node].kind {
+        NodeKind::Text(_) => dom[node].kind = NodeKind::Text(text.to_string()),
+        NodeKind::Comment(_) => dom[node].kind = NodeKind::Comment(text.to_string()),
+        NodeKind::Element(_) | NodeKind::DocumentFragment => {
+            for child in children(dom, node) {
+                remove(dom, child);
             }
-            let result1 = get_element_by_id(n.borrow().first_child(), id_name);
-            let result2 = get_element_by_id(n.borrow().next_sibling(), id_name);
-            if result1.is_none() {
-                return result2;
+            if !text.is_empty() {
+                let text = dom.alloc(Node::new(NodeKind::Text(text.to_string())));
+                dom.append(node, text);
             }
-            result1
         }
-        None => None,
+        NodeKind::Document | NodeKind::DocumentType { .. } => {}
     }
 }
 
-/// Returns the content of the JavaScript code inside the script tag.
+fn invalid_character_error(message: &str) -> Error {
+    Error::Other(format!("InvalidCharacterError: {}", message))
+}
+
+fn syntax_error(message: &str) -> Error {
+    Error::Other(format!("SyntaxError: {}", message))
+}
+
+/// Returns the attribute name that `name` refers to on `node`, which is lowercased for HTML
+/// elements.
+/// # Returns
+/// - An error if `node` is not an element, or if `name` can't be an attribute name.
+fn attribute_name(dom: &Dom, node: NodeId, name: &str) -> Result<String, Error> {
+    let element = match dom[node].get_element() {
+        Some(e) => e,
+        None => return Err(Error::Other("the node is not an element".to_string())),
+    };
+    let is_valid = !name.is_empty()
+        && !name
+            .chars()
+            .any(|c| c.is_ascii_whitespace() || matches!(c, '\0' | '"' | '\'' | '>' | '/' | '='));
+    if !is_valid {
+        return Err(invalid_character_error(&format!(
+            "\"{}\" is not a valid attribute name",
+            name
+        )));
+    }
+    match element.namespace() {
+        Namespace::Html => Ok(name.to_ascii_lowercase()),
+        _ => Ok(name.to_string()),
+    }
+}
+
+/// Marks the style of `node` out of date, and its ancestors as having a descendant whose style is
+/// out of date, so that the page restyles and lays out the document again.
+pub fn invalidate_style(dom: &mut Dom, node: NodeId) {
+    dom[node].set_needs_style_recalc(true);
+    let ancestors: Vec<NodeId> = dom.ancestors(node).collect();
+    for ancestor in ancestors {
+        if dom[ancestor].child_needs_style_recalc() {
+            break;
+        }
+        dom[ancestor].set_child_needs_style_recalc(true);
+    }
+}
+
+/// Returns true if the style of `root` or one of its descendants is out of date.
+pub fn needs_style_recalc(dom: &Dom, root: NodeId) -> bool {
+    dom[root].needs_style_recalc() || dom[root].child_needs_style_recalc()
+}
+
+/// Marks the style of `root` and its descendants up to date, after it has been recomputed.
+pub fn clear_style_invalidation(dom: &mut Dom, root: NodeId) {
+    let nodes: Vec<NodeId> = dom.inclusive_descendants(root).collect();
+    for node in nodes {
+        dom[node].set_needs_style_recalc(false);
+        dom[node].set_child_needs_style_recalc(false);
+    }
+}
+
+/// Invalidates the style of `node` if the attribute named `name` is one that selectors or
+/// inline styles depend on.
+fn attribute_changed(dom: &mut Dom, node: NodeId, name: &str) {
+    if matches!(name, "id" | "class" | "style") {
+        invalidate_style(dom, node);
+    }
+}
+
+pub fn has_attribute(dom: &Dom, node: NodeId, name: &str) -> bool {
+    match attribute_name(dom, node, name) {
+        Ok(name) => dom[node]
+            .get_element()
+            .is_some_and(|e| e.has_attribute(&name)),
+        Err(_) => false,
+    }
+}
+
+/// Sets the value of an attribute of `node`, adding the attribute if it doesn't exist.
 /// # Parameters
-/// - `root`: The root node of the DOM tree.
+/// - `dom`: The nodes of the document.
+/// - `node`: The element whose attribute is set.
+/// - `name`: The name of the attribute, which is lowercased for HTML elements.
+/// - `value`: The new value.
+/// # Returns
+/// - An error if `node` is not an element or `name` is not a valid attribute name.
+/// # References:
+/// - <https://dom.spec.whatwg.org/#dom-element-setattribute>
+pub fn set_attribute(dom: &mut Dom, node: NodeId, name: &str, value: &str) -> Result<(), Error> {
+    let name = attribute_name(dom, node, name)?;
+    if let Some(e) = dom[node].get_element_mut() {
+        e.set_attribute(&name, value);
+    }
+    attribute_changed(dom, node, &name);
+    Ok(())
+}
+
+/// Removes an attribute of `node`. Nothing happens if `node` has no such attribute.
+/// # References:
+/// - <https://dom.spec.whatwg.org/#dom-element-removeattribute>
+pub fn remove_attribute(dom: &mut Dom, node: NodeId, name: &str) {
+    let name = match attribute_name(dom, node, name) {
+        Ok(name) => name,
+        Err(_) => return,
+    };
+    let removed = dom[node]
+        .get_element_mut()
+        .is_some_and(|e| e.remove_attribute(&name));
+    if removed {
+        attribute_changed(dom, node, &name);
+    }
+}
+
+/// Removes an attribute of `node` if it exists and adds it with an empty value otherwise. If
+/// `force` is given, the attribute is only added if it is `true` and only removed if it is
+/// `false`.
+/// # Returns
+/// - Whether `node` has the attribute afterwards, or an error if `name` is not a valid attribute
+///   name.
+/// # References:
+/// - <https://dom.spec.whatwg.org/#dom-element-toggleattribute>
+pub fn toggle_attribute(
+    dom: &mut Dom,
+    node: NodeId,
+    name: &str,
+    force: Option<bool>,
+) -> Result<bool, Error> {
+    let name = attribute_name(dom, node, name)?;
+    let has_attribute = dom[node]
+        .get_element()
+        .is_some_and(|e| e.has_attribute(&name));
+    if has_attribute == force.unwrap_or(!has_attribute) {
+        return Ok(has_attribute);
+    }
+    if has_attribute {
+        remove_attribute(dom, node, &name);
+    } else {
+        set_attribute(dom, node, &name, "")?;
+    }
+    Ok(!has_attribute)
+}
+
+/// Checks that `token` can be a class, which must be non-empty and have no whitespace.
+/// # References:
+/// - <https://dom.spec.whatwg.org/#dom-domtokenlist-add>
+fn validate_token(token: &str) -> Result<(), Error> {
+    if token.is_empty() {
+        return Err(syntax_error("the token is empty"));
+    }
+    if token.chars().any(|c| c.is_ascii_whitespace()) {
+        return Err(invalid_character_error(&format!(
+            "the token \"{}\" contains whitespace",
+            token
+        )));
+    }
+    Ok(())
+}
+
+/// Changes the classes of `node` with `f` and writes them back to the `class` attribute. No
+/// class is changed if a token is invalid.
+fn update_class_list<T>(
+    dom: &mut Dom,
+    node: NodeId,
+    tokens: &[&str],
+    f: impl FnOnce(&mut ClassList) -> T,
+) -> Result<T, Error> {
+    for token in tokens {
+        validate_token(token)?;
+    }
+    let element = match dom[node].get_element_mut() {
+        Some(e) => e,
+        None => return Err(Error::Other("the node is not an element".to_string())),
+    };
+    let mut class_list = element.class_list();
+    let result = f(&mut class_list);
+    element.set_class_list(&class_list);
+    invalidate_style(dom, node);
+    Ok(result)
+}
+
+pub fn class_list_contains(dom: &Dom, node: NodeId, token: &str) -> bool {
+    dom[node]
+        .get_element()
+        .is_some_and(|e| e.class_list().contains(token))
+}
+
+/// Adds `tokens` to the classes of `node`, which is the `add` method of `classList`.
+/// # References:
+/// - <https://dom.spec.whatwg.org/#dom-domtokenlist-add>
+pub fn class_list_add(dom: &mut Dom, node: NodeId, tokens: &[&str]) -> Result<(), Error> {
+    update_class_list(dom, node, tokens, |class_list| {
+        tokens.iter().for_each(|token| class_list.add(token))
+    })
+}
+
+/// Removes `tokens` from the classes of `node`, which is the `remove` method of `classList`.
+/// # References:
+/// - <https://dom.spec.whatwg.org/#dom-domtokenlist-remove>
+pub fn class_list_remove(dom: &mut Dom, node: NodeId, tokens: &[&str]) -> Result<(), Error> {
+    update_class_list(dom, node, tokens, |class_list| {
+        tokens.iter().for_each(|token| class_list.remove(token))
+    })
+}
+
+/// Toggles `token` in the classes of `node`, which is the `toggle` method of `classList`.
 /// # Returns
-/// - Content of the JavaScript code as a string.
-pub fn get_js_content(root: Rc<RefCell<Node>>) -> String {
-    let js_node = match get_target_element_node(Some(root), ElementKind::Script) {
-        Some(node) => node,
-        None => return "".to_string(),
+/// - Whether `node` has the class afterwards.
+/// # References:
+/// - <https://dom.spec.whatwg.org/#dom-domtokenlist-toggle>
+pub fn class_list_toggle(
+    dom: &mut Dom,
+    node: NodeId,
+    token: &str,
+    force: Option<bool>,
+) -> Result<bool, Error> {
+    update_class_list(dom, node, &[token], |class_list| {
+        class_list.toggle(token, force)
+    })
+}
+
+/// Returns a `body` element that is not in any tree, which is the context for fragments that
+/// have no element to be parsed in.
+fn body_context(dom: &mut Dom) -> NodeId {
+    dom.alloc(Node::new(NodeKind::Element(Element::new(
+        "body",
+        Vec::new(),
+    ))))
+}
+
+/// Replaces the children of `node` with the nodes parsed from `markup`. The children of a
+/// `template` element are its template contents.
+/// # Parameters
+/// - `document`: The document that `node` belongs to.
+/// - `node`: The element whose `innerHTML` is set.
+/// - `markup`: The HTML fragment.
+/// # References:
+/// - <https://html.spec.whatwg.org/multipage/dynamic-markup-insertion.html#dom-element-innerhtml>
+pub fn set_inner_html(document: &mut Document, node: NodeId, markup: &str) {
+    let fragment = HtmlParser::parse_fragment(document, node, markup);
+    let dom = document.dom_mut();
+    let parent = dom[node].template_contents().unwrap_or(node);
+    for child in children(dom, parent) {
+        remove(dom, child);
+    }
+    for child in fragment {
+        dom.append(parent, child);
+    }
+}
+
+/// Replaces `node` with the nodes parsed from `markup` in the context of its parent.
+/// # Parameters
+/// - `document`: The document that `node` belongs to.
+/// - `node`: The element whose `outerHTML` is set.
+/// - `markup`: The HTML fragment.
+/// # Returns
+/// - An error if the parent of `node` is the document.
+/// # References:
+/// - <https://html.spec.whatwg.org/multipage/dynamic-markup-insertion.html#dom-element-outerhtml>
+pub fn set_outer_html(document: &mut Document, node: NodeId, markup: &str) -> Result<(), Error> {
+    let dom = document.dom_mut();
+    let parent = match dom.parent(node) {
+        Some(p) => p,
+        None => return Ok(()),
     };
-    let text_node = match js_node.borrow().first_child() {
-        Some(node) => node,
-        None => return "".to_string(),
+    let context = match dom[parent].kind {
+        NodeKind::Document => {
+            return Err(Error::Other(
+                "the outerHTML of the document element can't be set".to_string(),
+            ))
+        }
+        NodeKind::DocumentFragment => body_context(dom),
+        _ => parent,
     };
-    let content = match &text_node.borrow().kind() {
-        NodeKind::Text(ref s) => s.clone(),
-        _ => "".to_string(),
+    let fragment = HtmlParser::parse_fragment(document, context, markup);
+    let dom = document.dom_mut();
+    for child in fragment {
+        dom.insert_before(parent, child, Some(node));
+    }
+    remove(dom, node);
+    Ok(())
+}
+
+/// Inserts the nodes parsed from `markup` at `position` relative to `node`.
+/// # Parameters
+/// - `document`: The document that `node` belongs to.
+/// - `node`: The element that the position is relative to.
+/// - `position`: One of `beforebegin`, `afterbegin`, `beforeend` and `afterend`, in any case.
+/// - `markup`: The HTML fragment.
+/// # Returns
+/// - An error if the position is unknown, or if it is outside of `node` and `node` has no
+///   parent element.
+/// # References:
+/// - <https://html.spec.whatwg.org/multipage/dynamic-markup-insertion.html#dom-element-insertadjacenthtml>
+pub fn insert_adjacent_html(
+    document: &mut Document,
+    node: NodeId,
+    position: &str,
+    markup: &str,
+) -> Result<(), Error> {
+    let dom = document.dom_mut();
+    let position = position.to_ascii_lowercase();
+    let context = match position.as_str() {
+        "beforebegin" | "afterend" => match dom.parent(node) {
+            Some(parent) if !matches!(dom[parent].kind, NodeKind::Document) => parent,
+            _ => {
+                return Err(Error::Other(format!(
+                    "can't insert HTML {} an element without a parent element",
+                    position
+                )))
+            }
+        },
+        "afterbegin" | "beforeend" => node,
+        _ => {
+            return Err(Error::UnexpectedInput(format!(
+                "unknown position of insertAdjacentHTML: {}",
+                position
+            )))
+        }
     };
-    content
+    let is_html = match dom[context].kind {
+        NodeKind::Element(ref e) => e.namespace() == Namespace::Html && e.local_name() == "html",
+        _ => true,
+    };
+    let context = if is_html { body_context(dom) } else { context };
+    let fragment = HtmlParser::parse_fragment(document, context, markup);
+    let dom = document.dom_mut();
+    let (parent, reference) = match position.as_str() {
+        "beforebegin" => (dom.parent(node), Some(node)),
+        "afterbegin" => (Some(node), dom.first_child(node)),
+        "beforeend" => (Some(node), None),
+        _ => (dom.parent(node), dom.next_sibling(node)),
+    };
+    if let Some(parent) = parent {
+        for child in fragment {
+            dom.insert_before(parent, child, reference);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::html::serializer::serialize_children;
+    use crate::renderer::html::serializer::serialize_node;
+    use crate::renderer::html::token::HtmlTokenizer;
+    /// Returns the nodes of the parsed document, which tests take out of the shared cell.
+    fn parse(html: &str) -> (Dom, NodeId) {
+        let document = HtmlParser::new(HtmlTokenizer::new(html.to_string())).construct_tree();
+        let document = document.borrow();
+        (document.dom().clone(), document.node())
+    }
+    fn element(dom: &mut Dom, name: &str) -> NodeId {
+        dom.alloc(Node::new(NodeKind::Element(Element::new(name, Vec::new()))))
+    }
+    fn text(dom: &mut Dom, data: &str) -> NodeId {
+        dom.alloc(Node::new(NodeKind::Text(data.to_string())))
+    }
+    fn by_id(dom: &Dom, root: NodeId, id: &str) -> NodeId {
+        get_element_by_id(dom, root, id).expect("failed to get an element")
+    }
+    /// Checks that the parent, sibling and last child links of the subtree agree.
+    fn assert_links(dom: &Dom, node: NodeId) {
+        for n in dom.inclusive_descendants(node) {
+            let mut previous = None;
+            for child in dom.children(n) {
+                assert_eq!(Some(n), dom.parent(child));
+                assert_eq!(previous, dom.previous_sibling(child));
+                previous = Some(child);
+            }
+            assert_eq!(previous, dom.last_child(n));
+        }
+    }
+    #[test]
+    fn test_insert_remove_and_replace() {
+        let (mut dom, document) = parse("<div id=t><p>a</p><p>b</p></div><span id=s></span>");
+        let dom = &mut dom;
+        let div = by_id(dom, document, "t");
+        let span = by_id(dom, document, "s");
+        let first = dom.first_child(div).expect("failed to get p");
+        let c = text(dom, "c");
+        assert!(append_child(dom, div, c).is_ok());
+        // Inserting a node in a tree moves it.
+        assert!(insert_before(dom, div, span, Some(first)).is_ok());
+        let b = dom.next_sibling(first).expect("failed to get p");
+        let hr = element(dom, "hr");
+        assert!(replace_child(dom, div, hr, b).is_ok());
+        assert!(dom.parent(b).is_none());
+        assert!(remove_child(dom, div, first).is_ok());
+        assert_eq!("<span id=\"s\"></span><hr>c", serialize_children(dom, div));
+        assert_links(dom, document);
+        let fragment = dom.alloc(Node::new(NodeKind::DocumentFragment));
+        let i = element(dom, "i");
+        let u = element(dom, "u");
+        insert(dom, fragment, i, None);
+        insert(dom, fragment, u, None);
+        assert!(insert_before(dom, div, fragment, Some(span)).is_ok());
+        assert!(dom.first_child(fragment).is_none());
+        assert_eq!(
+            "<i></i><u></u><span id=\"s\"></span><hr>c",
+            serialize_children(dom, div)
+        );
+        assert_links(dom, document);
+    }
+    #[test]
+    fn test_hierarchy_errors() {
+        let (mut dom, document) = parse("<!doctype html><div id=t>a</div>");
+        let dom = &mut dom;
+        let div = by_id(dom, document, "t");
+        let a = dom.first_child(div).expect("failed to get a text");
+        let html = dom.ancestors(div).nth(1).expect("failed to get html");
+        let doctype = dom.first_child(document).expect("failed to get doctype");
+        let p = element(dom, "p");
+        let x = text(dom, "x");
+        let doctype_copy = clone_node(dom, doctype, false);
+        let results = [
+            append_child(dom, a, p),
+            append_child(dom, div, html),
+            append_child(dom, div, div),
+            insert_before(dom, div, p, Some(html)),
+            remove_child(dom, div, html),
+            append_child(dom, document, p),
+            append_child(dom, document, x),
+            append_child(dom, div, doctype_copy),
+            append_child(dom, document, doctype_copy),
+            replace_child(dom, document, p, doctype),
+        ];
+        for result in results {
+            assert!(result.is_err());
+        }
+        assert_eq!(
+            "<!DOCTYPE html><html><head></head><body><div id=\"t\">a</div></body></html>",
+            serialize_node(dom, document)
+        );
+        // The only element of a document can be replaced.
+        let new_html = element(dom, "html");
+        assert!(replace_child(dom, document, new_html, html).is_ok());
+        assert_eq!(
+            "<!DOCTYPE html><html></html>",
+            serialize_node(dom, document)
+        );
+    }
+    #[test]
+    fn test_clone_node_and_normalize() {
+        let (mut dom, document) =
+            parse("<div id=t class=x><b>1</b><template><i>2</i></template></div>");
+        let dom = &mut dom;
+        let div = by_id(dom, document, "t");
+        let shallow = clone_node(dom, div, false);
+        assert_eq!(
+            "<div id=\"t\" class=\"x\"></div>",
+            serialize_node(dom, shallow)
+        );
+        let deep = clone_node(dom, div, true);
+        assert_eq!(serialize_node(dom, div), serialize_node(dom, deep));
+        assert!(dom.parent(deep).is_none());
+        assert_links(dom, deep);
+        let p = element(dom, "p");
+        for data in ["", "a", "", "b"] {
+            let t = text(dom, data);
+            insert(dom, p, t, None);
+        }
+        let br = element(dom, "br");
+        insert(dom, p, br, None);
+        let empty = text(dom, "");
+        insert(dom, p, empty, None);
+        let b = element(dom, "b");
+        for data in ["c", "d"] {
+            let t = text(dom, data);
+            insert(dom, b, t, None);
+        }
+        insert(dom, p, b, None);
+        normalize(dom, p);
+        assert_eq!(4, children(dom, p).len() + children(dom, b).len());
+        assert_eq!(Some("abcd".to_string()), text_content(dom, p));
+        assert_links(dom, p);
+        set_text_content(dom, p, "e");
+        assert_eq!("<p>e</p>", serialize_node(dom, p));
+        set_text_content(dom, p, "");
+        assert!(dom.first_child(p).is_none());
+    }
+    #[test]
+    fn test_queries() {
+        let (dom, document) = parse(
+            "<div id=root class='a b'><P id=p1 class=b></P><section id=s><p id=p2 class='b a'></p></section></div><p id=p3 class=a></p>",
+        );
+        let dom = &dom;
+        let root = by_id(dom, document, "root");
+        let ids = |nodes: Vec<NodeId>| -> Vec<String> {
+            nodes
+                .iter()
+                .filter_map(|&n| dom[n].get_element()?.get_attribute("id"))
+                .collect()
+        };
+        assert_eq!(["p1", "p2"], *ids(get_elements_by_tag_name(dom, root, "P")));
+        assert_eq!(
+            ["root", "p1", "s", "p2", "p3"],
+            *ids(get_elements_by_tag_name(dom, document, "*"))
+        );
+        assert_eq!(
+            ["root", "p2"],
+            *ids(get_elements_by_class_name(dom, document, " b\ta "))
+        );
+        assert!(get_elements_by_class_name(dom, document, " ").is_empty());
+        assert_eq!(
+            ["root", "p1", "p2", "p3"],
+            *ids(query_selector_all(dom, document, "p, .a").expect("failed to query"))
+        );
+        let first = query_selector(dom, root, ".b").expect("failed to query");
+        assert_eq!(["p1"], *ids(first.into_iter().collect()));
+        assert!(query_selector(dom, root, "#p3")
+            .expect("failed to query")
+            .is_none());
+        assert!(query_selector_all(dom, root, "div > p").is_err());
+        assert!(query_selector(dom, root, "p,").is_err());
+    }
+    #[test]
+    fn test_attributes() {
+        let (mut dom, document) = parse("<div id=d><p id=t title=x></p></div>");
+        let dom = &mut dom;
+        let div = by_id(dom, document, "d");
+        let p = by_id(dom, document, "t");
+        assert!(set_attribute(dom, p, "Data-X", "1").is_ok());
+        assert!(set_attribute(dom, p, "title", "y").is_ok());
+        assert!(has_attribute(dom, p, "DATA-x"));
+        assert_eq!(
+            "<p id=\"t\" title=\"y\" data-x=\"1\"></p>",
+            serialize_node(dom, p)
+        );
+        // Only the attributes that the style depends on invalidate it.
+        assert!(!needs_style_recalc(dom, document));
+        assert!(set_attribute(dom, p, "a b", "1").is_err());
+        assert!(set_attribute(dom, document, "id", "1").is_err());
+        remove_attribute(dom, p, "title");
+        remove_attribute(dom, p, "missing");
+        assert!(!has_attribute(dom, p, "title"));
+        assert_eq!(Ok(true), toggle_attribute(dom, p, "hidden", None));
+        assert_eq!(Ok(true), toggle_attribute(dom, p, "hidden", Some(true)));
+        assert_eq!(Ok(false), toggle_attribute(dom, p, "hidden", None));
+        assert_eq!(Ok(false), toggle_attribute(dom, p, "hidden", Some(false)));
+        assert!(!needs_style_recalc(dom, document));
+        assert!(set_attribute(dom, p, "style", "color: red").is_ok());
+        assert!(dom[p].needs_style_recalc());
+        assert!(!dom[div].needs_style_recalc());
+        assert!(dom[div].child_needs_style_recalc());
+        assert!(needs_style_recalc(dom, document));
+        clear_style_invalidation(dom, document);
+        assert!(!needs_style_recalc(dom, document));
+        assert!(!dom[p].needs_style_recalc());
+        remove_attribute(dom, p, "id");
+        assert!(needs_style_recalc(dom, document));
+    }
+    #[test]
+    fn test_class_list() {
+        let (mut dom, document) = parse("<p id=t class=' a b  a '></p><p id=u></p>");
+        let dom = &mut dom;
+        let p = by_id(dom, document, "t");
+        let u = by_id(dom, document, "u");
+        let element = dom[p].get_element().expect("failed to get an element");
+        assert_eq!(["a", "b"], *element.class_list().tokens());
+        assert!(class_list_contains(dom, p, "b"));
+        assert!(!class_list_contains(dom, p, "c"));
+        assert!(class_list_add(dom, p, &["c", "a"]).is_ok());
+        // Updating the classes serializes them without the duplicates and the extra whitespace.
+        assert_eq!("<p id=\"t\" class=\"a b c\"></p>", serialize_node(dom, p));
+        assert!(class_list_remove(dom, p, &["a", "x"]).is_ok());
+        assert_eq!(Ok(false), class_list_toggle(dom, p, "b", None));
+        assert_eq!(Ok(true), class_list_toggle(dom, p, "d", Some(true)));
+        assert_eq!(Ok(false), class_list_toggle(dom, p, "e", Some(false)));
+        assert_eq!("<p id=\"t\" class=\"c d\"></p>", serialize_node(dom, p));
+        assert!(dom[p].needs_style_recalc());
+        assert!(class_list_add(dom, p, &["f", ""]).is_err());
+        assert!(class_list_add(dom, p, &["g h"]).is_err());
+        assert!(!class_list_contains(dom, p, "f"));
+        // Removing a class from an element without the attribute doesn't add an empty one.
+        assert!(class_list_remove(dom, u, &["a"]).is_ok());
+        assert_eq!("<p id=\"u\"></p>", serialize_node(dom, u));
+    }
+    #[test]
+    fn test_collect_garbage() {
+        let document = HtmlParser::new(HtmlTokenizer::new(
+            "<div id=t></div><template id=s><p>kept</p></template>".to_string(),
+        ))
+        .construct_tree();
+        let mut document = document.borrow_mut();
+        let root = document.node();
+        let div = by_id(document.dom(), root, "t");
+        set_inner_html(&mut document, div, "<p>a</p>b");
+        collect_garbage(document.dom_mut(), &[root]);
+        let len = document.dom().len();
+        for _ in 0..100 {
+            set_inner_html(&mut document, div, "<p>a</p>b");
+            collect_garbage(document.dom_mut(), &[root]);
+            assert_eq!(len, document.dom().len());
+        }
+        // A detached node that is still referred to keeps its tree alive.
+        let p = document.dom().first_child(div).expect("failed to get p");
+        set_inner_html(&mut document, div, "");
+        collect_garbage(document.dom_mut(), &[root, p]);
+        let dom = document.dom();
+        assert_eq!("<p>a</p>", serialize_node(dom, p));
+        // Only the text `b` is freed.
+        assert_eq!(len - 1, dom.len());
+        let template = by_id(dom, root, "s");
+        assert_eq!("<p>kept</p>", serialize_children(dom, template));
+    }
 }
@@ -0,0 +1,150 @@
+use crate::encoding::Encoding;
+use crate::renderer::dom::node::Dom;
+use crate::renderer::dom::node::Node;
+use crate::renderer::dom::node::NodeId;
+use crate::renderer::dom::node::NodeKind;
+use crate::renderer::dom::node::QuirksMode;
+use crate::url::Url;
+use alloc::string::String;
+use alloc::string::ToString;
+
+/// Enum representing how far a document has been loaded.
+/// # References:
+/// - <https://html.spec.whatwg.org/multipage/dom.html#current-document-readiness>
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DocumentReadyState {
+    /// The parser is still running.
+    Loading,
+    /// The parser has finished, and the deferred scripts are running.
+    Interactive,
+    /// The document and its subresources have been loaded.
+    Complete,
+}
+impl DocumentReadyState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DocumentReadyState::Loading => "loading",
+            DocumentReadyState::Interactive => "interactive",
+            DocumentReadyState::Complete => "complete",
+        }
+    }
+}
+
+/// Struct representing a document. It owns the node tree, whose root is the `Document` node,
+/// and the metadata of the document that doesn't belong to any node.
+/// # References:
+/// - <https://dom.spec.whatwg.org/#interface-document>
+#[derive(Debug, Clone)]
+pub struct Document {
+    dom: Dom,
+    node: NodeId,
+    url: Option<Url>,
+    referrer: String,
+    content_type: String,
+    ready_state: DocumentReadyState,
+    quirks_mode: QuirksMode,
+    character_set: Encoding,
+}
+impl Document {
+    pub fn new() -> Self {
+        Self::with_dom(Dom::new())
+    }
+    /// Returns a document whose `Document` node is allocated in `dom`. The fragment parser uses
+    /// it to build nodes in the tree of another document.
+    pub fn with_dom(mut dom: Dom) -> Self {
+        let node = dom.alloc(Node::new(NodeKind::Document));
+        Self {
+            dom,
+            node,
+            url: None,
+            referrer: String::new(),
+            content_type: "text/html".to_string(),
+            ready_state: DocumentReadyState::Loading,
+            quirks_mode: QuirksMode::NoQuirks,
+            character_set: Encoding::Utf8,
+        }
+    }
+    pub fn dom(&self) -> &Dom {
+        &self.dom
+    }
+    pub fn dom_mut(&mut self) -> &mut Dom {
+        &mut self.dom
+    }
+    /// Returns the `Document` node, which is the root of the tree.
+    pub fn node(&self) -> NodeId {
+        self.node
+    }
+    /// Returns the URL of the document, or `None` for a document that wasn't loaded from a URL,
+    /// whose URL is `about:blank`.
+    /// # References:
+    /// - <https://dom.spec.whatwg.org/#concept-document-url>
+    pub fn url(&self) -> Option<Url> {
+        self.url.clone()
+    }
+    pub fn set_url(&mut self, url: Url) {
+        self.url = Some(url);
+    }
+    /// Returns the serialized URL of the document, which scripts get as `document.URL`.
+    /// # References:
+    /// - <https://dom.spec.whatwg.org/#dom-document-url>
+    pub fn url_string(&self) -> String {
+        match &self.url {
+            Some(url) => url.serialize(),
+            None => "about:blank".to_string(),
+        }
+    }
+    /// Returns the URL of the document that navigated to this one, or an empty string if there
+    /// is none.
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/dom.html#dom-document-referrer>
+    pub fn referrer(&self) -> String {
+        self.referrer.clone()
+    }
+    pub fn set_referrer(&mut self, referrer: String) {
+        self.referrer = referrer;
+    }
+    /// # References:
+    /// - <https://dom.spec.whatwg.org/#concept-document-content-type>
+    pub fn content_type(&self) -> String {
+        self.content_type.clone()
+    }
+    pub fn set_content_type(&mut self, content_type: String) {
+        self.content_type = content_type;
+    }
+    pub fn ready_state(&self) -> DocumentReadyState {
+        self.ready_state
+    }
+    pub fn set_ready_state(&mut self, ready_state: DocumentReadyState) {
+        self.ready_state = ready_state;
+    }
+    /// # References:
+    /// - <https://dom.spec.whatwg.org/#concept-document-mode>
+    pub fn quirks_mode(&self) -> QuirksMode {
+        self.quirks_mode
+    }
+    pub fn set_quirks_mode(&mut self, quirks_mode: QuirksMode) {
+        self.quirks_mode = quirks_mode;
+    }
+    /// Returns `BackCompat` in quirks mode and `CSS1Compat` otherwise.
+    /// # References:
+    /// - <https://dom.spec.whatwg.org/#dom-document-compatmode>
+    pub fn compat_mode(&self) -> &'static str {
+        match self.quirks_mode {
+            QuirksMode::Quirks => "BackCompat",
+            _ => "CSS1Compat",
+        }
+    }
+    /// # References:
+    /// - <https://dom.spec.whatwg.org/#concept-document-encoding>
+    pub fn character_set(&self) -> Encoding {
+        self.character_set
+    }
+    pub fn set_character_set(&mut self, character_set: Encoding) {
+        self.character_set = character_set;
+    }
+}
+impl Default for Document {
+    fn default() -> Self {
+        Self::new()
+    }
+}
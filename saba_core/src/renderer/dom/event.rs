@@ -0,0 +1,507 @@
+use crate::renderer::dom::document::Document;
+use crate::renderer::dom::node::Dom;
+use crate::renderer::dom::node::NodeId;
+use alloc::string::String;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+
+/// Enum representing which phase of the dispatch an event is in.
+/// # References:
+/// - <https://dom.spec.whatwg.org/#dom-event-eventphase>
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum EventPhase {
+    /// The event isn't being dispatched.
+    None,
+    /// The event is travelling from the root down to the parent of the target.
+    Capturing,
+    /// The event has arrived at the target.
+    AtTarget,
+    /// The event is travelling from the parent of the target up to the root.
+    Bubbling,
+}
+impl EventPhase {
+    /// Returns the numeric value exposed as `Event.eventPhase`.
+    pub fn value(&self) -> u64 {
+        match self {
+            EventPhase::None => 0,
+            EventPhase::Capturing => 1,
+            EventPhase::AtTarget => 2,
+            EventPhase::Bubbling => 3,
+        }
+    }
+}
+
+/// Struct representing the data specific to a mouse event.
+/// # References:
+/// - <https://w3c.github.io/uievents/#interface-mouseevent>
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MouseEvent {
+    client_x: i64,
+    client_y: i64,
+    button: u64,
+}
+impl MouseEvent {
+    pub fn new(client_x: i64, client_y: i64, button: u64) -> Self {
+        Self {
+            client_x,
+            client_y,
+            button,
+        }
+    }
+    pub fn client_x(&self) -> i64 {
+        self.client_x
+    }
+    pub fn client_y(&self) -> i64 {
+        self.client_y
+    }
+    /// Returns the button that was pressed. 0 is the main button.
+    pub fn button(&self) -> u64 {
+        self.button
+    }
+}
+
+/// Struct representing the data specific to a keyboard event.
+/// # References:
+/// - <https://w3c.github.io/uievents/#interface-keyboardevent>
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyboardEvent {
+    key: String,
+}
+impl KeyboardEvent {
+    pub fn new(key: &str) -> Self {
+        Self {
+            key: key.to_string(),
+        }
+    }
+    /// Returns the key value, such as "a", "Enter" or "Backspace".
+    /// # References:
+    /// - <https://w3c.github.io/uievents-key/>
+    pub fn key(&self) -> String {
+        self.key.clone()
+    }
+}
+
+/// Enum representing the interface of an event, with the data that isn't common to all events.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EventInterface {
+    Event,
+    MouseEvent(MouseEvent),
+    KeyboardEvent(KeyboardEvent),
+}
+
+/// Struct representing an event that is dispatched to a node.
+/// # References:
+/// - <https://dom.spec.whatwg.org/#interface-event>
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    event_type: String,
+    bubbles: bool,
+    cancelable: bool,
+    target: Option<NodeId>,
+    current_target: Option<NodeId>,
+    phase: EventPhase,
+    stop_propagation: bool,
+    stop_immediate_propagation: bool,
+    canceled: bool,
+    /// Whether the event is being dispatched.
+    dispatching: bool,
+    interface: EventInterface,
+}
+impl Event {
+    pub fn new(event_type: &str, bubbles: bool, cancelable: bool) -> Self {
+        Self {
+            event_type: event_type.to_string(),
+            bubbles,
+            cancelable,
+            target: None,
+            current_target: None,
+            phase: EventPhase::None,
+            stop_propagation: false,
+            stop_immediate_propagation: false,
+            canceled: false,
+            dispatching: false,
+            interface: EventInterface::Event,
+        }
+    }
+    /// Creates a mouse event, such as "click", which bubbles and is cancelable.
+    pub fn new_mouse_event(event_type: &str, mouse_event: MouseEvent) -> Self {
+        let mut event = Self::new(event_type, true, true);
+        event.interface = EventInterface::MouseEvent(mouse_event);
+        event
+    }
+    /// Creates a keyboard event, such as "keydown", which bubbles and is cancelable.
+    pub fn new_keyboard_event(event_type: &str, keyboard_event: KeyboardEvent) -> Self {
+        let mut event = Self::new(event_type, true, true);
+        event.interface = EventInterface::KeyboardEvent(keyboard_event);
+        event
+    }
+    pub fn event_type(&self) -> String {
+        self.event_type.clone()
+    }
+    pub fn bubbles(&self) -> bool {
+        self.bubbles
+    }
+    pub fn cancelable(&self) -> bool {
+        self.cancelable
+    }
+    pub fn target(&self) -> Option<NodeId> {
+        self.target
+    }
+    pub fn current_target(&self) -> Option<NodeId> {
+        self.current_target
+    }
+    pub fn phase(&self) -> EventPhase {
+        self.phase
+    }
+    pub fn interface(&self) -> &EventInterface {
+        &self.interface
+    }
+    /// Stops the event from reaching any node after the current one.
+    /// # References:
+    /// - <https://dom.spec.whatwg.org/#dom-event-stoppropagation>
+    pub fn stop_propagation(&mut self) {
+        self.stop_propagation = true;
+    }
+    /// Stops the event from reaching any listener after the current one.
+    /// # References:
+    /// - <https://dom.spec.whatwg.org/#dom-event-stopimmediatepropagation>
+    pub fn stop_immediate_propagation(&mut self) {
+        self.stop_propagation = true;
+        self.stop_immediate_propagation = true;
+    }
+    /// Cancels the default action of the event. It has no effect if the event isn't cancelable.
+    /// # References:
+    /// - <https://dom.spec.whatwg.org/#dom-event-preventdefault>
+    pub fn prevent_default(&mut self) {
+        if self.cancelable {
+            self.canceled = true;
+        }
+    }
+    pub fn default_prevented(&self) -> bool {
+        self.canceled
+    }
+    /// Returns `true` while the event is being dispatched. An event can't be dispatched again
+    /// before its dispatch finishes.
+    /// # References:
+    /// - <https://dom.spec.whatwg.org/#dispatch-flag>
+    pub fn is_dispatching(&self) -> bool {
+        self.dispatching
+    }
+}
+
+/// Struct representing an event listener. The callback is an index that the caller of
+/// `dispatch_event` knows how to invoke, such as a function held by the JavaScript runtime.
+/// # References:
+/// - <https://dom.spec.whatwg.org/#concept-event-listener>
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EventListener {
+    event_type: String,
+    callback: usize,
+    capture: bool,
+}
+impl EventListener {
+    pub fn new(event_type: &str, callback: usize, capture: bool) -> Self {
+        Self {
+            event_type: event_type.to_string(),
+            callback,
+            capture,
+        }
+    }
+    pub fn event_type(&self) -> String {
+        self.event_type.clone()
+    }
+    pub fn callback(&self) -> usize {
+        self.callback
+    }
+    pub fn capture(&self) -> bool {
+        self.capture
+    }
+}
+
+/// A function that runs an event listener. The DOM isn't borrowed while it runs, so it may read
+/// and modify the tree, and add or remove listeners.
+pub type EventCallback<'a> = dyn FnMut(&EventListener, &mut Event) + 'a;
+
+/// Adds an event listener to a node, unless a listener with the same type, callback and capture
+/// is already registered.
+/// # References:
+/// - <https://dom.spec.whatwg.org/#add-an-event-listener>
+pub fn add_event_listener(dom: &mut Dom, node: NodeId, listener: EventListener) {
+    let listeners = dom[node].event_listeners_mut();
+    if !listeners.contains(&listener) {
+        listeners.push(listener);
+    }
+}
+
+/// Removes the event listener with the same type, callback and capture from a node.
+/// # References:
+/// - <https://dom.spec.whatwg.org/#remove-an-event-listener>
+pub fn remove_event_listener(dom: &mut Dom, node: NodeId, listener: &EventListener) {
+    dom[node].event_listeners_mut().retain(|l| l != listener);
+}
+
+/// Dispatches an event to a target node. The event goes down from the root to the target in the
+/// capturing phase, and back up to the root in the bubbling phase if it bubbles.
+/// # Parameters
+/// - `document`: The document that the target belongs to.
+/// - `target`: The node that the event is dispatched to.
+/// - `event`: The event. Its target, phase and flags are updated as it goes.
+/// - `invoke`: A function that runs each listener that the event reaches.
+/// # Returns
+/// - `false` if a listener canceled the event, otherwise `true`.
+/// # References:
+/// - <https://dom.spec.whatwg.org/#concept-event-dispatch>
+pub fn dispatch_event(
+    document: &RefCell<Document>,
+    target: NodeId,
+    event: &mut Event,
+    invoke: &mut EventCallback,
+) -> bool {
+    event.target = Some(target);
+    event.dispatching = true;
+    // The path is fixed before any listener runs, so changing the tree doesn't change which nodes
+    // the event reaches.
+    let path: Vec<NodeId> = document
+        .borrow()
+        .dom()
+        .inclusive_ancestors(target)
+        .collect();
+    for &node in path.iter().skip(1).rev() {
+        if event.stop_propagation {
+            break;
+        }
+        event.phase = EventPhase::Capturing;
+        invoke_listeners(document, node, event, true, invoke);
+    }
+    if !event.stop_propagation {
+        event.phase = EventPhase::AtTarget;
+        invoke_listeners(document, target, event, true, invoke);
+        invoke_listeners(document, target, event, false, invoke);
+    }
+    if event.bubbles {
+        for &node in path.iter().skip(1) {
+            if event.stop_propagation {
+                break;
+            }
+            event.phase = EventPhase::Bubbling;
+            invoke_listeners(document, node, event, false, invoke);
+        }
+    }
+    event.phase = EventPhase::None;
+    event.current_target = None;
+    event.dispatching = false;
+    event.stop_propagation = false;
+    event.stop_immediate_propagation = false;
+    !event.canceled
+}
+
+/// Runs the listeners of a node that match the event type and the capture flag.
+/// # References:
+/// - <https://dom.spec.whatwg.org/#concept-event-listener-inner-invoke>
+fn invoke_listeners(
+    document: &RefCell<Document>,
+    node: NodeId,
+    event: &mut Event,
+    capture: bool,
+    invoke: &mut EventCallback,
+) {
+    event.current_target = Some(node);
+    // Listeners added while the event is at this node don't run, so work on a copy.
+    let listeners = document.borrow().dom()[node].event_listeners().to_vec();
+    for listener in listeners {
+        if event.stop_immediate_propagation {
+            break;
+        }
+        if listener.event_type != event.event_type || listener.capture != capture {
+            continue;
+        }
+        // A listener that an earlier listener removed doesn't run.
+        if !document.borrow().dom()[node]
+            .event_listeners()
+            .contains(&listener)
+        {
+            continue;
+        }
+        invoke(&listener, event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::dom::api::get_element_by_id;
+    use crate::renderer::html::parser::HtmlParser;
+    use crate::renderer::html::token::HtmlTokenizer;
+    use alloc::format;
+    use alloc::rc::Rc;
+    use alloc::vec;
+    fn parse(html: &str) -> Rc<RefCell<Document>> {
+        HtmlParser::new(HtmlTokenizer::new(html.to_string())).construct_tree()
+    }
+    fn by_id(document: &RefCell<Document>, id: &str) -> NodeId {
+        let document = document.borrow();
+        get_element_by_id(document.dom(), document.node(), id).expect("failed to get a node")
+    }
+    const HTML: &str = "<div id=outer><p id=inner><b id=target>x</b></p></div>";
+    /// Registers a capturing and a non-capturing listener on `outer`, `inner` and `target`. The
+    /// callback index is the position of the node in `NAMES`.
+    fn listen(document: &RefCell<Document>, event_type: &str) -> Vec<NodeId> {
+        let nodes: Vec<NodeId> = ["outer", "inner", "target"]
+            .iter()
+            .map(|id| by_id(document, id))
+            .collect();
+        let mut doc = document.borrow_mut();
+        for (i, &node) in nodes.iter().enumerate() {
+            add_event_listener(doc.dom_mut(), node, EventListener::new(event_type, i, true));
+            add_event_listener(
+                doc.dom_mut(),
+                node,
+                EventListener::new(event_type, i, false),
+            );
+        }
+        nodes
+    }
+    const NAMES: [&str; 3] = ["outer", "inner", "target"];
+    fn describe(listener: &EventListener, event: &Event) -> String {
+        format!(
+            "{}:{}:{}",
+            NAMES[listener.callback()],
+            if listener.capture() {
+                "capture"
+            } else {
+                "bubble"
+            },
+            event.phase().value()
+        )
+    }
+    #[test]
+    fn test_dispatch_phases() {
+        let document = parse(HTML);
+        let nodes = listen(&document, "click");
+        let mut log = Vec::new();
+        let mut event = Event::new("click", true, true);
+        let not_canceled = dispatch_event(&document, nodes[2], &mut event, &mut |l, e| {
+            assert_eq!(Some(nodes[2]), e.target());
+            log.push(describe(l, e));
+        });
+        assert!(not_canceled);
+        assert_eq!(
+            vec![
+                "outer:capture:1",
+                "inner:capture:1",
+                "target:capture:2",
+                "target:bubble:2",
+                "inner:bubble:3",
+                "outer:bubble:3",
+            ],
+            log
+        );
+        assert_eq!(EventPhase::None, event.phase());
+        assert_eq!(None, event.current_target());
+    }
+    #[test]
+    fn test_dispatch_no_bubbles() {
+        let document = parse(HTML);
+        let nodes = listen(&document, "focus");
+        let mut log = Vec::new();
+        let mut event = Event::new("focus", false, false);
+        dispatch_event(&document, nodes[2], &mut event, &mut |l, e| {
+            log.push(describe(l, e))
+        });
+        assert_eq!(
+            vec![
+                "outer:capture:1",
+                "inner:capture:1",
+                "target:capture:2",
+                "target:bubble:2",
+            ],
+            log
+        );
+    }
+    #[test]
+    fn test_dispatch_other_type() {
+        let document = parse(HTML);
+        let nodes = listen(&document, "keydown");
+        let mut count = 0;
+        let mut event = Event::new("click", true, true);
+        dispatch_event(&document, nodes[2], &mut event, &mut |_, _| count += 1);
+        assert_eq!(0, count);
+    }
+    #[test]
+    fn test_stop_propagation() {
+        let document = parse(HTML);
+        let nodes = listen(&document, "click");
+        let mut log = Vec::new();
+        let mut event = Event::new("click", true, true);
+        dispatch_event(&document, nodes[2], &mut event, &mut |l, e| {
+            log.push(describe(l, e));
+            if NAMES[l.callback()] == "inner" && !l.capture() {
+                e.stop_propagation();
+            }
+        });
+        assert_eq!(Some(&"inner:bubble:3".to_string()), log.last());
+        assert_eq!(5, log.len());
+        let mut log = Vec::new();
+        let mut event = Event::new("click", true, true);
+        dispatch_event(&document, nodes[2], &mut event, &mut |l, e| {
+            log.push(describe(l, e));
+            if l.capture() {
+                e.stop_immediate_propagation();
+            }
+        });
+        assert_eq!(vec!["outer:capture:1"], log);
+    }
+    #[test]
+    fn test_prevent_default() {
+        let document = parse(HTML);
+        let nodes = listen(&document, "click");
+        let mut event = Event::new("click", true, true);
+        let not_canceled = dispatch_event(&document, nodes[2], &mut event, &mut |_, e| {
+            e.prevent_default()
+        });
+        assert!(!not_canceled);
+        assert!(event.default_prevented());
+        let mut event = Event::new("click", true, false);
+        let not_canceled = dispatch_event(&document, nodes[2], &mut event, &mut |_, e| {
+            e.prevent_default()
+        });
+        assert!(not_canceled);
+        assert!(!event.default_prevented());
+    }
+    #[test]
+    fn test_add_remove_event_listener() {
+        let document = parse(HTML);
+        let target = by_id(&document, "target");
+        let listener = EventListener::new("click", 0, false);
+        {
+            let mut doc = document.borrow_mut();
+            add_event_listener(doc.dom_mut(), target, listener.clone());
+            add_event_listener(doc.dom_mut(), target, listener.clone());
+            add_event_listener(doc.dom_mut(), target, EventListener::new("click", 0, true));
+            assert_eq!(2, doc.dom()[target].event_listeners().len());
+            remove_event_listener(doc.dom_mut(), target, &listener);
+            assert_eq!(1, doc.dom()[target].event_listeners().len());
+        }
+        // A listener that removes a later one stops it from running.
+        {
+            let mut doc = document.borrow_mut();
+            add_event_listener(doc.dom_mut(), target, EventListener::new("click", 1, false));
+            add_event_listener(doc.dom_mut(), target, EventListener::new("click", 2, false));
+        }
+        let mut called = Vec::new();
+        let mut event = Event::new("click", true, true);
+        dispatch_event(&document, target, &mut event, &mut |l, _| {
+            called.push(l.callback());
+            if l.callback() == 1 {
+                let mut doc = document.borrow_mut();
+                remove_event_listener(
+                    doc.dom_mut(),
+                    target,
+                    &EventListener::new("click", 2, false),
+                );
+            }
+        });
+        assert_eq!(vec![0, 1], called);
+    }
+}
@@ -0,0 +1,626 @@
+use crate::http::HttpRequest;
+use crate::renderer::dom::api::child_text_content;
+use crate::renderer::dom::api::get_element_by_id;
+use crate::renderer::dom::api::text_content;
+use crate::renderer::dom::node::Dom;
+use crate::renderer::dom::node::ElementKind;
+use crate::renderer::dom::node::NodeId;
+use crate::url::Url;
+use alloc::format;
+use alloc::string::String;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+
+/// Type representing a form data set, a list of name-value entries.
+/// # References:
+/// - <https://html.spec.whatwg.org/multipage/form-control-infrastructure.html#entry-list>
+pub type EntryList = Vec<(String, String)>;
+
+/// Enum representing the encoding type of a form submission.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Enctype {
+    UrlEncoded,
+    Multipart,
+    TextPlain,
+}
+impl Enctype {
+    fn from_attribute(value: Option<String>) -> Self {
+        match value.map(|v| v.to_ascii_lowercase()).as_deref() {
+            Some("multipart/form-data") => Enctype::Multipart,
+            Some("text/plain") => Enctype::TextPlain,
+            _ => Enctype::UrlEncoded,
+        }
+    }
+}
+
+/// Returns the value of an attribute of an element node.
+fn attribute(dom: &Dom, node: NodeId, name: &str) -> Option<String> {
+    dom[node].get_element().and_then(|e| e.get_attribute(name))
+}
+
+/// Returns the lowercased `type` attribute of an `input` or `button` element.
+/// # Parameters
+/// - `dom`: The nodes of the document.
+/// - `node`: The form control.
+/// # Returns
+/// - The type of the control, or an empty string if the node is not a typed control.
+pub fn control_type(dom: &Dom, node: NodeId) -> String {
+    match dom[node].element_kind() {
+        Some(ElementKind::Input) => attribute(dom, node, "type")
+            .map(|t| t.to_ascii_lowercase())
+            .unwrap_or("text".to_string()),
+        Some(ElementKind::Button) => match attribute(dom, node, "type")
+            .map(|t| t.to_ascii_lowercase())
+            .as_deref()
+        {
+            Some("reset") => "reset".to_string(),
+            Some("button") => "button".to_string(),
+            _ => "submit".to_string(),
+        },
+        _ => String::new(),
+    }
+}
+
+/// Returns true if the node is a submit button.
+/// # References:
+/// - <https://html.spec.whatwg.org/multipage/forms.html#concept-submit-button>
+pub fn is_submit_button(dom: &Dom, node: NodeId) -> bool {
+    match dom[node].element_kind() {
+        Some(ElementKind::Input) | Some(ElementKind::Button) => {}
+        _ => return false,
+    }
+    matches!(control_type(dom, node).as_str(), "submit" | "image")
+}
+
+/// Returns true if the node is a text field whose Enter key triggers implicit submission.
+pub fn is_text_field(dom: &Dom, node: NodeId) -> bool {
+    if dom[node].element_kind() != Some(ElementKind::Input) {
+        return false;
+    }
+    matches!(
+        control_type(dom, node).as_str(),
+        "text" | "search" | "url" | "tel" | "email" | "password" | "number"
+    )
+}
+
+/// Returns true if the node is a form control that is not submitted because it is disabled.
+fn is_disabled(dom: &Dom, node: NodeId) -> bool {
+    attribute(dom, node, "disabled").is_some()
+}
+
+/// Returns the text of an `option` element, which is its descendant text without the leading and
+/// trailing whitespace.
+/// # References:
+/// - <https://html.spec.whatwg.org/multipage/form-elements.html#dom-option-text>
+fn option_text(dom: &Dom, node: NodeId) -> String {
+    text_content(dom, node)
+        .unwrap_or_default()
+        .trim()
+        .to_string()
+}
+
+/// Returns the current value of a form control.
+/// # Parameters
+/// - `dom`: The nodes of the document.
+/// - `node`: The form control.
+/// # Returns
+/// - The value of the control, as it would be submitted.
+/// # References:
+/// - <https://html.spec.whatwg.org/multipage/input.html#dom-input-value>
+pub fn control_value(dom: &Dom, node: NodeId) -> String {
+    let element = match dom[node].get_element() {
+        Some(e) => e,
+        None => return String::new(),
+    };
+    if let Some(value) = element.dirty_value() {
+        return value;
+    }
+    match element.kind() {
+        Some(ElementKind::Textarea) => child_text_content(dom, node),
+        Some(ElementKind::Option) => match element.get_attribute("value") {
+            Some(value) => value,
+            None => option_text(dom, node),
+        },
+        Some(ElementKind::Input) => match control_type(dom, node).as_str() {
+            "checkbox" | "radio" => element.get_attribute("value").unwrap_or("on".to_string()),
+            _ => element.get_attribute("value").unwrap_or_default(),
+        },
+        _ => element.get_attribute("value").unwrap_or_default(),
+    }
+}
+
+/// Returns the text a form control shows inside its box.
+/// # Parameters
+/// - `dom`: The nodes of the document.
+/// - `node`: The form control.
+/// # Returns
+/// - The text to render, or `None` if the node is not rendered as a form control.
+pub fn display_text(dom: &Dom, node: NodeId) -> Option<String> {
+    let element = dom[node].get_element()?;
+    match element.kind() {
+        Some(ElementKind::Input) => {
+            let value = control_value(dom, node);
+            let text = match control_type(dom, node).as_str() {
+                "hidden" => return None,
+                "checkbox" => (if element.checked() { "[x]" } else { "[ ]" }).to_string(),
+                "radio" => (if element.checked() { "(*)" } else { "( )" }).to_string(),
+                "submit" | "image" => element
+                    .get_attribute("value")
+                    .unwrap_or("Submit".to_string()),
+                "reset" => element
+                    .get_attribute("value")
+                    .unwrap_or("Reset".to_string()),
+                "password" => "*".repeat(value.chars().count()),
+                _ => value,
+            };
+            Some(text)
+        }
+        Some(ElementKind::Textarea) => Some(control_value(dom, node).replace('\n', " ")),
+        Some(ElementKind::Select) => Some(
+            selected_options(dom, node)
+                .first()
+                .map(|&o| option_text(dom, o))
+                .unwrap_or_default(),
+        ),
+        _ => None,
+    }
+}
+
+/// Returns the form owner of a form-associated element.
+/// # Parameters
+/// - `dom`: The nodes of the document.
+/// - `node`: The form-associated element.
+/// # Returns
+/// - The `form` element the control belongs to, if any.
+/// # References:
+/// - <https://html.spec.whatwg.org/multipage/form-control-infrastructure.html#form-owner>
+pub fn form_owner(dom: &Dom, node: NodeId) -> Option<NodeId> {
+    if let Some(id) = attribute(dom, node, "form") {
+        let target = get_element_by_id(dom, dom.root_of(node), &id)?;
+        if dom[target].element_kind() == Some(ElementKind::Form) {
+            return Some(target);
+        }
+        return None;
+    }
+    dom.ancestors(node)
+        .find(|&n| dom[n].element_kind() == Some(ElementKind::Form))
+}
+
+/// Returns true if a node is a labelable element.
+/// # References:
+/// - <https://html.spec.whatwg.org/multipage/forms.html#category-label>
+fn is_labelable(dom: &Dom, node: NodeId) -> bool {
+    match dom[node].element_kind() {
+        Some(ElementKind::Input) => control_type(dom, node) != "hidden",
+        Some(ElementKind::Button | ElementKind::Select | ElementKind::Textarea) => true,
+        _ => false,
+    }
+}
+
+/// Returns the labeled control of a `label` element.
+/// # Parameters
+/// - `dom`: The nodes of the document.
+/// - `label`: The `label` element.
+/// # Returns
+/// - The control named by the `for` attribute, or else the first labelable descendant.
+/// # References:
+/// - <https://html.spec.whatwg.org/multipage/forms.html#labeled-control>
+pub fn labeled_control(dom: &Dom, label: NodeId) -> Option<NodeId> {
+    if let Some(id) = attribute(dom, label, "for") {
+        let target = get_element_by_id(dom, dom.root_of(label), &id)?;
+        if is_labelable(dom, target) {
+            return Some(target);
+        }
+        return None;
+    }
+    dom.descendants(label).find(|&n| is_labelable(dom, n))
+}
+
+/// Returns the selected `option` elements of a `select` element.
+/// # Parameters
+/// - `dom`: The nodes of the document.
+/// - `select`: The `select` element.
+/// # Returns
+/// - The options whose selectedness is true.
+/// # References:
+/// - <https://html.spec.whatwg.org/multipage/form-elements.html#concept-option-selectedness>
+pub fn selected_options(dom: &Dom, select: NodeId) -> Vec<NodeId> {
+    let options: Vec<NodeId> = dom
+        .descendants(select)
+        .filter(|&n| dom[n].element_kind() == Some(ElementKind::Option))
+        .collect();
+    let selected: Vec<NodeId> = options
+        .iter()
+        .copied()
+        .filter(|&o| attribute(dom, o, "selected").is_some())
+        .collect();
+    if !selected.is_empty() || attribute(dom, select, "multiple").is_some() {
+        return selected;
+    }
+    // A single-choice select with no explicit selection selects its first non-disabled option.
+    options
+        .into_iter()
+        .filter(|&o| !is_disabled(dom, o))
+        .take(1)
+        .collect()
+}
+
+/// Constructs the entry list of a form.
+/// # Parameters
+/// - `dom`: The nodes of the document.
+/// - `form`: The `form` element.
+/// - `submitter`: The submit button that triggered the submission, if any.
+/// # Returns
+/// - The list of name-value pairs to submit.
+/// # References:
+/// - <https://html.spec.whatwg.org/multipage/form-control-infrastructure.html#constructing-the-form-data-set>
+pub fn construct_entry_list(dom: &Dom, form: NodeId, submitter: Option<NodeId>) -> EntryList {
+    let mut entries = EntryList::new();
+    for field in dom.descendants(dom.root_of(form)) {
+        let kind = match dom[field].element_kind() {
+            Some(
+                k @ (ElementKind::Input
+                | ElementKind::Button
+                | ElementKind::Select
+                | ElementKind::Textarea),
+            ) => k,
+            _ => continue,
+        };
+        if form_owner(dom, field) != Some(form) || is_disabled(dom, field) {
+            continue;
+        }
+        let ty = control_type(dom, field);
+        let is_button = kind == ElementKind::Button
+            || matches!(ty.as_str(), "submit" | "reset" | "button" | "image");
+        if is_button && submitter != Some(field) {
+            continue;
+        }
+        if (ty == "checkbox" || ty == "radio")
+            && !dom[field].get_element().is_some_and(|e| e.checked())
+        {
+            continue;
+        }
+        if ty == "image" {
+            let prefix = match attribute(dom, field, "name") {
+                Some(name) if !name.is_empty() => format!("{}.", name),
+                _ => String::new(),
+            };
+            entries.push((format!("{}x", prefix), "0".to_string()));
+            entries.push((format!("{}y", prefix), "0".to_string()));
+            continue;
+        }
+        let name = match attribute(dom, field, "name") {
+            Some(name) if !name.is_empty() => name,
+            _ => continue,
+        };
+        if kind == ElementKind::Select {
+            for option in selected_options(dom, field) {
+                if !is_disabled(dom, option) {
+                    entries.push((name.clone(), control_value(dom, option)));
+                }
+            }
+            continue;
+        }
+        if ty == "hidden" && name.eq_ignore_ascii_case("_charset_") {
+            entries.push((name, "UTF-8".to_string()));
+            continue;
+        }
+        entries.push((name, control_value(dom, field)));
+    }
+    entries
+}
+
+/// Converts every line break in a string to CRLF.
+fn normalize_newlines(s: &str) -> String {
+    s.replace("\r\n", "\n")
+        .replace('\r', "\n")
+        .replace('\n', "\r\n")
+}
+
+/// Percent-encodes a string with the application/x-www-form-urlencoded percent-encode set.
+/// # References:
+/// - <https://url.spec.whatwg.org/#application-x-www-form-urlencoded-percent-encode-set>
+fn form_urlencode(s: &str) -> String {
+    let mut result = String::new();
+    for b in s.bytes() {
+        match b {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'*' | b'-' | b'.' | b'_' => {
+                result.push(b as char)
+            }
+            b' ' => result.push('+'),
+            _ => result.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    result
+}
+
+/// Serializes an entry list as application/x-www-form-urlencoded.
+/// # Parameters
+/// - `entries`: The entry list.
+/// # Returns
+/// - The serialized string, e.g. `a=1&b=x+y`.
+/// # References:
+/// - <https://url.spec.whatwg.org/#concept-urlencoded-serializer>
+pub fn urlencoded_serialize(entries: &EntryList) -> String {
+    entries
+        .iter()
+        .map(|(name, value)| {
+            format!(
+                "{}={}",
+                form_urlencode(&normalize_newlines(name)),
+                form_urlencode(&normalize_newlines(value))
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("&")
+}
+
+/// Escapes a field name for use in a multipart/form-data header.
+fn escape_multipart_name(s: &str) -> String {
+    s.replace('\n', "%0A")
+        .replace('\r', "%0D")
+        .replace('"', "%22")
+}
+
+/// Returns a boundary string that does not occur in any of the entries.
+fn multipart_boundary(entries: &EntryList) -> String {
+    let mut hash: u32 = 0x811c9dc5;
+    for (name, value) in entries {
+        for b in name.bytes().chain(value.bytes()) {
+            hash = (hash ^ b as u32).wrapping_mul(0x01000193);
+        }
+    }
+    loop {
+        let boundary = format!("----SabaFormBoundary{:08x}", hash);
+        if !entries
+            .iter()
+            .any(|(n, v)| n.contains(&boundary) || v.contains(&boundary))
+        {
+            return boundary;
+        }
+        hash = hash.wrapping_mul(0x01000193).wrapping_add(1);
+    }
+}
+
+/// Encodes an entry list as multipart/form-data.
+/// # Parameters
+/// - `entries`: The entry list.
+/// - `boundary`: The boundary string separating the parts.
+/// # Returns
+/// - The request body.
+/// # References:
+/// - <https://html.spec.whatwg.org/multipage/form-control-infrastructure.html#multipart-form-data>
+pub fn multipart_encode(entries: &EntryList, boundary: &str) -> String {
+    let mut body = String::new();
+    for (name, value) in entries {
+        body.push_str(&format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n",
+            boundary,
+            escape_multipart_name(&normalize_newlines(name)),
+            normalize_newlines(value)
+        ));
+    }
+    body.push_str(&format!("--{}--\r\n", boundary));
+    body
+}
+
+/// Encodes an entry list as text/plain.
+/// # References:
+/// - <https://html.spec.whatwg.org/multipage/form-control-infrastructure.html#text/plain-encoding-algorithm>
+fn text_plain_encode(entries: &EntryList) -> String {
+    let mut body = String::new();
+    for (name, value) in entries {
+        body.push_str(&format!(
+            "{}={}\r\n",
+            normalize_newlines(name),
+            normalize_newlines(value)
+        ));
+    }
+    body
+}
+
+/// Returns the radio buttons in the same group as the given one, including itself.
+/// # Parameters
+/// - `dom`: The nodes of the document.
+/// - `node`: A radio button.
+/// # Returns
+/// - The radio buttons with the same form owner and name.
+/// # References:
+/// - <https://html.spec.whatwg.org/multipage/input.html#radio-button-group>
+pub fn radio_button_group(dom: &Dom, node: NodeId) -> Vec<NodeId> {
+    let name = match attribute(dom, node, "name") {
+        Some(name) if !name.is_empty() => name,
+        _ => return Vec::from([node]),
+    };
+    let owner = form_owner(dom, node);
+    dom.descendants(dom.root_of(node))
+        .filter(|&n| {
+            control_type(dom, n) == "radio"
+                && attribute(dom, n, "name").as_ref() == Some(&name)
+                && form_owner(dom, n) == owner
+        })
+        .collect()
+}
+
+/// Returns the first submit button of a form in tree order.
+/// # References:
+/// - <https://html.spec.whatwg.org/multipage/form-control-infrastructure.html#default-button>
+pub fn default_button(dom: &Dom, form: NodeId) -> Option<NodeId> {
+    dom.descendants(dom.root_of(form))
+        .find(|&n| is_submit_button(dom, n) && form_owner(dom, n) == Some(form))
+}
+
+/// Runs the form submission algorithm and plans the resulting navigation.
+/// # Parameters
+/// - `dom`: The nodes of the document.
+/// - `form`: The `form` element to submit.
+/// - `submitter`: The submit button that triggered the submission, if any.
+/// - `document_url`: The URL of the document, used to resolve the action URL.
+/// # Returns
+/// - The request to navigate to, or `None` if the form cannot be submitted.
+/// # References:
+/// - <https://html.spec.whatwg.org/multipage/form-control-infrastructure.html#form-submission-algorithm>
+pub fn submit_form(
+    dom: &Dom,
+    form: NodeId,
+    submitter: Option<NodeId>,
+    document_url: &Url,
+) -> Option<HttpRequest> {
+    let submitter_attribute = |name: &str| submitter.and_then(|s| attribute(dom, s, name));
+    let entries = construct_entry_list(dom, form, submitter);
+    let method = submitter_attribute("formmethod")
+        .or(attribute(dom, form, "method"))
+        .map(|m| m.to_ascii_lowercase())
+        .unwrap_or("get".to_string());
+    let action = submitter_attribute("formaction")
+        .or(attribute(dom, form, "action"))
+        .unwrap_or_default();
+    let enctype = Enctype::from_attribute(
+        submitter_attribute("formenctype").or(attribute(dom, form, "enctype")),
+    );
+    let action_url = document_url.resolve(&action).ok()?;
+    if method == "post" {
+        let request = match enctype {
+            Enctype::UrlEncoded => HttpRequest::post(
+                action_url.serialize(),
+                "application/x-www-form-urlencoded",
+                urlencoded_serialize(&entries),
+            ),
+            Enctype::Multipart => {
+                let boundary = multipart_boundary(&entries);
+                HttpRequest::post(
+                    action_url.serialize(),
+                    &format!("multipart/form-data; boundary={}", boundary),
+                    multipart_encode(&entries, &boundary),
+                )
+            }
+            Enctype::TextPlain => HttpRequest::post(
+                action_url.serialize(),
+                "text/plain",
+                text_plain_encode(&entries),
+            ),
+        };
+        return Some(request);
+    }
+    // "get" and any invalid method value mutate the action URL.
+    Some(HttpRequest::get(
+        action_url
+            .with_search_part(&urlencoded_serialize(&entries))
+            .serialize(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::dom::api::get_target_element_node;
+    use crate::renderer::html::parser::HtmlParser;
+    use crate::renderer::html::token::HtmlTokenizer;
+    use alloc::vec;
+    fn parse(html: &str) -> (Dom, NodeId) {
+        let t = HtmlTokenizer::new(html.to_string());
+        let document = HtmlParser::new(t).construct_tree();
+        let document = document.borrow();
+        (document.dom().clone(), document.node())
+    }
+    fn base_url() -> Url {
+        Url::new("http://example.com/dir/index.html".to_string())
+            .parse()
+            .expect("failed to parse url")
+    }
+    #[test]
+    fn test_entry_list() {
+        let (dom, document) = parse(
+            r#"<html><body><form><input name="q" value="saba"><input type="checkbox" name="c1" checked><input type="checkbox" name="c2"><input name="off" disabled value="x"><select name="s"><option>a</option><option value="B" selected>b</option></select><textarea name="t">line1</textarea><input type="submit" name="go" value="Go"></form></body></html>"#,
+        );
+        let form =
+            get_target_element_node(&dom, document, ElementKind::Form).expect("form should exist");
+        assert_eq!(
+            construct_entry_list(&dom, form, None),
+            vec![
+                ("q".to_string(), "saba".to_string()),
+                ("c1".to_string(), "on".to_string()),
+                ("s".to_string(), "B".to_string()),
+                ("t".to_string(), "line1".to_string()),
+            ]
+        );
+    }
+    #[test]
+    fn test_labeled_control() {
+        let (dom, document) = parse(
+            r#"<label for="t">T</label><label>C <input type="checkbox" id="c"></label><textarea id="t"></textarea><label for="c">C</label><label for="x">X</label>"#,
+        );
+        let body =
+            get_target_element_node(&dom, document, ElementKind::Body).expect("body should exist");
+        let controls: Vec<Option<String>> = dom
+            .descendants(body)
+            .filter(|&n| dom[n].element_kind() == Some(ElementKind::Label))
+            .map(|l| labeled_control(&dom, l).and_then(|c| attribute(&dom, c, "id")))
+            .collect();
+        assert_eq!(
+            controls,
+            vec![
+                Some("t".to_string()),
+                Some("c".to_string()),
+                Some("c".to_string()),
+                None
+            ]
+        );
+    }
+    #[test]
+    fn test_urlencoded_serialize() {
+        let entries = vec![
+            ("a b".to_string(), "x&y=z".to_string()),
+            ("jp".to_string(), "\u{3042}".to_string()),
+            ("nl".to_string(), "1\n2".to_string()),
+        ];
+        assert_eq!(
+            urlencoded_serialize(&entries),
+            "a+b=x%26y%3Dz&jp=%E3%81%82&nl=1%0D%0A2".to_string()
+        );
+    }
+    #[test]
+    fn test_submit_get() {
+        let (dom, document) = parse(
+            r#"<html><body><form action="/search"><input name="q" value="a b"><button name="btn" value="1">Go</button></form></body></html>"#,
+        );
+        let form =
+            get_target_element_node(&dom, document, ElementKind::Form).expect("form should exist");
+        let button = get_target_element_node(&dom, document, ElementKind::Button)
+            .expect("button should exist");
+        assert_eq!(
+            submit_form(&dom, form, Some(button), &base_url()),
+            Some(HttpRequest::get(
+                "http://example.com/search?q=a+b&btn=1".to_string()
+            ))
+        );
+    }
+    #[test]
+    fn test_submit_post_urlencoded() {
+        let (dom, document) = parse(
+            r#"<html><body><form method="POST" action="post"><input name="q" value="1"></form></body></html>"#,
+        );
+        let form =
+            get_target_element_node(&dom, document, ElementKind::Form).expect("form should exist");
+        assert_eq!(
+            submit_form(&dom, form, None, &base_url()),
+            Some(HttpRequest::post(
+                "http://example.com/dir/post".to_string(),
+                "application/x-www-form-urlencoded",
+                "q=1".to_string()
+            ))
+        );
+    }
+    #[test]
+    fn test_multipart_encode() {
+        let entries = vec![
+            ("a".to_string(), "1".to_string()),
+            ("b\"".to_string(), "x\ny".to_string()),
+        ];
+        assert_eq!(
+            multipart_encode(&entries, "BOUNDARY"),
+            "--BOUNDARY\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\n1\r\n--BOUNDARY\r\nContent-Disposition: form-data; name=\"b%22\"\r\n\r\nx\r\ny\r\n--BOUNDARY--\r\n"
+                .to_string()
+        );
+    }
+}
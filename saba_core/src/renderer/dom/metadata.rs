@@ -0,0 +1,229 @@
+use crate::renderer::dom::api::child_text_content;
+use crate::renderer::dom::api::get_elements_by_kind;
+use crate::renderer::dom::node::Dom;
+use crate::renderer::dom::node::ElementKind;
+use crate::renderer::dom::node::NodeId;
+use crate::url::Url;
+use alloc::string::String;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+
+/// Struct representing a navigation scheduled by `<meta http-equiv="refresh">`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Refresh {
+    /// The delay before the navigation, in seconds.
+    delay: u64,
+    /// The URL to navigate to, or `None` to reload the document.
+    url: Option<String>,
+}
+impl Refresh {
+    pub fn delay(&self) -> u64 {
+        self.delay
+    }
+    pub fn url(&self) -> Option<String> {
+        self.url.clone()
+    }
+}
+
+/// Returns the text of the first `title` element with the ASCII whitespace stripped and
+/// collapsed, which is the getter of `document.title`.
+/// # Parameters
+/// - `document`: The document node.
+/// # Returns
+/// - The title, or an empty string if the document has no `title` element.
+/// # References:
+/// - <https://html.spec.whatwg.org/multipage/dom.html#document.title>
+pub fn document_title(dom: &Dom, document: NodeId) -> String {
+    match get_elements_by_kind(dom, document, ElementKind::Title).first() {
+        Some(&title) => child_text_content(dom, title)
+            .split_ascii_whitespace()
+            .collect::<Vec<&str>>()
+            .join(" "),
+        None => String::new(),
+    }
+}
+
+/// Returns the base URL of the document, which relative URLs in the document are resolved
+/// against.
+/// # Parameters
+/// - `document`: The document node.
+/// - `document_url`: The URL of the document.
+/// # Returns
+/// - The `href` of the first `base` element with one, resolved against `document_url`, or
+///   `document_url` if there is no such element or its `href` is not a valid URL.
+/// # References:
+/// - <https://html.spec.whatwg.org/multipage/semantics.html#frozen-base-url>
+pub fn base_url(dom: &Dom, document: NodeId, document_url: &Url) -> Url {
+    let href = get_elements_by_kind(dom, document, ElementKind::Base)
+        .into_iter()
+        .find_map(|base| dom[base].get_element()?.get_attribute("href"));
+    match href {
+        Some(href) => document_url
+            .resolve(&href)
+            .unwrap_or_else(|_| document_url.clone()),
+        None => document_url.clone(),
+    }
+}
+
+/// Returns the `content` of the first `meta` element whose `name` matches `name` ASCII
+/// case-insensitively, such as `description` or `author`.
+/// # Parameters
+/// - `document`: The document node.
+/// - `name`: The metadata name.
+/// # Returns
+/// - The value of the metadata, or `None` if the document doesn't have it.
+/// # References:
+/// - <https://html.spec.whatwg.org/multipage/semantics.html#attr-meta-name>
+pub fn meta_content(dom: &Dom, document: NodeId, name: &str) -> Option<String> {
+    get_elements_by_kind(dom, document, ElementKind::Meta)
+        .into_iter()
+        .find_map(|meta| {
+            let element = dom[meta].get_element()?;
+            if !element
+                .get_attribute("name")
+                .is_some_and(|n| n.eq_ignore_ascii_case(name))
+            {
+                return None;
+            }
+            element.get_attribute("content")
+        })
+}
+
+/// Returns the navigation scheduled by the first `<meta http-equiv="refresh">` element with a
+/// valid `content`. Later ones are ignored.
+/// # Parameters
+/// - `document`: The document node.
+/// # References:
+/// - <https://html.spec.whatwg.org/multipage/semantics.html#attr-meta-http-equiv-refresh>
+pub fn refresh(dom: &Dom, document: NodeId) -> Option<Refresh> {
+    get_elements_by_kind(dom, document, ElementKind::Meta)
+        .into_iter()
+        .find_map(|meta| {
+            let element = dom[meta].get_element()?;
+            if !element
+                .get_attribute("http-equiv")
+                .is_some_and(|e| e.eq_ignore_ascii_case("refresh"))
+            {
+                return None;
+            }
+            parse_refresh(&element.get_attribute("content")?)
+        })
+}
+
+/// Parses the `content` of a `<meta http-equiv="refresh">` element, such as `5; url=next.html`.
+/// # References:
+/// - <https://html.spec.whatwg.org/multipage/browsing-the-web.html#shared-declarative-refresh-steps>
+pub fn parse_refresh(content: &str) -> Option<Refresh> {
+    let input = content.trim_start_matches(|c: char| c.is_ascii_whitespace());
+    let digits = input.len() - input.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    if digits == 0 && !input.starts_with('.') {
+        return None;
+    }
+    // A delay too long to represent never expires in practice.
+    let delay = match digits {
+        0 => 0,
+        _ => input[..digits].parse::<u64>().unwrap_or(u64::MAX),
+    };
+    // The fractional part of the delay is ignored.
+    let input = input.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.');
+    if input.is_empty() {
+        return Some(Refresh { delay, url: None });
+    }
+    if !input.starts_with(|c: char| c == ';' || c == ',' || c.is_ascii_whitespace()) {
+        return None;
+    }
+    let mut input = input.trim_start_matches(|c: char| c.is_ascii_whitespace());
+    if let Some(rest) = input.strip_prefix(|c: char| c == ';' || c == ',') {
+        input = rest.trim_start_matches(|c: char| c.is_ascii_whitespace());
+    }
+    // `get` returns `None` instead of panicking when byte 3 isn't a character boundary.
+    if input
+        .get(..3)
+        .is_some_and(|p| p.eq_ignore_ascii_case("url"))
+    {
+        let rest = input[3..].trim_start_matches(|c: char| c.is_ascii_whitespace());
+        if let Some(rest) = rest.strip_prefix('=') {
+            input = rest.trim_start_matches(|c: char| c.is_ascii_whitespace());
+        }
+    }
+    let url = match input.chars().next() {
+        Some(quote @ ('"' | '\'')) => {
+            let rest = &input[1..];
+            match rest.find(quote) {
+                Some(end) => &rest[..end],
+                None => rest,
+            }
+        }
+        _ => input,
+    };
+    Some(Refresh {
+        delay,
+        url: Some(url.to_string()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::html::parser::HtmlParser;
+    use crate::renderer::html::token::HtmlTokenizer;
+    fn parse(html: &str) -> (Dom, NodeId) {
+        let t = HtmlTokenizer::new(html.to_string());
+        let document = HtmlParser::new(t).construct_tree();
+        let document = document.borrow();
+        (document.dom().clone(), document.node())
+    }
+    #[test]
+    fn test_metadata() {
+        let (dom, document) = parse(
+            "<head><title>\n  Hello,\tsaba  </title><title>second</title><base target=_blank><base href=\"/docs/\"><base href=\"/other/\"><meta name=Description content=\"a toy browser\"><meta name=author></head>",
+        );
+        assert_eq!("Hello, saba", document_title(&dom, document));
+        let url = Url::new("http://example.com/index.html".to_string())
+            .parse()
+            .expect("failed to parse url");
+        assert_eq!(
+            "http://example.com/docs/",
+            base_url(&dom, document, &url).serialize()
+        );
+        assert_eq!(
+            Some("a toy browser".to_string()),
+            meta_content(&dom, document, "description")
+        );
+        assert_eq!(None, meta_content(&dom, document, "author"));
+        let (dom, document) = parse("<p>no title");
+        assert_eq!("", document_title(&dom, document));
+    }
+    #[test]
+    fn test_parse_refresh() {
+        for (content, expected) in [
+            ("5", Some((5, None))),
+            ("0; url=next.html", Some((0, Some("next.html")))),
+            ("3.5, URL = 'a b.html' ignored", Some((3, Some("a b.html")))),
+            ("1 \"quoted.html", Some((1, Some("quoted.html")))),
+            ("2;next.html", Some((2, Some("next.html")))),
+            (".5", Some((0, None))),
+            ("2x", None),
+            ("url=next.html", None),
+            ("5; éé", Some((5, Some("éé")))),
+            ("5; uré=a", Some((5, Some("uré=a")))),
+            ("5; é", Some((5, Some("é")))),
+        ] {
+            let expected = expected.map(|(delay, url): (u64, Option<&str>)| Refresh {
+                delay,
+                url: url.map(|u| u.to_string()),
+            });
+            assert_eq!(expected, parse_refresh(content), "{}", content);
+        }
+        let (dom, document) = parse(
+            "<meta http-equiv=refresh content=x><meta http-equiv=Refresh content=\"1;url=/a\"><meta http-equiv=refresh content=2>",
+        );
+        assert_eq!(
+            Some(Refresh {
+                delay: 1,
+                url: Some("/a".to_string()),
+            }),
+            refresh(&dom, document)
+        );
+    }
+}
@@ -1,2 +1,8 @@
 pub mod api;
+pub mod document;
+pub mod event;
+pub mod form;
+pub mod metadata;
 pub mod node;
+pub mod traversal;
+pub mod window;
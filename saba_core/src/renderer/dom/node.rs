@@ -1,47 +1,32 @@
+use crate::renderer::arena::Arena;
+use crate::renderer::arena::Id;
+use crate::renderer::dom::event::EventListener;
 use crate::renderer::html::attribute::Attribute;
 use alloc::format;
-use alloc::rc::Rc;
-use alloc::rc::Weak;
 use alloc::string::String;
+use alloc::string::ToString;
 use alloc::vec::Vec;
-use core::cell::RefCell;
 use core::fmt::Display;
 use core::fmt::Formatter;
 use core::str::FromStr;
 
-/// Struct representing a window object.
-/// # References:
-/// - <https://html.spec.whatwg.org/multipage/nav-history-apis.html#window>
-#[derive(Debug, Clone)]
-pub struct Window {
-    document: Rc<RefCell<Node>>,
-}
-impl Window {
-    pub fn new() -> Self {
-        let window = Self {
-            document: Rc::new(RefCell::new(Node::new(NodeKind::Document))),
-        };
-        window
-            .document
-            .borrow_mut()
-            .set_window(Rc::downgrade(&Rc::new(RefCell::new(window.clone()))));
-        window
-    }
-    pub fn document(&self) -> Rc<RefCell<Node>> {
-        self.document.clone()
-    }
-}
+/// Type representing the nodes of a document, including the ones that are not in the tree.
+pub type Dom = Arena<Node>;
 
-/// Struct representing a node in the DOM tree.
+/// Type representing a handle to a node in a `Dom`.
+pub type NodeId = Id<Node>;
+
+/// Struct representing a node in the DOM tree. The links between nodes are kept by the `Dom`.
 #[derive(Debug, Clone)]
 pub struct Node {
     pub kind: NodeKind,
-    window: Weak<RefCell<Window>>,
-    parent: Weak<RefCell<Node>>,
-    first_child: Option<Rc<RefCell<Node>>>,
-    last_child: Weak<RefCell<Node>>,
-    previous_sibling: Weak<RefCell<Node>>,
-    next_sibling: Option<Rc<RefCell<Node>>>,
+    template_contents: Option<NodeId>,
+    /// Whether the computed style of this node is out of date.
+    needs_style_recalc: bool,
+    /// Whether the computed style of a descendant of this node is out of date.
+    child_needs_style_recalc: bool,
+    /// The event listeners registered on this node, in the order they were added.
+    event_listeners: Vec<EventListener>,
 }
 impl PartialEq for Node {
     fn eq(&self, other: &Self) -> bool {
@@ -52,80 +37,105 @@ impl Node {
     pub fn new(kind: NodeKind) -> Self {
         Self {
             kind,
-            window: Weak::new(),
-            parent: Weak::new(),
-            first_child: None,
-            last_child: Weak::new(),
-            previous_sibling: Weak::new(),
-            next_sibling: None,
+            template_contents: None,
+            needs_style_recalc: false,
+            child_needs_style_recalc: false,
+            event_listeners: Vec::new(),
         }
     }
-    pub fn set_window(&mut self, window: Weak<RefCell<Window>>) {
-        self.window = window;
-    }
-    pub fn set_parent(&mut self, parent: Weak<RefCell<Node>>) {
-        self.parent = parent;
-    }
-    pub fn parent(&mut self) -> Weak<RefCell<Node>> {
-        self.parent.clone()
+    pub fn kind(&self) -> NodeKind {
+        self.kind.clone()
     }
-    pub fn set_first_child(&mut self, first_child: Option<Rc<RefCell<Node>>>) {
-        self.first_child = first_child;
+    /// Returns the document fragment that holds the children of a `template` element, which are
+    /// not rendered.
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/scripting.html#template-contents>
+    pub fn template_contents(&self) -> Option<NodeId> {
+        self.template_contents
     }
-    pub fn first_child(&self) -> Option<Rc<RefCell<Node>>> {
-        self.first_child.as_ref().cloned()
+    pub fn set_template_contents(&mut self, template_contents: Option<NodeId>) {
+        self.template_contents = template_contents;
     }
-    pub fn set_last_child(&mut self, last_child: Weak<RefCell<Node>>) {
-        self.last_child = last_child;
+    pub fn needs_style_recalc(&self) -> bool {
+        self.needs_style_recalc
     }
-    pub fn last_child(&self) -> Weak<RefCell<Node>> {
-        self.last_child.clone()
+    pub fn set_needs_style_recalc(&mut self, needs_style_recalc: bool) {
+        self.needs_style_recalc = needs_style_recalc;
     }
-    pub fn set_previous_sibling(&mut self, previous_sibling: Weak<RefCell<Node>>) {
-        self.previous_sibling = previous_sibling;
+    pub fn child_needs_style_recalc(&self) -> bool {
+        self.child_needs_style_recalc
     }
-    pub fn previous_sibling(&self) -> Weak<RefCell<Node>> {
-        self.previous_sibling.clone()
+    pub fn set_child_needs_style_recalc(&mut self, child_needs_style_recalc: bool) {
+        self.child_needs_style_recalc = child_needs_style_recalc;
     }
-    pub fn set_next_sibling(&mut self, next_sibling: Option<Rc<RefCell<Node>>>) {
-        self.next_sibling = next_sibling;
+    /// # References:
+    /// - <https://dom.spec.whatwg.org/#eventtarget-event-listener-list>
+    pub fn event_listeners(&self) -> &[EventListener] {
+        &self.event_listeners
     }
-    pub fn next_sibling(&self) -> Option<Rc<RefCell<Node>>> {
-        self.next_sibling.as_ref().cloned()
+    pub fn event_listeners_mut(&mut self) -> &mut Vec<EventListener> {
+        &mut self.event_listeners
     }
-    pub fn kind(&self) -> NodeKind {
-        self.kind.clone()
+    pub fn get_element(&self) -> Option<&Element> {
+        match self.kind {
+            NodeKind::Element(ref e) => Some(e),
+            _ => None,
+        }
     }
-    pub fn get_element(&self) -> Option<Element> {
+    pub fn get_element_mut(&mut self) -> Option<&mut Element> {
         match self.kind {
-            NodeKind::Document | NodeKind::Text(_) => None,
-            NodeKind::Element(ref e) => Some(e.clone()),
+            NodeKind::Element(ref mut e) => Some(e),
+            _ => None,
         }
     }
     pub fn element_kind(&self) -> Option<ElementKind> {
         match self.kind {
-            NodeKind::Document | NodeKind::Text(_) => None,
-            NodeKind::Element(ref e) => Some(e.kind()),
+            NodeKind::Element(ref e) => e.kind(),
+            _ => None,
         }
     }
 }
 
+/// Enum representing the mode of a document, chosen from its DOCTYPE.
+/// # References:
+/// - <https://dom.spec.whatwg.org/#concept-document-quirks>
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum QuirksMode {
+    NoQuirks,
+    Quirks,
+    LimitedQuirks,
+}
+
 /// Enum representing the kind of a node in the DOM tree.
 #[derive(Debug, Clone)]
 pub enum NodeKind {
     Document,
+    /// A document fragment, such as the contents of a `template` element.
+    DocumentFragment,
+    /// The DOCTYPE of a document. Missing names and identifiers are empty strings.
+    DocumentType {
+        name: String,
+        public_id: String,
+        system_id: String,
+    },
     Element(Element),
     Text(String),
+    Comment(String),
 }
 impl PartialEq for NodeKind {
     fn eq(&self, other: &Self) -> bool {
         match &self {
             NodeKind::Document => matches!(other, NodeKind::Document),
+            NodeKind::DocumentFragment => matches!(other, NodeKind::DocumentFragment),
             NodeKind::Element(e1) => match &other {
-                NodeKind::Element(e2) => e1.kind == e2.kind,
+                NodeKind::Element(e2) => {
+                    e1.namespace == e2.namespace && e1.local_name == e2.local_name
+                }
                 _ => false,
             },
             NodeKind::Text(_) => matches!(other, NodeKind::Text(_)),
+            NodeKind::Comment(_) => matches!(other, NodeKind::Comment(_)),
+            NodeKind::DocumentType { .. } => matches!(other, NodeKind::DocumentType { .. }),
         }
     }
 }
@@ -133,29 +143,89 @@ impl PartialEq for NodeKind {
 /// Enum representing the element in the DOM tree.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Element {
-    kind: ElementKind,
+    local_name: String,
+    namespace: Namespace,
+    kind: Option<ElementKind>,
     attributes: Vec<Attribute>,
+    value: Option<String>,
+    checked: Option<bool>,
 }
 impl Element {
-    pub fn new(element_name: &str, attributes: Vec<Attribute>) -> Self {
+    /// Creates an element in the HTML namespace.
+    pub fn new(local_name: &str, attributes: Vec<Attribute>) -> Self {
+        Self::new_ns(Namespace::Html, local_name, attributes)
+    }
+    /// Creates an element in `namespace`. Only HTML elements are classified by `ElementKind`.
+    /// # References:
+    /// - <https://dom.spec.whatwg.org/#concept-create-element>
+    pub fn new_ns(namespace: Namespace, local_name: &str, attributes: Vec<Attribute>) -> Self {
+        let kind = match namespace {
+            Namespace::Html => ElementKind::from_str(local_name).ok(),
+            _ => None,
+        };
         Self {
-            kind: ElementKind::from_str(element_name)
-                .expect("Failed to convert string to ElementKind."),
+            local_name: local_name.to_string(),
+            namespace,
+            kind,
             attributes,
+            value: None,
+            checked: None,
         }
     }
-    pub fn kind(&self) -> ElementKind {
+    /// Returns the kind of an element, or `None` if the element is not known to this browser. Such
+    /// elements behave as generic elements, like `HTMLUnknownElement`.
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/dom.html#htmlunknownelement>
+    pub fn kind(&self) -> Option<ElementKind> {
         self.kind
     }
-    pub fn is_block_element(&self) -> bool {
-        match self.kind {
-            ElementKind::Body | ElementKind::H1 | ElementKind::H2 | ElementKind::P => true,
-            _ => false,
+    /// # References:
+    /// - <https://dom.spec.whatwg.org/#concept-element-local-name>
+    pub fn local_name(&self) -> String {
+        self.local_name.clone()
+    }
+    /// # References:
+    /// - <https://dom.spec.whatwg.org/#concept-element-namespace>
+    pub fn namespace(&self) -> Namespace {
+        self.namespace
+    }
+    /// Returns the local name, which is uppercased for HTML elements.
+    /// # References:
+    /// - <https://dom.spec.whatwg.org/#dom-element-tagname>
+    pub fn tag_name(&self) -> String {
+        match self.namespace {
+            Namespace::Html => self.local_name.to_ascii_uppercase(),
+            _ => self.local_name.clone(),
         }
     }
+    /// Returns the value set by the user, which overrides the default value of a form control.
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/form-control-infrastructure.html#concept-fe-dirty>
+    pub fn dirty_value(&self) -> Option<String> {
+        self.value.clone()
+    }
+    pub fn set_value(&mut self, value: String) {
+        self.value = Some(value);
+    }
+    /// Returns the checkedness of a checkbox or radio button.
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/form-control-infrastructure.html#concept-fe-checked>
+    pub fn checked(&self) -> bool {
+        match self.checked {
+            Some(checked) => checked,
+            None => self.get_attribute("checked").is_some(),
+        }
+    }
+    pub fn set_checked(&mut self, checked: bool) {
+        self.checked = Some(checked);
+    }
     pub fn attributes(&self) -> Vec<Attribute> {
         self.attributes.clone()
     }
+    /// Appends `attribute` without checking whether an attribute of the same name exists.
+    pub fn append_attribute(&mut self, attribute: Attribute) {
+        self.attributes.push(attribute);
+    }
     pub fn get_attribute(&self, name: &str) -> Option<String> {
         for attr in &self.attributes {
             if attr.name() == name {
@@ -164,20 +234,202 @@ impl Element {
         }
         None
     }
+    pub fn has_attribute(&self, name: &str) -> bool {
+        self.attributes.iter().any(|attr| attr.name() == name)
+    }
+    /// Changes the value of the attribute named `name`, or appends the attribute if there is
+    /// none.
+    /// # References:
+    /// - <https://dom.spec.whatwg.org/#concept-element-attributes-set-value>
+    pub fn set_attribute(&mut self, name: &str, value: &str) {
+        match self.attributes.iter_mut().find(|attr| attr.name() == name) {
+            Some(attr) => attr.set_value(value),
+            None => {
+                let mut attr = Attribute::new();
+                attr.set_name(name);
+                attr.set_value(value);
+                self.attributes.push(attr);
+            }
+        }
+    }
+    /// Removes the attribute named `name`.
+    /// # Returns
+    /// - `true` if the element had the attribute.
+    /// # References:
+    /// - <https://dom.spec.whatwg.org/#concept-element-attributes-remove-by-name>
+    pub fn remove_attribute(&mut self, name: &str) -> bool {
+        let len = self.attributes.len();
+        self.attributes.retain(|attr| attr.name() != name);
+        self.attributes.len() != len
+    }
+    /// Returns the classes of the element, which are the tokens of its `class` attribute.
+    /// # References:
+    /// - <https://dom.spec.whatwg.org/#dom-element-classlist>
+    pub fn class_list(&self) -> ClassList {
+        ClassList::parse(&self.get_attribute("class").unwrap_or_default())
+    }
+    /// Replaces the `class` attribute with the serialized `class_list`. An element without a
+    /// `class` attribute doesn't get an empty one.
+    /// # References:
+    /// - <https://dom.spec.whatwg.org/#concept-dtl-update>
+    pub fn set_class_list(&mut self, class_list: &ClassList) {
+        if !self.has_attribute("class") && class_list.is_empty() {
+            return;
+        }
+        self.set_attribute("class", &class_list.serialize());
+    }
+}
+
+/// Struct representing the classes of an element as an ordered set of tokens, like a
+/// `DOMTokenList`. Changes are written back with `Element::set_class_list`.
+/// # References:
+/// - <https://dom.spec.whatwg.org/#interface-domtokenlist>
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ClassList {
+    tokens: Vec<String>,
+}
+impl ClassList {
+    /// Splits `value` on ASCII whitespace, dropping duplicate tokens.
+    /// # References:
+    /// - <https://dom.spec.whatwg.org/#concept-ordered-set-parser>
+    pub fn parse(value: &str) -> Self {
+        let mut class_list = Self::default();
+        for token in value.split_ascii_whitespace() {
+            class_list.add(token);
+        }
+        class_list
+    }
+    pub fn tokens(&self) -> &[String] {
+        &self.tokens
+    }
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+    pub fn contains(&self, token: &str) -> bool {
+        self.tokens.iter().any(|t| t == token)
+    }
+    pub fn add(&mut self, token: &str) {
+        if !self.contains(token) {
+            self.tokens.push(token.to_string());
+        }
+    }
+    pub fn remove(&mut self, token: &str) {
+        self.tokens.retain(|t| t != token);
+    }
+    /// Removes `token` if it is in the set and adds it otherwise. If `force` is given, the token
+    /// is only added if it is `true` and only removed if it is `false`.
+    /// # Returns
+    /// - `true` if the token is in the set afterwards.
+    /// # References:
+    /// - <https://dom.spec.whatwg.org/#dom-domtokenlist-toggle>
+    pub fn toggle(&mut self, token: &str, force: Option<bool>) -> bool {
+        if self.contains(token) {
+            if force != Some(true) {
+                self.remove(token);
+                return false;
+            }
+            return true;
+        }
+        if force != Some(false) {
+            self.add(token);
+            return true;
+        }
+        false
+    }
+    /// # References:
+    /// - <https://dom.spec.whatwg.org/#concept-ordered-set-serializer>
+    pub fn serialize(&self) -> String {
+        self.tokens.join(" ")
+    }
 }
 
-/// Struct representing the attribute in an element.
+/// Enum representing the namespace of an element.
+/// # References:
+/// - <https://infra.spec.whatwg.org/#namespaces>
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Namespace {
+    Html,
+    MathMl,
+    Svg,
+}
+impl Namespace {
+    pub fn url(&self) -> &'static str {
+        match self {
+            Namespace::Html => "http://www.w3.org/1999/xhtml",
+            Namespace::MathMl => "http://www.w3.org/1998/Math/MathML",
+            Namespace::Svg => "http://www.w3.org/2000/svg",
+        }
+    }
+}
+
+/// Enum representing the kind of an HTML element that this browser knows.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum ElementKind {
     Html,
     Head,
+    Title,
+    Base,
+    Meta,
+    Link,
     Style,
     Script,
     Body,
-    P,
+    Article,
+    Section,
+    Nav,
+    Aside,
+    Header,
+    Footer,
+    Main,
+    Address,
     H1,
     H2,
+    H3,
+    H4,
+    H5,
+    H6,
+    P,
+    Div,
+    Hr,
+    Pre,
+    Blockquote,
+    Ul,
+    Ol,
+    Li,
+    Dl,
+    Dt,
+    Dd,
     A,
+    Span,
+    Em,
+    Strong,
+    B,
+    I,
+    U,
+    Code,
+    Br,
+    Img,
+    Table,
+    Caption,
+    Colgroup,
+    Col,
+    Thead,
+    Tbody,
+    Tfoot,
+    Tr,
+    Td,
+    Th,
+    Form,
+    Label,
+    Input,
+    Textarea,
+    Select,
+    Option,
+    Button,
+    Xmp,
+    Iframe,
+    Noembed,
+    Noframes,
 }
 impl FromStr for ElementKind {
     type Err = String;
@@ -185,13 +437,69 @@ impl FromStr for ElementKind {
         match s {
             "html" => Ok(ElementKind::Html),
             "head" => Ok(ElementKind::Head),
+            "title" => Ok(ElementKind::Title),
+            "base" => Ok(ElementKind::Base),
+            "meta" => Ok(ElementKind::Meta),
+            "link" => Ok(ElementKind::Link),
             "style" => Ok(ElementKind::Style),
             "script" => Ok(ElementKind::Script),
             "body" => Ok(ElementKind::Body),
-            "p" => Ok(ElementKind::P),
+            "article" => Ok(ElementKind::Article),
+            "section" => Ok(ElementKind::Section),
+            "nav" => Ok(ElementKind::Nav),
+            "aside" => Ok(ElementKind::Aside),
+            "header" => Ok(ElementKind::Header),
+            "footer" => Ok(ElementKind::Footer),
+            "main" => Ok(ElementKind::Main),
+            "address" => Ok(ElementKind::Address),
             "h1" => Ok(ElementKind::H1),
             "h2" => Ok(ElementKind::H2),
+            "h3" => Ok(ElementKind::H3),
+            "h4" => Ok(ElementKind::H4),
+            "h5" => Ok(ElementKind::H5),
+            "h6" => Ok(ElementKind::H6),
+            "p" => Ok(ElementKind::P),
+            "div" => Ok(ElementKind::Div),
+            "hr" => Ok(ElementKind::Hr),
+            "pre" => Ok(ElementKind::Pre),
+            "blockquote" => Ok(ElementKind::Blockquote),
+            "ul" => Ok(ElementKind::Ul),
+            "ol" => Ok(ElementKind::Ol),
+            "li" => Ok(ElementKind::Li),
+            "dl" => Ok(ElementKind::Dl),
+            "dt" => Ok(ElementKind::Dt),
+            "dd" => Ok(ElementKind::Dd),
             "a" => Ok(ElementKind::A),
+            "span" => Ok(ElementKind::Span),
+            "em" => Ok(ElementKind::Em),
+            "strong" => Ok(ElementKind::Strong),
+            "b" => Ok(ElementKind::B),
+            "i" => Ok(ElementKind::I),
+            "u" => Ok(ElementKind::U),
+            "code" => Ok(ElementKind::Code),
+            "br" => Ok(ElementKind::Br),
+            "img" => Ok(ElementKind::Img),
+            "table" => Ok(ElementKind::Table),
+            "caption" => Ok(ElementKind::Caption),
+            "colgroup" => Ok(ElementKind::Colgroup),
+            "col" => Ok(ElementKind::Col),
+            "thead" => Ok(ElementKind::Thead),
+            "tbody" => Ok(ElementKind::Tbody),
+            "tfoot" => Ok(ElementKind::Tfoot),
+            "tr" => Ok(ElementKind::Tr),
+            "td" => Ok(ElementKind::Td),
+            "th" => Ok(ElementKind::Th),
+            "form" => Ok(ElementKind::Form),
+            "label" => Ok(ElementKind::Label),
+            "input" => Ok(ElementKind::Input),
+            "textarea" => Ok(ElementKind::Textarea),
+            "select" => Ok(ElementKind::Select),
+            "option" => Ok(ElementKind::Option),
+            "button" => Ok(ElementKind::Button),
+            "xmp" => Ok(ElementKind::Xmp),
+            "iframe" => Ok(ElementKind::Iframe),
+            "noembed" => Ok(ElementKind::Noembed),
+            "noframes" => Ok(ElementKind::Noframes),
             _ => Err(format!("Unimplemented element name {:?}.", s)),
         }
     }
@@ -201,13 +509,69 @@ impl Display for ElementKind {
         let s = match self {
             ElementKind::Html => "html",
             ElementKind::Head => "head",
+            ElementKind::Title => "title",
+            ElementKind::Base => "base",
+            ElementKind::Meta => "meta",
+            ElementKind::Link => "link",
             ElementKind::Style => "style",
             ElementKind::Script => "script",
             ElementKind::Body => "body",
+            ElementKind::Article => "article",
+            ElementKind::Section => "section",
+            ElementKind::Nav => "nav",
+            ElementKind::Aside => "aside",
+            ElementKind::Header => "header",
+            ElementKind::Footer => "footer",
+            ElementKind::Main => "main",
+            ElementKind::Address => "address",
             ElementKind::H1 => "h1",
             ElementKind::H2 => "h2",
+            ElementKind::H3 => "h3",
+            ElementKind::H4 => "h4",
+            ElementKind::H5 => "h5",
+            ElementKind::H6 => "h6",
             ElementKind::P => "p",
+            ElementKind::Div => "div",
+            ElementKind::Hr => "hr",
+            ElementKind::Pre => "pre",
+            ElementKind::Blockquote => "blockquote",
+            ElementKind::Ul => "ul",
+            ElementKind::Ol => "ol",
+            ElementKind::Li => "li",
+            ElementKind::Dl => "dl",
+            ElementKind::Dt => "dt",
+            ElementKind::Dd => "dd",
             ElementKind::A => "a",
+            ElementKind::Span => "span",
+            ElementKind::Em => "em",
+            ElementKind::Strong => "strong",
+            ElementKind::B => "b",
+            ElementKind::I => "i",
+            ElementKind::U => "u",
+            ElementKind::Code => "code",
+            ElementKind::Br => "br",
+            ElementKind::Img => "img",
+            ElementKind::Table => "table",
+            ElementKind::Caption => "caption",
+            ElementKind::Colgroup => "colgroup",
+            ElementKind::Col => "col",
+            ElementKind::Thead => "thead",
+            ElementKind::Tbody => "tbody",
+            ElementKind::Tfoot => "tfoot",
+            ElementKind::Tr => "tr",
+            ElementKind::Td => "td",
+            ElementKind::Th => "th",
+            ElementKind::Form => "form",
+            ElementKind::Label => "label",
+            ElementKind::Input => "input",
+            ElementKind::Textarea => "textarea",
+            ElementKind::Select => "select",
+            ElementKind::Option => "option",
+            ElementKind::Button => "button",
+            ElementKind::Xmp => "xmp",
+            ElementKind::Iframe => "iframe",
+            ElementKind::Noembed => "noembed",
+            ElementKind::Noframes => "noframes",
         };
         write!(f, "{}", s)
     }
@@ -0,0 +1,531 @@
+use crate::renderer::dom::document::Document;
+use crate::renderer::dom::node::Dom;
+use crate::renderer::dom::node::Node;
+use crate::renderer::dom::node::NodeId;
+use crate::renderer::dom::node::NodeKind;
+use core::cell::RefCell;
+
+/// The `whatToShow` bit that shows every node.
+pub const SHOW_ALL: u32 = 0xFFFF_FFFF;
+/// The `whatToShow` bit that shows Element nodes.
+pub const SHOW_ELEMENT: u32 = 0x1;
+/// The `whatToShow` bit that shows Text nodes.
+pub const SHOW_TEXT: u32 = 0x4;
+/// The `whatToShow` bit that shows Comment nodes.
+pub const SHOW_COMMENT: u32 = 0x80;
+/// The `whatToShow` bit that shows Document nodes.
+pub const SHOW_DOCUMENT: u32 = 0x100;
+/// The `whatToShow` bit that shows DocumentType nodes.
+pub const SHOW_DOCUMENT_TYPE: u32 = 0x200;
+/// The `whatToShow` bit that shows DocumentFragment nodes.
+pub const SHOW_DOCUMENT_FRAGMENT: u32 = 0x400;
+
+/// Enum representing the result of a node filter.
+/// # References:
+/// - <https://dom.spec.whatwg.org/#interface-nodefilter>
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FilterResult {
+    /// The node is returned.
+    Accept,
+    /// The node and its descendants are skipped. A NodeIterator treats this as `Skip`.
+    Reject,
+    /// The node is skipped, but its descendants are not.
+    Skip,
+}
+
+/// A node filter, which is called with each node that `whatToShow` shows. The DOM isn't borrowed
+/// while it runs, so it may read and modify the tree.
+pub type NodeFilter<'a> = dyn FnMut(NodeId) -> FilterResult + 'a;
+
+/// A node filter that accepts every node, for a TreeWalker or a NodeIterator without a filter.
+pub fn accept_all(_node: NodeId) -> FilterResult {
+    FilterResult::Accept
+}
+
+/// Returns the `nodeType` of `node`.
+/// # References:
+/// - <https://dom.spec.whatwg.org/#dom-node-nodetype>
+pub fn node_type(node: &Node) -> u32 {
+    match node.kind {
+        NodeKind::Element(_) => 1,
+        NodeKind::Text(_) => 3,
+        NodeKind::Comment(_) => 8,
+        NodeKind::Document => 9,
+        NodeKind::DocumentType { .. } => 10,
+        NodeKind::DocumentFragment => 11,
+    }
+}
+
+/// Returns the result of filtering `node` with `what_to_show` and then `filter`.
+/// # References:
+/// - <https://dom.spec.whatwg.org/#concept-node-filter>
+fn filter_node(
+    document: &RefCell<Document>,
+    node: NodeId,
+    what_to_show: u32,
+    filter: &mut NodeFilter,
+) -> FilterResult {
+    let bit = 1 << (node_type(&document.borrow().dom()[node]) - 1);
+    if what_to_show & bit == 0 {
+        return FilterResult::Skip;
+    }
+    filter(node)
+}
+
+/// Struct representing a TreeWalker, which moves its current node around the subtree of its root
+/// over the nodes that its `what_to_show` and filter accept. The filter is passed to each method
+/// rather than kept in the walker, so that a script can run it with the JavaScript runtime. The
+/// document is only borrowed between calls to the filter.
+/// # References:
+/// - <https://dom.spec.whatwg.org/#interface-treewalker>
+#[derive(Debug, Clone)]
+pub struct TreeWalker {
+    root: NodeId,
+    what_to_show: u32,
+    current: NodeId,
+}
+impl TreeWalker {
+    pub fn new(root: NodeId, what_to_show: u32) -> Self {
+        Self {
+            current: root,
+            root,
+            what_to_show,
+        }
+    }
+    pub fn root(&self) -> NodeId {
+        self.root
+    }
+    pub fn what_to_show(&self) -> u32 {
+        self.what_to_show
+    }
+    pub fn current_node(&self) -> NodeId {
+        self.current
+    }
+    pub fn set_current_node(&mut self, node: NodeId) {
+        self.current = node;
+    }
+    /// Moves to the nearest accepted ancestor of the current node within the root.
+    /// # References:
+    /// - <https://dom.spec.whatwg.org/#dom-treewalker-parentnode>
+    pub fn parent_node(
+        &mut self,
+        document: &RefCell<Document>,
+        filter: &mut NodeFilter,
+    ) -> Option<NodeId> {
+        let mut node = self.current;
+        while node != self.root {
+            node = document.borrow().dom().parent(node)?;
+            if filter_node(document, node, self.what_to_show, filter) == FilterResult::Accept {
+                self.current = node;
+                return Some(node);
+            }
+        }
+        None
+    }
+    /// - <https://dom.spec.whatwg.org/#dom-treewalker-firstchild>
+    pub fn first_child(
+        &mut self,
+        document: &RefCell<Document>,
+        filter: &mut NodeFilter,
+    ) -> Option<NodeId> {
+        self.traverse_children(document, filter, true)
+    }
+    /// - <https://dom.spec.whatwg.org/#dom-treewalker-lastchild>
+    pub fn last_child(
+        &mut self,
+        document: &RefCell<Document>,
+        filter: &mut NodeFilter,
+    ) -> Option<NodeId> {
+        self.traverse_children(document, filter, false)
+    }
+    /// - <https://dom.spec.whatwg.org/#dom-treewalker-previoussibling>
+    pub fn previous_sibling(
+        &mut self,
+        document: &RefCell<Document>,
+        filter: &mut NodeFilter,
+    ) -> Option<NodeId> {
+        self.traverse_siblings(document, filter, false)
+    }
+    /// - <https://dom.spec.whatwg.org/#dom-treewalker-nextsibling>
+    pub fn next_sibling(
+        &mut self,
+        document: &RefCell<Document>,
+        filter: &mut NodeFilter,
+    ) -> Option<NodeId> {
+        self.traverse_siblings(document, filter, true)
+    }
+    /// Moves to the previous accepted node in tree order.
+    /// # References:
+    /// - <https://dom.spec.whatwg.org/#dom-treewalker-previousnode>
+    pub fn previous_node(
+        &mut self,
+        document: &RefCell<Document>,
+        filter: &mut NodeFilter,
+    ) -> Option<NodeId> {
+        let mut node = self.current;
+        while node != self.root {
+            let mut sibling = document.borrow().dom().previous_sibling(node);
+            while let Some(s) = sibling {
+                node = s;
+                let mut result = filter_node(document, node, self.what_to_show, filter);
+                while result != FilterResult::Reject {
+                    let last_child = document.borrow().dom().last_child(node);
+                    match last_child {
+                        Some(child) => node = child,
+                        None => break,
+                    }
+                    result = filter_node(document, node, self.what_to_show, filter);
+                }
+                if result == FilterResult::Accept {
+                    self.current = node;
+                    return Some(node);
+                }
+                sibling = document.borrow().dom().previous_sibling(node);
+            }
+            node = document.borrow().dom().parent(node)?;
+            if filter_node(document, node, self.what_to_show, filter) == FilterResult::Accept {
+                self.current = node;
+                return Some(node);
+            }
+        }
+        None
+    }
+    /// Moves to the next accepted node in tree order.
+    /// # References:
+    /// - <https://dom.spec.whatwg.org/#dom-treewalker-nextnode>
+    pub fn next_node(
+        &mut self,
+        document: &RefCell<Document>,
+        filter: &mut NodeFilter,
+    ) -> Option<NodeId> {
+        let mut node = self.current;
+        let mut result = FilterResult::Accept;
+        loop {
+            while result != FilterResult::Reject {
+                let first_child = document.borrow().dom().first_child(node);
+                match first_child {
+                    Some(child) => node = child,
+                    None => break,
+                }
+                result = filter_node(document, node, self.what_to_show, filter);
+                if result == FilterResult::Accept {
+                    self.current = node;
+                    return Some(node);
+                }
+            }
+            node = document
+                .borrow()
+                .dom()
+                .following_skipping_children(node, self.root)?;
+            result = filter_node(document, node, self.what_to_show, filter);
+            if result == FilterResult::Accept {
+                self.current = node;
+                return Some(node);
+            }
+        }
+    }
+    /// # References:
+    /// - <https://dom.spec.whatwg.org/#concept-traverse-children>
+    fn traverse_children(
+        &mut self,
+        document: &RefCell<Document>,
+        filter: &mut NodeFilter,
+        first: bool,
+    ) -> Option<NodeId> {
+        let child = |node: NodeId| match first {
+            true => document.borrow().dom().first_child(node),
+            false => document.borrow().dom().last_child(node),
+        };
+        let sibling = |node: NodeId| match first {
+            true => document.borrow().dom().next_sibling(node),
+            false => document.borrow().dom().previous_sibling(node),
+        };
+        let mut node = child(self.current)?;
+        'outer: loop {
+            match filter_node(document, node, self.what_to_show, filter) {
+                FilterResult::Accept => {
+                    self.current = node;
+                    return Some(node);
+                }
+                FilterResult::Skip => {
+                    if let Some(c) = child(node) {
+                        node = c;
+                        continue;
+                    }
+                }
+                FilterResult::Reject => {}
+            }
+            loop {
+                if let Some(s) = sibling(node) {
+                    node = s;
+                    continue 'outer;
+                }
+                let parent = document.borrow().dom().parent(node)?;
+                if parent == self.root || parent == self.current {
+                    return None;
+                }
+                node = parent;
+            }
+        }
+    }
+    /// # References:
+    /// - <https://dom.spec.whatwg.org/#concept-traverse-siblings>
+    fn traverse_siblings(
+        &mut self,
+        document: &RefCell<Document>,
+        filter: &mut NodeFilter,
+        next: bool,
+    ) -> Option<NodeId> {
+        let child = |node: NodeId| match next {
+            true => document.borrow().dom().first_child(node),
+            false => document.borrow().dom().last_child(node),
+        };
+        let sibling = |node: NodeId| match next {
+            true => document.borrow().dom().next_sibling(node),
+            false => document.borrow().dom().previous_sibling(node),
+        };
+        let mut node = self.current;
+        if node == self.root {
+            return None;
+        }
+        loop {
+            let mut s = sibling(node);
+            while let Some(n) = s {
+                node = n;
+                let result = filter_node(document, node, self.what_to_show, filter);
+                if result == FilterResult::Accept {
+                    self.current = node;
+                    return Some(node);
+                }
+                s = child(node);
+                if result == FilterResult::Reject || s.is_none() {
+                    s = sibling(node);
+                }
+            }
+            node = document.borrow().dom().parent(node)?;
+            if node == self.root {
+                return None;
+            }
+            if filter_node(document, node, self.what_to_show, filter) == FilterResult::Accept {
+                return None;
+            }
+        }
+    }
+}
+
+/// Struct representing a NodeIterator, which iterates over the accepted nodes in the subtree of
+/// its root in tree order. Unlike a TreeWalker, it has a position between two nodes, and it
+/// doesn't skip the descendants of a rejected node.
+/// # References:
+/// - <https://dom.spec.whatwg.org/#interface-nodeiterator>
+#[derive(Debug, Clone)]
+pub struct NodeIterator {
+    root: NodeId,
+    what_to_show: u32,
+    reference: NodeId,
+    pointer_before_reference: bool,
+}
+impl NodeIterator {
+    pub fn new(root: NodeId, what_to_show: u32) -> Self {
+        Self {
+            reference: root,
+            root,
+            what_to_show,
+            pointer_before_reference: true,
+        }
+    }
+    pub fn root(&self) -> NodeId {
+        self.root
+    }
+    pub fn what_to_show(&self) -> u32 {
+        self.what_to_show
+    }
+    pub fn reference_node(&self) -> NodeId {
+        self.reference
+    }
+    pub fn pointer_before_reference_node(&self) -> bool {
+        self.pointer_before_reference
+    }
+    /// - <https://dom.spec.whatwg.org/#dom-nodeiterator-nextnode>
+    pub fn next_node(
+        &mut self,
+        document: &RefCell<Document>,
+        filter: &mut NodeFilter,
+    ) -> Option<NodeId> {
+        self.traverse(document, filter, true)
+    }
+    /// - <https://dom.spec.whatwg.org/#dom-nodeiterator-previousnode>
+    pub fn previous_node(
+        &mut self,
+        document: &RefCell<Document>,
+        filter: &mut NodeFilter,
+    ) -> Option<NodeId> {
+        self.traverse(document, filter, false)
+    }
+    /// Moves the position of the iterator out of `to_be_removed`, which must be called before the
+    /// node is removed from its parent.
+    /// # References:
+    /// - <https://dom.spec.whatwg.org/#nodeiterator-pre-removing-steps>
+    pub fn pre_remove(&mut self, dom: &Dom, to_be_removed: NodeId) {
+        if !dom.is_inclusive_ancestor(to_be_removed, self.reference) || to_be_removed == self.root {
+            return;
+        }
+        if self.pointer_before_reference {
+            match dom.following_skipping_children(to_be_removed, self.root) {
+                Some(next) => {
+                    self.reference = next;
+                    return;
+                }
+                None => self.pointer_before_reference = false,
+            }
+        }
+        self.reference = match dom.previous_sibling(to_be_removed) {
+            Some(sibling) => dom.last_inclusive_descendant(sibling),
+            None => match dom.parent(to_be_removed) {
+                Some(parent) => parent,
+                None => return,
+            },
+        };
+    }
+    /// # References:
+    /// - <https://dom.spec.whatwg.org/#concept-nodeiterator-traverse>
+    fn traverse(
+        &mut self,
+        document: &RefCell<Document>,
+        filter: &mut NodeFilter,
+        next: bool,
+    ) -> Option<NodeId> {
+        let mut node = self.reference;
+        let mut before = self.pointer_before_reference;
+        loop {
+            if next {
+                if before {
+                    before = false;
+                } else {
+                    node = document.borrow().dom().following(node, self.root)?;
+                }
+            } else if before {
+                node = document.borrow().dom().preceding(node, self.root)?;
+            } else {
+                before = true;
+            }
+            if filter_node(document, node, self.what_to_show, filter) == FilterResult::Accept {
+                break;
+            }
+        }
+        self.reference = node;
+        self.pointer_before_reference = before;
+        Some(node)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::dom::api::get_element_by_id;
+    use crate::renderer::dom::api::remove;
+    use crate::renderer::html::parser::HtmlParser;
+    use crate::renderer::html::token::HtmlTokenizer;
+    use alloc::rc::Rc;
+    use alloc::string::String;
+    use alloc::string::ToString;
+    use alloc::vec::Vec;
+    fn parse(html: &str) -> (Rc<RefCell<Document>>, NodeId) {
+        let document = HtmlParser::new(HtmlTokenizer::new(html.to_string())).construct_tree();
+        let node = document.borrow().node();
+        (document, node)
+    }
+    fn by_id(doc: &RefCell<Document>, document: NodeId, id: &str) -> NodeId {
+        get_element_by_id(doc.borrow().dom(), document, id).expect("failed to get a node")
+    }
+    /// Returns the id of an element or the data of a Text node.
+    fn name(doc: &RefCell<Document>, node: NodeId) -> String {
+        match doc.borrow().dom()[node].kind {
+            NodeKind::Element(ref e) => e.get_attribute("id").unwrap_or_default(),
+            NodeKind::Text(ref data) => data.clone(),
+            _ => String::new(),
+        }
+    }
+    const HTML: &str = "<div id=r><p id=a>1<b id=b>2</b></p><p id=c>3</p></div>";
+    #[test]
+    fn test_tree_walker() {
+        let (doc, document) = parse(HTML);
+        let doc = &*doc;
+        let r = by_id(doc, document, "r");
+        let mut walker = TreeWalker::new(r, SHOW_ELEMENT);
+        let mut skip_a = |node: NodeId| match name(doc, node).as_str() {
+            "a" => FilterResult::Skip,
+            _ => FilterResult::Accept,
+        };
+        let step = |node: Option<NodeId>| name(doc, node.expect("failed to move"));
+        assert_eq!("b", step(walker.first_child(doc, &mut skip_a)));
+        assert_eq!("c", step(walker.next_sibling(doc, &mut skip_a)));
+        assert!(walker.next_sibling(doc, &mut skip_a).is_none());
+        assert_eq!("b", step(walker.previous_sibling(doc, &mut skip_a)));
+        assert_eq!("r", step(walker.parent_node(doc, &mut skip_a)));
+        assert!(walker.parent_node(doc, &mut skip_a).is_none());
+        assert_eq!("c", step(walker.last_child(doc, &mut skip_a)));
+        assert_eq!("b", step(walker.previous_node(doc, &mut skip_a)));
+        assert_eq!("r", step(walker.previous_node(doc, &mut skip_a)));
+        assert!(walker.previous_node(doc, &mut skip_a).is_none());
+        let mut reject_a = |node: NodeId| match name(doc, node).as_str() {
+            "a" => FilterResult::Reject,
+            _ => FilterResult::Accept,
+        };
+        assert_eq!("c", step(walker.next_node(doc, &mut reject_a)));
+        assert!(walker.next_node(doc, &mut reject_a).is_none());
+        let mut walker = TreeWalker::new(r, SHOW_ALL);
+        let mut names = Vec::new();
+        while let Some(node) = walker.next_node(doc, &mut accept_all) {
+            names.push(name(doc, node));
+        }
+        assert_eq!(["a", "1", "b", "2", "c", "3"], *names);
+    }
+    #[test]
+    fn test_filter_can_modify_the_tree() {
+        let (doc, document) = parse(HTML);
+        let doc = &*doc;
+        let r = by_id(doc, document, "r");
+        let mut walker = TreeWalker::new(r, SHOW_ELEMENT);
+        let mut remove_b = |node: NodeId| {
+            if name(doc, node) == "b" {
+                remove(doc.borrow_mut().dom_mut(), node);
+            }
+            FilterResult::Accept
+        };
+        let mut names = Vec::new();
+        while let Some(node) = walker.next_node(doc, &mut remove_b) {
+            names.push(name(doc, node));
+        }
+        // The walker stays on the removed node, which has no following nodes.
+        assert_eq!(["a", "b"], *names);
+        assert!(get_element_by_id(doc.borrow().dom(), document, "b").is_none());
+    }
+    #[test]
+    fn test_node_iterator() {
+        let (doc, document) = parse(HTML);
+        let doc = &*doc;
+        let r = by_id(doc, document, "r");
+        let mut iterator = NodeIterator::new(r, SHOW_TEXT);
+        let mut reject_b = |node: NodeId| match name(doc, node).as_str() {
+            "b" => FilterResult::Reject,
+            _ => FilterResult::Accept,
+        };
+        let step = |node: Option<NodeId>| name(doc, node.expect("failed to move"));
+        assert_eq!("1", step(iterator.next_node(doc, &mut reject_b)));
+        // Rejecting an element doesn't skip its descendants.
+        assert_eq!("2", step(iterator.next_node(doc, &mut reject_b)));
+        assert_eq!("2", step(iterator.previous_node(doc, &mut reject_b)));
+        assert_eq!("1", step(iterator.previous_node(doc, &mut reject_b)));
+        assert!(iterator.previous_node(doc, &mut reject_b).is_none());
+        assert_eq!("1", step(iterator.next_node(doc, &mut reject_b)));
+        assert_eq!("2", step(iterator.next_node(doc, &mut reject_b)));
+        let a = by_id(doc, document, "a");
+        iterator.pre_remove(doc.borrow().dom(), a);
+        remove(doc.borrow_mut().dom_mut(), a);
+        assert_eq!(r, iterator.reference_node());
+        assert!(!iterator.pointer_before_reference_node());
+        assert_eq!("3", step(iterator.next_node(doc, &mut reject_b)));
+        assert!(iterator.next_node(doc, &mut reject_b).is_none());
+    }
+}
@@ -0,0 +1,29 @@
+use crate::renderer::dom::document::Document;
+use crate::renderer::js::runtime::JsRuntime;
+use alloc::rc::Rc;
+use core::cell::RefCell;
+
+/// Struct representing a window object. It owns the document shown in it and the JavaScript
+/// runtime whose global scope the scripts of the document share.
+/// # References:
+/// - <https://html.spec.whatwg.org/multipage/nav-history-apis.html#window>
+#[derive(Debug, Clone)]
+pub struct Window {
+    document: Rc<RefCell<Document>>,
+    runtime: JsRuntime,
+}
+impl Window {
+    pub fn new(document: Rc<RefCell<Document>>) -> Self {
+        let runtime = JsRuntime::new(document.clone());
+        Self { document, runtime }
+    }
+    pub fn document(&self) -> &Rc<RefCell<Document>> {
+        &self.document
+    }
+    pub fn runtime(&self) -> &JsRuntime {
+        &self.runtime
+    }
+    pub fn runtime_mut(&mut self) -> &mut JsRuntime {
+        &mut self.runtime
+    }
+}
@@ -1,4 +1,5 @@
 use alloc::string::String;
+use alloc::string::ToString;
 
 /// Struct representing an HTML attribute.
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -23,7 +24,14 @@ impl Attribute {
     pub fn name(&self) -> String {
         self.name.clone()
     }
+    /// Replaces the name, which the parser uses to restore the case of SVG and MathML attributes.
+    pub fn set_name(&mut self, name: &str) {
+        self.name = name.to_string();
+    }
     pub fn value(&self) -> String {
         self.value.clone()
     }
+    pub fn set_value(&mut self, value: &str) {
+        self.value = value.to_string();
+    }
 }
@@ -1,3 +1,5 @@
 pub mod attribute;
+pub mod entity;
 pub mod parser;
+pub mod serializer;
 pub mod token;
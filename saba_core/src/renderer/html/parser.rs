@@ -1,16 +1,481 @@
 use super::attribute::Attribute;
 use super::token::HtmlToken;
+use crate::renderer::diagnostics::ParseError;
+use crate::renderer::diagnostics::SourceLanguage;
+use crate::renderer::diagnostics::SpannedTokenizer;
+use crate::renderer::dom::api::insert;
+use crate::renderer::dom::api::remove;
+use crate::renderer::dom::document::Document;
+use crate::renderer::dom::node::Dom;
 use crate::renderer::dom::node::Element;
 use crate::renderer::dom::node::ElementKind;
+use crate::renderer::dom::node::Namespace;
 use crate::renderer::dom::node::Node;
+use crate::renderer::dom::node::NodeId;
 use crate::renderer::dom::node::NodeKind;
-use crate::renderer::dom::node::Window;
+use crate::renderer::dom::node::QuirksMode;
 use crate::renderer::html::token::HtmlTokenizer;
+use crate::renderer::html::token::State;
 use alloc::rc::Rc;
 use alloc::string::String;
+use alloc::string::ToString;
 use alloc::vec::Vec;
+use core::cell::Ref;
 use core::cell::RefCell;
-use core::str::FromStr;
+use core::cell::RefMut;
+
+/// The public identifier prefixes of DOCTYPEs that put a document in quirks mode.
+/// # References:
+/// - <https://html.spec.whatwg.org/multipage/parsing.html#the-initial-insertion-mode>
+static QUIRKS_PUBLIC_ID_PREFIXES: [&str; 55] = [
+    "+//silmaril//dtd html pro v0r11 19970101//",
+    "-//as//dtd html 3.0 aswedit + extensions//",
+    "-//advasoft ltd//dtd html 3.0 aswedit + extensions//",
+    "-//ietf//dtd html 2.0 level 1//",
+    "-//ietf//dtd html 2.0 level 2//",
+    "-//ietf//dtd html 2.0 strict level 1//",
+    "-//ietf//dtd html 2.0 strict level 2//",
+    "-//ietf//dtd html 2.0 strict//",
+    "-//ietf//dtd html 2.0//",
+    "-//ietf//dtd html 2.1e//",
+    "-//ietf//dtd html 3.0//",
+    "-//ietf//dtd html 3.2 final//",
+    "-//ietf//dtd html 3.2//",
+    "-//ietf//dtd html 3//",
+    "-//ietf//dtd html level 0//",
+    "-//ietf//dtd html level 1//",
+    "-//ietf//dtd html level 2//",
+    "-//ietf//dtd html level 3//",
+    "-//ietf//dtd html strict level 0//",
+    "-//ietf//dtd html strict level 1//",
+    "-//ietf//dtd html strict level 2//",
+    "-//ietf//dtd html strict level 3//",
+    "-//ietf//dtd html strict//",
+    "-//ietf//dtd html//",
+    "-//metrius//dtd metrius presentational//",
+    "-//microsoft//dtd internet explorer 2.0 html strict//",
+    "-//microsoft//dtd internet explorer 2.0 html//",
+    "-//microsoft//dtd internet explorer 2.0 tables//",
+    "-//microsoft//dtd internet explorer 3.0 html strict//",
+    "-//microsoft//dtd internet explorer 3.0 html//",
+    "-//microsoft//dtd internet explorer 3.0 tables//",
+    "-//netscape comm. corp.//dtd html//",
+    "-//netscape comm. corp.//dtd strict html//",
+    "-//o'reilly and associates//dtd html 2.0//",
+    "-//o'reilly and associates//dtd html extended 1.0//",
+    "-//o'reilly and associates//dtd html extended relaxed 1.0//",
+    "-//sq//dtd html 2.0 hotmetal + extensions//",
+    "-//softquad software//dtd hotmetal pro 6.0::19990601::extensions to html 4.0//",
+    "-//softquad//dtd hotmetal pro 4.0::19971010::extensions to html 4.0//",
+    "-//spyglass//dtd html 2.0 extended//",
+    "-//sun microsystems corp.//dtd hotjava html//",
+    "-//sun microsystems corp.//dtd hotjava strict html//",
+    "-//w3c//dtd html 3 1995-03-24//",
+    "-//w3c//dtd html 3.2 draft//",
+    "-//w3c//dtd html 3.2 final//",
+    "-//w3c//dtd html 3.2//",
+    "-//w3c//dtd html 3.2s draft//",
+    "-//w3c//dtd html 4.0 frameset//",
+    "-//w3c//dtd html 4.0 transitional//",
+    "-//w3c//dtd html experimental 19960712//",
+    "-//w3c//dtd html experimental 970421//",
+    "-//w3c//dtd w3 html//",
+    "-//w3o//dtd w3 html 3.0//",
+    "-//webtechs//dtd mozilla html 2.0//",
+    "-//webtechs//dtd mozilla html//",
+];
+
+/// The elements whose end tags are implied by the start and end tags of other elements.
+/// # References:
+/// - <https://html.spec.whatwg.org/multipage/parsing.html#generate-implied-end-tags>
+static IMPLIED_END_TAGS: [&str; 10] = [
+    "dd", "dt", "li", "optgroup", "option", "p", "rb", "rp", "rt", "rtc",
+];
+
+/// The elements whose end tags are implied when a `template` element is closed.
+/// # References:
+/// - <https://html.spec.whatwg.org/multipage/parsing.html#generate-all-implied-end-tags-thoroughly>
+static IMPLIED_END_TAGS_THOROUGHLY: [&str; 18] = [
+    "caption", "colgroup", "dd", "dt", "li", "optgroup", "option", "p", "rb", "rp", "rt", "rtc",
+    "tbody", "td", "tfoot", "th", "thead", "tr",
+];
+
+/// The HTML elements in the special category.
+/// # References:
+/// - <https://html.spec.whatwg.org/multipage/parsing.html#special>
+static SPECIAL_ELEMENTS: [&str; 83] = [
+    "address",
+    "applet",
+    "area",
+    "article",
+    "aside",
+    "base",
+    "basefont",
+    "bgsound",
+    "blockquote",
+    "body",
+    "br",
+    "button",
+    "caption",
+    "center",
+    "col",
+    "colgroup",
+    "dd",
+    "details",
+    "dir",
+    "div",
+    "dl",
+    "dt",
+    "embed",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "frame",
+    "frameset",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "head",
+    "header",
+    "hgroup",
+    "hr",
+    "html",
+    "iframe",
+    "img",
+    "input",
+    "keygen",
+    "li",
+    "link",
+    "listing",
+    "main",
+    "marquee",
+    "menu",
+    "meta",
+    "nav",
+    "noembed",
+    "noframes",
+    "noscript",
+    "object",
+    "ol",
+    "p",
+    "param",
+    "plaintext",
+    "pre",
+    "script",
+    "search",
+    "section",
+    "select",
+    "source",
+    "style",
+    "summary",
+    "table",
+    "tbody",
+    "td",
+    "template",
+    "textarea",
+    "tfoot",
+    "th",
+    "thead",
+    "title",
+    "tr",
+    "track",
+    "ul",
+    "wbr",
+    "xmp",
+];
+
+/// The start tags that close the open SVG and MathML elements in foreign content.
+/// # References:
+/// - <https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inforeign>
+static FOREIGN_BREAKOUT_TAGS: [&str; 44] = [
+    "b",
+    "big",
+    "blockquote",
+    "body",
+    "br",
+    "center",
+    "code",
+    "dd",
+    "div",
+    "dl",
+    "dt",
+    "em",
+    "embed",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "head",
+    "hr",
+    "i",
+    "img",
+    "li",
+    "listing",
+    "menu",
+    "meta",
+    "nobr",
+    "ol",
+    "p",
+    "pre",
+    "ruby",
+    "s",
+    "small",
+    "span",
+    "strong",
+    "strike",
+    "sub",
+    "sup",
+    "table",
+    "tt",
+    "u",
+    "ul",
+    "var",
+];
+
+/// The SVG tag names whose case is restored from the lowercased tag names of tokens.
+/// # References:
+/// - <https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inforeign>
+static SVG_TAG_NAMES: [(&str, &str); 37] = [
+    ("altglyph", "altGlyph"),
+    ("altglyphdef", "altGlyphDef"),
+    ("altglyphitem", "altGlyphItem"),
+    ("animatecolor", "animateColor"),
+    ("animatemotion", "animateMotion"),
+    ("animatetransform", "animateTransform"),
+    ("clippath", "clipPath"),
+    ("feblend", "feBlend"),
+    ("fecolormatrix", "feColorMatrix"),
+    ("fecomponenttransfer", "feComponentTransfer"),
+    ("fecomposite", "feComposite"),
+    ("feconvolvematrix", "feConvolveMatrix"),
+    ("fediffuselighting", "feDiffuseLighting"),
+    ("fedisplacementmap", "feDisplacementMap"),
+    ("fedistantlight", "feDistantLight"),
+    ("fedropshadow", "feDropShadow"),
+    ("feflood", "feFlood"),
+    ("fefunca", "feFuncA"),
+    ("fefuncb", "feFuncB"),
+    ("fefuncg", "feFuncG"),
+    ("fefuncr", "feFuncR"),
+    ("fegaussianblur", "feGaussianBlur"),
+    ("feimage", "feImage"),
+    ("femerge", "feMerge"),
+    ("femergenode", "feMergeNode"),
+    ("femorphology", "feMorphology"),
+    ("feoffset", "feOffset"),
+    ("fepointlight", "fePointLight"),
+    ("fespecularlighting", "feSpecularLighting"),
+    ("fespotlight", "feSpotLight"),
+    ("fetile", "feTile"),
+    ("feturbulence", "feTurbulence"),
+    ("foreignobject", "foreignObject"),
+    ("glyphref", "glyphRef"),
+    ("lineargradient", "linearGradient"),
+    ("radialgradient", "radialGradient"),
+    ("textpath", "textPath"),
+];
+
+/// The SVG attribute names whose case is restored from the lowercased names of tokens.
+/// # References:
+/// - <https://html.spec.whatwg.org/multipage/parsing.html#adjust-svg-attributes>
+static SVG_ATTRIBUTE_NAMES: [(&str, &str); 58] = [
+    ("attributename", "attributeName"),
+    ("attributetype", "attributeType"),
+    ("basefrequency", "baseFrequency"),
+    ("baseprofile", "baseProfile"),
+    ("calcmode", "calcMode"),
+    ("clippathunits", "clipPathUnits"),
+    ("diffuseconstant", "diffuseConstant"),
+    ("edgemode", "edgeMode"),
+    ("filterunits", "filterUnits"),
+    ("glyphref", "glyphRef"),
+    ("gradienttransform", "gradientTransform"),
+    ("gradientunits", "gradientUnits"),
+    ("kernelmatrix", "kernelMatrix"),
+    ("kernelunitlength", "kernelUnitLength"),
+    ("keypoints", "keyPoints"),
+    ("keysplines", "keySplines"),
+    ("keytimes", "keyTimes"),
+    ("lengthadjust", "lengthAdjust"),
+    ("limitingconeangle", "limitingConeAngle"),
+    ("markerheight", "markerHeight"),
+    ("markerunits", "markerUnits"),
+    ("markerwidth", "markerWidth"),
+    ("maskcontentunits", "maskContentUnits"),
+    ("maskunits", "maskUnits"),
+    ("numoctaves", "numOctaves"),
+    ("pathlength", "pathLength"),
+    ("patterncontentunits", "patternContentUnits"),
+    ("patterntransform", "patternTransform"),
+    ("patternunits", "patternUnits"),
+    ("pointsatx", "pointsAtX"),
+    ("pointsaty", "pointsAtY"),
+    ("pointsatz", "pointsAtZ"),
+    ("preservealpha", "preserveAlpha"),
+    ("preserveaspectratio", "preserveAspectRatio"),
+    ("primitiveunits", "primitiveUnits"),
+    ("refx", "refX"),
+    ("refy", "refY"),
+    ("repeatcount", "repeatCount"),
+    ("repeatdur", "repeatDur"),
+    ("requiredextensions", "requiredExtensions"),
+    ("requiredfeatures", "requiredFeatures"),
+    ("specularconstant", "specularConstant"),
+    ("specularexponent", "specularExponent"),
+    ("spreadmethod", "spreadMethod"),
+    ("startoffset", "startOffset"),
+    ("stddeviation", "stdDeviation"),
+    ("stitchtiles", "stitchTiles"),
+    ("surfacescale", "surfaceScale"),
+    ("systemlanguage", "systemLanguage"),
+    ("tablevalues", "tableValues"),
+    ("targetx", "targetX"),
+    ("targety", "targetY"),
+    ("textlength", "textLength"),
+    ("viewbox", "viewBox"),
+    ("viewtarget", "viewTarget"),
+    ("xchannelselector", "xChannelSelector"),
+    ("ychannelselector", "yChannelSelector"),
+    ("zoomandpan", "zoomAndPan"),
+];
+
+fn is_whitespace(c: char) -> bool {
+    matches!(c, '\t' | '\n' | '\x0C' | '\r' | ' ')
+}
+
+/// Returns true if `node` is an HTML element whose local name is one of `names`.
+fn is_html_element(dom: &Dom, node: NodeId, names: &[&str]) -> bool {
+    match dom[node].kind {
+        NodeKind::Element(ref e) => {
+            e.namespace() == Namespace::Html && names.contains(&e.local_name().as_str())
+        }
+        _ => false,
+    }
+}
+
+/// Returns true if `element` is in the special category, which stops the search for a matching
+/// element when an end tag without specific handling is processed.
+/// # References:
+/// - <https://html.spec.whatwg.org/multipage/parsing.html#special>
+fn is_special(element: &Element) -> bool {
+    let name = element.local_name();
+    match element.namespace() {
+        Namespace::Html => SPECIAL_ELEMENTS.contains(&name.as_str()),
+        Namespace::MathMl => matches!(
+            name.as_str(),
+            "mi" | "mo" | "mn" | "ms" | "mtext" | "annotation-xml"
+        ),
+        Namespace::Svg => matches!(name.as_str(), "foreignObject" | "desc" | "title"),
+    }
+}
+
+/// Returns true if `element` is a MathML text integration point, whose children are parsed as
+/// HTML.
+/// # References:
+/// - <https://html.spec.whatwg.org/multipage/parsing.html#mathml-text-integration-point>
+fn is_mathml_text_integration_point(element: &Element) -> bool {
+    element.namespace() == Namespace::MathMl
+        && matches!(
+            element.local_name().as_str(),
+            "mi" | "mo" | "mn" | "ms" | "mtext"
+        )
+}
+
+/// Returns true if `element` is an HTML integration point, whose children are parsed as HTML.
+/// # References:
+/// - <https://html.spec.whatwg.org/multipage/parsing.html#html-integration-point>
+fn is_html_integration_point(element: &Element) -> bool {
+    match element.namespace() {
+        Namespace::MathMl => {
+            element.local_name() == "annotation-xml"
+                && element.get_attribute("encoding").is_some_and(|encoding| {
+                    encoding.eq_ignore_ascii_case("text/html")
+                        || encoding.eq_ignore_ascii_case("application/xhtml+xml")
+                })
+        }
+        Namespace::Svg => matches!(
+            element.local_name().as_str(),
+            "foreignObject" | "desc" | "title"
+        ),
+        Namespace::Html => false,
+    }
+}
+
+/// Restores the case of the attribute names of an SVG or MathML element.
+/// # References:
+/// - <https://html.spec.whatwg.org/multipage/parsing.html#adjust-svg-attributes>
+/// - <https://html.spec.whatwg.org/multipage/parsing.html#adjust-mathml-attributes>
+fn adjust_foreign_attributes(namespace: Namespace, attributes: &[Attribute]) -> Vec<Attribute> {
+    let mut attributes = attributes.to_vec();
+    for attribute in attributes.iter_mut() {
+        let name = attribute.name();
+        let adjusted = match namespace {
+            Namespace::Svg => SVG_ATTRIBUTE_NAMES
+                .iter()
+                .find(|(lower, _)| *lower == name)
+                .map(|(_, adjusted)| *adjusted),
+            Namespace::MathMl if name == "definitionurl" => Some("definitionURL"),
+            _ => None,
+        };
+        if let Some(adjusted) = adjusted {
+            attribute.set_name(adjusted);
+        }
+    }
+    attributes
+}
+
+/// The heading elements, which close each other.
+static HEADINGS: [&str; 6] = ["h1", "h2", "h3", "h4", "h5", "h6"];
+
+/// Returns the mode of a document with the given DOCTYPE.
+/// # Parameters
+/// - `name`: The name of the DOCTYPE, if any.
+/// - `public_id`: The public identifier of the DOCTYPE, if any.
+/// - `system_id`: The system identifier of the DOCTYPE, if any.
+/// - `force_quirks`: The force-quirks flag of the DOCTYPE token.
+/// # References:
+/// - <https://html.spec.whatwg.org/multipage/parsing.html#the-initial-insertion-mode>
+pub fn quirks_mode_for_doctype(
+    name: &Option<String>,
+    public_id: &Option<String>,
+    system_id: &Option<String>,
+    force_quirks: bool,
+) -> QuirksMode {
+    let public = public_id.as_ref().map(|id| id.to_ascii_lowercase());
+    let system = system_id.as_ref().map(|id| id.to_ascii_lowercase());
+    let public_starts_with = |prefix: &str| public.as_ref().is_some_and(|p| p.starts_with(prefix));
+    if force_quirks
+        || name.as_deref() != Some("html")
+        || public.as_deref() == Some("-//w3o//dtd w3 html strict 3.0//en//")
+        || public.as_deref() == Some("-/w3c/dtd html 4.0 transitional/en")
+        || public.as_deref() == Some("html")
+        || system.as_deref() == Some("http://www.ibm.com/data/dtd/v11/ibmxhtml1-transitional.dtd")
+        || QUIRKS_PUBLIC_ID_PREFIXES
+            .iter()
+            .any(|prefix| public_starts_with(prefix))
+        || (system.is_none()
+            && (public_starts_with("-//w3c//dtd html 4.01 frameset//")
+                || public_starts_with("-//w3c//dtd html 4.01 transitional//")))
+    {
+        return QuirksMode::Quirks;
+    }
+    if public_starts_with("-//w3c//dtd xhtml 1.0 frameset//")
+        || public_starts_with("-//w3c//dtd xhtml 1.0 transitional//")
+        || (system.is_some()
+            && (public_starts_with("-//w3c//dtd html 4.01 frameset//")
+                || public_starts_with("-//w3c//dtd html 4.01 transitional//")))
+    {
+        return QuirksMode::LimitedQuirks;
+    }
+    QuirksMode::NoQuirks
+}
 
 /// Enum representing the insertion mode of the HTML parser.
 /// # References:
@@ -21,570 +486,2570 @@ pub enum InsertionMode {
     BeforeHtml,
     BeforeHead,
     InHead,
+    InHeadNoscript,
     AfterHead,
     InBody,
     Text,
+    InTable,
+    InTableText,
+    InCaption,
+    InColumnGroup,
+    InTableBody,
+    InRow,
+    InCell,
+    InSelect,
+    InSelectInTable,
+    InTemplate,
     AfterBody,
+    InFrameset,
+    AfterFrameset,
     AfterAfterBody,
+    AfterAfterFrameset,
 }
+
+/// Enum representing the kind of scope in which an element is looked up in the stack of open
+/// elements.
+/// # References:
+/// - <https://html.spec.whatwg.org/multipage/parsing.html#has-an-element-in-the-specific-scope>
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Scope {
+    Default,
+    ListItem,
+    Button,
+    Table,
+    Select,
+}
+impl Scope {
+    /// Returns true if `element` bounds the scope, which hides the elements below it.
+    fn is_boundary(&self, element: &Element) -> bool {
+        let name = element.local_name();
+        match element.namespace() {
+            Namespace::Html if *self == Scope::Select => {
+                !matches!(name.as_str(), "optgroup" | "option")
+            }
+            Namespace::Html if *self == Scope::Table => {
+                matches!(name.as_str(), "html" | "table" | "template")
+            }
+            Namespace::Html => {
+                matches!(
+                    name.as_str(),
+                    "applet"
+                        | "caption"
+                        | "html"
+                        | "table"
+                        | "td"
+                        | "th"
+                        | "marquee"
+                        | "object"
+                        | "template"
+                ) || (*self == Scope::ListItem && matches!(name.as_str(), "ol" | "ul"))
+                    || (*self == Scope::Button && name == "button")
+            }
+            _ if matches!(*self, Scope::Table | Scope::Select) => *self == Scope::Select,
+            Namespace::MathMl => matches!(
+                name.as_str(),
+                "mi" | "mo" | "mn" | "ms" | "mtext" | "annotation-xml"
+            ),
+            Namespace::Svg => matches!(name.as_str(), "foreignObject" | "desc" | "title"),
+        }
+    }
+}
+
+/// Enum representing an entry in the list of active formatting elements.
+/// # References:
+/// - <https://html.spec.whatwg.org/multipage/parsing.html#list-of-active-formatting-elements>
+#[derive(Debug, Clone)]
+enum FormattingEntry {
+    /// A marker, which keeps formatting elements from leaking into cells, captions, templates and
+    /// objects.
+    Marker,
+    Element(NodeId),
+}
+
 #[derive(Debug, Clone)]
 pub struct HtmlParser {
-    window: Rc<RefCell<Window>>,
+    document: Rc<RefCell<Document>>,
     mode: InsertionMode,
     original_insertion_mode: InsertionMode,
-    stack_of_open_elements: Vec<Rc<RefCell<Node>>>,
+    template_insertion_modes: Vec<InsertionMode>,
+    stack_of_open_elements: Vec<NodeId>,
+    active_formatting_elements: Vec<FormattingEntry>,
+    head_element: Option<NodeId>,
+    form_element: Option<NodeId>,
+    /// Whether a `frameset` start tag can still replace the body.
+    frameset_ok: bool,
+    /// Whether misnested content in a table is inserted before the table.
+    foster_parenting: bool,
+    /// The characters in a table, which are foster parented if any of them is not whitespace.
+    pending_table_characters: String,
+    /// Whether a line feed right after a `pre`, `listing` or `textarea` start tag is dropped.
+    ignore_line_feed: bool,
+    /// The context element when parsing a fragment, such as the element whose `innerHTML` is set.
+    context: Option<NodeId>,
+    /// The `script` element whose end tag was just processed, which the page runs before the
+    /// parser continues.
+    pending_script: Option<NodeId>,
     t: HtmlTokenizer,
+    errors: Vec<ParseError>,
 }
 impl HtmlParser {
     pub fn new(t: HtmlTokenizer) -> Self {
+        Self::with_document(t, Rc::new(RefCell::new(Document::new())))
+    }
+    /// Returns a parser that builds the tree of `document`, such as a document whose URL and
+    /// encoding are already known from the response.
+    pub fn with_document(t: HtmlTokenizer, document: Rc<RefCell<Document>>) -> Self {
         Self {
-            window: Rc::new(RefCell::new(Window::new())),
+            document,
             mode: InsertionMode::Initial,
             original_insertion_mode: InsertionMode::Initial,
+            template_insertion_modes: Vec::new(),
             stack_of_open_elements: Vec::new(),
+            active_formatting_elements: Vec::new(),
+            head_element: None,
+            form_element: None,
+            frameset_ok: true,
+            foster_parenting: false,
+            pending_table_characters: String::new(),
+            ignore_line_feed: false,
+            context: None,
+            pending_script: None,
             t,
+            errors: Vec::new(),
+        }
+    }
+    /// Parses `markup` as the children of `context`, like `innerHTML`. The context element decides
+    /// how the markup is tokenized and which insertion mode it starts in, but it is not changed.
+    /// The nodes are built in the tree of `document` under a `Document` node of their own.
+    /// # Parameters
+    /// - `document`: The document that `context` belongs to.
+    /// - `context`: The element that the parsed nodes are going to be inserted into.
+    /// - `markup`: The HTML fragment.
+    /// # Returns
+    /// - The parsed nodes, which are not in any tree.
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/parsing.html#html-fragment-parsing-algorithm>
+    pub fn parse_fragment(document: &mut Document, context: NodeId, markup: &str) -> Vec<NodeId> {
+        // The parser shares the nodes through a cell, so they are moved into a document of the
+        // parser for the parsing and moved back afterwards.
+        let mut fragment = Document::with_dom(core::mem::take(document.dom_mut()));
+        fragment.set_quirks_mode(document.quirks_mode());
+        let shared = Rc::new(RefCell::new(fragment));
+        let children = Self::parse_fragment_in(&shared, context, markup);
+        *document.dom_mut() = core::mem::take(shared.borrow_mut().dom_mut());
+        children
+    }
+    fn parse_fragment_in(
+        document: &Rc<RefCell<Document>>,
+        context: NodeId,
+        markup: &str,
+    ) -> Vec<NodeId> {
+        let mut parser =
+            HtmlParser::with_document(HtmlTokenizer::new(markup.to_string()), document.clone());
+        let element = parser.element(context);
+        if let Some(ref e) = element.filter(|e| e.namespace() == Namespace::Html) {
+            let state = match e.local_name().as_str() {
+                "title" | "textarea" => Some(State::Rcdata),
+                "style" | "xmp" | "iframe" | "noembed" | "noframes" => Some(State::Rawtext),
+                "script" => Some(State::ScriptData),
+                "plaintext" => Some(State::Plaintext),
+                _ => None,
+            };
+            if let Some(state) = state {
+                parser.t.switch_to(state);
+            }
+        }
+        let html = parser.insert_element("html", Vec::new());
+        parser.context = Some(context);
+        if parser.is_html_element(context, &["template"]) {
+            parser
+                .template_insertion_modes
+                .push(InsertionMode::InTemplate);
+        }
+        parser.reset_insertion_mode_appropriately();
+        // A form control in the fragment belongs to the form that contains the context.
+        let ancestors: Vec<NodeId> = parser.dom().inclusive_ancestors(context).collect();
+        parser.form_element = ancestors
+            .into_iter()
+            .find(|&n| parser.is_html_element(n, &["form"]));
+        parser.construct_tree();
+        let mut dom = parser.dom_mut();
+        let children: Vec<NodeId> = dom.children(html).collect();
+        for &child in &children {
+            remove(&mut dom, child);
+        }
+        children
+    }
+    /// Returns the parse errors of the tokenizer and the tree construction, in source order.
+    pub fn errors(&self) -> Vec<ParseError> {
+        let mut errors = self.t.errors();
+        errors.extend(self.errors.iter().cloned());
+        errors.sort_by_key(|e| e.span().start());
+        errors
+    }
+    /// Records a parse error at the token that was consumed last.
+    fn parse_error(&mut self, code: &str) {
+        self.errors
+            .push(ParseError::new(SourceLanguage::Html, code, self.t.span()));
+    }
+    /// Records a parse error for a token that is not allowed in the current insertion mode.
+    fn unexpected_token(&mut self, token: &HtmlToken) {
+        let code = match token {
+            HtmlToken::StartTag { .. } => "unexpected-start-tag",
+            HtmlToken::EndTag { .. } => "unexpected-end-tag",
+            HtmlToken::Char('\0') => "unexpected-null-character",
+            HtmlToken::Char(_) => "unexpected-character",
+            HtmlToken::Doctype { .. } => "unexpected-doctype",
+            HtmlToken::Comment(_) | HtmlToken::Eof => "unexpected-token",
+        };
+        self.parse_error(code);
+    }
+    /// Borrows the nodes of the document. The borrow must not be held across the processing of a
+    /// token, because scripts modify the nodes between tokens.
+    fn dom(&self) -> Ref<'_, Dom> {
+        Ref::map(self.document.borrow(), |d| d.dom())
+    }
+    fn dom_mut(&self) -> RefMut<'_, Dom> {
+        RefMut::map(self.document.borrow_mut(), |d| d.dom_mut())
+    }
+    /// Returns the `Document` node, which is the root of the tree.
+    fn document_node(&self) -> NodeId {
+        self.document.borrow().node()
+    }
+    /// Returns a copy of the element of `node`, if it is an element.
+    fn element(&self, node: NodeId) -> Option<Element> {
+        self.dom()[node].get_element().cloned()
+    }
+    /// Returns true if `node` is an HTML element whose local name is one of `names`.
+    fn is_html_element(&self, node: NodeId, names: &[&str]) -> bool {
+        is_html_element(&self.dom(), node, names)
+    }
+    /// Returns true if `node` is an element in the special category.
+    fn is_special(&self, node: NodeId) -> bool {
+        self.dom()[node].get_element().is_some_and(is_special)
+    }
+    fn insert(&self, parent: NodeId, node: NodeId, reference: Option<NodeId>) {
+        insert(&mut self.dom_mut(), parent, node, reference);
+    }
+    fn remove(&self, node: NodeId) {
+        remove(&mut self.dom_mut(), node);
+    }
+    fn current_node(&self) -> Option<NodeId> {
+        self.stack_of_open_elements.last().copied()
+    }
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/parsing.html#adjusted-current-node>
+    fn adjusted_current_node(&self) -> Option<NodeId> {
+        match self.context {
+            Some(context) if self.stack_of_open_elements.len() == 1 => Some(context),
+            _ => self.current_node(),
+        }
+    }
+    /// Returns true if the current node is an HTML element whose local name is one of `names`.
+    fn current_node_is(&self, names: &[&str]) -> bool {
+        self.current_node()
+            .is_some_and(|n| self.is_html_element(n, names))
+    }
+    /// Returns true if an HTML element whose local name is one of `names` is open.
+    fn contain_in_stack(&self, names: &[&str]) -> bool {
+        self.stack_of_open_elements
+            .iter()
+            .any(|&n| self.is_html_element(n, names))
+    }
+    fn stack_position(&self, node: NodeId) -> Option<usize> {
+        self.stack_of_open_elements.iter().position(|&n| n == node)
+    }
+    /// Returns true if an HTML element whose local name is one of `names` is open above the
+    /// nearest element that bounds `scope`.
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/parsing.html#has-an-element-in-the-specific-scope>
+    fn has_element_in_scope(&self, names: &[&str], scope: Scope) -> bool {
+        let dom = self.dom();
+        for &node in self.stack_of_open_elements.iter().rev() {
+            if is_html_element(&dom, node, names) {
+                return true;
+            }
+            match dom[node].get_element() {
+                Some(e) if !scope.is_boundary(e) => {}
+                _ => return false,
+            }
+        }
+        false
+    }
+    /// Returns true if `target` is open above the nearest element that bounds `scope`.
+    fn has_node_in_scope(&self, target: NodeId, scope: Scope) -> bool {
+        let dom = self.dom();
+        for &node in self.stack_of_open_elements.iter().rev() {
+            if node == target {
+                return true;
+            }
+            match dom[node].get_element() {
+                Some(e) if !scope.is_boundary(e) => {}
+                _ => return false,
+            }
+        }
+        false
+    }
+    /// Pops elements until an HTML element whose local name is one of `names` has been popped.
+    fn pop_until(&mut self, names: &[&str]) {
+        while let Some(node) = self.stack_of_open_elements.pop() {
+            if self.is_html_element(node, names) {
+                return;
+            }
+        }
+    }
+    /// Pops elements until `target` has been popped.
+    fn pop_until_node(&mut self, target: NodeId) {
+        while let Some(node) = self.stack_of_open_elements.pop() {
+            if node == target {
+                return;
+            }
+        }
+    }
+    /// Pops the elements whose end tags can be omitted, except an element named `except`.
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/parsing.html#generate-implied-end-tags>
+    fn generate_implied_end_tags(&mut self, except: Option<&str>) {
+        while self.current_node_is(&IMPLIED_END_TAGS)
+            && !except.is_some_and(|name| self.current_node_is(&[name]))
+        {
+            self.stack_of_open_elements.pop();
+        }
+    }
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/parsing.html#generate-all-implied-end-tags-thoroughly>
+    fn generate_all_implied_end_tags_thoroughly(&mut self) {
+        while self.current_node_is(&IMPLIED_END_TAGS_THOROUGHLY) {
+            self.stack_of_open_elements.pop();
+        }
+    }
+    /// Closes the `p` element in button scope, which is implied by the start tag of a block.
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/parsing.html#close-a-p-element>
+    fn close_p_element(&mut self) {
+        self.generate_implied_end_tags(Some("p"));
+        if !self.current_node_is(&["p"]) {
+            self.parse_error("unexpected-end-tag");
+        }
+        self.pop_until(&["p"]);
+    }
+    /// Closes the `p` element in button scope if there is one.
+    fn close_p_element_in_button_scope(&mut self) {
+        if self.has_element_in_scope(&["p"], Scope::Button) {
+            self.close_p_element();
+        }
+    }
+    /// Pops elements until the current node is `html` or an HTML element whose local name is one
+    /// of `names`.
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/parsing.html#clear-the-stack-back-to-a-table-context>
+    fn clear_stack_back_to(&mut self, names: &[&str]) {
+        while !self.current_node_is(names) && !self.current_node_is(&["html"]) {
+            if self.stack_of_open_elements.pop().is_none() {
+                return;
+            }
+        }
+    }
+    /// Resets the insertion mode from the elements in the stack of open elements, after the
+    /// element that decided the mode has been popped.
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/parsing.html#reset-the-insertion-mode-appropriately>
+    fn reset_insertion_mode_appropriately(&mut self) {
+        for i in (0..self.stack_of_open_elements.len()).rev() {
+            let last = i == 0;
+            let node = match self.context {
+                Some(context) if last => context,
+                _ => self.stack_of_open_elements[i],
+            };
+            let name = match self.element(node) {
+                Some(e) if e.namespace() == Namespace::Html => e.local_name(),
+                _ => String::new(),
+            };
+            self.mode = match name.as_str() {
+                "select" => {
+                    let in_table = !last
+                        && self.stack_of_open_elements[1..i]
+                            .iter()
+                            .rev()
+                            .take_while(|&&n| !self.is_html_element(n, &["template"]))
+                            .any(|&n| self.is_html_element(n, &["table"]));
+                    match in_table {
+                        true => InsertionMode::InSelectInTable,
+                        false => InsertionMode::InSelect,
+                    }
+                }
+                "td" | "th" if !last => InsertionMode::InCell,
+                "tr" => InsertionMode::InRow,
+                "tbody" | "thead" | "tfoot" => InsertionMode::InTableBody,
+                "caption" => InsertionMode::InCaption,
+                "colgroup" => InsertionMode::InColumnGroup,
+                "table" => InsertionMode::InTable,
+                "template" => match self.template_insertion_modes.last() {
+                    Some(mode) => *mode,
+                    None => InsertionMode::InTemplate,
+                },
+                "head" if !last => InsertionMode::InHead,
+                "body" => InsertionMode::InBody,
+                "frameset" => InsertionMode::InFrameset,
+                "html" => match self.head_element {
+                    Some(_) => InsertionMode::AfterHead,
+                    None => InsertionMode::BeforeHead,
+                },
+                _ if last => InsertionMode::InBody,
+                _ => continue,
+            };
+            return;
+        }
+    }
+    fn create_char(&self, c: char) -> Node {
+        let mut s = String::new();
+        s.push(c);
+        Node::new(NodeKind::Text(s))
+    }
+    /// Returns the parent and the following sibling of a node to be inserted. The insertion place
+    /// is the end of `override_target` or of the current node, except that misnested content in
+    /// a table goes before the table while foster parenting is enabled.
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/parsing.html#appropriate-place-for-inserting-a-node>
+    fn appropriate_insertion_place(
+        &self,
+        override_target: Option<NodeId>,
+    ) -> (NodeId, Option<NodeId>) {
+        let target = match override_target.or_else(|| self.current_node()) {
+            Some(n) => n,
+            None => return (self.document_node(), None),
+        };
+        let (parent, reference) = if self.foster_parenting
+            && self.is_html_element(target, &["table", "tbody", "tfoot", "thead", "tr"])
+        {
+            self.foster_parenting_place()
+        } else {
+            (target, None)
+        };
+        // The children of `template` go into its template contents.
+        let contents = match self.is_html_element(parent, &["template"]) {
+            true => self.dom()[parent].template_contents(),
+            false => None,
+        };
+        match contents {
+            Some(contents) => (contents, None),
+            None => (parent, reference),
+        }
+    }
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/parsing.html#foster-parent>
+    fn foster_parenting_place(&self) -> (NodeId, Option<NodeId>) {
+        let stack = &self.stack_of_open_elements;
+        let last_template = stack
+            .iter()
+            .rposition(|&n| self.is_html_element(n, &["template"]));
+        let last_table = stack
+            .iter()
+            .rposition(|&n| self.is_html_element(n, &["table"]));
+        match (last_template, last_table) {
+            (Some(template), Some(table)) if template > table => (stack[template], None),
+            (Some(template), None) => (stack[template], None),
+            (_, None) => (stack[0], None),
+            (_, Some(table)) => match self.dom().parent(stack[table]) {
+                Some(parent) => (parent, Some(stack[table])),
+                None => (stack[table - 1], None),
+            },
+        }
+    }
+    /// Inserts a character at the appropriate place, appending it to the text node that is
+    /// there already.
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/parsing.html#insert-a-character>
+    fn insert_character(&mut self, c: char) {
+        let (parent, reference) = self.appropriate_insertion_place(None);
+        let mut dom = self.dom_mut();
+        // The document cannot have text children.
+        if matches!(dom[parent].kind, NodeKind::Document) {
+            return;
+        }
+        let previous = match reference {
+            Some(r) => dom.previous_sibling(r),
+            None => dom.last_child(parent),
+        };
+        if let Some(previous) = previous {
+            if let NodeKind::Text(ref mut s) = dom[previous].kind {
+                s.push(c);
+                return;
+            }
+        }
+        let node = dom.alloc(self.create_char(c));
+        insert(&mut dom, parent, node, reference);
+    }
+    /// Creates an element. A `template` element gets a document fragment for its contents.
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/parsing.html#create-an-element-for-the-token>
+    fn create_element(
+        &self,
+        namespace: Namespace,
+        tag: &str,
+        attributes: Vec<Attribute>,
+    ) -> NodeId {
+        let mut dom = self.dom_mut();
+        let node = dom.alloc(Node::new(NodeKind::Element(Element::new_ns(
+            namespace, tag, attributes,
+        ))));
+        if namespace == Namespace::Html && tag == "template" {
+            let contents = dom.alloc(Node::new(NodeKind::DocumentFragment));
+            dom[node].set_template_contents(Some(contents));
+        }
+        node
+    }
+    /// Creates an element with the same name and attributes as `node`.
+    fn clone_element(&self, node: NodeId) -> NodeId {
+        let element = self
+            .element(node)
+            .expect("a formatting element should be an element");
+        self.create_element(
+            element.namespace(),
+            &element.local_name(),
+            element.attributes(),
+        )
+    }
+    fn insert_element(&mut self, tag: &str, attributes: Vec<Attribute>) -> NodeId {
+        self.insert_foreign_element(Namespace::Html, tag, attributes)
+    }
+    /// Inserts an element in `namespace` at the appropriate place and pushes it onto the stack of
+    /// open elements.
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/parsing.html#insert-a-foreign-element>
+    fn insert_foreign_element(
+        &mut self,
+        namespace: Namespace,
+        tag: &str,
+        attributes: Vec<Attribute>,
+    ) -> NodeId {
+        let (parent, reference) = self.appropriate_insertion_place(None);
+        let node = self.create_element(namespace, tag, attributes);
+        self.insert(parent, node, reference);
+        self.stack_of_open_elements.push(node);
+        node
+    }
+    /// Inserts an element whose content is text, and switches the tokenizer so that the content
+    /// is parsed as text until the end tag of the element.
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/parsing.html#generic-raw-text-element-parsing-algorithm>
+    fn insert_text_element(&mut self, tag: &str, attributes: Vec<Attribute>, state: State) {
+        self.insert_element(tag, attributes);
+        self.t.switch_to(state);
+        self.original_insertion_mode = self.mode;
+        self.mode = InsertionMode::Text;
+    }
+    /// Inserts a comment as the last child of `parent`, or at the appropriate place if `parent` is
+    /// `None`.
+    fn insert_comment(&mut self, data: &str, parent: Option<NodeId>) {
+        let (parent, reference) = match parent {
+            Some(p) => (p, None),
+            None => self.appropriate_insertion_place(None),
+        };
+        let node = self
+            .dom_mut()
+            .alloc(Node::new(NodeKind::Comment(data.to_string())));
+        self.insert(parent, node, reference);
+    }
+    /// Adds the attributes of an `html` or `body` start tag that `node` does not have yet.
+    fn merge_attributes(&self, node: NodeId, attributes: &[Attribute]) {
+        if let Some(e) = self.dom_mut()[node].get_element_mut() {
+            for attribute in attributes {
+                if e.get_attribute(&attribute.name()).is_none() {
+                    e.append_attribute(attribute.clone());
+                }
+            }
+        }
+    }
+    fn formatting_position(&self, node: NodeId) -> Option<usize> {
+        self.active_formatting_elements
+            .iter()
+            .position(|e| matches!(e, FormattingEntry::Element(n) if *n == node))
+    }
+    /// Returns the position of the last formatting element named `name` after the last marker.
+    fn last_formatting_element(&self, name: &str) -> Option<usize> {
+        for (i, entry) in self.active_formatting_elements.iter().enumerate().rev() {
+            match entry {
+                FormattingEntry::Marker => return None,
+                FormattingEntry::Element(n) if self.is_html_element(*n, &[name]) => return Some(i),
+                FormattingEntry::Element(_) => {}
+            }
+        }
+        None
+    }
+    /// Pushes `node` onto the list of active formatting elements. Only the last three elements
+    /// with the same name and attributes are kept.
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/parsing.html#push-onto-the-list-of-active-formatting-elements>
+    fn push_active_formatting_element(&mut self, node: NodeId) {
+        let element = self
+            .element(node)
+            .expect("a formatting element should be an element");
+        let attributes = element.attributes();
+        let mut same = Vec::new();
+        for (i, entry) in self.active_formatting_elements.iter().enumerate().rev() {
+            let other = match entry {
+                FormattingEntry::Marker => break,
+                FormattingEntry::Element(n) => match self.element(*n) {
+                    Some(e) => e,
+                    None => continue,
+                },
+            };
+            let other_attributes = other.attributes();
+            if other.namespace() == element.namespace()
+                && other.local_name() == element.local_name()
+                && other_attributes.len() == attributes.len()
+                && other_attributes.iter().all(|a| attributes.contains(a))
+            {
+                same.push(i);
+            }
+        }
+        if same.len() >= 3 {
+            self.active_formatting_elements.remove(same[same.len() - 1]);
+        }
+        self.active_formatting_elements
+            .push(FormattingEntry::Element(node));
+    }
+    /// Reopens the formatting elements that were closed implicitly, such as `b` in
+    /// `<p><b>a<p>b`.
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/parsing.html#reconstruct-the-active-formatting-elements>
+    fn reconstruct_active_formatting_elements(&mut self) {
+        let open = |entry: &FormattingEntry| match entry {
+            FormattingEntry::Marker => true,
+            FormattingEntry::Element(n) => self.stack_position(*n).is_some(),
+        };
+        match self.active_formatting_elements.last() {
+            Some(entry) if !open(entry) => {}
+            _ => return,
+        }
+        let mut i = self.active_formatting_elements.len() - 1;
+        while i > 0 && !open(&self.active_formatting_elements[i - 1]) {
+            i -= 1;
+        }
+        for j in i..self.active_formatting_elements.len() {
+            let node = match self.active_formatting_elements[j] {
+                FormattingEntry::Element(n) => n,
+                FormattingEntry::Marker => continue,
+            };
+            let element = self
+                .element(node)
+                .expect("a formatting element should be an element");
+            let new_node = self.insert_foreign_element(
+                element.namespace(),
+                &element.local_name(),
+                element.attributes(),
+            );
+            self.active_formatting_elements[j] = FormattingEntry::Element(new_node);
+        }
+    }
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/parsing.html#clear-the-list-of-active-formatting-elements-up-to-the-last-marker>
+    fn clear_active_formatting_elements_to_last_marker(&mut self) {
+        while let Some(entry) = self.active_formatting_elements.pop() {
+            if let FormattingEntry::Marker = entry {
+                return;
+            }
+        }
+    }
+    /// Closes the formatting element named `subject`, reopening the formatting elements that are
+    /// misnested in it, as in `<b><i></b></i>`.
+    /// # Returns
+    /// - `false` if there is no such formatting element, in which case the end tag is processed as
+    ///   any other end tag.
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/parsing.html#adoption-agency-algorithm>
+    fn adoption_agency(&mut self, subject: &str) -> bool {
+        if let Some(current) = self.current_node() {
+            if self.is_html_element(current, &[subject])
+                && self.formatting_position(current).is_none()
+            {
+                self.stack_of_open_elements.pop();
+                return true;
+            }
+        }
+        for _ in 0..8 {
+            let formatting_index = match self.last_formatting_element(subject) {
+                Some(i) => i,
+                None => return false,
+            };
+            let formatting_element = match self.active_formatting_elements[formatting_index] {
+                FormattingEntry::Element(n) => n,
+                FormattingEntry::Marker => return false,
+            };
+            let stack_index = match self.stack_position(formatting_element) {
+                Some(i) => i,
+                None => {
+                    self.parse_error("unexpected-end-tag");
+                    self.active_formatting_elements.remove(formatting_index);
+                    return true;
+                }
+            };
+            if !self.has_node_in_scope(formatting_element, Scope::Default) {
+                self.parse_error("unexpected-end-tag");
+                return true;
+            }
+            if stack_index != self.stack_of_open_elements.len() - 1 {
+                self.parse_error("unexpected-end-tag");
+            }
+            let furthest_block_index = (stack_index + 1..self.stack_of_open_elements.len())
+                .find(|&i| self.is_special(self.stack_of_open_elements[i]));
+            let furthest_block_index = match furthest_block_index {
+                Some(i) => i,
+                None => {
+                    self.stack_of_open_elements.truncate(stack_index);
+                    self.active_formatting_elements.remove(formatting_index);
+                    return true;
+                }
+            };
+            let furthest_block = self.stack_of_open_elements[furthest_block_index];
+            let common_ancestor = self.stack_of_open_elements[stack_index - 1];
+            let mut bookmark = formatting_index;
+            let mut last_node = furthest_block;
+            let mut node_index = furthest_block_index;
+            let mut inner_loop_counter = 0;
+            loop {
+                inner_loop_counter += 1;
+                node_index -= 1;
+                let node = self.stack_of_open_elements[node_index];
+                if node == formatting_element {
+                    break;
+                }
+                let mut position = self.formatting_position(node);
+                if inner_loop_counter > 3 {
+                    if let Some(i) = position.take() {
+                        self.active_formatting_elements.remove(i);
+                        if i < bookmark {
+                            bookmark -= 1;
+                        }
+                    }
+                }
+                let position = match position {
+                    Some(i) => i,
+                    None => {
+                        self.stack_of_open_elements.remove(node_index);
+                        continue;
+                    }
+                };
+                let new_node = self.clone_element(node);
+                self.active_formatting_elements[position] = FormattingEntry::Element(new_node);
+                self.stack_of_open_elements[node_index] = new_node;
+                if last_node == furthest_block {
+                    bookmark = position + 1;
+                }
+                self.insert(new_node, last_node, None);
+                last_node = new_node;
+            }
+            let (parent, reference) = self.appropriate_insertion_place(Some(common_ancestor));
+            self.insert(parent, last_node, reference);
+            let new_element = self.clone_element(formatting_element);
+            loop {
+                let child = self.dom().first_child(furthest_block);
+                match child {
+                    Some(child) => self.insert(new_element, child, None),
+                    None => break,
+                }
+            }
+            self.insert(furthest_block, new_element, None);
+            if let Some(i) = self.formatting_position(formatting_element) {
+                self.active_formatting_elements.remove(i);
+                if i < bookmark {
+                    bookmark -= 1;
+                }
+            }
+            self.active_formatting_elements
+                .insert(bookmark, FormattingEntry::Element(new_element));
+            if let Some(i) = self.stack_position(formatting_element) {
+                self.stack_of_open_elements.remove(i);
+            }
+            let i = self
+                .stack_position(furthest_block)
+                .expect("the furthest block should be open");
+            self.stack_of_open_elements.insert(i + 1, new_element);
+        }
+        true
+    }
+    /// Processes an end tag without specific handling in the "in body" insertion mode, which
+    /// closes the nearest open element with the same name unless a special element is open
+    /// inside it.
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inbody>
+    fn close_element(&mut self, tag: &str) {
+        for i in (0..self.stack_of_open_elements.len()).rev() {
+            let node = self.stack_of_open_elements[i];
+            if self.is_html_element(node, &[tag]) {
+                self.generate_implied_end_tags(Some(tag));
+                if i != self.stack_of_open_elements.len() - 1 {
+                    self.parse_error("unexpected-end-tag");
+                }
+                self.pop_until_node(node);
+                return;
+            }
+            if self.is_special(node) {
+                break;
+            }
+        }
+        self.parse_error("unexpected-end-tag");
+    }
+    /// Closes the open `template` element for its end tag.
+    fn close_template(&mut self) {
+        if !self.contain_in_stack(&["template"]) {
+            self.parse_error("unexpected-end-tag");
+            return;
+        }
+        self.generate_all_implied_end_tags_thoroughly();
+        if !self.current_node_is(&["template"]) {
+            self.parse_error("unexpected-end-tag");
+        }
+        self.pop_until(&["template"]);
+        self.clear_active_formatting_elements_to_last_marker();
+        self.template_insertion_modes.pop();
+        self.reset_insertion_mode_appropriately();
+    }
+    /// Closes the `caption` element in table scope.
+    /// # Returns
+    /// - `false` if there is no such element, in which case the token is ignored.
+    fn close_caption(&mut self) -> bool {
+        if !self.has_element_in_scope(&["caption"], Scope::Table) {
+            self.parse_error("unexpected-end-tag");
+            return false;
+        }
+        self.generate_implied_end_tags(None);
+        if !self.current_node_is(&["caption"]) {
+            self.parse_error("unexpected-end-tag");
+        }
+        self.pop_until(&["caption"]);
+        self.clear_active_formatting_elements_to_last_marker();
+        self.mode = InsertionMode::InTable;
+        true
+    }
+    /// Closes the `tr` element in table scope.
+    /// # Returns
+    /// - `false` if there is no such element, in which case the token is ignored.
+    fn close_row(&mut self) -> bool {
+        if !self.has_element_in_scope(&["tr"], Scope::Table) {
+            self.parse_error("unexpected-end-tag");
+            return false;
+        }
+        self.clear_stack_back_to(&["tr", "template"]);
+        self.stack_of_open_elements.pop();
+        self.mode = InsertionMode::InTableBody;
+        true
+    }
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/parsing.html#close-the-cell>
+    fn close_cell(&mut self) {
+        self.generate_implied_end_tags(None);
+        if !self.current_node_is(&["td", "th"]) {
+            self.parse_error("unexpected-end-tag");
+        }
+        self.pop_until(&["td", "th"]);
+        self.clear_active_formatting_elements_to_last_marker();
+        self.mode = InsertionMode::InRow;
+    }
+    /// Pops the `select` element in select scope and resets the insertion mode.
+    /// # Returns
+    /// - `false` if there is no such element, in which case the token is ignored.
+    fn close_select(&mut self) -> bool {
+        if !self.has_element_in_scope(&["select"], Scope::Select) {
+            return false;
+        }
+        self.pop_until(&["select"]);
+        self.reset_insertion_mode_appropriately();
+        true
+    }
+    /// Replaces the current template insertion mode and switches to it.
+    fn switch_template_mode(&mut self, mode: InsertionMode) {
+        self.template_insertion_modes.pop();
+        self.template_insertion_modes.push(mode);
+        self.mode = mode;
+    }
+    /// Feeds a chunk of the document to the tokenizer and builds the tree as far as the input
+    /// goes. The tokenizer should be created by `HtmlTokenizer::new_incremental()`.
+    pub fn feed(&mut self, chunk: &str) {
+        self.t.feed(chunk);
+        self.construct_tree();
+    }
+    /// Feeds a chunk of the document encoded in UTF-8, like `feed()`.
+    pub fn feed_bytes(&mut self, bytes: &[u8]) {
+        self.t.feed_bytes(bytes);
+        self.construct_tree();
+    }
+    /// Closes the input and builds the rest of the tree.
+    pub fn finish(&mut self) -> Rc<RefCell<Document>> {
+        self.t.close();
+        self.construct_tree()
+    }
+    /// Returns the document, which may still be under construction.
+    pub fn document(&self) -> Rc<RefCell<Document>> {
+        self.document.clone()
+    }
+    /// Builds the tree from the tokens. With an incremental tokenizer, this returns when the
+    /// tokenizer needs more input, and can be called again to continue after more is fed.
+    pub fn construct_tree(&mut self) -> Rc<RefCell<Document>> {
+        self.build(false);
+        self.pending_script = None;
+        self.document.clone()
+    }
+    /// Builds the tree until the end tag of a `script` element is processed, so that the script
+    /// can run before the rest of the document is parsed, as a parser-blocking script.
+    /// # Returns
+    /// - The `script` element, or `None` if the tree is built to the end of the input.
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/parsing.html#scriptEndTag>
+    pub fn run_until_script(&mut self) -> Option<NodeId> {
+        self.build(true);
+        self.pending_script.take()
+    }
+    fn build(&mut self, pause_at_script: bool) {
+        loop {
+            if pause_at_script && self.pending_script.is_some() {
+                break;
+            }
+            // CDATA sections are only allowed in SVG and MathML content.
+            let foreign = self
+                .adjusted_current_node()
+                .and_then(|n| self.element(n))
+                .is_some_and(|e| e.namespace() != Namespace::Html);
+            self.t.set_cdata_allowed(foreign);
+            let token = match self.t.next() {
+                Some(token) => token,
+                None => break,
+            };
+            if self.ignore_line_feed {
+                self.ignore_line_feed = false;
+                if token == HtmlToken::Char('\n') {
+                    continue;
+                }
+            }
+            let eof = token == HtmlToken::Eof;
+            self.process_token(token);
+            if eof {
+                break;
+            }
+        }
+    }
+    /// Processes a token by the rules of the current insertion mode, or by the rules for foreign
+    /// content in SVG and MathML.
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/parsing.html#tree-construction-dispatcher>
+    fn process_token(&mut self, token: HtmlToken) {
+        let element = self.adjusted_current_node().and_then(|n| self.element(n));
+        let foreign = match element {
+            Some(ref e) if e.namespace() != Namespace::Html => match token {
+                HtmlToken::StartTag { ref tag, .. }
+                    if is_mathml_text_integration_point(e)
+                        && tag != "mglyph"
+                        && tag != "malignmark" =>
+                {
+                    false
+                }
+                HtmlToken::Char(_) if is_mathml_text_integration_point(e) => false,
+                HtmlToken::StartTag { ref tag, .. }
+                    if e.namespace() == Namespace::MathMl
+                        && e.local_name() == "annotation-xml"
+                        && tag == "svg" =>
+                {
+                    false
+                }
+                HtmlToken::StartTag { .. } | HtmlToken::Char(_) => !is_html_integration_point(e),
+                HtmlToken::Eof => false,
+                _ => true,
+            },
+            _ => false,
+        };
+        match foreign {
+            true => self.in_foreign_content(token),
+            false => self.process_in(self.mode, token),
+        }
+    }
+    /// Processes a token by the rules of the insertion mode `mode`.
+    fn process_in(&mut self, mode: InsertionMode, token: HtmlToken) {
+        match mode {
+            InsertionMode::Initial => self.initial(token),
+            InsertionMode::BeforeHtml => self.before_html(token),
+            InsertionMode::BeforeHead => self.before_head(token),
+            InsertionMode::InHead => self.in_head(token),
+            InsertionMode::InHeadNoscript => self.in_head_noscript(token),
+            InsertionMode::AfterHead => self.after_head(token),
+            InsertionMode::InBody => self.in_body(token),
+            InsertionMode::Text => self.text(token),
+            InsertionMode::InTable => self.in_table(token),
+            InsertionMode::InTableText => self.in_table_text(token),
+            InsertionMode::InCaption => self.in_caption(token),
+            InsertionMode::InColumnGroup => self.in_column_group(token),
+            InsertionMode::InTableBody => self.in_table_body(token),
+            InsertionMode::InRow => self.in_row(token),
+            InsertionMode::InCell => self.in_cell(token),
+            InsertionMode::InSelect => self.in_select(token),
+            InsertionMode::InSelectInTable => self.in_select_in_table(token),
+            InsertionMode::InTemplate => self.in_template(token),
+            InsertionMode::AfterBody => self.after_body(token),
+            InsertionMode::InFrameset => self.in_frameset(token),
+            InsertionMode::AfterFrameset => self.after_frameset(token),
+            InsertionMode::AfterAfterBody => self.after_after_body(token),
+            InsertionMode::AfterAfterFrameset => self.after_after_frameset(token),
+        }
+    }
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/parsing.html#the-initial-insertion-mode>
+    fn initial(&mut self, token: HtmlToken) {
+        let document = self.document_node();
+        match token {
+            HtmlToken::Char(c) if is_whitespace(c) => {}
+            HtmlToken::Comment(ref data) => self.insert_comment(data, Some(document)),
+            HtmlToken::Doctype {
+                ref name,
+                ref public_id,
+                ref system_id,
+                force_quirks,
+            } => {
+                let mut dom = self.dom_mut();
+                let doctype = dom.alloc(Node::new(NodeKind::DocumentType {
+                    name: name.clone().unwrap_or_default(),
+                    public_id: public_id.clone().unwrap_or_default(),
+                    system_id: system_id.clone().unwrap_or_default(),
+                }));
+                dom.append(document, doctype);
+                drop(dom);
+                self.document
+                    .borrow_mut()
+                    .set_quirks_mode(quirks_mode_for_doctype(
+                        name,
+                        public_id,
+                        system_id,
+                        force_quirks,
+                    ));
+                self.mode = InsertionMode::BeforeHtml;
+            }
+            _ => {
+                // A document without a DOCTYPE is rendered in quirks mode.
+                self.parse_error("missing-doctype");
+                self.document
+                    .borrow_mut()
+                    .set_quirks_mode(QuirksMode::Quirks);
+                self.mode = InsertionMode::BeforeHtml;
+                self.process_token(token);
+            }
+        }
+    }
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/parsing.html#the-before-html-insertion-mode>
+    fn before_html(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::Doctype { .. } => self.parse_error("unexpected-doctype"),
+            HtmlToken::Comment(ref data) => {
+                let document = self.document_node();
+                self.insert_comment(data, Some(document));
+            }
+            HtmlToken::Char(c) if is_whitespace(c) => {}
+            HtmlToken::StartTag {
+                ref tag,
+                ref attributes,
+                ..
+            } if tag == "html" => {
+                self.insert_element(tag, attributes.to_vec());
+                self.mode = InsertionMode::BeforeHead;
+            }
+            HtmlToken::EndTag { ref tag }
+                if !matches!(tag.as_str(), "head" | "body" | "html" | "br") =>
+            {
+                self.parse_error("unexpected-end-tag");
+            }
+            _ => {
+                self.insert_element("html", Vec::new());
+                self.mode = InsertionMode::BeforeHead;
+                self.process_token(token);
+            }
+        }
+    }
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/parsing.html#the-before-head-insertion-mode>
+    fn before_head(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::Char(c) if is_whitespace(c) => {}
+            HtmlToken::Comment(ref data) => self.insert_comment(data, None),
+            HtmlToken::Doctype { .. } => self.parse_error("unexpected-doctype"),
+            HtmlToken::StartTag { ref tag, .. } if tag == "html" => self.in_body(token),
+            HtmlToken::StartTag {
+                ref tag,
+                ref attributes,
+                ..
+            } if tag == "head" => {
+                self.head_element = Some(self.insert_element(tag, attributes.to_vec()));
+                self.mode = InsertionMode::InHead;
+            }
+            HtmlToken::EndTag { ref tag }
+                if !matches!(tag.as_str(), "head" | "body" | "html" | "br") =>
+            {
+                self.parse_error("unexpected-end-tag");
+            }
+            _ => {
+                self.head_element = Some(self.insert_element("head", Vec::new()));
+                self.mode = InsertionMode::InHead;
+                self.process_token(token);
+            }
+        }
+    }
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inhead>
+    fn in_head(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::Char(c) if is_whitespace(c) => self.insert_character(c),
+            HtmlToken::Comment(ref data) => self.insert_comment(data, None),
+            HtmlToken::Doctype { .. } => self.parse_error("unexpected-doctype"),
+            HtmlToken::StartTag {
+                ref tag,
+                ref attributes,
+                ..
+            } => match tag.as_str() {
+                "html" => self.in_body(token.clone()),
+                "base" | "basefont" | "bgsound" | "link" | "meta" => {
+                    // These are void elements, so they are popped immediately.
+                    self.insert_element(tag, attributes.to_vec());
+                    self.stack_of_open_elements.pop();
+                }
+                "title" => self.insert_text_element(tag, attributes.to_vec(), State::Rcdata),
+                "noframes" | "style" => {
+                    self.insert_text_element(tag, attributes.to_vec(), State::Rawtext)
+                }
+                "noscript" => {
+                    self.insert_element(tag, attributes.to_vec());
+                    self.mode = InsertionMode::InHeadNoscript;
+                }
+                "script" => self.insert_text_element(tag, attributes.to_vec(), State::ScriptData),
+                "template" => {
+                    self.insert_element(tag, attributes.to_vec());
+                    self.active_formatting_elements
+                        .push(FormattingEntry::Marker);
+                    self.frameset_ok = false;
+                    self.mode = InsertionMode::InTemplate;
+                    self.template_insertion_modes
+                        .push(InsertionMode::InTemplate);
+                }
+                "head" => self.parse_error("unexpected-start-tag"),
+                _ => {
+                    self.stack_of_open_elements.pop();
+                    self.mode = InsertionMode::AfterHead;
+                    self.process_token(token.clone());
+                }
+            },
+            HtmlToken::EndTag { ref tag } => match tag.as_str() {
+                "head" => {
+                    self.stack_of_open_elements.pop();
+                    self.mode = InsertionMode::AfterHead;
+                }
+                "template" => self.close_template(),
+                "body" | "html" | "br" => {
+                    self.stack_of_open_elements.pop();
+                    self.mode = InsertionMode::AfterHead;
+                    self.process_token(token.clone());
+                }
+                _ => self.parse_error("unexpected-end-tag"),
+            },
+            _ => {
+                // Text and the end of the input imply the end of `head`.
+                self.stack_of_open_elements.pop();
+                self.mode = InsertionMode::AfterHead;
+                self.process_token(token);
+            }
+        }
+    }
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inheadnoscript>
+    fn in_head_noscript(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::Doctype { .. } => self.parse_error("unexpected-doctype"),
+            HtmlToken::StartTag { ref tag, .. } if tag == "html" => self.in_body(token),
+            HtmlToken::EndTag { ref tag } if tag == "noscript" => {
+                self.stack_of_open_elements.pop();
+                self.mode = InsertionMode::InHead;
+            }
+            HtmlToken::Char(c) if is_whitespace(c) => self.in_head(token),
+            HtmlToken::Comment(_) => self.in_head(token),
+            HtmlToken::StartTag { ref tag, .. }
+                if matches!(
+                    tag.as_str(),
+                    "basefont" | "bgsound" | "link" | "meta" | "noframes" | "style"
+                ) =>
+            {
+                self.in_head(token)
+            }
+            HtmlToken::StartTag { ref tag, .. } if matches!(tag.as_str(), "head" | "noscript") => {
+                self.parse_error("unexpected-start-tag")
+            }
+            HtmlToken::EndTag { ref tag } if tag != "br" => self.parse_error("unexpected-end-tag"),
+            _ => {
+                self.unexpected_token(&token);
+                self.stack_of_open_elements.pop();
+                self.mode = InsertionMode::InHead;
+                self.process_token(token);
+            }
+        }
+    }
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/parsing.html#the-after-head-insertion-mode>
+    fn after_head(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::Char(c) if is_whitespace(c) => self.insert_character(c),
+            HtmlToken::Comment(ref data) => self.insert_comment(data, None),
+            HtmlToken::Doctype { .. } => self.parse_error("unexpected-doctype"),
+            HtmlToken::StartTag {
+                ref tag,
+                ref attributes,
+                ..
+            } => match tag.as_str() {
+                "html" => self.in_body(token.clone()),
+                "body" => {
+                    self.insert_element(tag, attributes.to_vec());
+                    self.frameset_ok = false;
+                    self.mode = InsertionMode::InBody;
+                }
+                "frameset" => {
+                    self.insert_element(tag, attributes.to_vec());
+                    self.mode = InsertionMode::InFrameset;
+                }
+                "base" | "basefont" | "bgsound" | "link" | "meta" | "noframes" | "script"
+                | "style" | "template" | "title" => {
+                    // Elements of `head` after it are still inserted into it.
+                    self.parse_error("unexpected-start-tag");
+                    let head = match self.head_element {
+                        Some(head) => head,
+                        None => return,
+                    };
+                    self.stack_of_open_elements.push(head);
+                    self.in_head(token.clone());
+                    if let Some(i) = self.stack_position(head) {
+                        self.stack_of_open_elements.remove(i);
+                    }
+                }
+                "head" => self.parse_error("unexpected-start-tag"),
+                _ => {
+                    self.insert_element("body", Vec::new());
+                    self.mode = InsertionMode::InBody;
+                    self.process_token(token.clone());
+                }
+            },
+            HtmlToken::EndTag { ref tag } if tag == "template" => self.in_head(token),
+            HtmlToken::EndTag { ref tag } if !matches!(tag.as_str(), "body" | "html" | "br") => {
+                self.parse_error("unexpected-end-tag")
+            }
+            _ => {
+                self.insert_element("body", Vec::new());
+                self.mode = InsertionMode::InBody;
+                self.process_token(token);
+            }
+        }
+    }
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inbody>
+    fn in_body(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::Char('\0') => self.parse_error("unexpected-null-character"),
+            HtmlToken::Char(c) => {
+                self.reconstruct_active_formatting_elements();
+                self.insert_character(c);
+                if !is_whitespace(c) {
+                    self.frameset_ok = false;
+                }
+            }
+            HtmlToken::Comment(ref data) => self.insert_comment(data, None),
+            HtmlToken::Doctype { .. } => self.parse_error("unexpected-doctype"),
+            HtmlToken::StartTag {
+                ref tag,
+                self_closing,
+                ref attributes,
+            } => self.start_tag_in_body(tag, self_closing, attributes),
+            HtmlToken::EndTag { ref tag } => self.end_tag_in_body(tag),
+            HtmlToken::Eof => {
+                if !self.template_insertion_modes.is_empty() {
+                    self.in_template(token);
+                }
+            }
+        }
+    }
+    fn start_tag_in_body(&mut self, tag: &str, self_closing: bool, attributes: &[Attribute]) {
+        let token = HtmlToken::StartTag {
+            tag: tag.to_string(),
+            self_closing,
+            attributes: attributes.to_vec(),
+        };
+        match tag {
+            "html" => {
+                self.parse_error("unexpected-start-tag");
+                if self.contain_in_stack(&["template"]) {
+                    return;
+                }
+                if let Some(&html) = self.stack_of_open_elements.first() {
+                    self.merge_attributes(html, attributes);
+                }
+            }
+            "base" | "basefont" | "bgsound" | "link" | "meta" | "noframes" | "script" | "style"
+            | "template" | "title" => self.in_head(token),
+            "body" => {
+                self.parse_error("unexpected-start-tag");
+                let body = match self.stack_of_open_elements.get(1) {
+                    Some(&body) if self.is_html_element(body, &["body"]) => body,
+                    _ => return,
+                };
+                if self.contain_in_stack(&["template"]) {
+                    return;
+                }
+                self.frameset_ok = false;
+                self.merge_attributes(body, attributes);
+            }
+            "frameset" => {
+                self.parse_error("unexpected-start-tag");
+                let body = match self.stack_of_open_elements.get(1) {
+                    Some(&body) if self.is_html_element(body, &["body"]) => body,
+                    _ => return,
+                };
+                if !self.frameset_ok {
+                    return;
+                }
+                self.remove(body);
+                self.stack_of_open_elements.truncate(1);
+                self.insert_element(tag, attributes.to_vec());
+                self.mode = InsertionMode::InFrameset;
+            }
+            "address" | "article" | "aside" | "blockquote" | "center" | "details" | "dialog"
+            | "dir" | "div" | "dl" | "fieldset" | "figcaption" | "figure" | "footer" | "header"
+            | "hgroup" | "main" | "menu" | "nav" | "ol" | "p" | "search" | "section"
+            | "summary" | "ul" => {
+                self.close_p_element_in_button_scope();
+                self.insert_element(tag, attributes.to_vec());
+            }
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                self.close_p_element_in_button_scope();
+                if self.current_node_is(&HEADINGS) {
+                    self.parse_error("unexpected-start-tag");
+                    self.stack_of_open_elements.pop();
+                }
+                self.insert_element(tag, attributes.to_vec());
+            }
+            "pre" | "listing" => {
+                self.close_p_element_in_button_scope();
+                self.insert_element(tag, attributes.to_vec());
+                self.ignore_line_feed = true;
+                self.frameset_ok = false;
+            }
+            "form" => {
+                let in_template = self.contain_in_stack(&["template"]);
+                // Nested forms are a parse error and the start tag is ignored.
+                if self.form_element.is_some() && !in_template {
+                    self.parse_error("unexpected-start-tag");
+                    return;
+                }
+                self.close_p_element_in_button_scope();
+                let form = self.insert_element(tag, attributes.to_vec());
+                if !in_template {
+                    self.form_element = Some(form);
+                }
+            }
+            "li" | "dd" | "dt" => {
+                // An open item of the same list is closed implicitly.
+                self.frameset_ok = false;
+                let targets: &[&str] = match tag {
+                    "li" => &["li"],
+                    _ => &["dd", "dt"],
+                };
+                for i in (0..self.stack_of_open_elements.len()).rev() {
+                    let node = self.stack_of_open_elements[i];
+                    if self.is_html_element(node, targets) {
+                        let element = self.element(node).expect("should be an element");
+                        let name = element.local_name();
+                        self.generate_implied_end_tags(Some(&name));
+                        if !self.current_node_is(&[&name]) {
+                            self.parse_error("unexpected-start-tag");
+                        }
+                        self.pop_until(&[&name]);
+                        break;
+                    }
+                    if self.is_special(node)
+                        && !self.is_html_element(node, &["address", "div", "p"])
+                    {
+                        break;
+                    }
+                }
+                self.close_p_element_in_button_scope();
+                self.insert_element(tag, attributes.to_vec());
+            }
+            "plaintext" => {
+                self.close_p_element_in_button_scope();
+                self.insert_element(tag, attributes.to_vec());
+                self.t.switch_to(State::Plaintext);
+            }
+            "button" => {
+                if self.has_element_in_scope(&["button"], Scope::Default) {
+                    self.parse_error("unexpected-start-tag");
+                    self.generate_implied_end_tags(None);
+                    self.pop_until(&["button"]);
+                }
+                self.reconstruct_active_formatting_elements();
+                self.insert_element(tag, attributes.to_vec());
+                self.frameset_ok = false;
+            }
+            "a" => {
+                // An open `a` is closed, because links cannot be nested.
+                if let Some(i) = self.last_formatting_element("a") {
+                    self.parse_error("unexpected-start-tag");
+                    let a = match self.active_formatting_elements[i] {
+                        FormattingEntry::Element(n) => n,
+                        FormattingEntry::Marker => return,
+                    };
+                    self.adoption_agency("a");
+                    if let Some(i) = self.formatting_position(a) {
+                        self.active_formatting_elements.remove(i);
+                    }
+                    if let Some(i) = self.stack_position(a) {
+                        self.stack_of_open_elements.remove(i);
+                    }
+                }
+                self.reconstruct_active_formatting_elements();
+                let node = self.insert_element(tag, attributes.to_vec());
+                self.push_active_formatting_element(node);
+            }
+            "b" | "big" | "code" | "em" | "font" | "i" | "s" | "small" | "strike" | "strong"
+            | "tt" | "u" => {
+                self.reconstruct_active_formatting_elements();
+                let node = self.insert_element(tag, attributes.to_vec());
+                self.push_active_formatting_element(node);
+            }
+            "nobr" => {
+                self.reconstruct_active_formatting_elements();
+                if self.has_element_in_scope(&["nobr"], Scope::Default) {
+                    self.parse_error("unexpected-start-tag");
+                    self.adoption_agency("nobr");
+                    self.reconstruct_active_formatting_elements();
+                }
+                let node = self.insert_element(tag, attributes.to_vec());
+                self.push_active_formatting_element(node);
+            }
+            "applet" | "marquee" | "object" => {
+                self.reconstruct_active_formatting_elements();
+                self.insert_element(tag, attributes.to_vec());
+                self.active_formatting_elements
+                    .push(FormattingEntry::Marker);
+                self.frameset_ok = false;
+            }
+            "table" => {
+                let quirks_mode = self.document.borrow().quirks_mode();
+                if quirks_mode != QuirksMode::Quirks {
+                    self.close_p_element_in_button_scope();
+                }
+                self.insert_element(tag, attributes.to_vec());
+                self.frameset_ok = false;
+                self.mode = InsertionMode::InTable;
+            }
+            "area" | "br" | "embed" | "img" | "keygen" | "wbr" | "input" => {
+                // These are void elements, so they are popped immediately.
+                self.reconstruct_active_formatting_elements();
+                self.insert_element(tag, attributes.to_vec());
+                self.stack_of_open_elements.pop();
+                let hidden = tag == "input"
+                    && attributes
+                        .iter()
+                        .any(|a| a.name() == "type" && a.value().eq_ignore_ascii_case("hidden"));
+                if !hidden {
+                    self.frameset_ok = false;
+                }
+            }
+            "param" | "source" | "track" => {
+                self.insert_element(tag, attributes.to_vec());
+                self.stack_of_open_elements.pop();
+            }
+            "hr" => {
+                self.close_p_element_in_button_scope();
+                self.insert_element(tag, attributes.to_vec());
+                self.stack_of_open_elements.pop();
+                self.frameset_ok = false;
+            }
+            "image" => {
+                // An `image` start tag is a misspelling of `img`.
+                self.parse_error("unexpected-start-tag");
+                self.start_tag_in_body("img", self_closing, attributes);
+            }
+            "textarea" => {
+                self.insert_text_element(tag, attributes.to_vec(), State::Rcdata);
+                self.ignore_line_feed = true;
+                self.frameset_ok = false;
+            }
+            "xmp" => {
+                self.close_p_element_in_button_scope();
+                self.reconstruct_active_formatting_elements();
+                self.frameset_ok = false;
+                self.insert_text_element(tag, attributes.to_vec(), State::Rawtext);
+            }
+            "iframe" => {
+                self.frameset_ok = false;
+                self.insert_text_element(tag, attributes.to_vec(), State::Rawtext);
+            }
+            "noembed" => self.insert_text_element(tag, attributes.to_vec(), State::Rawtext),
+            "select" => {
+                self.reconstruct_active_formatting_elements();
+                self.insert_element(tag, attributes.to_vec());
+                self.frameset_ok = false;
+                self.mode = match self.mode {
+                    InsertionMode::InTable
+                    | InsertionMode::InCaption
+                    | InsertionMode::InTableBody
+                    | InsertionMode::InRow
+                    | InsertionMode::InCell => InsertionMode::InSelectInTable,
+                    _ => InsertionMode::InSelect,
+                };
+            }
+            "optgroup" | "option" => {
+                if self.current_node_is(&["option"]) {
+                    self.stack_of_open_elements.pop();
+                }
+                self.reconstruct_active_formatting_elements();
+                self.insert_element(tag, attributes.to_vec());
+            }
+            "rb" | "rtc" | "rp" | "rt" => {
+                if self.has_element_in_scope(&["ruby"], Scope::Default) {
+                    match tag {
+                        "rb" | "rtc" => self.generate_implied_end_tags(None),
+                        _ => self.generate_implied_end_tags(Some("rtc")),
+                    }
+                    if !self.current_node_is(&["ruby", "rtc"]) {
+                        self.parse_error("unexpected-start-tag");
+                    }
+                }
+                self.insert_element(tag, attributes.to_vec());
+            }
+            "math" | "svg" => {
+                let namespace = match tag {
+                    "svg" => Namespace::Svg,
+                    _ => Namespace::MathMl,
+                };
+                self.reconstruct_active_formatting_elements();
+                self.insert_foreign_element(
+                    namespace,
+                    tag,
+                    adjust_foreign_attributes(namespace, attributes),
+                );
+                if self_closing {
+                    self.stack_of_open_elements.pop();
+                }
+            }
+            "caption" | "col" | "colgroup" | "frame" | "head" | "tbody" | "td" | "tfoot" | "th"
+            | "thead" | "tr" => {
+                // The parts of a table are ignored outside of a table.
+                self.parse_error("unexpected-start-tag");
+            }
+            _ => {
+                // Elements that are not known are inserted as generic elements.
+                self.reconstruct_active_formatting_elements();
+                self.insert_element(tag, attributes.to_vec());
+            }
+        }
+    }
+    fn end_tag_in_body(&mut self, tag: &str) {
+        match tag {
+            "template" => self.close_template(),
+            "body" | "html" => {
+                if !self.has_element_in_scope(&["body"], Scope::Default) {
+                    self.parse_error("unexpected-end-tag");
+                    return;
+                }
+                self.mode = InsertionMode::AfterBody;
+                if tag == "html" {
+                    self.process_token(HtmlToken::EndTag {
+                        tag: tag.to_string(),
+                    });
+                }
+            }
+            "address" | "article" | "aside" | "blockquote" | "button" | "center" | "details"
+            | "dialog" | "dir" | "div" | "dl" | "fieldset" | "figcaption" | "figure" | "footer"
+            | "header" | "hgroup" | "listing" | "main" | "menu" | "nav" | "ol" | "pre"
+            | "search" | "section" | "summary" | "ul" => {
+                if !self.has_element_in_scope(&[tag], Scope::Default) {
+                    self.parse_error("unexpected-end-tag");
+                    return;
+                }
+                self.generate_implied_end_tags(None);
+                if !self.current_node_is(&[tag]) {
+                    self.parse_error("unexpected-end-tag");
+                }
+                self.pop_until(&[tag]);
+            }
+            "form" => {
+                if self.contain_in_stack(&["template"]) {
+                    if !self.has_element_in_scope(&["form"], Scope::Default) {
+                        self.parse_error("unexpected-end-tag");
+                        return;
+                    }
+                    self.generate_implied_end_tags(None);
+                    if !self.current_node_is(&["form"]) {
+                        self.parse_error("unexpected-end-tag");
+                    }
+                    self.pop_until(&["form"]);
+                    return;
+                }
+                let form = match self.form_element.take() {
+                    Some(form) if self.has_node_in_scope(form, Scope::Default) => form,
+                    _ => {
+                        self.parse_error("unexpected-end-tag");
+                        return;
+                    }
+                };
+                self.generate_implied_end_tags(None);
+                if self.current_node() != Some(form) {
+                    self.parse_error("unexpected-end-tag");
+                }
+                if let Some(i) = self.stack_position(form) {
+                    self.stack_of_open_elements.remove(i);
+                }
+            }
+            "p" => {
+                // A stray end tag of `p` creates an empty paragraph.
+                if !self.has_element_in_scope(&["p"], Scope::Button) {
+                    self.parse_error("unexpected-end-tag");
+                    self.insert_element(tag, Vec::new());
+                }
+                self.close_p_element();
+            }
+            "li" | "dd" | "dt" => {
+                let scope = match tag {
+                    "li" => Scope::ListItem,
+                    _ => Scope::Default,
+                };
+                if !self.has_element_in_scope(&[tag], scope) {
+                    self.parse_error("unexpected-end-tag");
+                    return;
+                }
+                self.generate_implied_end_tags(Some(tag));
+                if !self.current_node_is(&[tag]) {
+                    self.parse_error("unexpected-end-tag");
+                }
+                self.pop_until(&[tag]);
+            }
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                // The end tag of any heading closes the open heading.
+                if !self.has_element_in_scope(&HEADINGS, Scope::Default) {
+                    self.parse_error("unexpected-end-tag");
+                    return;
+                }
+                self.generate_implied_end_tags(None);
+                if !self.current_node_is(&[tag]) {
+                    self.parse_error("unexpected-end-tag");
+                }
+                self.pop_until(&HEADINGS);
+            }
+            "a" | "b" | "big" | "code" | "em" | "font" | "i" | "nobr" | "s" | "small"
+            | "strike" | "strong" | "tt" | "u" => {
+                if !self.adoption_agency(tag) {
+                    self.close_element(tag);
+                }
+            }
+            "applet" | "marquee" | "object" => {
+                if !self.has_element_in_scope(&[tag], Scope::Default) {
+                    self.parse_error("unexpected-end-tag");
+                    return;
+                }
+                self.generate_implied_end_tags(None);
+                if !self.current_node_is(&[tag]) {
+                    self.parse_error("unexpected-end-tag");
+                }
+                self.pop_until(&[tag]);
+                self.clear_active_formatting_elements_to_last_marker();
+            }
+            "br" => {
+                // An end tag of `br` is treated as a start tag.
+                self.parse_error("unexpected-end-tag");
+                self.start_tag_in_body(tag, false, &[]);
+            }
+            _ => self.close_element(tag),
+        }
+    }
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-incdata>
+    fn text(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::Char(c) => self.insert_character(c),
+            HtmlToken::Eof => {
+                self.stack_of_open_elements.pop();
+                self.mode = self.original_insertion_mode;
+                self.process_token(token);
+            }
+            HtmlToken::EndTag { .. } => {
+                // The tokenizer only emits the end tag of the current node here.
+                let node = self.stack_of_open_elements.pop();
+                self.mode = self.original_insertion_mode;
+                // Scripts in a fragment are never run.
+                if let Some(node) = node {
+                    if self.context.is_none()
+                        && self.dom()[node].element_kind() == Some(ElementKind::Script)
+                    {
+                        self.pending_script = Some(node);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-intable>
+    fn in_table(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::Char(_)
+                if self
+                    .current_node_is(&["table", "tbody", "template", "tfoot", "thead", "tr"]) =>
+            {
+                self.pending_table_characters = String::new();
+                self.original_insertion_mode = self.mode;
+                self.mode = InsertionMode::InTableText;
+                self.process_token(token);
+            }
+            HtmlToken::Comment(ref data) => self.insert_comment(data, None),
+            HtmlToken::Doctype { .. } => self.parse_error("unexpected-doctype"),
+            HtmlToken::StartTag {
+                ref tag,
+                ref attributes,
+                ..
+            } => match tag.as_str() {
+                "caption" => {
+                    self.clear_stack_back_to(&["table", "template"]);
+                    self.active_formatting_elements
+                        .push(FormattingEntry::Marker);
+                    self.insert_element(tag, attributes.to_vec());
+                    self.mode = InsertionMode::InCaption;
+                }
+                "colgroup" => {
+                    self.clear_stack_back_to(&["table", "template"]);
+                    self.insert_element(tag, attributes.to_vec());
+                    self.mode = InsertionMode::InColumnGroup;
+                }
+                "col" => {
+                    self.clear_stack_back_to(&["table", "template"]);
+                    self.insert_element("colgroup", Vec::new());
+                    self.mode = InsertionMode::InColumnGroup;
+                    self.process_token(token.clone());
+                }
+                "tbody" | "tfoot" | "thead" => {
+                    self.clear_stack_back_to(&["table", "template"]);
+                    self.insert_element(tag, attributes.to_vec());
+                    self.mode = InsertionMode::InTableBody;
+                }
+                "td" | "th" | "tr" => {
+                    // Rows are always in a table section.
+                    self.clear_stack_back_to(&["table", "template"]);
+                    self.insert_element("tbody", Vec::new());
+                    self.mode = InsertionMode::InTableBody;
+                    self.process_token(token.clone());
+                }
+                "table" => {
+                    self.parse_error("unexpected-start-tag");
+                    if !self.has_element_in_scope(&["table"], Scope::Table) {
+                        return;
+                    }
+                    self.pop_until(&["table"]);
+                    self.reset_insertion_mode_appropriately();
+                    self.process_token(token.clone());
+                }
+                "style" | "script" | "template" => self.in_head(token.clone()),
+                "input"
+                    if attributes.iter().any(|a| {
+                        a.name() == "type" && a.value().eq_ignore_ascii_case("hidden")
+                    }) =>
+                {
+                    self.parse_error("unexpected-start-tag");
+                    self.insert_element(tag, attributes.to_vec());
+                    self.stack_of_open_elements.pop();
+                }
+                "form" => {
+                    self.parse_error("unexpected-start-tag");
+                    if self.contain_in_stack(&["template"]) || self.form_element.is_some() {
+                        return;
+                    }
+                    self.form_element = Some(self.insert_element(tag, attributes.to_vec()));
+                    self.stack_of_open_elements.pop();
+                }
+                _ => self.foster_parent(token.clone()),
+            },
+            HtmlToken::EndTag { ref tag } => match tag.as_str() {
+                "table" => {
+                    if !self.has_element_in_scope(&["table"], Scope::Table) {
+                        self.parse_error("unexpected-end-tag");
+                        return;
+                    }
+                    self.pop_until(&["table"]);
+                    self.reset_insertion_mode_appropriately();
+                }
+                "body" | "caption" | "col" | "colgroup" | "html" | "tbody" | "td" | "tfoot"
+                | "th" | "thead" | "tr" => self.parse_error("unexpected-end-tag"),
+                "template" => self.in_head(token.clone()),
+                _ => self.foster_parent(token.clone()),
+            },
+            HtmlToken::Eof => self.in_body(token),
+            HtmlToken::Char(_) => self.foster_parent(token),
         }
     }
-    fn contain_in_stack(&mut self, element_kind: ElementKind) -> bool {
-        for i in 0..self.stack_of_open_elements.len() {
-            if self.stack_of_open_elements[i].borrow().element_kind() == Some(element_kind) {
-                return true;
+    /// Processes a token that is misnested in a table by the rules of the "in body" insertion
+    /// mode, inserting the nodes before the table.
+    fn foster_parent(&mut self, token: HtmlToken) {
+        self.unexpected_token(&token);
+        self.foster_parenting = true;
+        self.in_body(token);
+        self.foster_parenting = false;
+    }
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-intabletext>
+    fn in_table_text(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::Char('\0') => self.parse_error("unexpected-null-character"),
+            HtmlToken::Char(c) => self.pending_table_characters.push(c),
+            _ => {
+                let pending = core::mem::take(&mut self.pending_table_characters);
+                if pending.chars().any(|c| !is_whitespace(c)) {
+                    // Text in a table is moved before the table.
+                    self.parse_error("unexpected-character");
+                    self.foster_parenting = true;
+                    for c in pending.chars() {
+                        self.in_body(HtmlToken::Char(c));
+                    }
+                    self.foster_parenting = false;
+                } else {
+                    for c in pending.chars() {
+                        self.insert_character(c);
+                    }
+                }
+                self.mode = self.original_insertion_mode;
+                self.process_token(token);
             }
         }
-        false
     }
-    fn pop_until(&mut self, element_kind: ElementKind) {
-        assert!(
-            self.contain_in_stack(element_kind),
-            "stack does not have an element {:?}",
-            element_kind
-        );
-        loop {
-            let current = match self.stack_of_open_elements.pop() {
-                Some(n) => n,
-                None => return,
-            };
-            if current.borrow().element_kind() == Some(element_kind) {
-                return;
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-incaption>
+    fn in_caption(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::EndTag { ref tag } if tag == "caption" => {
+                self.close_caption();
+            }
+            HtmlToken::StartTag { ref tag, .. }
+                if matches!(
+                    tag.as_str(),
+                    "caption"
+                        | "col"
+                        | "colgroup"
+                        | "tbody"
+                        | "td"
+                        | "tfoot"
+                        | "th"
+                        | "thead"
+                        | "tr"
+                ) =>
+            {
+                if self.close_caption() {
+                    self.process_token(token);
+                }
+            }
+            HtmlToken::EndTag { ref tag } if tag == "table" => {
+                if self.close_caption() {
+                    self.process_token(token);
+                }
+            }
+            HtmlToken::EndTag { ref tag }
+                if matches!(
+                    tag.as_str(),
+                    "body"
+                        | "col"
+                        | "colgroup"
+                        | "html"
+                        | "tbody"
+                        | "td"
+                        | "tfoot"
+                        | "th"
+                        | "thead"
+                        | "tr"
+                ) =>
+            {
+                self.parse_error("unexpected-end-tag")
             }
+            _ => self.in_body(token),
         }
     }
-    fn pop_current_node(&mut self, element_kind: ElementKind) -> bool {
-        let current = match self.stack_of_open_elements.last() {
-            Some(n) => n,
-            None => return false,
-        };
-        if current.borrow().element_kind() == Some(element_kind) {
-            self.stack_of_open_elements.pop();
-            return true;
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-incolgroup>
+    fn in_column_group(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::Char(c) if is_whitespace(c) => self.insert_character(c),
+            HtmlToken::Comment(ref data) => self.insert_comment(data, None),
+            HtmlToken::Doctype { .. } => self.parse_error("unexpected-doctype"),
+            HtmlToken::StartTag { ref tag, .. } if tag == "html" => self.in_body(token),
+            HtmlToken::StartTag {
+                ref tag,
+                ref attributes,
+                ..
+            } if tag == "col" => {
+                self.insert_element(tag, attributes.to_vec());
+                self.stack_of_open_elements.pop();
+            }
+            HtmlToken::EndTag { ref tag } if tag == "colgroup" => {
+                if !self.current_node_is(&["colgroup"]) {
+                    self.parse_error("unexpected-end-tag");
+                    return;
+                }
+                self.stack_of_open_elements.pop();
+                self.mode = InsertionMode::InTable;
+            }
+            HtmlToken::EndTag { ref tag } if tag == "col" => self.parse_error("unexpected-end-tag"),
+            HtmlToken::StartTag { ref tag, .. } | HtmlToken::EndTag { ref tag }
+                if tag == "template" =>
+            {
+                self.in_head(token)
+            }
+            HtmlToken::Eof => self.in_body(token),
+            _ => {
+                if !self.current_node_is(&["colgroup"]) {
+                    self.unexpected_token(&token);
+                    return;
+                }
+                self.stack_of_open_elements.pop();
+                self.mode = InsertionMode::InTable;
+                self.process_token(token);
+            }
         }
-        false
-    }
-    fn create_char(&self, c: char) -> Node {
-        let mut s = String::new();
-        s.push(c);
-        Node::new(NodeKind::Text(s))
     }
-    fn insert_char(&mut self, c: char) {
-        let current = match self.stack_of_open_elements.last() {
-            Some(n) => n.clone(),
-            None => return,
-        };
-        if let NodeKind::Text(ref mut s) = current.borrow_mut().kind {
-            s.push(c);
-            return;
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-intbody>
+    fn in_table_body(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::StartTag {
+                ref tag,
+                ref attributes,
+                ..
+            } if tag == "tr" => {
+                self.clear_stack_back_to(&["tbody", "tfoot", "thead", "template"]);
+                self.insert_element(tag, attributes.to_vec());
+                self.mode = InsertionMode::InRow;
+            }
+            HtmlToken::StartTag { ref tag, .. } if tag == "th" || tag == "td" => {
+                self.parse_error("unexpected-start-tag");
+                self.clear_stack_back_to(&["tbody", "tfoot", "thead", "template"]);
+                self.insert_element("tr", Vec::new());
+                self.mode = InsertionMode::InRow;
+                self.process_token(token);
+            }
+            HtmlToken::EndTag { ref tag }
+                if matches!(tag.as_str(), "tbody" | "tfoot" | "thead") =>
+            {
+                if !self.has_element_in_scope(&[tag], Scope::Table) {
+                    self.parse_error("unexpected-end-tag");
+                    return;
+                }
+                self.clear_stack_back_to(&["tbody", "tfoot", "thead", "template"]);
+                self.stack_of_open_elements.pop();
+                self.mode = InsertionMode::InTable;
+            }
+            HtmlToken::StartTag { ref tag, .. }
+                if matches!(
+                    tag.as_str(),
+                    "caption" | "col" | "colgroup" | "tbody" | "tfoot" | "thead"
+                ) =>
+            {
+                self.close_table_body(token)
+            }
+            HtmlToken::EndTag { ref tag } if tag == "table" => self.close_table_body(token),
+            HtmlToken::EndTag { ref tag }
+                if matches!(
+                    tag.as_str(),
+                    "body" | "caption" | "col" | "colgroup" | "html" | "td" | "th" | "tr"
+                ) =>
+            {
+                self.parse_error("unexpected-end-tag")
+            }
+            _ => self.in_table(token),
         }
-        if c == '\n' || c == ' ' {
+    }
+    /// Closes the open table section and reprocesses `token` in the table.
+    fn close_table_body(&mut self, token: HtmlToken) {
+        if !self.has_element_in_scope(&["tbody", "thead", "tfoot"], Scope::Table) {
+            self.unexpected_token(&token);
             return;
         }
-        let node = Rc::new(RefCell::new(self.create_char(c)));
-        if current.borrow().first_child().is_some() {
-            current
-                .borrow()
-                .first_child()
-                .unwrap()
-                .borrow_mut()
-                .set_next_sibling(Some(node.clone()));
-            node.borrow_mut().set_previous_sibling(Rc::downgrade(
-                &current
-                    .borrow()
-                    .first_child()
-                    .expect("failed to get a first child"),
-            ));
-        } else {
-            current.borrow_mut().set_first_child(Some(node.clone()));
-        }
-        current.borrow_mut().set_last_child(Rc::downgrade(&node));
-        node.borrow_mut().set_parent(Rc::downgrade(&current));
-        self.stack_of_open_elements.push(node);
+        self.clear_stack_back_to(&["tbody", "tfoot", "thead", "template"]);
+        self.stack_of_open_elements.pop();
+        self.mode = InsertionMode::InTable;
+        self.process_token(token);
     }
-    fn create_element(&self, tag: &str, attributes: Vec<Attribute>) -> Node {
-        Node::new(NodeKind::Element(Element::new(tag, attributes)))
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-intr>
+    fn in_row(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::StartTag {
+                ref tag,
+                ref attributes,
+                ..
+            } if tag == "th" || tag == "td" => {
+                self.clear_stack_back_to(&["tr", "template"]);
+                self.insert_element(tag, attributes.to_vec());
+                self.mode = InsertionMode::InCell;
+                self.active_formatting_elements
+                    .push(FormattingEntry::Marker);
+            }
+            HtmlToken::EndTag { ref tag } if tag == "tr" => {
+                self.close_row();
+            }
+            HtmlToken::StartTag { ref tag, .. }
+                if matches!(
+                    tag.as_str(),
+                    "caption" | "col" | "colgroup" | "tbody" | "tfoot" | "thead" | "tr"
+                ) =>
+            {
+                if self.close_row() {
+                    self.process_token(token);
+                }
+            }
+            HtmlToken::EndTag { ref tag } if tag == "table" => {
+                if self.close_row() {
+                    self.process_token(token);
+                }
+            }
+            HtmlToken::EndTag { ref tag }
+                if matches!(tag.as_str(), "tbody" | "tfoot" | "thead") =>
+            {
+                if !self.has_element_in_scope(&[tag], Scope::Table) {
+                    self.parse_error("unexpected-end-tag");
+                    return;
+                }
+                if self.close_row() {
+                    self.process_token(token);
+                }
+            }
+            HtmlToken::EndTag { ref tag }
+                if matches!(
+                    tag.as_str(),
+                    "body" | "caption" | "col" | "colgroup" | "html" | "td" | "th"
+                ) =>
+            {
+                self.parse_error("unexpected-end-tag")
+            }
+            _ => self.in_table(token),
+        }
     }
-    fn insert_element(&mut self, tag: &str, attributes: Vec<Attribute>) {
-        let window = self.window.borrow();
-        let current = match self.stack_of_open_elements.last() {
-            Some(n) => n.clone(),
-            None => window.document(),
-        };
-        let node = Rc::new(RefCell::new(self.create_element(tag, attributes)));
-        if current.borrow().first_child().is_some() {
-            let mut last_sibling = current.borrow().first_child();
-            loop {
-                last_sibling = match last_sibling {
-                    Some(ref node) => {
-                        if node.borrow().next_sibling().is_some() {
-                            node.borrow().next_sibling()
-                        } else {
-                            break;
-                        }
-                    }
-                    None => unimplemented!("last_sibling should be Some"),
-                };
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-intd>
+    fn in_cell(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::EndTag { ref tag } if tag == "td" || tag == "th" => {
+                if !self.has_element_in_scope(&[tag], Scope::Table) {
+                    self.parse_error("unexpected-end-tag");
+                    return;
+                }
+                self.generate_implied_end_tags(None);
+                if !self.current_node_is(&[tag]) {
+                    self.parse_error("unexpected-end-tag");
+                }
+                self.pop_until(&[tag]);
+                self.clear_active_formatting_elements_to_last_marker();
+                self.mode = InsertionMode::InRow;
             }
-            last_sibling
-                .unwrap()
-                .borrow_mut()
-                .set_next_sibling(Some(node.clone()));
-            node.borrow_mut().set_previous_sibling(Rc::downgrade(
-                &current
-                    .borrow()
-                    .first_child()
-                    .expect("failed to get a first child"),
-            ))
-        } else {
-            current.borrow_mut().set_first_child(Some(node.clone()));
+            HtmlToken::StartTag { ref tag, .. }
+                if matches!(
+                    tag.as_str(),
+                    "caption"
+                        | "col"
+                        | "colgroup"
+                        | "tbody"
+                        | "td"
+                        | "tfoot"
+                        | "th"
+                        | "thead"
+                        | "tr"
+                ) =>
+            {
+                if !self.has_element_in_scope(&["td", "th"], Scope::Table) {
+                    self.parse_error("unexpected-start-tag");
+                    return;
+                }
+                self.close_cell();
+                self.process_token(token);
+            }
+            HtmlToken::EndTag { ref tag }
+                if matches!(
+                    tag.as_str(),
+                    "body" | "caption" | "col" | "colgroup" | "html"
+                ) =>
+            {
+                self.parse_error("unexpected-end-tag")
+            }
+            HtmlToken::EndTag { ref tag }
+                if matches!(tag.as_str(), "table" | "tbody" | "tfoot" | "thead" | "tr") =>
+            {
+                if !self.has_element_in_scope(&[tag], Scope::Table) {
+                    self.parse_error("unexpected-end-tag");
+                    return;
+                }
+                self.close_cell();
+                self.process_token(token);
+            }
+            _ => self.in_body(token),
         }
-        current.borrow_mut().set_last_child(Rc::downgrade(&node));
-        node.borrow_mut().set_parent(Rc::downgrade(&current));
-        self.stack_of_open_elements.push(node);
     }
-    pub fn construct_tree(&mut self) -> Rc<RefCell<Window>> {
-        let mut token = self.t.next();
-        while token.is_some() {
-            match self.mode {
-                InsertionMode::Initial => {
-                    if let Some(HtmlToken::Char(_)) = token {
-                        token = self.t.next();
-                        continue;
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inselect>
+    fn in_select(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::Char('\0') => self.parse_error("unexpected-null-character"),
+            HtmlToken::Char(c) => self.insert_character(c),
+            HtmlToken::Comment(ref data) => self.insert_comment(data, None),
+            HtmlToken::Doctype { .. } => self.parse_error("unexpected-doctype"),
+            HtmlToken::StartTag {
+                ref tag,
+                ref attributes,
+                ..
+            } => match tag.as_str() {
+                "html" => self.in_body(token.clone()),
+                "option" | "optgroup" | "hr" => {
+                    if self.current_node_is(&["option"]) {
+                        self.stack_of_open_elements.pop();
                     }
-                    self.mode = InsertionMode::BeforeHtml;
-                    continue;
-                }
-                InsertionMode::BeforeHtml => {
-                    match token {
-                        Some(HtmlToken::Char(c)) => {
-                            if c == ' ' || c == '\n' {
-                                token = self.t.next();
-                                continue;
-                            }
-                        }
-                        Some(HtmlToken::StartTag {
-                            ref tag,
-                            self_closing: _,
-                            ref attributes,
-                        }) => {
-                            if tag == "html" {
-                                self.insert_element(tag, attributes.to_vec());
-                                self.mode = InsertionMode::BeforeHead;
-                                token = self.t.next();
-                                continue;
-                            }
-                        }
-                        Some(HtmlToken::Eof) | None => {
-                            return self.window.clone();
-                        }
-                        _ => {}
+                    if tag != "option" && self.current_node_is(&["optgroup"]) {
+                        self.stack_of_open_elements.pop();
                     }
-                    self.insert_element("html", Vec::new());
-                    self.mode = InsertionMode::BeforeHead;
-                    continue;
-                }
-                InsertionMode::BeforeHead => {
-                    match token {
-                        Some(HtmlToken::Char(c)) => {
-                            if c == ' ' || c == '\n' {
-                                token = self.t.next();
-                                continue;
-                            }
-                        }
-                        Some(HtmlToken::StartTag {
-                            ref tag,
-                            self_closing: _,
-                            ref attributes,
-                        }) => {
-                            if tag == "head" {
-                                self.insert_element(tag, attributes.to_vec());
-                                self.mode = InsertionMode::InHead;
-                                token = self.t.next();
-                                continue;
-                            }
-                        }
-                        Some(HtmlToken::Eof) | None => {
-                            return self.window.clone();
-                        }
-                        _ => {}
+                    self.insert_element(tag, attributes.to_vec());
+                    if tag == "hr" {
+                        self.stack_of_open_elements.pop();
                     }
-                    self.insert_element("head", Vec::new());
-                    self.mode = InsertionMode::InHead;
-                    continue;
                 }
-                InsertionMode::InHead => {
-                    match token {
-                        Some(HtmlToken::Char(c)) => {
-                            if c == ' ' || c == '\n' {
-                                token = self.t.next();
-                                continue;
-                            }
-                        }
-                        Some(HtmlToken::StartTag {
-                            ref tag,
-                            self_closing: _,
-                            ref attributes,
-                        }) => {
-                            if tag == "style" || tag == "script" {
-                                self.insert_element(tag, attributes.to_vec());
-                                self.original_insertion_mode = self.mode;
-                                self.mode = InsertionMode::Text;
-                                token = self.t.next();
-                                continue;
-                            }
-                            if tag == "body" {
-                                self.pop_until(ElementKind::Head);
-                                self.mode = InsertionMode::AfterHead;
-                                continue;
-                            }
-                            if let Ok(_element_kind) = ElementKind::from_str(tag) {
-                                self.pop_until(ElementKind::Head);
-                                self.mode = InsertionMode::AfterHead;
-                                continue;
-                            }
-                        }
-                        Some(HtmlToken::EndTag { ref tag }) => {
-                            if tag == "head" {
-                                self.mode = InsertionMode::AfterHead;
-                                token = self.t.next();
-                                self.pop_until(ElementKind::Head);
-                                continue;
-                            }
-                        }
-                        Some(HtmlToken::Eof) | None => {
-                            return self.window.clone();
-                        } // _ => {}
-                    }
-                    token = self.t.next();
-                    continue;
+                "select" => {
+                    // A nested `select` closes the open one.
+                    self.parse_error("unexpected-start-tag");
+                    self.close_select();
                 }
-                InsertionMode::AfterHead => {
-                    match token {
-                        Some(HtmlToken::Char(c)) => {
-                            if c == ' ' || c == '\n' {
-                                self.insert_char(c);
-                                token = self.t.next();
-                                continue;
-                            }
-                        }
-                        Some(HtmlToken::StartTag {
-                            ref tag,
-                            self_closing: _,
-                            ref attributes,
-                        }) => {
-                            if tag == "body" {
-                                self.insert_element(tag, attributes.to_vec());
-                                token = self.t.next();
-                                self.mode = InsertionMode::InBody;
-                                continue;
-                            }
-                        }
-                        Some(HtmlToken::Eof) | None => {
-                            return self.window.clone();
-                        }
-                        _ => {}
+                "input" | "keygen" | "textarea" => {
+                    self.parse_error("unexpected-start-tag");
+                    if self.close_select() {
+                        self.process_token(token.clone());
                     }
-                    self.insert_element("body", Vec::new());
-                    self.mode = InsertionMode::InBody;
-                    continue;
                 }
-                InsertionMode::InBody => match token {
-                    Some(HtmlToken::StartTag {
-                        ref tag,
-                        self_closing: _,
-                        ref attributes,
-                    }) => match tag.as_str() {
-                        "p" => {
-                            self.insert_element(tag, attributes.to_vec());
-                            token = self.t.next();
-                            continue;
-                        }
-                        "h1" | "h2" => {
-                            self.insert_element(tag, attributes.to_vec());
-                            token = self.t.next();
-                            continue;
-                        }
-                        "a" => {
-                            self.insert_element(tag, attributes.to_vec());
-                            token = self.t.next();
-                            continue;
-                        }
-                        _ => {
-                            token = self.t.next();
-                        }
-                    },
-                    Some(HtmlToken::EndTag { ref tag }) => match tag.as_str() {
-                        "body" => {
-                            self.mode = InsertionMode::AfterBody;
-                            token = self.t.next();
-                            if !self.contain_in_stack(ElementKind::Body) {
-                                continue;
-                            }
-                            self.pop_until(ElementKind::Body);
-                            continue;
-                        }
-                        "html" => {
-                            if self.pop_current_node(ElementKind::Body) {
-                                self.mode = InsertionMode::AfterBody;
-                                assert!(self.pop_current_node(ElementKind::Html));
-                            } else {
-                                token = self.t.next();
-                            }
-                            continue;
-                        }
-                        "p" => {
-                            let element_kind = ElementKind::from_str(tag)
-                                .expect("failed to convert string to ElementKind");
-                            token = self.t.next();
-                            self.pop_until(element_kind);
-                            continue;
-                        }
-                        "h1" | "h2" => {
-                            let element_kind = ElementKind::from_str(tag)
-                                .expect("failed to convert string to ElementKind");
-                            token = self.t.next();
-                            self.pop_until(element_kind);
-                            continue;
-                        }
-                        "a" => {
-                            let element_kind = ElementKind::from_str(tag)
-                                .expect("failed to convert string to ElementKind");
-                            token = self.t.next();
-                            self.pop_until(element_kind);
-                            continue;
-                        }
-                        _ => {
-                            token = self.t.next();
-                        }
-                    },
-                    Some(HtmlToken::Eof) | None => {
-                        return self.window.clone();
+                "script" | "template" => self.in_head(token.clone()),
+                _ => self.parse_error("unexpected-start-tag"),
+            },
+            HtmlToken::EndTag { ref tag } => match tag.as_str() {
+                "optgroup" => {
+                    let len = self.stack_of_open_elements.len();
+                    if self.current_node_is(&["option"])
+                        && len >= 2
+                        && self.is_html_element(self.stack_of_open_elements[len - 2], &["optgroup"])
+                    {
+                        self.stack_of_open_elements.pop();
                     }
-                    Some(HtmlToken::Char(c)) => {
-                        self.insert_char(c);
-                        token = self.t.next();
-                        continue;
-                    } // _ => {}
-                },
-                InsertionMode::Text => {
-                    match token {
-                        Some(HtmlToken::Eof) | None => {
-                            return self.window.clone();
-                        }
-                        Some(HtmlToken::EndTag { ref tag }) => {
-                            if tag == "style" {
-                                self.pop_until(ElementKind::Style);
-                                self.mode = self.original_insertion_mode;
-                                token = self.t.next();
-                                continue;
-                            }
-                            if tag == "script" {
-                                self.pop_until(ElementKind::Script);
-                                self.mode = self.original_insertion_mode;
-                                token = self.t.next();
-                                continue;
-                            }
-                        }
-                        Some(HtmlToken::Char(c)) => {
-                            self.insert_char(c);
-                            token = self.t.next();
-                            continue;
-                        }
-                        _ => {}
+                    if !self.current_node_is(&["optgroup"]) {
+                        self.parse_error("unexpected-end-tag");
+                        return;
                     }
-                    self.mode = self.original_insertion_mode;
+                    self.stack_of_open_elements.pop();
                 }
-                InsertionMode::AfterBody => {
-                    match token {
-                        Some(HtmlToken::Char(_c)) => {
-                            token = self.t.next();
-                            continue;
-                        }
-                        Some(HtmlToken::EndTag { ref tag }) => {
-                            if tag == "html" {
-                                self.mode = InsertionMode::AfterAfterBody;
-                                token = self.t.next();
-                                continue;
-                            }
-                        }
-                        Some(HtmlToken::Eof) | None => {
-                            return self.window.clone();
-                        }
-                        _ => {}
+                "option" => {
+                    if !self.current_node_is(&["option"]) {
+                        self.parse_error("unexpected-end-tag");
+                        return;
                     }
-                    self.mode = InsertionMode::InBody;
+                    self.stack_of_open_elements.pop();
                 }
-                InsertionMode::AfterAfterBody => {
-                    match token {
-                        Some(HtmlToken::Char(_c)) => {
-                            token = self.t.next();
-                            continue;
-                        }
-                        Some(HtmlToken::Eof) | None => {
-                            return self.window.clone();
-                        }
-                        _ => {}
+                "select" => {
+                    if !self.close_select() {
+                        self.parse_error("unexpected-end-tag");
+                    }
+                }
+                "template" => self.in_head(token.clone()),
+                _ => self.parse_error("unexpected-end-tag"),
+            },
+            HtmlToken::Eof => self.in_body(token),
+        }
+    }
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inselectintable>
+    fn in_select_in_table(&mut self, token: HtmlToken) {
+        let table_tag = |tag: &str| {
+            matches!(
+                tag,
+                "caption" | "table" | "tbody" | "tfoot" | "thead" | "tr" | "td" | "th"
+            )
+        };
+        match token {
+            HtmlToken::StartTag { ref tag, .. } if table_tag(tag) => {
+                // A table tag closes the `select`.
+                self.parse_error("unexpected-start-tag");
+                self.pop_until(&["select"]);
+                self.reset_insertion_mode_appropriately();
+                self.process_token(token);
+            }
+            HtmlToken::EndTag { ref tag } if table_tag(tag) => {
+                self.parse_error("unexpected-end-tag");
+                if !self.has_element_in_scope(&[tag], Scope::Table) {
+                    return;
+                }
+                self.pop_until(&["select"]);
+                self.reset_insertion_mode_appropriately();
+                self.process_token(token);
+            }
+            _ => self.in_select(token),
+        }
+    }
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-intemplate>
+    fn in_template(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::Char(_) | HtmlToken::Comment(_) | HtmlToken::Doctype { .. } => {
+                self.in_body(token)
+            }
+            HtmlToken::StartTag { ref tag, .. } => {
+                let mode = match tag.as_str() {
+                    "base" | "basefont" | "bgsound" | "link" | "meta" | "noframes" | "script"
+                    | "style" | "template" | "title" => return self.in_head(token),
+                    "caption" | "colgroup" | "tbody" | "tfoot" | "thead" => InsertionMode::InTable,
+                    "col" => InsertionMode::InColumnGroup,
+                    "tr" => InsertionMode::InTableBody,
+                    "td" | "th" => InsertionMode::InRow,
+                    _ => InsertionMode::InBody,
+                };
+                self.switch_template_mode(mode);
+                self.process_token(token);
+            }
+            HtmlToken::EndTag { ref tag } if tag == "template" => self.in_head(token),
+            HtmlToken::EndTag { .. } => self.parse_error("unexpected-end-tag"),
+            HtmlToken::Eof => {
+                if !self.contain_in_stack(&["template"]) {
+                    return;
+                }
+                self.parse_error("unexpected-token");
+                self.pop_until(&["template"]);
+                self.clear_active_formatting_elements_to_last_marker();
+                self.template_insertion_modes.pop();
+                self.reset_insertion_mode_appropriately();
+                self.process_token(token);
+            }
+        }
+    }
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-afterbody>
+    fn after_body(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::Char(c) if is_whitespace(c) => self.in_body(token),
+            HtmlToken::Comment(ref data) => {
+                // Comments after the body go into the `html` element.
+                let html = self.stack_of_open_elements.first().cloned();
+                self.insert_comment(data, html);
+            }
+            HtmlToken::Doctype { .. } => self.parse_error("unexpected-doctype"),
+            HtmlToken::StartTag { ref tag, .. } if tag == "html" => self.in_body(token),
+            HtmlToken::EndTag { ref tag } if tag == "html" => {
+                if self.context.is_some() {
+                    self.parse_error("unexpected-end-tag");
+                    return;
+                }
+                self.mode = InsertionMode::AfterAfterBody;
+            }
+            HtmlToken::Eof => {}
+            _ => {
+                self.unexpected_token(&token);
+                self.mode = InsertionMode::InBody;
+                self.process_token(token);
+            }
+        }
+    }
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inframeset>
+    fn in_frameset(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::Char(c) if is_whitespace(c) => self.insert_character(c),
+            HtmlToken::Comment(ref data) => self.insert_comment(data, None),
+            HtmlToken::Doctype { .. } => self.parse_error("unexpected-doctype"),
+            HtmlToken::StartTag { ref tag, .. } if tag == "html" => self.in_body(token),
+            HtmlToken::StartTag {
+                ref tag,
+                ref attributes,
+                ..
+            } if tag == "frameset" || tag == "frame" => {
+                self.insert_element(tag, attributes.to_vec());
+                if tag == "frame" {
+                    self.stack_of_open_elements.pop();
+                }
+            }
+            HtmlToken::EndTag { ref tag } if tag == "frameset" => {
+                if self.current_node_is(&["html"]) {
+                    self.parse_error("unexpected-end-tag");
+                    return;
+                }
+                self.stack_of_open_elements.pop();
+                if self.context.is_none() && !self.current_node_is(&["frameset"]) {
+                    self.mode = InsertionMode::AfterFrameset;
+                }
+            }
+            HtmlToken::StartTag { ref tag, .. } if tag == "noframes" => self.in_head(token),
+            HtmlToken::Eof => {}
+            _ => self.unexpected_token(&token),
+        }
+    }
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-afterframeset>
+    fn after_frameset(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::Char(c) if is_whitespace(c) => self.insert_character(c),
+            HtmlToken::Comment(ref data) => self.insert_comment(data, None),
+            HtmlToken::Doctype { .. } => self.parse_error("unexpected-doctype"),
+            HtmlToken::StartTag { ref tag, .. } if tag == "html" => self.in_body(token),
+            HtmlToken::EndTag { ref tag } if tag == "html" => {
+                self.mode = InsertionMode::AfterAfterFrameset;
+            }
+            HtmlToken::StartTag { ref tag, .. } if tag == "noframes" => self.in_head(token),
+            HtmlToken::Eof => {}
+            _ => self.unexpected_token(&token),
+        }
+    }
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/parsing.html#the-after-after-body-insertion-mode>
+    fn after_after_body(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::Comment(ref data) => {
+                let document = self.document_node();
+                self.insert_comment(data, Some(document));
+            }
+            HtmlToken::Doctype { .. } => self.in_body(token),
+            HtmlToken::Char(c) if is_whitespace(c) => self.in_body(token),
+            HtmlToken::StartTag { ref tag, .. } if tag == "html" => self.in_body(token),
+            HtmlToken::Eof => {}
+            _ => {
+                self.unexpected_token(&token);
+                self.mode = InsertionMode::InBody;
+                self.process_token(token);
+            }
+        }
+    }
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/parsing.html#the-after-after-frameset-insertion-mode>
+    fn after_after_frameset(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::Comment(ref data) => {
+                let document = self.document_node();
+                self.insert_comment(data, Some(document));
+            }
+            HtmlToken::Doctype { .. } => self.in_body(token),
+            HtmlToken::Char(c) if is_whitespace(c) => self.in_body(token),
+            HtmlToken::StartTag { ref tag, .. } if tag == "html" => self.in_body(token),
+            HtmlToken::StartTag { ref tag, .. } if tag == "noframes" => self.in_head(token),
+            HtmlToken::Eof => {}
+            _ => self.unexpected_token(&token),
+        }
+    }
+    /// Processes a token in SVG or MathML content. Start tags of common HTML elements close the
+    /// foreign elements.
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inforeign>
+    fn in_foreign_content(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::Char('\0') => {
+                self.parse_error("unexpected-null-character");
+                self.insert_character('\u{FFFD}');
+            }
+            HtmlToken::Char(c) => {
+                self.insert_character(c);
+                if !is_whitespace(c) {
+                    self.frameset_ok = false;
+                }
+            }
+            HtmlToken::Comment(ref data) => self.insert_comment(data, None),
+            HtmlToken::Doctype { .. } => self.parse_error("unexpected-doctype"),
+            HtmlToken::StartTag {
+                ref tag,
+                ref attributes,
+                ..
+            } if FOREIGN_BREAKOUT_TAGS.contains(&tag.as_str())
+                || (tag == "font"
+                    && attributes
+                        .iter()
+                        .any(|a| matches!(a.name().as_str(), "color" | "face" | "size"))) =>
+            {
+                self.parse_error("unexpected-start-tag");
+                self.pop_foreign_elements();
+                self.process_in(self.mode, token);
+            }
+            HtmlToken::EndTag { ref tag } if tag == "br" || tag == "p" => {
+                self.parse_error("unexpected-end-tag");
+                self.pop_foreign_elements();
+                self.process_in(self.mode, token);
+            }
+            HtmlToken::StartTag {
+                ref tag,
+                self_closing,
+                ref attributes,
+            } => {
+                let namespace = self
+                    .adjusted_current_node()
+                    .and_then(|n| self.element(n))
+                    .map_or(Namespace::Html, |e| e.namespace());
+                let tag = match namespace {
+                    Namespace::Svg => SVG_TAG_NAMES
+                        .iter()
+                        .find(|(lower, _)| lower == tag)
+                        .map_or(tag.clone(), |(_, adjusted)| adjusted.to_string()),
+                    _ => tag.clone(),
+                };
+                self.insert_foreign_element(
+                    namespace,
+                    &tag,
+                    adjust_foreign_attributes(namespace, attributes),
+                );
+                if self_closing {
+                    self.stack_of_open_elements.pop();
+                }
+            }
+            HtmlToken::EndTag { ref tag } => {
+                let name = |parser: &Self, node: NodeId| {
+                    parser
+                        .element(node)
+                        .map(|e| e.local_name().to_ascii_lowercase())
+                };
+                let mut i = self.stack_of_open_elements.len() - 1;
+                if name(self, self.stack_of_open_elements[i]).as_ref() != Some(tag) {
+                    self.parse_error("unexpected-end-tag");
+                }
+                while i > 0 {
+                    let node = self.stack_of_open_elements[i];
+                    if name(self, node).as_ref() == Some(tag) {
+                        self.pop_until_node(node);
+                        return;
+                    }
+                    i -= 1;
+                    let html = self
+                        .element(self.stack_of_open_elements[i])
+                        .is_some_and(|e| e.namespace() == Namespace::Html);
+                    if html {
+                        self.process_in(self.mode, token);
+                        return;
                     }
-                    self.mode = InsertionMode::InBody;
                 }
             }
+            HtmlToken::Eof => {}
+        }
+    }
+    /// Pops the SVG and MathML elements up to an HTML element or an integration point.
+    fn pop_foreign_elements(&mut self) {
+        while let Some(current) = self.current_node() {
+            let element = match self.element(current) {
+                Some(e) => e,
+                None => return,
+            };
+            if element.namespace() == Namespace::Html
+                || is_mathml_text_integration_point(&element)
+                || is_html_integration_point(&element)
+            {
+                return;
+            }
+            self.stack_of_open_elements.pop();
         }
-        self.window.clone()
     }
 }
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::alloc::string::ToString;
+    use crate::renderer::dom::api::get_target_element_node;
+    use crate::renderer::dom::node::ElementKind;
+    use alloc::format;
     use alloc::vec;
     #[test]
     fn test_empty() {
         let html = "".to_string();
         let t = HtmlTokenizer::new(html);
-        let window = HtmlParser::new(t).construct_tree();
-        let expected = Rc::new(RefCell::new(Node::new(NodeKind::Document)));
-        assert_eq!(expected, window.borrow().document());
+        let doc = HtmlParser::new(t).construct_tree();
+        let doc = doc.borrow();
+        let expected = Node::new(NodeKind::Document);
+        assert_eq!(expected, doc.dom()[doc.node()]);
     }
     #[test]
     fn test_body() {
         let html = "<html><head></head><body></body></html>".to_string();
         let t = HtmlTokenizer::new(html);
-        let window = HtmlParser::new(t).construct_tree();
-        let document = window.borrow().document();
-        assert_eq!(
-            Rc::new(RefCell::new(Node::new(NodeKind::Document))),
-            document
-        );
-        let html = document
-            .borrow()
-            .first_child()
+        let doc = HtmlParser::new(t).construct_tree();
+        let doc = doc.borrow();
+        let dom = doc.dom();
+        let document = doc.node();
+        assert_eq!(Node::new(NodeKind::Document), dom[document]);
+        let html = dom
+            .first_child(document)
             .expect("failed to get a first child of document");
         assert_eq!(
-            Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new(
-                "html",
-                Vec::new()
-            ))))),
-            html
+            Node::new(NodeKind::Element(Element::new("html", Vec::new()))),
+            dom[html]
         );
-        let head = html
-            .borrow()
-            .first_child()
+        let head = dom
+            .first_child(html)
             .expect("failed to get a first child of html");
         assert_eq!(
-            Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new(
-                "head",
-                Vec::new()
-            ))))),
-            head
+            Node::new(NodeKind::Element(Element::new("head", Vec::new()))),
+            dom[head]
         );
-        let body = head
-            .borrow()
-            .next_sibling()
+        let body = dom
+            .next_sibling(head)
             .expect("failed to get a next sibling of head");
         assert_eq!(
-            Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new(
-                "body",
-                Vec::new()
-            ))))),
-            body
+            Node::new(NodeKind::Element(Element::new("body", Vec::new()))),
+            dom[body]
         );
     }
     #[test]
     fn test_text() {
         let html = "<html><head></head><body>text</body></html>".to_string();
         let t = HtmlTokenizer::new(html);
-        let window = HtmlParser::new(t).construct_tree();
-        let document = window.borrow().document();
-        assert_eq!(
-            Rc::new(RefCell::new(Node::new(NodeKind::Document))),
-            document
-        );
-        let html = document
-            .borrow()
-            .first_child()
+        let doc = HtmlParser::new(t).construct_tree();
+        let doc = doc.borrow();
+        let dom = doc.dom();
+        let document = doc.node();
+        assert_eq!(Node::new(NodeKind::Document), dom[document]);
+        let html = dom
+            .first_child(document)
             .expect("failed to get a first child of document");
         assert_eq!(
-            Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new(
-                "html",
-                Vec::new()
-            ))))),
-            html
+            Node::new(NodeKind::Element(Element::new("html", Vec::new()))),
+            dom[html]
         );
-        let body = html
-            .borrow()
-            .first_child()
-            .expect("failed to get a first child of document")
-            .borrow()
-            .next_sibling()
+        let body = dom
+            .next_sibling(
+                dom.first_child(html)
+                    .expect("failed to get a first child of document"),
+            )
             .expect("failed to get a next sibling of head");
         assert_eq!(
-            Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new(
-                "body",
-                Vec::new()
-            ))))),
-            body
+            Node::new(NodeKind::Element(Element::new("body", Vec::new()))),
+            dom[body]
         );
-        let text = body
-            .borrow()
-            .first_child()
+        let text = dom
+            .first_child(body)
             .expect("failed to get a first child of document");
-        assert_eq!(
-            Rc::new(RefCell::new(Node::new(NodeKind::Text("text".to_string())))),
-            text
-        );
+        assert_eq!(Node::new(NodeKind::Text("text".to_string())), dom[text]);
     }
     #[test]
     fn test_multiple_nodes() {
         let html = "<html><head></head><body><p><a foo=bar>text</a></p></body></html>".to_string();
         let t = HtmlTokenizer::new(html);
-        let window = HtmlParser::new(t).construct_tree();
-        let document = window.borrow().document();
+        let doc = HtmlParser::new(t).construct_tree();
+        let doc = doc.borrow();
+        let dom = doc.dom();
+        let document = doc.node();
 
-        let body = document
-            .borrow()
-            .first_child()
-            .expect("failed to get a first child of document")
-            .borrow()
-            .first_child()
-            .expect("failed to get a first child of document")
-            .borrow()
-            .next_sibling()
+        let body = dom
+            .next_sibling(
+                dom.first_child(
+                    dom.first_child(document)
+                        .expect("failed to get a first child of document"),
+                )
+                .expect("failed to get a first child of document"),
+            )
             .expect("failed to get a next sibling of head");
         assert_eq!(
-            Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new(
-                "body",
-                Vec::new()
-            ))))),
-            body
+            Node::new(NodeKind::Element(Element::new("body", Vec::new()))),
+            dom[body]
         );
 
-        let p = body
-            .borrow()
-            .first_child()
+        let p = dom
+            .first_child(body)
             .expect("failed to get a first child of body");
         assert_eq!(
-            Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new(
-                "p",
-                Vec::new()
-            ))))),
-            p
+            Node::new(NodeKind::Element(Element::new("p", Vec::new()))),
+            dom[p]
         );
 
         let mut attr = Attribute::new();
@@ -594,25 +3059,457 @@ mod tests {
         attr.add_char('b', false);
         attr.add_char('a', false);
         attr.add_char('r', false);
-        let a = p
-            .borrow()
-            .first_child()
+        let a = dom
+            .first_child(p)
             .expect("failed to get a first child of p");
         assert_eq!(
-            Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new(
-                "a",
-                vec![attr]
-            ))))),
-            a
+            Node::new(NodeKind::Element(Element::new("a", vec![attr]))),
+            dom[a]
         );
 
-        let text = a
-            .borrow()
-            .first_child()
+        let text = dom
+            .first_child(a)
             .expect("failed to get a first child of a");
+        assert_eq!(Node::new(NodeKind::Text("text".to_string())), dom[text]);
+    }
+    #[test]
+    fn test_form() {
+        let html = "<html><head></head><body><form>Name: <input name=q><button>Go</button></form></body></html>".to_string();
+        let t = HtmlTokenizer::new(html);
+        let doc = HtmlParser::new(t).construct_tree();
+        let doc = doc.borrow();
+        let dom = doc.dom();
+        let document = doc.node();
+        let form = dom
+            .first_child(
+                dom.last_child(
+                    dom.first_child(document)
+                        .expect("failed to get a first child of document"),
+                )
+                .expect("failed to get a last child of html"),
+            )
+            .expect("failed to get a first child of body");
+        assert_eq!(
+            Node::new(NodeKind::Element(Element::new("form", Vec::new()))),
+            dom[form]
+        );
+        let text = dom
+            .first_child(form)
+            .expect("failed to get a first child of form");
+        assert_eq!(Node::new(NodeKind::Text("Name: ".to_string())), dom[text]);
+        let input = dom
+            .next_sibling(text)
+            .expect("failed to get a next sibling of text");
+        assert_eq!(Some(ElementKind::Input), dom[input].element_kind());
+        assert!(dom.first_child(input).is_none());
+        let button = dom
+            .next_sibling(input)
+            .expect("failed to get a next sibling of input");
+        assert_eq!(Some(ElementKind::Button), dom[button].element_kind());
+        assert_eq!(Some(button), dom.last_child(form));
+    }
+    #[test]
+    fn test_comment_and_doctype() {
+        let html = "<!DOCTYPE html><!--a--><html><body><!--b--></body></html><!--c-->".to_string();
+        let t = HtmlTokenizer::new(html);
+        let doc = HtmlParser::new(t).construct_tree();
+        let doc = doc.borrow();
+        let dom = doc.dom();
+        let document = doc.node();
+        assert_eq!(QuirksMode::NoQuirks, doc.quirks_mode());
+        let doctype = dom
+            .first_child(document)
+            .expect("failed to get a first child of document");
+        match dom[doctype].kind() {
+            NodeKind::DocumentType { name, .. } => assert_eq!("html", name),
+            kind => panic!("unexpected node {:?}", kind),
+        }
+        let comment = dom
+            .next_sibling(doctype)
+            .expect("failed to get a next sibling of doctype");
+        match dom[comment].kind() {
+            NodeKind::Comment(data) => assert_eq!("a", data),
+            kind => panic!("unexpected node {:?}", kind),
+        }
+        let body =
+            get_target_element_node(dom, document, ElementKind::Body).expect("failed to get body");
+        match dom.first_child(body).map(|n| dom[n].kind()) {
+            Some(NodeKind::Comment(data)) => assert_eq!("b", data),
+            kind => panic!("unexpected node {:?}", kind),
+        }
+        let last = dom
+            .last_child(document)
+            .expect("failed to get a last child of document");
+        assert_eq!(NodeKind::Comment("c".to_string()), dom[last].kind());
+    }
+    #[test]
+    fn test_unknown_elements() {
+        let html = "<html><head><meta charset=utf-8></head><body><x-box><span>a</x-box>b<svg><circle/><g></g></svg><img>c</x-foo></body></html>".to_string();
+        let t = HtmlTokenizer::new(html);
+        let mut parser = HtmlParser::new(t);
+        let doc = parser.construct_tree();
+        let doc = doc.borrow();
+        let dom = doc.dom();
+        let document = doc.node();
+        let head =
+            get_target_element_node(dom, document, ElementKind::Head).expect("failed to get head");
+        let meta = dom.first_child(head).expect("failed to get meta");
+        assert_eq!(
+            NodeKind::Element(Element::new("meta", Vec::new())),
+            dom[meta].kind()
+        );
+        let body =
+            get_target_element_node(dom, document, ElementKind::Body).expect("failed to get body");
+        let x_box = dom.first_child(body).expect("failed to get x-box");
+        let element = dom[x_box]
+            .get_element()
+            .expect("x-box should be an element");
+        assert_eq!(None, element.kind());
+        assert_eq!("x-box", element.local_name());
+        assert_eq!("X-BOX", element.tag_name());
+        let span = dom.first_child(x_box).expect("failed to get span");
+        assert_eq!(
+            NodeKind::Element(Element::new("span", Vec::new())),
+            dom[span].kind()
+        );
+        let text = dom.next_sibling(x_box).expect("failed to get text");
+        assert_eq!(NodeKind::Text("b".to_string()), dom[text].kind());
+        let svg = dom.next_sibling(text).expect("failed to get svg");
+        let circle = dom.first_child(svg).expect("failed to get circle");
+        assert_eq!(
+            NodeKind::Element(Element::new_ns(Namespace::Svg, "circle", Vec::new())),
+            dom[circle].kind()
+        );
+        let g = dom.next_sibling(circle).expect("failed to get g");
+        assert_eq!(
+            NodeKind::Element(Element::new_ns(Namespace::Svg, "g", Vec::new())),
+            dom[g].kind()
+        );
+        let img = dom.next_sibling(svg).expect("failed to get img");
+        assert!(dom.first_child(img).is_none());
+        let text = dom.next_sibling(img).expect("failed to get text");
+        assert_eq!(NodeKind::Text("c".to_string()), dom[text].kind());
+        let codes: Vec<String> = parser.errors().iter().map(|e| e.code()).collect();
+        assert_eq!(
+            vec![
+                "missing-doctype",
+                "unexpected-end-tag",
+                "unexpected-end-tag"
+            ],
+            codes
+        );
+    }
+    /// Returns the outline of a subtree, with the children of an element in parentheses.
+    fn outline(dom: &Dom, node: NodeId) -> String {
+        let mut result = match dom[node].kind() {
+            NodeKind::Element(e) => e.local_name(),
+            NodeKind::Text(t) => format!("{:?}", t),
+            NodeKind::Comment(c) => format!("<!--{}-->", c),
+            _ => String::new(),
+        };
+        let mut children = Vec::new();
+        let mut child = dom.first_child(node);
+        while let Some(c) = child {
+            children.push(outline(dom, c));
+            child = dom.next_sibling(c);
+        }
+        if !children.is_empty() {
+            result.push_str(&format!("({})", children.join(" ")));
+        }
+        result
+    }
+    #[test]
+    fn test_implied_end_tags() {
+        for (html, expected) in [
+            ("<p>a<div>b</div>", r#"body(p("a") div("b"))"#),
+            ("<ul><li>1<li>2</ul>c", r#"body(ul(li("1") li("2")) "c")"#),
+            (
+                "<dl><dt>a<dd>b<dt>c</dl>",
+                r#"body(dl(dt("a") dd("b") dt("c")))"#,
+            ),
+            ("<h1>a<h2>b</h1>c", r#"body(h1("a") h2("b") "c")"#),
+            ("<p>a</p></p>", r#"body(p("a") p)"#),
+            ("<div><p>a</div>b", r#"body(div(p("a")) "b")"#),
+            ("<pre>\n  a\n</pre>", r#"body(pre("  a\n"))"#),
+            ("<p>a<hr>b", r#"body(p("a") hr "b")"#),
+            (
+                "<table><tr><td>1<td>2<tr><th>3</table>",
+                r#"body(table(tbody(tr(td("1") td("2")) tr(th("3")))))"#,
+            ),
+            ("<td>a</td>", r#"body("a")"#),
+            ("a</br>b", r#"body("a" br "b")"#),
+        ] {
+            let t = HtmlTokenizer::new(format!("<!DOCTYPE html>{}", html));
+            let doc = HtmlParser::new(t).construct_tree();
+            let doc = doc.borrow();
+            let dom = doc.dom();
+            let body = get_target_element_node(dom, doc.node(), ElementKind::Body)
+                .expect("failed to get body");
+            assert_eq!(expected, outline(dom, body), "{}", html);
+        }
+    }
+    /// Returns the outline of the body of `html`, which is parsed after a DOCTYPE.
+    fn body_outline(html: &str) -> String {
+        let t = HtmlTokenizer::new(format!("<!DOCTYPE html>{}", html));
+        let doc = HtmlParser::new(t).construct_tree();
+        let doc = doc.borrow();
+        let dom = doc.dom();
+        let body = get_target_element_node(dom, doc.node(), ElementKind::Body)
+            .expect("failed to get body");
+        outline(dom, body)
+    }
+    #[test]
+    fn test_adoption_agency() {
+        for (html, expected) in [
+            ("<b><i>a</b>b</i>", r#"body(b(i("a")) i("b"))"#),
+            ("<a>1<p>2</a>3</p>", r#"body(a("1") p(a("2") "3"))"#),
+            ("<p><b>a<p>b", r#"body(p(b("a")) p(b("b")))"#),
+            ("<a>1<a>2", r#"body(a("1") a("2"))"#),
+            ("<b>1<div>2</b>3</div>", r#"body(b("1") div(b("2") "3"))"#),
+        ] {
+            assert_eq!(expected, body_outline(html), "{}", html);
+        }
+    }
+    #[test]
+    fn test_tables() {
+        for (html, expected) in [
+            (
+                "<table>a<tr><td>b</table>",
+                r#"body("a" table(tbody(tr(td("b")))))"#,
+            ),
+            (
+                "<table><caption>c</caption><col><tr><td>1</td></tr></table>",
+                r#"body(table(caption("c") colgroup(col) tbody(tr(td("1")))))"#,
+            ),
+            (
+                "<table><tr><td><b>a</td>b</table>",
+                r#"body("b" table(tbody(tr(td(b("a"))))))"#,
+            ),
+            (
+                "<table><tr><td><select><option>1<td>2</table>",
+                r#"body(table(tbody(tr(td(select(option("1"))) td("2")))))"#,
+            ),
+            (
+                "<select><option>1<option>2<optgroup><option>3</select>",
+                r#"body(select(option("1") option("2") optgroup(option("3"))))"#,
+            ),
+            ("<table><div>a</div></table>", r#"body(div("a") table)"#),
+        ] {
+            assert_eq!(expected, body_outline(html), "{}", html);
+        }
+    }
+    #[test]
+    fn test_template_and_frameset() {
+        let t = HtmlTokenizer::new("<!DOCTYPE html><template><td>a</td></template>".to_string());
+        let doc = HtmlParser::new(t).construct_tree();
+        let doc = doc.borrow();
+        let dom = doc.dom();
+        let document = doc.node();
+        let head =
+            get_target_element_node(dom, document, ElementKind::Head).expect("failed to get head");
+        let template = dom.first_child(head).expect("failed to get template");
+        assert!(dom.first_child(template).is_none());
+        let contents = dom[template]
+            .template_contents()
+            .expect("failed to get template contents");
+        assert_eq!(NodeKind::DocumentFragment, dom[contents].kind());
+        assert_eq!(r#"(td("a"))"#, outline(dom, contents));
+
+        let t = HtmlTokenizer::new(
+            "<!DOCTYPE html><frameset><frame><frameset></frameset></frameset>".to_string(),
+        );
+        let doc = HtmlParser::new(t).construct_tree();
+        let doc = doc.borrow();
+        let dom = doc.dom();
+        let html = dom.last_child(doc.node()).expect("failed to get html");
+        assert_eq!("html(head frameset(frame frameset))", outline(dom, html));
+    }
+    #[test]
+    fn test_foreign_content() {
+        let html = "<svg><foreignobject><p>a</p></foreignobject><clippath viewbox=\"0 0 1 1\"/><![CDATA[<x>]]></svg><math><mi><b>b</b></mi></math><p>c<![CDATA[d]]>";
+        assert_eq!(
+            r#"body(svg(foreignObject(p("a")) clipPath "<x>") math(mi(b("b"))) p("c" <!--[CDATA[d]]-->))"#,
+            body_outline(html)
+        );
+        let t = HtmlTokenizer::new(format!("<!DOCTYPE html>{}", html));
+        let doc = HtmlParser::new(t).construct_tree();
+        let doc = doc.borrow();
+        let dom = doc.dom();
+        let document = doc.node();
+        let svg = dom
+            .first_child(
+                get_target_element_node(dom, document, ElementKind::Body)
+                    .expect("failed to get body"),
+            )
+            .expect("failed to get svg");
+        let clip_path = dom
+            .next_sibling(dom.first_child(svg).expect("failed to get foreignObject"))
+            .expect("failed to get clipPath");
+        let attribute = dom[clip_path]
+            .get_element()
+            .expect("should be an element")
+            .attributes();
+        assert_eq!("viewBox", attribute[0].name());
+
+        assert_eq!(
+            r#"body(plaintext("<b>a</plaintext>"))"#,
+            body_outline("<plaintext><b>a</plaintext>")
+        );
+    }
+    #[test]
+    fn test_quirks_mode() {
+        for (html, mode) in [
+            ("<p>", QuirksMode::Quirks),
+            ("<!DOCTYPE html>", QuirksMode::NoQuirks),
+            ("<!DOCTYPE html PUBLIC \"-//W3C//DTD HTML 4.01 Transitional//EN\">", QuirksMode::Quirks),
+            (
+                "<!DOCTYPE html PUBLIC \"-//W3C//DTD HTML 4.01 Transitional//EN\" \"http://www.w3.org/TR/html4/loose.dtd\">",
+                QuirksMode::LimitedQuirks,
+            ),
+            ("<!DOCTYPE svg>", QuirksMode::Quirks),
+        ] {
+            let t = HtmlTokenizer::new(html.to_string());
+            let doc = HtmlParser::new(t).construct_tree();
+            let doc = doc.borrow();
+            assert_eq!(mode, doc.quirks_mode(), "{}", html);
+        }
+    }
+    #[test]
+    fn test_text_content_elements() {
+        let html = "<html><head><title>a &amp; <b></title><style>\n p > a { color: red; }</style></head><body><xmp><p></xmp><textarea><p>&lt;</textarea></body></html>".to_string();
+        let t = HtmlTokenizer::new(html);
+        let doc = HtmlParser::new(t).construct_tree();
+        let doc = doc.borrow();
+        let dom = doc.dom();
+        let document = doc.node();
+        for (kind, expected) in [
+            (ElementKind::Title, "a & <b>"),
+            (ElementKind::Style, "\n p > a { color: red; }"),
+            (ElementKind::Xmp, "<p>"),
+            (ElementKind::Textarea, "<p><"),
+        ] {
+            let node =
+                get_target_element_node(dom, document, kind).expect("failed to get an element");
+            let text = dom.first_child(node).expect("failed to get a first child");
+            let kind = dom[text].kind();
+            match kind {
+                NodeKind::Text(s) => assert_eq!(expected, s),
+                kind => panic!("unexpected node {:?}", kind),
+            }
+        }
+        assert!(get_target_element_node(dom, document, ElementKind::P).is_none());
+    }
+    #[test]
+    fn test_parse_errors() {
+        let html = "<html><body></p><p>a</p></h1><!DOCTYPE html></body></html>".to_string();
+        let t = HtmlTokenizer::new(html);
+        let mut parser = HtmlParser::new(t);
+        let doc = parser.construct_tree();
+        let doc = doc.borrow();
+        let codes: Vec<String> = parser.errors().iter().map(|e| e.code()).collect();
+        assert_eq!(
+            vec![
+                "missing-doctype",
+                "unexpected-end-tag",
+                "unexpected-end-tag",
+                "unexpected-doctype"
+            ],
+            codes
+        );
+        let dom = doc.dom();
+        let document = doc.node();
+        assert!(get_target_element_node(dom, document, ElementKind::P).is_some());
+    }
+    #[test]
+    fn test_run_until_script() {
+        let html = "<p>a</p><script>1</script><style>p{}</style><p>b</p><script>2</script><p>c"
+            .to_string();
+        let mut parser = HtmlParser::new(HtmlTokenizer::new(html));
+        let doc = parser.document();
+        let document = doc.borrow().node();
+        let first = parser
+            .run_until_script()
+            .expect("failed to stop at the first script");
+        {
+            let doc = doc.borrow();
+            let dom = doc.dom();
+            assert_eq!(Some(ElementKind::Script), dom[first].element_kind());
+            // The parser hasn't reached the elements after the script yet.
+            assert!(dom.next_sibling(first).is_none());
+        }
+        let second = parser
+            .run_until_script()
+            .expect("failed to stop at the second script");
+        assert_ne!(first, second);
+        assert!(parser.run_until_script().is_none());
+        let doc = doc.borrow();
+        let dom = doc.dom();
+        let body =
+            get_target_element_node(dom, document, ElementKind::Body).expect("failed to get body");
+        assert_eq!(
+            Some(ElementKind::P),
+            dom.last_child(body).and_then(|n| dom[n].element_kind())
+        );
+    }
+    #[test]
+    fn test_parse_fragment() {
+        for (namespace, context, markup, expected) in [
+            (Namespace::Html, "div", "<p>a<p>b", r#"p("a") p("b")"#),
+            (Namespace::Html, "ul", "<li>1<li>2", r#"li("1") li("2")"#),
+            (Namespace::Html, "tr", "<td>1<td>2", r#"td("1") td("2")"#),
+            (Namespace::Html, "td", "a<td>b</tr>c", r#""abc""#),
+            (
+                Namespace::Html,
+                "select",
+                "<option>a<p>b",
+                r#"option("ab")"#,
+            ),
+            (Namespace::Html, "textarea", "<b>a</b>", r#""<b>a</b>""#),
+            (Namespace::Html, "template", "<tr><td>1", r#"tr(td("1"))"#),
+            (Namespace::Svg, "svg", "<circle/><p>a", r#"circle p("a")"#),
+        ] {
+            let mut document = Document::new();
+            let context = document
+                .dom_mut()
+                .alloc(Node::new(NodeKind::Element(Element::new_ns(
+                    namespace,
+                    context,
+                    Vec::new(),
+                ))));
+            let nodes = HtmlParser::parse_fragment(&mut document, context, markup);
+            let dom = document.dom();
+            let outlines: Vec<String> = nodes.iter().map(|n| outline(dom, *n)).collect();
+            assert_eq!(expected, outlines.join(" "), "{}", markup);
+            assert!(nodes.iter().all(|n| dom.parent(*n).is_none()));
+            assert!(dom.first_child(context).is_none());
+        }
+    }
+    #[test]
+    fn test_incremental_tree_construction() {
+        let html = "<html><head><title>a</title></head><body><p>hello</p><a href=\"x\">world</a></body></html>";
+        let expected = HtmlParser::new(HtmlTokenizer::new(html.to_string())).construct_tree();
+        let mut parser = HtmlParser::new(HtmlTokenizer::new_incremental());
+        let (first, second) = html.split_at(html.find("<a").expect("failed to find `<a`"));
+        parser.feed(first);
+        let doc = parser.document();
+        let document = doc.borrow().node();
+        {
+            let doc = doc.borrow();
+            let dom = doc.dom();
+            assert!(get_target_element_node(dom, document, ElementKind::P).is_some());
+            assert!(get_target_element_node(dom, document, ElementKind::A).is_none());
+        }
+        parser.feed(second);
+        let doc = parser.finish();
+        let doc = doc.borrow();
+        let dom = doc.dom();
         assert_eq!(
-            Rc::new(RefCell::new(Node::new(NodeKind::Text("text".to_string())))),
-            text
+            outline(expected.borrow().dom(), expected.borrow().node()),
+            outline(dom, document)
         );
+        let a = get_target_element_node(dom, document, ElementKind::A)
+            .expect("failed to get an element");
+        let text = dom.first_child(a).expect("failed to get a first child");
+        assert_eq!(NodeKind::Text("world".to_string()), dom[text].kind());
     }
 }
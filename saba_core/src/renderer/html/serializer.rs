@@ -0,0 +1,181 @@
+use crate::renderer::dom::node::Dom;
+use crate::renderer::dom::node::Namespace;
+use crate::renderer::dom::node::NodeId;
+use crate::renderer::dom::node::NodeKind;
+use alloc::string::String;
+
+/// The elements that have no end tag and no contents.
+/// # References:
+/// - <https://html.spec.whatwg.org/multipage/syntax.html#void-elements>
+static VOID_ELEMENTS: [&str; 17] = [
+    "area", "base", "basefont", "bgsound", "br", "col", "embed", "frame", "hr", "img", "input",
+    "keygen", "link", "meta", "param", "source", "track",
+];
+
+/// The elements whose text is serialized without escaping, because the tokenizer doesn't decode
+/// character references in them.
+static RAW_TEXT_ELEMENTS: [&str; 7] = [
+    "style",
+    "script",
+    "xmp",
+    "iframe",
+    "noembed",
+    "noframes",
+    "plaintext",
+];
+
+/// Serializes the children of `node`, which is the getter of `innerHTML`. The children of a
+/// `template` element are its template contents.
+/// # Parameters
+/// - `dom`: The nodes of the document.
+/// - `node`: The node whose children are serialized.
+/// # Returns
+/// - The HTML markup of the children.
+/// # References:
+/// - <https://html.spec.whatwg.org/multipage/parsing.html#html-fragment-serialisation-algorithm>
+pub fn serialize_children(dom: &Dom, node: NodeId) -> String {
+    let mut result = String::new();
+    serialize_children_into(dom, node, &mut result);
+    result
+}
+
+/// Serializes `node` and its descendants, which is the getter of `outerHTML`. A document is
+/// serialized as its children, including the DOCTYPE.
+/// # Parameters
+/// - `dom`: The nodes of the document.
+/// - `node`: The node to serialize.
+/// # Returns
+/// - The HTML markup of the node.
+/// # References:
+/// - <https://html.spec.whatwg.org/multipage/dynamic-markup-insertion.html#dom-element-outerhtml>
+pub fn serialize_node(dom: &Dom, node: NodeId) -> String {
+    let mut result = String::new();
+    serialize_node_into(dom, node, &mut result);
+    result
+}
+
+fn serialize_children_into(dom: &Dom, node: NodeId, result: &mut String) {
+    let parent = dom[node].template_contents().unwrap_or(node);
+    for child in dom.children(parent) {
+        serialize_node_into(dom, child, result);
+    }
+}
+
+fn serialize_node_into(dom: &Dom, node: NodeId, result: &mut String) {
+    match dom[node].kind {
+        NodeKind::Document | NodeKind::DocumentFragment => {
+            serialize_children_into(dom, node, result)
+        }
+        NodeKind::DocumentType { ref name, .. } => {
+            result.push_str("<!DOCTYPE ");
+            result.push_str(name);
+            result.push('>');
+        }
+        NodeKind::Element(ref e) => {
+            let name = e.local_name();
+            result.push('<');
+            result.push_str(&name);
+            for attribute in e.attributes() {
+                result.push(' ');
+                result.push_str(&attribute.name());
+                result.push_str("=\"");
+                result.push_str(&escape(&attribute.value(), true));
+                result.push('"');
+            }
+            result.push('>');
+            if e.namespace() == Namespace::Html && VOID_ELEMENTS.contains(&name.as_str()) {
+                return;
+            }
+            serialize_children_into(dom, node, result);
+            result.push_str("</");
+            result.push_str(&name);
+            result.push('>');
+        }
+        NodeKind::Text(ref text) => {
+            let is_raw = match dom.parent(node).map(|p| &dom[p].kind) {
+                Some(NodeKind::Element(e)) => {
+                    e.namespace() == Namespace::Html
+                        && RAW_TEXT_ELEMENTS.contains(&e.local_name().as_str())
+                }
+                _ => false,
+            };
+            if is_raw {
+                result.push_str(text);
+            } else {
+                result.push_str(&escape(text, false));
+            }
+        }
+        NodeKind::Comment(ref data) => {
+            result.push_str("<!--");
+            result.push_str(data);
+            result.push_str("-->");
+        }
+    }
+}
+
+/// Escapes the characters that would be parsed as markup.
+/// # References:
+/// - <https://html.spec.whatwg.org/multipage/parsing.html#escapingString>
+fn escape(s: &str, attribute_mode: bool) -> String {
+    let mut result = String::new();
+    for c in s.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '\u{00A0}' => result.push_str("&nbsp;"),
+            '"' if attribute_mode => result.push_str("&quot;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::dom::api::get_element_by_id;
+    use crate::renderer::html::parser::HtmlParser;
+    use crate::renderer::html::token::HtmlTokenizer;
+    use alloc::string::ToString;
+    fn parse(html: &str) -> (Dom, NodeId) {
+        let document = HtmlParser::new(HtmlTokenizer::new(html.to_string())).construct_tree();
+        let document = document.borrow();
+        (document.dom().clone(), document.node())
+    }
+    #[test]
+    fn test_serialize_document() {
+        let (dom, document) =
+            parse("<!doctype html><title>a&amp;b</title><p class=x>hello<br>world");
+        assert_eq!(
+            "<!DOCTYPE html><html><head><title>a&amp;b</title></head><body><p class=\"x\">hello<br>world</p></body></html>",
+            serialize_node(&dom, document)
+        );
+    }
+    #[test]
+    fn test_escape() {
+        let (dom, document) = parse(
+            "<div id=t title='a\"b&amp;c'>1 &lt; 2 &amp;&nbsp;<!--note--><script>if (a < b) {}</script></div>",
+        );
+        let div = get_element_by_id(&dom, document, "t").expect("failed to get div");
+        assert_eq!(
+            "1 &lt; 2 &amp;&nbsp;<!--note--><script>if (a < b) {}</script>",
+            serialize_children(&dom, div)
+        );
+        assert_eq!(
+            "<div id=\"t\" title=\"a&quot;b&amp;c\">1 &lt; 2 &amp;&nbsp;<!--note--><script>if (a < b) {}</script></div>",
+            serialize_node(&dom, div)
+        );
+    }
+    #[test]
+    fn test_template_and_foreign_elements() {
+        let (dom, document) = parse(
+            "<div id=t><template><p>a</p></template><svg viewbox='0 0 1 1'><circle/></svg></div>",
+        );
+        let div = get_element_by_id(&dom, document, "t").expect("failed to get div");
+        assert_eq!(
+            "<template><p>a</p></template><svg viewBox=\"0 0 1 1\"><circle></circle></svg>",
+            serialize_children(&dom, div)
+        );
+    }
+}
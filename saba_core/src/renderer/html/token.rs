@@ -1,7 +1,50 @@
+use crate::renderer::diagnostics::LineIndex;
+use crate::renderer::diagnostics::ParseError;
+use crate::renderer::diagnostics::SourceLanguage;
+use crate::renderer::diagnostics::Span;
+use crate::renderer::diagnostics::SpannedTokenizer;
 use crate::renderer::html::attribute::Attribute;
+use crate::renderer::html::entity::match_named_character_reference;
+use crate::renderer::html::entity::MAX_NAME_LENGTH;
+use alloc::collections::VecDeque;
+use alloc::format;
 use alloc::string::String;
 use alloc::vec::Vec;
 
+/// The replacements of the C1 control code points in numeric character references, taken from
+/// windows-1252.
+/// # References:
+/// - <https://html.spec.whatwg.org/multipage/parsing.html#numeric-character-reference-end-state>
+static C1_REPLACEMENTS: [(u32, char); 27] = [
+    (0x80, '\u{20AC}'),
+    (0x82, '\u{201A}'),
+    (0x83, '\u{0192}'),
+    (0x84, '\u{201E}'),
+    (0x85, '\u{2026}'),
+    (0x86, '\u{2020}'),
+    (0x87, '\u{2021}'),
+    (0x88, '\u{02C6}'),
+    (0x89, '\u{2030}'),
+    (0x8A, '\u{0160}'),
+    (0x8B, '\u{2039}'),
+    (0x8C, '\u{0152}'),
+    (0x8E, '\u{017D}'),
+    (0x91, '\u{2018}'),
+    (0x92, '\u{2019}'),
+    (0x93, '\u{201C}'),
+    (0x94, '\u{201D}'),
+    (0x95, '\u{2022}'),
+    (0x96, '\u{2013}'),
+    (0x97, '\u{2014}'),
+    (0x98, '\u{02DC}'),
+    (0x99, '\u{2122}'),
+    (0x9A, '\u{0161}'),
+    (0x9B, '\u{203A}'),
+    (0x9C, '\u{0153}'),
+    (0x9E, '\u{017E}'),
+    (0x9F, '\u{0178}'),
+];
+
 /// Enum representing HTML token.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum HtmlToken {
@@ -14,6 +57,14 @@ pub enum HtmlToken {
         tag: String,
     },
     Char(char),
+    Comment(String),
+    /// A DOCTYPE token. Missing names and identifiers are `None`, which is distinct from empty.
+    Doctype {
+        name: Option<String>,
+        public_id: Option<String>,
+        system_id: Option<String>,
+        force_quirks: bool,
+    },
     Eof,
 }
 
@@ -35,11 +86,54 @@ pub enum State {
     AttributeValueUnquoted,
     AfterAttributeValueQuoted,
     SelfClosingStartTag,
+    Rcdata,
+    RcdataLessThanSign,
+    RcdataEndTagOpen,
+    RcdataEndTagName,
+    Rawtext,
+    RawtextLessThanSign,
+    RawtextEndTagOpen,
+    RawtextEndTagName,
     ScriptData,
     ScriptDataLessThanSign,
     ScriptDataEndTagOpen,
     ScriptDataEndTagName,
-    TemporaryBuffer,
+    CharacterReference,
+    NamedCharacterReference,
+    AmbiguousAmpersand,
+    NumericCharacterReference,
+    HexadecimalCharacterReferenceStart,
+    DecimalCharacterReferenceStart,
+    HexadecimalCharacterReference,
+    DecimalCharacterReference,
+    MarkupDeclarationOpen,
+    CommentStart,
+    CommentStartDash,
+    Comment,
+    CommentEndDash,
+    CommentEnd,
+    CommentEndBang,
+    BogusComment,
+    Doctype,
+    BeforeDoctypeName,
+    DoctypeName,
+    AfterDoctypeName,
+    AfterDoctypePublicKeyword,
+    BeforeDoctypePublicIdentifier,
+    DoctypePublicIdentifierDoubleQuoted,
+    DoctypePublicIdentifierSingleQuoted,
+    AfterDoctypePublicIdentifier,
+    BetweenDoctypePublicAndSystemIdentifiers,
+    AfterDoctypeSystemKeyword,
+    BeforeDoctypeSystemIdentifier,
+    DoctypeSystemIdentifierDoubleQuoted,
+    DoctypeSystemIdentifierSingleQuoted,
+    AfterDoctypeSystemIdentifier,
+    BogusDoctype,
+    Plaintext,
+    CdataSection,
+    CdataSectionBracket,
+    CdataSectionEnd,
 }
 
 /// Struct representing HTML tokenizer.
@@ -51,29 +145,238 @@ pub struct HtmlTokenizer {
     latest_token: Option<HtmlToken>,
     input: Vec<char>,
     buf: String,
+    return_state: State,
+    character_reference_code: u32,
+    pending_chars: VecDeque<char>,
+    last_start_tag: Option<String>,
+    line_index: LineIndex,
+    token_start: usize,
+    errors: Vec<ParseError>,
+    /// Whether all of the input has been fed.
+    closed: bool,
+    /// Whether `next()` stopped at the end of the fed input, in the middle of a token or not.
+    suspended: bool,
+    /// The bytes at the end of the fed input that don't form a complete UTF-8 sequence yet.
+    pending_bytes: Vec<u8>,
+    /// Whether a CDATA section can start, which the tree builder allows in foreign content.
+    cdata_allowed: bool,
 }
 impl HtmlTokenizer {
+    /// Creates a tokenizer for a whole document.
     pub fn new(html: String) -> Self {
+        let mut tokenizer = Self::new_incremental();
+        tokenizer.feed(&html);
+        tokenizer.close();
+        tokenizer
+    }
+    /// Creates a tokenizer whose input is fed in chunks by `feed()` or `feed_bytes()`. `next()`
+    /// returns `None` when it reaches the end of the fed input, and continues from there once
+    /// more input is fed, until `close()` is called.
+    pub fn new_incremental() -> Self {
         Self {
             state: State::Data,
             pos: 0,
             reconsume: false,
             latest_token: None,
-            input: html.chars().collect(),
+            line_index: LineIndex::new(&[]),
+            token_start: 0,
+            errors: Vec::new(),
+            input: Vec::new(),
             buf: String::new(),
+            return_state: State::Data,
+            character_reference_code: 0,
+            pending_chars: VecDeque::new(),
+            last_start_tag: None,
+            closed: false,
+            suspended: false,
+            pending_bytes: Vec::new(),
+            cdata_allowed: false,
+        }
+    }
+    /// Appends a chunk of the document to the input.
+    pub fn feed(&mut self, chunk: &str) {
+        assert!(!self.closed, "the input of the tokenizer is already closed");
+        let chars: Vec<char> = chunk.chars().collect();
+        self.line_index.extend(self.input.len(), &chars);
+        self.input.extend(chars);
+    }
+    /// Appends a chunk of the document encoded in UTF-8. A sequence split between chunks is
+    /// decoded once the rest of it arrives, and an invalid sequence becomes U+FFFD.
+    pub fn feed_bytes(&mut self, bytes: &[u8]) {
+        self.pending_bytes.extend_from_slice(bytes);
+        let mut chunk = String::new();
+        let mut rest = &self.pending_bytes[..];
+        loop {
+            match core::str::from_utf8(rest) {
+                Ok(s) => {
+                    chunk.push_str(s);
+                    rest = &[];
+                    break;
+                }
+                Err(e) => {
+                    let (valid, after) = rest.split_at(e.valid_up_to());
+                    chunk.push_str(core::str::from_utf8(valid).unwrap_or_default());
+                    match e.error_len() {
+                        Some(len) => {
+                            chunk.push('\u{FFFD}');
+                            rest = &after[len..];
+                        }
+                        // The sequence may be completed by the next chunk.
+                        None => {
+                            rest = after;
+                            break;
+                        }
+                    }
+                }
+            }
         }
+        self.pending_bytes = rest.to_vec();
+        self.feed(&chunk);
+    }
+    /// Marks the end of the input, so that the tokenizer emits the last tokens.
+    pub fn close(&mut self) {
+        if !self.pending_bytes.is_empty() {
+            self.pending_bytes.clear();
+            self.feed("\u{FFFD}");
+        }
+        self.closed = true;
+    }
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+    /// Returns whether the last `next()` returned `None` because it needs more input.
+    pub fn is_suspended(&self) -> bool {
+        self.suspended
+    }
+    /// Stops at the end of the fed input. The current character, if any, is consumed again when
+    /// `next()` is called after more input is fed.
+    fn suspend(&mut self) -> Option<HtmlToken> {
+        self.suspended = true;
+        None
+    }
+    /// Returns whether the input that is fed so far ends before `len` characters from the
+    /// current input character, so that looking ahead has to wait for more input.
+    fn needs_more_input(&self, len: usize) -> bool {
+        !self.closed && self.pos - 1 + len > self.input.len()
+    }
+    /// Returns whether the input that is fed so far ends in the middle of `word` from the current
+    /// input character, ignoring ASCII case.
+    fn is_word_pending(&self, word: &str) -> bool {
+        let start = self.pos - 1;
+        self.needs_more_input(word.chars().count())
+            && self.input[start..]
+                .iter()
+                .zip(word.chars())
+                .all(|(a, b)| a.eq_ignore_ascii_case(&b))
+    }
+    /// Allows or disallows CDATA sections. The tree builder allows them when the adjusted current
+    /// node is not an HTML element.
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/parsing.html#markup-declaration-open-state>
+    pub fn set_cdata_allowed(&mut self, allowed: bool) {
+        self.cdata_allowed = allowed;
+    }
+    /// Switches the state of the tokenizer. The tree builder uses this to parse the content of
+    /// elements such as `title`, `style` and `script` as text.
+    /// # Parameters
+    /// - `state`: The new state, such as `State::Rcdata`, `State::Rawtext` or `State::ScriptData`.
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/parsing.html#generic-raw-text-element-parsing-algorithm>
+    pub fn switch_to(&mut self, state: State) {
+        self.state = state;
+    }
+    /// Sets the tag name of the last start tag, which decides whether an end tag in RCDATA,
+    /// RAWTEXT or script data closes the element. It is set by the tokenizer itself, and set
+    /// beforehand when tokenizing starts in the middle of an element.
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/parsing.html#appropriate-end-tag-token>
+    pub fn set_last_start_tag(&mut self, tag: Option<String>) {
+        self.last_start_tag = tag;
+    }
+    /// Returns the parse errors found so far.
+    pub fn errors(&self) -> Vec<ParseError> {
+        self.errors.clone()
+    }
+    /// Returns the offset of the next character to consume.
+    fn offset(&self) -> usize {
+        (self.pos - self.reconsume as usize).min(self.input.len())
+    }
+    /// Records a parse error at the current input character.
+    /// # Parameters
+    /// - `code`: The error code in the spec, e.g. `eof-in-tag`.
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/parsing.html#parse-errors>
+    fn parse_error(&mut self, code: &str) {
+        let end = self.pos.min(self.input.len());
+        let start = self.pos.saturating_sub(1).min(end);
+        let span = self.line_index.span(start, end);
+        self.errors
+            .push(ParseError::new(SourceLanguage::Html, code, span));
     }
     fn is_eof(&self) -> bool {
         self.pos > self.input.len()
     }
+    /// Consumes the next input character. At the end of the input, this returns `'\0'` and
+    /// `is_eof()` becomes true.
     fn consume_next_input(&mut self) -> char {
-        let c = self.input[self.pos];
+        let c = self.input.get(self.pos).copied().unwrap_or('\0');
         self.pos += 1;
         c
     }
     fn reconsume_input(&mut self) -> char {
         self.reconsume = false;
-        self.input[self.pos - 1]
+        self.input.get(self.pos - 1).copied().unwrap_or('\0')
+    }
+    fn is_in_attribute_value(&self) -> bool {
+        matches!(
+            self.return_state,
+            State::AttributeValueDoubleQuoted
+                | State::AttributeValueSingleQuoted
+                | State::AttributeValueUnquoted
+        )
+    }
+    /// Appends the characters of the temporary buffer to the current attribute value, or emits
+    /// them as character tokens.
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/parsing.html#flush-code-points-consumed-as-a-character-reference>
+    fn flush_code_points_consumed_as_character_reference(&mut self) {
+        let buf = core::mem::take(&mut self.buf);
+        for c in buf.chars() {
+            if self.is_in_attribute_value() {
+                self.append_attribute(c, false);
+            } else {
+                self.pending_chars.push_back(c);
+            }
+        }
+    }
+    /// Resolves the number of a numeric character reference into the temporary buffer.
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/parsing.html#numeric-character-reference-end-state>
+    fn end_numeric_character_reference(&mut self) {
+        let code = self.character_reference_code;
+        match code {
+            0 => self.parse_error("null-character-reference"),
+            0x110000.. => self.parse_error("character-reference-outside-unicode-range"),
+            0xD800..=0xDFFF => self.parse_error("surrogate-character-reference"),
+            0xFDD0..=0xFDEF => self.parse_error("noncharacter-character-reference"),
+            n if n & 0xFFFE == 0xFFFE => self.parse_error("noncharacter-character-reference"),
+            0x0D | 0x80..=0x9F => self.parse_error("control-character-reference"),
+            n if n < 0x20 && !matches!(n, 0x09 | 0x0A | 0x0C) || n == 0x7F => {
+                self.parse_error("control-character-reference")
+            }
+            _ => {}
+        }
+        let c = match code {
+            0 | 0xD800..=0xDFFF => '\u{FFFD}',
+            0x80..=0x9F => match C1_REPLACEMENTS.iter().find(|(n, _)| *n == code) {
+                Some((_, c)) => *c,
+                None => char::from_u32(code).unwrap_or('\u{FFFD}'),
+            },
+            _ => char::from_u32(code).unwrap_or('\u{FFFD}'),
+        };
+        self.buf = String::new();
+        self.buf.push(c);
+        self.flush_code_points_consumed_as_character_reference();
     }
     fn create_tag(&mut self, start_tag_token: bool) {
         if start_tag_token {
@@ -102,11 +405,55 @@ impl HtmlTokenizer {
     }
     fn take_latest_token(&mut self) -> Option<HtmlToken> {
         assert!(self.latest_token.is_some());
-        let t = self.latest_token.as_ref().cloned();
+        let mut t = self.latest_token.as_ref().cloned();
         self.latest_token = None;
         assert!(self.latest_token.is_none());
+        if let Some(HtmlToken::StartTag {
+            ref tag,
+            ref mut attributes,
+            ..
+        }) = t
+        {
+            self.last_start_tag = Some(tag.clone());
+            // An attribute with the same name as an earlier one is dropped.
+            let mut names: Vec<String> = Vec::new();
+            let len = attributes.len();
+            attributes.retain(|a| {
+                if names.contains(&a.name()) {
+                    return false;
+                }
+                names.push(a.name());
+                true
+            });
+            if attributes.len() != len {
+                self.parse_error("duplicate-attribute");
+            }
+        }
         t
     }
+    /// Returns true if the current end tag token closes the last start tag emitted.
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/parsing.html#appropriate-end-tag-token>
+    fn is_appropriate_end_tag(&self) -> bool {
+        match (&self.latest_token, &self.last_start_tag) {
+            (Some(HtmlToken::EndTag { tag }), Some(last)) => tag == last,
+            _ => false,
+        }
+    }
+    /// Returns the RCDATA, RAWTEXT or script data state of the family the current state is in.
+    fn text_state(&self) -> State {
+        match self.state {
+            State::Rcdata
+            | State::RcdataLessThanSign
+            | State::RcdataEndTagOpen
+            | State::RcdataEndTagName => State::Rcdata,
+            State::Rawtext
+            | State::RawtextLessThanSign
+            | State::RawtextEndTagOpen
+            | State::RawtextEndTagName => State::Rawtext,
+            _ => State::ScriptData,
+        }
+    }
     fn start_new_attribute(&mut self) {
         assert!(self.latest_token.is_some());
         if let Some(t) = self.latest_token.as_mut() {
@@ -118,6 +465,14 @@ impl HtmlTokenizer {
                 } => {
                     attributes.push(Attribute::new());
                 }
+                // Attributes of an end tag are dropped.
+                HtmlToken::EndTag { .. } => {
+                    self.errors.push(ParseError::new(
+                        SourceLanguage::Html,
+                        "end-tag-with-attributes",
+                        self.line_index.span(self.offset(), self.offset() + 1),
+                    ));
+                }
                 _ => panic!("`latest_token` should be either StartTab"),
             }
         }
@@ -135,6 +490,7 @@ impl HtmlTokenizer {
                     assert!(len > 0);
                     attributes[len - 1].add_char(c, is_name);
                 }
+                HtmlToken::EndTag { .. } => {}
                 _ => panic!("`latest_token` should be StartTag"),
             }
         }
@@ -148,24 +504,156 @@ impl HtmlTokenizer {
                     ref mut self_closing,
                     attributes: _,
                 } => *self_closing = true,
+                HtmlToken::EndTag { .. } => self.parse_error("end-tag-with-trailing-solidus"),
                 _ => panic!("`latest_token` should be StartTag"),
             }
         }
     }
+    /// Consumes `word` if the input continues with it from the current character, ignoring
+    /// ASCII case.
+    fn consume_word(&mut self, word: &str) -> bool {
+        let start = self.pos - 1;
+        let len = word.chars().count();
+        if start + len > self.input.len()
+            || !self.input[start..start + len]
+                .iter()
+                .zip(word.chars())
+                .all(|(a, b)| a.eq_ignore_ascii_case(&b))
+        {
+            return false;
+        }
+        self.pos = start + len;
+        true
+    }
+    /// Consumes `word` like `consume_word()`, but matches ASCII case exactly.
+    fn consume_exact_word(&mut self, word: &str) -> bool {
+        let start = self.pos - 1;
+        let len = word.chars().count();
+        if start + len > self.input.len()
+            || !self.input[start..start + len]
+                .iter()
+                .copied()
+                .eq(word.chars())
+        {
+            return false;
+        }
+        self.pos = start + len;
+        true
+    }
+    fn create_comment(&mut self) {
+        self.latest_token = Some(HtmlToken::Comment(String::new()));
+    }
+    fn append_comment(&mut self, s: &str) {
+        match self.latest_token.as_mut() {
+            Some(HtmlToken::Comment(ref mut data)) => data.push_str(s),
+            _ => panic!("`latest_token` should be Comment"),
+        }
+    }
+    fn create_doctype(&mut self) {
+        self.latest_token = Some(HtmlToken::Doctype {
+            name: None,
+            public_id: None,
+            system_id: None,
+            force_quirks: false,
+        });
+    }
+    fn append_doctype_name(&mut self, c: char) {
+        match self.latest_token.as_mut() {
+            Some(HtmlToken::Doctype { ref mut name, .. }) => {
+                name.get_or_insert_with(String::new)
+                    .push(c.to_ascii_lowercase());
+            }
+            _ => panic!("`latest_token` should be Doctype"),
+        }
+    }
+    /// Starts the public identifier if `public` is true, or the system identifier otherwise.
+    fn start_doctype_identifier(&mut self, public: bool) {
+        match self.latest_token.as_mut() {
+            Some(HtmlToken::Doctype {
+                ref mut public_id,
+                ref mut system_id,
+                ..
+            }) => {
+                if public {
+                    *public_id = Some(String::new());
+                } else {
+                    *system_id = Some(String::new());
+                }
+            }
+            _ => panic!("`latest_token` should be Doctype"),
+        }
+    }
+    fn append_doctype_identifier(&mut self, c: char, public: bool) {
+        match self.latest_token.as_mut() {
+            Some(HtmlToken::Doctype {
+                public_id: Some(ref mut id),
+                ..
+            }) if public => id.push(c),
+            Some(HtmlToken::Doctype {
+                system_id: Some(ref mut id),
+                ..
+            }) if !public => id.push(c),
+            _ => panic!("`latest_token` should be Doctype with an identifier"),
+        }
+    }
+    fn set_force_quirks_flag(&mut self) {
+        match self.latest_token.as_mut() {
+            Some(HtmlToken::Doctype {
+                ref mut force_quirks,
+                ..
+            }) => *force_quirks = true,
+            _ => panic!("`latest_token` should be Doctype"),
+        }
+    }
+    /// Emits the current comment or DOCTYPE token at the end of the input. The end of the input
+    /// is reconsumed in the data state, so an `Eof` token follows.
+    fn emit_at_eof(&mut self) -> Option<HtmlToken> {
+        self.reconsume = true;
+        self.state = State::Data;
+        self.take_latest_token()
+    }
 }
+
+fn is_whitespace(c: char) -> bool {
+    matches!(c, '\t' | '\n' | '\x0C' | ' ')
+}
+
 impl Iterator for HtmlTokenizer {
     type Item = HtmlToken;
     fn next(&mut self) -> Option<Self::Item> {
-        if self.pos >= self.input.len() {
+        if let Some(c) = self.pending_chars.pop_front() {
+            return Some(HtmlToken::Char(c));
+        }
+        // The end of the input is consumed once, which emits an `Eof` token.
+        if self.pos > self.input.len() && !self.reconsume {
             return None;
         }
+        if self.pos == self.input.len() && !self.reconsume && !self.closed {
+            return self.suspend();
+        }
+        // A token that was started before suspending keeps its start.
+        if !self.suspended {
+            self.token_start = self.offset();
+        }
+        self.suspended = false;
         loop {
+            if let Some(c) = self.pending_chars.pop_front() {
+                return Some(HtmlToken::Char(c));
+            }
+            if self.pos >= self.input.len() && !self.reconsume && !self.closed {
+                return self.suspend();
+            }
             let c = match self.reconsume {
                 true => self.reconsume_input(),
                 false => self.consume_next_input(),
             };
             match self.state {
                 State::Data => {
+                    if c == '&' {
+                        self.return_state = State::Data;
+                        self.state = State::CharacterReference;
+                        continue;
+                    }
                     if c == '<' {
                         self.state = State::TagOpen;
                         continue;
@@ -173,9 +661,16 @@ impl Iterator for HtmlTokenizer {
                     if self.is_eof() {
                         return Some(HtmlToken::Eof);
                     }
+                    if c == '\0' {
+                        self.parse_error("unexpected-null-character");
+                    }
                     return Some(HtmlToken::Char(c));
                 }
                 State::TagOpen => {
+                    if c == '!' {
+                        self.state = State::MarkupDeclarationOpen;
+                        continue;
+                    }
                     if c == '/' {
                         self.state = State::EndTagOpen;
                         continue;
@@ -186,15 +681,29 @@ impl Iterator for HtmlTokenizer {
                         self.create_tag(true);
                         continue;
                     }
+                    if c == '?' {
+                        self.parse_error("unexpected-question-mark-instead-of-tag-name");
+                        self.create_comment();
+                        self.reconsume = true;
+                        self.state = State::BogusComment;
+                        continue;
+                    }
                     if self.is_eof() {
-                        return Some(HtmlToken::Eof);
+                        self.parse_error("eof-before-tag-name");
+                    } else {
+                        self.parse_error("invalid-first-character-of-tag-name");
                     }
                     self.reconsume = true;
                     self.state = State::Data;
+                    return Some(HtmlToken::Char('<'));
                 }
                 State::EndTagOpen => {
                     if self.is_eof() {
-                        return Some(HtmlToken::Eof);
+                        self.parse_error("eof-before-tag-name");
+                        self.reconsume = true;
+                        self.state = State::Data;
+                        self.pending_chars.push_back('/');
+                        return Some(HtmlToken::Char('<'));
                     }
                     if c.is_ascii_alphabetic() {
                         self.reconsume = true;
@@ -202,6 +711,15 @@ impl Iterator for HtmlTokenizer {
                         self.create_tag(false);
                         continue;
                     }
+                    if c == '>' {
+                        self.parse_error("missing-end-tag-name");
+                        self.state = State::Data;
+                        continue;
+                    }
+                    self.parse_error("invalid-first-character-of-tag-name");
+                    self.create_comment();
+                    self.reconsume = true;
+                    self.state = State::BogusComment;
                 }
                 State::TagName => {
                     if c == ' ' {
@@ -221,8 +739,14 @@ impl Iterator for HtmlTokenizer {
                         continue;
                     }
                     if self.is_eof() {
+                        self.parse_error("eof-in-tag");
                         return Some(HtmlToken::Eof);
                     }
+                    if c == '\0' {
+                        self.parse_error("unexpected-null-character");
+                        self.append_tag_name('\u{FFFD}');
+                        continue;
+                    }
                     self.append_tag_name(c);
                 }
                 State::BeforeAttributeName => {
@@ -268,6 +792,7 @@ impl Iterator for HtmlTokenizer {
                         return self.take_latest_token();
                     }
                     if self.is_eof() {
+                        self.parse_error("eof-in-tag");
                         return Some(HtmlToken::Eof);
                     }
                     self.reconsume = true;
@@ -278,6 +803,9 @@ impl Iterator for HtmlTokenizer {
                     if c == ' ' {
                         continue;
                     }
+                    if c == '>' {
+                        self.parse_error("missing-attribute-value");
+                    }
                     if c == '"' {
                         self.state = State::AttributeValueDoubleQuoted;
                         continue;
@@ -294,7 +822,13 @@ impl Iterator for HtmlTokenizer {
                         self.state = State::AfterAttributeValueQuoted;
                         continue;
                     }
+                    if c == '&' {
+                        self.return_state = State::AttributeValueDoubleQuoted;
+                        self.state = State::CharacterReference;
+                        continue;
+                    }
                     if self.is_eof() {
+                        self.parse_error("eof-in-tag");
                         return Some(HtmlToken::Eof);
                     }
                     self.append_attribute(c, false);
@@ -304,7 +838,13 @@ impl Iterator for HtmlTokenizer {
                         self.state = State::AfterAttributeValueQuoted;
                         continue;
                     }
+                    if c == '&' {
+                        self.return_state = State::AttributeValueSingleQuoted;
+                        self.state = State::CharacterReference;
+                        continue;
+                    }
                     if self.is_eof() {
+                        self.parse_error("eof-in-tag");
                         return Some(HtmlToken::Eof);
                     }
                     self.append_attribute(c, false);
@@ -314,11 +854,17 @@ impl Iterator for HtmlTokenizer {
                         self.state = State::BeforeAttributeName;
                         continue;
                     }
+                    if c == '&' {
+                        self.return_state = State::AttributeValueUnquoted;
+                        self.state = State::CharacterReference;
+                        continue;
+                    }
                     if c == '>' {
                         self.state = State::Data;
                         return self.take_latest_token();
                     }
                     if self.is_eof() {
+                        self.parse_error("eof-in-tag");
                         return Some(HtmlToken::Eof);
                     }
                     self.append_attribute(c, false);
@@ -337,10 +883,12 @@ impl Iterator for HtmlTokenizer {
                         return self.take_latest_token();
                     }
                     if self.is_eof() {
+                        self.parse_error("eof-in-tag");
                         return Some(HtmlToken::Eof);
                     }
+                    self.parse_error("missing-whitespace-between-attributes");
                     self.reconsume = true;
-                    self.state = State::BeforeAttributeValue;
+                    self.state = State::BeforeAttributeName;
                 }
                 State::SelfClosingStartTag => {
                     if c == '>' {
@@ -349,12 +897,25 @@ impl Iterator for HtmlTokenizer {
                         return self.take_latest_token();
                     }
                     if self.is_eof() {
+                        self.parse_error("eof-in-tag");
                         return Some(HtmlToken::Eof);
                     }
+                    self.parse_error("unexpected-solidus-in-tag");
+                    self.reconsume = true;
+                    self.state = State::BeforeAttributeName;
                 }
-                State::ScriptData => {
+                State::Rcdata | State::Rawtext | State::ScriptData => {
+                    if c == '&' && self.state == State::Rcdata {
+                        self.return_state = State::Rcdata;
+                        self.state = State::CharacterReference;
+                        continue;
+                    }
                     if c == '<' {
-                        self.state = State::ScriptDataLessThanSign;
+                        self.state = match self.state {
+                            State::Rcdata => State::RcdataLessThanSign,
+                            State::Rawtext => State::RawtextLessThanSign,
+                            _ => State::ScriptDataLessThanSign,
+                        };
                         continue;
                     }
                     if self.is_eof() {
@@ -362,70 +923,615 @@ impl Iterator for HtmlTokenizer {
                     }
                     return Some(HtmlToken::Char(c));
                 }
-                State::ScriptDataLessThanSign => {
+                State::RcdataLessThanSign
+                | State::RawtextLessThanSign
+                | State::ScriptDataLessThanSign => {
                     if c == '/' {
                         self.buf = String::new();
-                        self.state = State::ScriptDataEndTagOpen;
+                        self.state = match self.text_state() {
+                            State::Rcdata => State::RcdataEndTagOpen,
+                            State::Rawtext => State::RawtextEndTagOpen,
+                            _ => State::ScriptDataEndTagOpen,
+                        };
                         continue;
                     }
                     self.reconsume = true;
-                    self.state = State::ScriptData;
+                    self.state = self.text_state();
                     return Some(HtmlToken::Char('<'));
                 }
-                State::ScriptDataEndTagOpen => {
+                State::RcdataEndTagOpen
+                | State::RawtextEndTagOpen
+                | State::ScriptDataEndTagOpen => {
                     if c.is_ascii_alphabetic() {
                         self.reconsume = true;
-                        self.state = State::ScriptDataEndTagName;
+                        self.state = match self.text_state() {
+                            State::Rcdata => State::RcdataEndTagName,
+                            State::Rawtext => State::RawtextEndTagName,
+                            _ => State::ScriptDataEndTagName,
+                        };
                         self.create_tag(false);
                         continue;
                     }
                     self.reconsume = true;
-                    self.state = State::ScriptData;
+                    self.state = self.text_state();
+                    self.pending_chars.push_back('/');
                     return Some(HtmlToken::Char('<'));
                 }
-                State::ScriptDataEndTagName => {
-                    if c == '>' {
-                        self.state = State::Data;
-                        return self.take_latest_token();
+                State::RcdataEndTagName
+                | State::RawtextEndTagName
+                | State::ScriptDataEndTagName => {
+                    if self.is_appropriate_end_tag() && !self.is_eof() {
+                        if is_whitespace(c) {
+                            self.state = State::BeforeAttributeName;
+                            continue;
+                        }
+                        if c == '/' {
+                            self.state = State::SelfClosingStartTag;
+                            continue;
+                        }
+                        if c == '>' {
+                            self.state = State::Data;
+                            return self.take_latest_token();
+                        }
                     }
                     if c.is_ascii_alphabetic() {
                         self.buf.push(c);
                         self.append_tag_name(c.to_ascii_lowercase());
                         continue;
                     }
-                    self.state = State::TemporaryBuffer;
-                    self.buf = String::from("</") + &self.buf;
-                    self.buf.push(c);
-                    continue;
+                    // Not an end tag of the element, so the characters are text.
+                    self.latest_token = None;
+                    self.pending_chars.push_back('/');
+                    self.pending_chars.extend(self.buf.chars());
+                    self.buf = String::new();
+                    self.reconsume = true;
+                    self.state = self.text_state();
+                    return Some(HtmlToken::Char('<'));
+                }
+                State::CharacterReference => {
+                    self.buf = String::from("&");
+                    if c.is_ascii_alphanumeric() && !self.is_eof() {
+                        self.reconsume = true;
+                        self.state = State::NamedCharacterReference;
+                        continue;
+                    }
+                    if c == '#' {
+                        self.buf.push(c);
+                        self.state = State::NumericCharacterReference;
+                        continue;
+                    }
+                    self.flush_code_points_consumed_as_character_reference();
+                    self.reconsume = true;
+                    self.state = self.return_state.clone();
+                }
+                State::NamedCharacterReference => {
+                    let start = self.pos - 1;
+                    // Wait until the longest name that can match is available.
+                    if self.needs_more_input(MAX_NAME_LENGTH)
+                        && self.input[start..]
+                            .iter()
+                            .all(|c| c.is_ascii_alphanumeric())
+                    {
+                        self.reconsume = true;
+                        return self.suspend();
+                    }
+                    let (name, chars) = match match_named_character_reference(&self.input[start..])
+                    {
+                        Some(reference) => reference,
+                        None => {
+                            self.flush_code_points_consumed_as_character_reference();
+                            self.reconsume = true;
+                            self.state = State::AmbiguousAmpersand;
+                            continue;
+                        }
+                    };
+                    self.pos = start + name.len();
+                    self.buf.push_str(name);
+                    self.state = self.return_state.clone();
+                    // For historical reasons, a reference without a semicolon in an attribute
+                    // value is kept as is when it is followed by `=` or an alphanumeric.
+                    let next = self.input.get(self.pos).copied();
+                    if self.is_in_attribute_value()
+                        && !name.ends_with(';')
+                        && matches!(next, Some(n) if n == '=' || n.is_ascii_alphanumeric())
+                    {
+                        self.flush_code_points_consumed_as_character_reference();
+                        continue;
+                    }
+                    if !name.ends_with(';') {
+                        self.parse_error("missing-semicolon-after-character-reference");
+                    }
+                    self.buf = String::from(chars);
+                    self.flush_code_points_consumed_as_character_reference();
+                }
+                State::AmbiguousAmpersand => {
+                    if c.is_ascii_alphanumeric() && !self.is_eof() {
+                        if self.is_in_attribute_value() {
+                            self.append_attribute(c, false);
+                            continue;
+                        }
+                        return Some(HtmlToken::Char(c));
+                    }
+                    if c == ';' {
+                        self.parse_error("unknown-named-character-reference");
+                    }
+                    self.reconsume = true;
+                    self.state = self.return_state.clone();
+                }
+                State::NumericCharacterReference => {
+                    self.character_reference_code = 0;
+                    if c == 'x' || c == 'X' {
+                        self.buf.push(c);
+                        self.state = State::HexadecimalCharacterReferenceStart;
+                        continue;
+                    }
+                    self.reconsume = true;
+                    self.state = State::DecimalCharacterReferenceStart;
+                }
+                State::HexadecimalCharacterReferenceStart => {
+                    self.reconsume = true;
+                    if c.is_ascii_hexdigit() && !self.is_eof() {
+                        self.state = State::HexadecimalCharacterReference;
+                        continue;
+                    }
+                    self.parse_error("absence-of-digits-in-numeric-character-reference");
+                    self.flush_code_points_consumed_as_character_reference();
+                    self.state = self.return_state.clone();
                 }
-                State::TemporaryBuffer => {
+                State::DecimalCharacterReferenceStart => {
                     self.reconsume = true;
-                    if self.buf.chars().count() == 0 {
-                        self.state = State::ScriptData;
+                    if c.is_ascii_digit() && !self.is_eof() {
+                        self.state = State::DecimalCharacterReference;
                         continue;
                     }
-                    let c = self
-                        .buf
-                        .chars()
-                        .nth(0)
-                        .expect("self.buf should have at least 1 char");
-                    self.buf.remove(0);
+                    self.parse_error("absence-of-digits-in-numeric-character-reference");
+                    self.flush_code_points_consumed_as_character_reference();
+                    self.state = self.return_state.clone();
+                }
+                State::HexadecimalCharacterReference | State::DecimalCharacterReference => {
+                    let radix = match self.state {
+                        State::HexadecimalCharacterReference => 16,
+                        _ => 10,
+                    };
+                    if let Some(digit) = c.to_digit(radix).filter(|_| !self.is_eof()) {
+                        // Saturate above the largest code point so that long inputs don't overflow.
+                        self.character_reference_code = self
+                            .character_reference_code
+                            .saturating_mul(radix)
+                            .saturating_add(digit)
+                            .min(0x110000);
+                        continue;
+                    }
+                    if c != ';' || self.is_eof() {
+                        self.parse_error("missing-semicolon-after-character-reference");
+                        self.reconsume = true;
+                    }
+                    self.end_numeric_character_reference();
+                    self.state = self.return_state.clone();
+                }
+                State::MarkupDeclarationOpen => {
+                    if self.is_word_pending("--")
+                        || self.is_word_pending("doctype")
+                        || self.is_word_pending("[cdata[")
+                    {
+                        self.reconsume = true;
+                        return self.suspend();
+                    }
+                    if !self.is_eof() && self.consume_word("--") {
+                        self.create_comment();
+                        self.state = State::CommentStart;
+                        continue;
+                    }
+                    if !self.is_eof() && self.consume_word("doctype") {
+                        self.state = State::Doctype;
+                        continue;
+                    }
+                    if !self.is_eof() && self.consume_exact_word("[CDATA[") {
+                        if self.cdata_allowed {
+                            self.state = State::CdataSection;
+                            continue;
+                        }
+                        self.parse_error("cdata-in-html-content");
+                        self.create_comment();
+                        self.append_comment("[CDATA[");
+                        self.state = State::BogusComment;
+                        continue;
+                    }
+                    self.parse_error("incorrectly-opened-comment");
+                    self.create_comment();
+                    self.reconsume = true;
+                    self.state = State::BogusComment;
+                }
+                State::CommentStart => {
+                    if c == '-' {
+                        self.state = State::CommentStartDash;
+                        continue;
+                    }
+                    if c == '>' {
+                        self.parse_error("abrupt-closing-of-empty-comment");
+                        self.state = State::Data;
+                        return self.take_latest_token();
+                    }
+                    self.reconsume = true;
+                    self.state = State::Comment;
+                }
+                State::CommentStartDash | State::CommentEndDash => {
+                    if self.is_eof() {
+                        self.parse_error("eof-in-comment");
+                        return self.emit_at_eof();
+                    }
+                    if c == '-' {
+                        self.state = State::CommentEnd;
+                        continue;
+                    }
+                    if c == '>' && self.state == State::CommentStartDash {
+                        self.parse_error("abrupt-closing-of-empty-comment");
+                        self.state = State::Data;
+                        return self.take_latest_token();
+                    }
+                    self.append_comment("-");
+                    self.reconsume = true;
+                    self.state = State::Comment;
+                }
+                State::Comment => {
+                    if self.is_eof() {
+                        self.parse_error("eof-in-comment");
+                        return self.emit_at_eof();
+                    }
+                    if c == '-' {
+                        self.state = State::CommentEndDash;
+                        continue;
+                    }
+                    if c == '\0' {
+                        self.parse_error("unexpected-null-character");
+                        self.append_comment("\u{FFFD}");
+                        continue;
+                    }
+                    self.append_comment(c.encode_utf8(&mut [0; 4]));
+                }
+                State::CommentEnd => {
+                    if self.is_eof() {
+                        self.parse_error("eof-in-comment");
+                        return self.emit_at_eof();
+                    }
+                    if c == '>' {
+                        self.state = State::Data;
+                        return self.take_latest_token();
+                    }
+                    if c == '!' {
+                        self.state = State::CommentEndBang;
+                        continue;
+                    }
+                    if c == '-' {
+                        self.append_comment("-");
+                        continue;
+                    }
+                    self.append_comment("--");
+                    self.reconsume = true;
+                    self.state = State::Comment;
+                }
+                State::CommentEndBang => {
+                    if self.is_eof() {
+                        self.parse_error("eof-in-comment");
+                        return self.emit_at_eof();
+                    }
+                    if c == '>' {
+                        self.parse_error("incorrectly-closed-comment");
+                        self.state = State::Data;
+                        return self.take_latest_token();
+                    }
+                    self.append_comment("--!");
+                    if c == '-' {
+                        self.state = State::CommentEndDash;
+                        continue;
+                    }
+                    self.reconsume = true;
+                    self.state = State::Comment;
+                }
+                State::BogusComment => {
+                    if self.is_eof() {
+                        return self.emit_at_eof();
+                    }
+                    if c == '>' {
+                        self.state = State::Data;
+                        return self.take_latest_token();
+                    }
+                    if c == '\0' {
+                        self.parse_error("unexpected-null-character");
+                        self.append_comment("\u{FFFD}");
+                        continue;
+                    }
+                    self.append_comment(c.encode_utf8(&mut [0; 4]));
+                }
+                State::Doctype => {
+                    if self.is_eof() {
+                        self.create_doctype();
+                        self.set_force_quirks_flag();
+                        self.parse_error("eof-in-doctype");
+                        return self.emit_at_eof();
+                    }
+                    if !is_whitespace(c) {
+                        if c != '>' {
+                            self.parse_error("missing-whitespace-before-doctype-name");
+                        }
+                        self.reconsume = true;
+                    }
+                    self.state = State::BeforeDoctypeName;
+                }
+                State::BeforeDoctypeName => {
+                    if is_whitespace(c) {
+                        continue;
+                    }
+                    self.create_doctype();
+                    if self.is_eof() {
+                        self.set_force_quirks_flag();
+                        self.parse_error("eof-in-doctype");
+                        return self.emit_at_eof();
+                    }
+                    if c == '>' {
+                        self.parse_error("missing-doctype-name");
+                        self.set_force_quirks_flag();
+                        self.state = State::Data;
+                        return self.take_latest_token();
+                    }
+                    self.append_doctype_name(c);
+                    self.state = State::DoctypeName;
+                }
+                State::DoctypeName => {
+                    if self.is_eof() {
+                        self.set_force_quirks_flag();
+                        self.parse_error("eof-in-doctype");
+                        return self.emit_at_eof();
+                    }
+                    if is_whitespace(c) {
+                        self.state = State::AfterDoctypeName;
+                        continue;
+                    }
+                    if c == '>' {
+                        self.state = State::Data;
+                        return self.take_latest_token();
+                    }
+                    self.append_doctype_name(c);
+                }
+                State::AfterDoctypeName => {
+                    if self.is_eof() {
+                        self.set_force_quirks_flag();
+                        self.parse_error("eof-in-doctype");
+                        return self.emit_at_eof();
+                    }
+                    if is_whitespace(c) {
+                        continue;
+                    }
+                    if c == '>' {
+                        self.state = State::Data;
+                        return self.take_latest_token();
+                    }
+                    if self.is_word_pending("public") || self.is_word_pending("system") {
+                        self.reconsume = true;
+                        return self.suspend();
+                    }
+                    if self.consume_word("public") {
+                        self.state = State::AfterDoctypePublicKeyword;
+                        continue;
+                    }
+                    if self.consume_word("system") {
+                        self.state = State::AfterDoctypeSystemKeyword;
+                        continue;
+                    }
+                    self.parse_error("invalid-character-sequence-after-doctype-name");
+                    self.set_force_quirks_flag();
+                    self.reconsume = true;
+                    self.state = State::BogusDoctype;
+                }
+                State::AfterDoctypePublicKeyword
+                | State::BeforeDoctypePublicIdentifier
+                | State::AfterDoctypeSystemKeyword
+                | State::BeforeDoctypeSystemIdentifier => {
+                    if self.is_eof() {
+                        self.set_force_quirks_flag();
+                        self.parse_error("eof-in-doctype");
+                        return self.emit_at_eof();
+                    }
+                    let public = matches!(
+                        self.state,
+                        State::AfterDoctypePublicKeyword | State::BeforeDoctypePublicIdentifier
+                    );
+                    if is_whitespace(c) {
+                        self.state = match public {
+                            true => State::BeforeDoctypePublicIdentifier,
+                            false => State::BeforeDoctypeSystemIdentifier,
+                        };
+                        continue;
+                    }
+                    let keyword = match public {
+                        true => "public",
+                        false => "system",
+                    };
+                    if c == '"' || c == '\'' {
+                        if matches!(
+                            self.state,
+                            State::AfterDoctypePublicKeyword | State::AfterDoctypeSystemKeyword
+                        ) {
+                            self.parse_error(&format!(
+                                "missing-whitespace-after-doctype-{}-keyword",
+                                keyword
+                            ));
+                        }
+                        self.start_doctype_identifier(public);
+                        self.state = match (public, c == '"') {
+                            (true, true) => State::DoctypePublicIdentifierDoubleQuoted,
+                            (true, false) => State::DoctypePublicIdentifierSingleQuoted,
+                            (false, true) => State::DoctypeSystemIdentifierDoubleQuoted,
+                            (false, false) => State::DoctypeSystemIdentifierSingleQuoted,
+                        };
+                        continue;
+                    }
+                    self.set_force_quirks_flag();
+                    if c == '>' {
+                        self.parse_error(&format!("missing-doctype-{}-identifier", keyword));
+                        self.state = State::Data;
+                        return self.take_latest_token();
+                    }
+                    self.parse_error(&format!(
+                        "missing-quote-before-doctype-{}-identifier",
+                        keyword
+                    ));
+                    self.reconsume = true;
+                    self.state = State::BogusDoctype;
+                }
+                State::DoctypePublicIdentifierDoubleQuoted
+                | State::DoctypePublicIdentifierSingleQuoted
+                | State::DoctypeSystemIdentifierDoubleQuoted
+                | State::DoctypeSystemIdentifierSingleQuoted => {
+                    if self.is_eof() {
+                        self.set_force_quirks_flag();
+                        self.parse_error("eof-in-doctype");
+                        return self.emit_at_eof();
+                    }
+                    let (public, quote) = match self.state {
+                        State::DoctypePublicIdentifierDoubleQuoted => (true, '"'),
+                        State::DoctypePublicIdentifierSingleQuoted => (true, '\''),
+                        State::DoctypeSystemIdentifierDoubleQuoted => (false, '"'),
+                        _ => (false, '\''),
+                    };
+                    if c == quote {
+                        self.state = match public {
+                            true => State::AfterDoctypePublicIdentifier,
+                            false => State::AfterDoctypeSystemIdentifier,
+                        };
+                        continue;
+                    }
+                    if c == '>' {
+                        self.parse_error(match public {
+                            true => "abrupt-doctype-public-identifier",
+                            false => "abrupt-doctype-system-identifier",
+                        });
+                        self.set_force_quirks_flag();
+                        self.state = State::Data;
+                        return self.take_latest_token();
+                    }
+                    self.append_doctype_identifier(c, public);
+                }
+                State::AfterDoctypePublicIdentifier
+                | State::BetweenDoctypePublicAndSystemIdentifiers => {
+                    if self.is_eof() {
+                        self.set_force_quirks_flag();
+                        self.parse_error("eof-in-doctype");
+                        return self.emit_at_eof();
+                    }
+                    if is_whitespace(c) {
+                        self.state = State::BetweenDoctypePublicAndSystemIdentifiers;
+                        continue;
+                    }
+                    if c == '>' {
+                        self.state = State::Data;
+                        return self.take_latest_token();
+                    }
+                    if c == '"' || c == '\'' {
+                        if self.state == State::AfterDoctypePublicIdentifier {
+                            self.parse_error(
+                                "missing-whitespace-between-doctype-public-and-system-identifiers",
+                            );
+                        }
+                        self.start_doctype_identifier(false);
+                        self.state = match c {
+                            '"' => State::DoctypeSystemIdentifierDoubleQuoted,
+                            _ => State::DoctypeSystemIdentifierSingleQuoted,
+                        };
+                        continue;
+                    }
+                    self.parse_error("missing-quote-before-doctype-system-identifier");
+                    self.set_force_quirks_flag();
+                    self.reconsume = true;
+                    self.state = State::BogusDoctype;
+                }
+                State::AfterDoctypeSystemIdentifier => {
+                    if self.is_eof() {
+                        self.set_force_quirks_flag();
+                        self.parse_error("eof-in-doctype");
+                        return self.emit_at_eof();
+                    }
+                    if is_whitespace(c) {
+                        continue;
+                    }
+                    if c == '>' {
+                        self.state = State::Data;
+                        return self.take_latest_token();
+                    }
+                    self.parse_error("unexpected-character-after-doctype-system-identifier");
+                    self.reconsume = true;
+                    self.state = State::BogusDoctype;
+                }
+                State::BogusDoctype => {
+                    if self.is_eof() {
+                        return self.emit_at_eof();
+                    }
+                    if c == '>' {
+                        self.state = State::Data;
+                        return self.take_latest_token();
+                    }
+                }
+                State::Plaintext => {
+                    if self.is_eof() {
+                        return Some(HtmlToken::Eof);
+                    }
+                    return Some(HtmlToken::Char(c));
+                }
+                State::CdataSection => {
+                    if c == ']' && !self.is_eof() {
+                        self.state = State::CdataSectionBracket;
+                        continue;
+                    }
+                    if self.is_eof() {
+                        self.parse_error("eof-in-cdata");
+                        return Some(HtmlToken::Eof);
+                    }
                     return Some(HtmlToken::Char(c));
-                } //_ => {}
+                }
+                State::CdataSectionBracket => {
+                    if c == ']' {
+                        self.state = State::CdataSectionEnd;
+                        continue;
+                    }
+                    self.reconsume = true;
+                    self.state = State::CdataSection;
+                    return Some(HtmlToken::Char(']'));
+                }
+                State::CdataSectionEnd => {
+                    if c == ']' {
+                        return Some(HtmlToken::Char(']'));
+                    }
+                    if c == '>' && !self.is_eof() {
+                        self.state = State::Data;
+                        continue;
+                    }
+                    self.reconsume = true;
+                    self.state = State::CdataSection;
+                    self.pending_chars.push_back(']');
+                    return Some(HtmlToken::Char(']'));
+                }
             }
         }
     }
 }
 
+impl SpannedTokenizer for HtmlTokenizer {
+    fn span(&self) -> Span {
+        self.line_index.span(self.token_start, self.offset())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::alloc::string::ToString;
+    use alloc::format;
     use alloc::vec;
     #[test]
     fn test_empty() {
         let html = "".to_string();
         let mut tokenizer = HtmlTokenizer::new(html);
+        assert_eq!(Some(HtmlToken::Eof), tokenizer.next());
         assert!(tokenizer.next().is_none());
     }
     #[test]
@@ -521,4 +1627,227 @@ mod tests {
             assert_eq!(Some(e), tokenizer.next());
         }
     }
+    #[test]
+    fn test_character_references_in_data() {
+        let html = "&amp;&lt;&nbsp;&#12354;&#x3042;&#X41;&notin;&notit;&copy &#128;&#0;&#x110000;&#55296;&nosuch; &#;&#x;& &".to_string();
+        let tokenizer = HtmlTokenizer::new(html);
+        let text: String = tokenizer
+            .filter_map(|t| match t {
+                HtmlToken::Char(c) => Some(c),
+                HtmlToken::Eof => None,
+                _ => panic!("unexpected token {:?}", t),
+            })
+            .collect();
+        assert_eq!(
+            "&<\u{a0}\u{3042}\u{3042}A\u{2209}\u{ac}it;\u{a9} \u{20ac}\u{fffd}\u{fffd}\u{fffd}&nosuch; &#;&#x;& &",
+            text
+        );
+    }
+    #[test]
+    fn test_character_references_in_attribute() {
+        let html =
+            "<a href=\"?a=1&amp;b=2&copy=3&not;&notx\" title=&lt;&#x41 alt='&AMP'>".to_string();
+        let mut tokenizer = HtmlTokenizer::new(html);
+        let mut attributes = Vec::new();
+        for (name, value) in [
+            ("href", "?a=1&b=2&copy=3\u{ac}&notx"),
+            ("title", "<A"),
+            ("alt", "&"),
+        ] {
+            let mut attr = Attribute::new();
+            name.chars().for_each(|c| attr.add_char(c, true));
+            value.chars().for_each(|c| attr.add_char(c, false));
+            attributes.push(attr);
+        }
+        assert_eq!(
+            Some(HtmlToken::StartTag {
+                tag: "a".to_string(),
+                self_closing: false,
+                attributes,
+            }),
+            tokenizer.next()
+        );
+    }
+    #[test]
+    fn test_comment_and_doctype() {
+        let html = "<!DOCTYPE html><!-- a -- b --><!--->x<?php?></ x><!doctype html PUBLIC \"-//W3C//DTD HTML 4.01//EN\" 'dtd'>".to_string();
+        let tokenizer = HtmlTokenizer::new(html);
+        let expected = [
+            HtmlToken::Doctype {
+                name: Some("html".to_string()),
+                public_id: None,
+                system_id: None,
+                force_quirks: false,
+            },
+            HtmlToken::Comment(" a -- b ".to_string()),
+            HtmlToken::Comment("".to_string()),
+            HtmlToken::Char('x'),
+            HtmlToken::Comment("?php?".to_string()),
+            HtmlToken::Comment(" x".to_string()),
+            HtmlToken::Doctype {
+                name: Some("html".to_string()),
+                public_id: Some("-//W3C//DTD HTML 4.01//EN".to_string()),
+                system_id: Some("dtd".to_string()),
+                force_quirks: false,
+            },
+            HtmlToken::Eof,
+        ];
+        assert_eq!(expected.to_vec(), tokenizer.collect::<Vec<_>>());
+    }
+    #[test]
+    fn test_null_in_comment() {
+        let tokenizer = HtmlTokenizer::new("<!--\0a-\0--\0--><?\0>".to_string());
+        assert_eq!(
+            vec![
+                HtmlToken::Comment("\u{FFFD}a-\u{FFFD}--\u{FFFD}".to_string()),
+                HtmlToken::Comment("?\u{FFFD}".to_string()),
+                HtmlToken::Eof
+            ],
+            tokenizer.collect::<Vec<_>>()
+        );
+    }
+    #[test]
+    fn test_comment_and_doctype_at_eof() {
+        let tokenizer = HtmlTokenizer::new("<!-- open".to_string());
+        assert_eq!(
+            vec![HtmlToken::Comment(" open".to_string()), HtmlToken::Eof],
+            tokenizer.collect::<Vec<_>>()
+        );
+        let tokenizer = HtmlTokenizer::new("<!DOCTYPE".to_string());
+        assert_eq!(
+            vec![
+                HtmlToken::Doctype {
+                    name: None,
+                    public_id: None,
+                    system_id: None,
+                    force_quirks: true,
+                },
+                HtmlToken::Eof
+            ],
+            tokenizer.collect::<Vec<_>>()
+        );
+    }
+    #[test]
+    fn test_rcdata_and_rawtext() {
+        for (state, tag, content, expected) in [
+            (
+                State::Rcdata,
+                "title",
+                "a &lt; <b></titlex></title>",
+                "a < <b></titlex>",
+            ),
+            (
+                State::Rawtext,
+                "style",
+                "p > a &lt; </p></style>",
+                "p > a &lt; </p>",
+            ),
+            (
+                State::ScriptData,
+                "script",
+                "if (a</b) {}</script>",
+                "if (a</b) {}",
+            ),
+        ] {
+            let mut tokenizer = HtmlTokenizer::new(format!("<{}>{}", tag, content));
+            assert_eq!(
+                Some(HtmlToken::StartTag {
+                    tag: tag.to_string(),
+                    self_closing: false,
+                    attributes: Vec::new(),
+                }),
+                tokenizer.next()
+            );
+            tokenizer.switch_to(state);
+            let mut text = String::new();
+            loop {
+                match tokenizer.next() {
+                    Some(HtmlToken::Char(c)) => text.push(c),
+                    Some(HtmlToken::EndTag { tag: end }) => {
+                        assert_eq!(tag, end);
+                        break;
+                    }
+                    t => panic!("unexpected token {:?}", t),
+                }
+            }
+            assert_eq!(expected, text);
+        }
+    }
+    #[test]
+    fn test_parse_errors() {
+        for (html, expected) in [
+            ("<p id=a id=b>", vec![("duplicate-attribute", 1, 13)]),
+            (
+                "<p>\n&amp</p>",
+                vec![("missing-semicolon-after-character-reference", 2, 4)],
+            ),
+            (
+                "</p a=\"b\"/>",
+                vec![
+                    ("end-tag-with-attributes", 1, 5),
+                    ("end-tag-with-trailing-solidus", 1, 11),
+                ],
+            ),
+            ("<!-->", vec![("abrupt-closing-of-empty-comment", 1, 5)]),
+            ("<!DOCTYPE>", vec![("missing-doctype-name", 1, 10)]),
+            ("<p", vec![("eof-in-tag", 1, 3)]),
+            ("&#0;", vec![("null-character-reference", 1, 4)]),
+        ] {
+            let mut tokenizer = HtmlTokenizer::new(html.to_string());
+            while tokenizer.next().is_some() {}
+            let errors: Vec<(String, usize, usize)> = tokenizer
+                .errors()
+                .iter()
+                .map(|e| (e.code(), e.span().start().line(), e.span().start().column()))
+                .collect();
+            let expected: Vec<(String, usize, usize)> = expected
+                .iter()
+                .map(|(code, line, column)| (code.to_string(), *line, *column))
+                .collect();
+            assert_eq!(expected, errors, "{}", html);
+        }
+    }
+    #[test]
+    fn test_spans() {
+        let mut tokenizer = HtmlTokenizer::new("<p>\n<a href=x>b</a>".to_string());
+        let mut spans = Vec::new();
+        while let Some(token) = tokenizer.next() {
+            if let HtmlToken::Char(_) | HtmlToken::Eof = token {
+                continue;
+            }
+            let span = tokenizer.span();
+            spans.push((
+                span.start().line(),
+                span.start().column(),
+                span.end().line(),
+                span.end().column(),
+            ));
+        }
+        assert_eq!(vec![(1, 1, 1, 4), (2, 1, 2, 11), (2, 12, 2, 16)], spans);
+    }
+    #[test]
+    fn test_incremental_input() {
+        let html = "<!DOCTYPE html PUBLIC \"a\"><!-- c --><p class=\"x&amp;y\">\u{3042}&notin;&not &lt</p><title>a</title>";
+        let expected: Vec<HtmlToken> = HtmlTokenizer::new(html.to_string()).collect();
+        let bytes = html.as_bytes();
+        for size in 1..bytes.len() {
+            let mut tokenizer = HtmlTokenizer::new_incremental();
+            let mut tokens = Vec::new();
+            for chunk in bytes.chunks(size) {
+                tokenizer.feed_bytes(chunk);
+                while let Some(token) = tokenizer.next() {
+                    if let HtmlToken::StartTag { ref tag, .. } = token {
+                        if tag == "title" {
+                            tokenizer.switch_to(State::Rcdata);
+                        }
+                    }
+                    tokens.push(token);
+                }
+                assert!(tokenizer.is_suspended());
+            }
+            tokenizer.close();
+            tokens.extend(&mut tokenizer);
+            assert_eq!(expected, tokens, "chunk size {}", size);
+        }
+    }
 }
@@ -1,13 +1,31 @@
+use crate::renderer::diagnostics::ParseError;
+use crate::renderer::diagnostics::SourceLanguage;
+use crate::renderer::diagnostics::SourcePosition;
+use crate::renderer::diagnostics::Span;
+use crate::renderer::diagnostics::TokenStream;
 use crate::renderer::js::token::JsLexer;
 use crate::renderer::js::token::Token;
 use alloc::rc::Rc;
 use alloc::string::String;
 use alloc::vec::Vec;
-use core::iter::Peekable;
 
-/// Enum representing a node in the JavaScript AST.
+/// Struct representing a node in the JavaScript AST.
+#[derive(Debug, Clone)]
+pub struct Node {
+    kind: NodeKind,
+    span: Span,
+}
+impl PartialEq for Node {
+    /// Nodes are equal when their kinds are equal, wherever they are in the source.
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind
+    }
+}
+impl Eq for Node {}
+
+/// Enum representing the kind of a node in the JavaScript AST.
 #[derive(Debug, Clone, PartialEq, Eq)]
-pub enum Node {
+pub enum NodeKind {
     ExpressionStatement(Option<Rc<Node>>),
     AdditiveExpression {
         operator: char,
@@ -22,6 +40,9 @@ pub enum Node {
     MemberExpression {
         object: Option<Rc<Node>>,
         property: Option<Rc<Node>>,
+        /// Whether the property is an expression in brackets, such as `list[i]`, rather than a
+        /// name after a dot.
+        computed: bool,
     },
     NumericLiteral(u64),
     VariableDeclaration {
@@ -48,75 +69,136 @@ pub enum Node {
         callee: Option<Rc<Node>>,
         arguments: Vec<Option<Rc<Node>>>,
     },
+    NewExpression {
+        callee: Option<Rc<Node>>,
+        arguments: Vec<Option<Rc<Node>>>,
+    },
+    FunctionExpression {
+        id: Option<Rc<Node>>,
+        params: Vec<Option<Rc<Node>>>,
+        body: Option<Rc<Node>>,
+    },
 }
 impl Node {
+    pub fn new(kind: NodeKind) -> Self {
+        Self {
+            kind,
+            span: Span::default(),
+        }
+    }
+    pub fn kind(&self) -> &NodeKind {
+        &self.kind
+    }
+    /// Returns the range of the source text that the node was parsed from.
+    pub fn span(&self) -> Span {
+        self.span
+    }
     pub fn new_expression_statement(expression: Option<Rc<Self>>) -> Option<Rc<Self>> {
-        Some(Rc::new(Node::ExpressionStatement(expression)))
+        Some(Rc::new(Node::new(NodeKind::ExpressionStatement(
+            expression,
+        ))))
     }
     pub fn new_additive_expression(
         operator: char,
         left: Option<Rc<Node>>,
         right: Option<Rc<Node>>,
     ) -> Option<Rc<Self>> {
-        Some(Rc::new(Node::AdditiveExpression {
+        Some(Rc::new(Node::new(NodeKind::AdditiveExpression {
             operator,
             left,
             right,
-        }))
+        })))
     }
     pub fn new_assignment_expression(
         operator: char,
         left: Option<Rc<Node>>,
         right: Option<Rc<Node>>,
     ) -> Option<Rc<Self>> {
-        Some(Rc::new(Node::AssignmentExpression {
+        Some(Rc::new(Node::new(NodeKind::AssignmentExpression {
             operator,
             left,
             right,
-        }))
+        })))
     }
     pub fn new_member_expression(
         object: Option<Rc<Self>>,
         property: Option<Rc<Self>>,
+        computed: bool,
     ) -> Option<Rc<Self>> {
-        Some(Rc::new(Node::MemberExpression { object, property }))
+        Some(Rc::new(Node::new(NodeKind::MemberExpression {
+            object,
+            property,
+            computed,
+        })))
     }
     pub fn new_numeric_literal(value: u64) -> Option<Rc<Self>> {
-        Some(Rc::new(Node::NumericLiteral(value)))
+        Some(Rc::new(Node::new(NodeKind::NumericLiteral(value))))
     }
     pub fn new_variable_declarator(
         id: Option<Rc<Self>>,
         init: Option<Rc<Self>>,
     ) -> Option<Rc<Self>> {
-        Some(Rc::new(Node::VariableDeclarator { id, init }))
+        Some(Rc::new(Node::new(NodeKind::VariableDeclarator {
+            id,
+            init,
+        })))
     }
     pub fn new_variable_declaration(declarations: Vec<Option<Rc<Self>>>) -> Option<Rc<Self>> {
-        Some(Rc::new(Node::VariableDeclaration { declarations }))
+        Some(Rc::new(Node::new(NodeKind::VariableDeclaration {
+            declarations,
+        })))
     }
     pub fn new_identifier(name: String) -> Option<Rc<Self>> {
-        Some(Rc::new(Node::Identifier(name)))
+        Some(Rc::new(Node::new(NodeKind::Identifier(name))))
     }
     pub fn new_string_literal(value: String) -> Option<Rc<Self>> {
-        Some(Rc::new(Node::StringLiteral(value)))
+        Some(Rc::new(Node::new(NodeKind::StringLiteral(value))))
     }
     pub fn new_block_statement(body: Vec<Option<Rc<Self>>>) -> Option<Rc<Self>> {
-        Some(Rc::new(Node::BlockStatement { body }))
+        Some(Rc::new(Node::new(NodeKind::BlockStatement { body })))
     }
     pub fn new_return_statement(argument: Option<Rc<Self>>) -> Option<Rc<Self>> {
-        Some(Rc::new(Node::ReturnStatement { argument }))
+        Some(Rc::new(Node::new(NodeKind::ReturnStatement { argument })))
     }
     pub fn new_function_declaration(
         id: Option<Rc<Self>>,
         params: Vec<Option<Rc<Self>>>,
         body: Option<Rc<Self>>,
     ) -> Option<Rc<Self>> {
-        Some(Rc::new(Node::FunctionDeclaration { id, params, body }))
+        Some(Rc::new(Node::new(NodeKind::FunctionDeclaration {
+            id,
+            params,
+            body,
+        })))
     }
     pub fn new_call_expression(
         callee: Option<Rc<Self>>,
         arguments: Vec<Option<Rc<Self>>>,
     ) -> Option<Rc<Self>> {
-        Some(Rc::new(Node::CallExpression { callee, arguments }))
+        Some(Rc::new(Node::new(NodeKind::CallExpression {
+            callee,
+            arguments,
+        })))
+    }
+    pub fn new_new_expression(
+        callee: Option<Rc<Self>>,
+        arguments: Vec<Option<Rc<Self>>>,
+    ) -> Option<Rc<Self>> {
+        Some(Rc::new(Node::new(NodeKind::NewExpression {
+            callee,
+            arguments,
+        })))
+    }
+    pub fn new_function_expression(
+        id: Option<Rc<Self>>,
+        params: Vec<Option<Rc<Self>>>,
+        body: Option<Rc<Self>>,
+    ) -> Option<Rc<Self>> {
+        Some(Rc::new(Node::new(NodeKind::FunctionExpression {
+            id,
+            params,
+            body,
+        })))
     }
 }
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -137,56 +219,140 @@ impl Program {
 
 /// Struct representing a JavaScript parser.
 pub struct JsParser {
-    t: Peekable<JsLexer>,
+    t: TokenStream<JsLexer>,
+    errors: Vec<ParseError>,
 }
 impl JsParser {
     pub fn new(t: JsLexer) -> Self {
-        Self { t: t.peekable() }
+        Self {
+            t: TokenStream::new(t),
+            errors: Vec::new(),
+        }
+    }
+    /// Returns the parse errors of the lexer and the parser, in source order.
+    pub fn errors(&self) -> Vec<ParseError> {
+        let mut errors = self.t.tokenizer().errors();
+        errors.extend(self.errors.iter().cloned());
+        errors.sort_by_key(|e| e.span().start());
+        errors
+    }
+    /// Records a parse error at the token that was consumed last.
+    fn parse_error(&mut self, code: &str) {
+        self.errors.push(ParseError::new(
+            SourceLanguage::JavaScript,
+            code,
+            self.t.span(),
+        ));
+    }
+    /// Returns the position where the next token starts.
+    fn start(&mut self) -> SourcePosition {
+        self.t.peek_span().start()
+    }
+    /// Sets the span of a node that was just created, from `start` to the end of the token that
+    /// was consumed last.
+    fn spanned(&self, node: Option<Rc<Node>>, start: SourcePosition) -> Option<Rc<Node>> {
+        let mut node = node?;
+        if let Some(n) = Rc::get_mut(&mut node) {
+            n.span = Span::new(start, self.t.span().end().max(start));
+        }
+        Some(node)
+    }
+    /// Consumes the next token if it is the punctuator `c`, and records a parse error otherwise.
+    fn expect_punctuator(&mut self, c: char) -> bool {
+        if self.t.peek() == Some(&Token::Punctuator(c)) {
+            assert!(self.t.next().is_some());
+            return true;
+        }
+        match self.t.next() {
+            Some(_) => self.parse_error("unexpected-token"),
+            None => self.parse_error("unexpected-end-of-input"),
+        }
+        false
     }
     fn primary_expression(&mut self) -> Option<Rc<Node>> {
+        let start = self.start();
         let t = match self.t.next() {
             Some(token) => token,
-            None => return None,
+            None => {
+                self.parse_error("unexpected-end-of-input");
+                return None;
+            }
         };
-        match t {
+        let node = match t {
             Token::Identifier(value) => Node::new_identifier(value),
             Token::StringLiteral(value) => Node::new_string_literal(value),
             Token::Number(value) => Node::new_numeric_literal(value),
-            _ => None,
-        }
+            Token::Keyword(keyword) if keyword == "function" => self.function_expression(),
+            _ => {
+                self.parse_error("unexpected-token");
+                None
+            }
+        };
+        self.spanned(node, start)
     }
     fn member_expression(&mut self) -> Option<Rc<Node>> {
-        let expr = self.primary_expression();
-        let t = match self.t.peek() {
-            Some(token) => token,
-            None => return expr,
-        };
-        match t {
-            Token::Punctuator(c) => {
-                if c == &'.' {
+        let start = self.start();
+        if let Some(Token::Keyword(keyword)) = self.t.peek() {
+            if keyword == "new" {
+                assert!(self.t.next().is_some());
+                let callee = self.member_expression();
+                let mut arguments = Vec::new();
+                if let Some(Token::Punctuator('(')) = self.t.peek() {
                     assert!(self.t.next().is_some());
-                    return Node::new_member_expression(expr, self.identifier());
+                    arguments = self.arguments();
                 }
-                expr
+                let node = Node::new_new_expression(callee, arguments);
+                return self.spanned(node, start);
             }
-            _ => expr,
         }
+        let mut expr = self.primary_expression();
+        loop {
+            match self.t.peek() {
+                Some(Token::Punctuator('.')) => {
+                    assert!(self.t.next().is_some());
+                    let property = self.identifier();
+                    expr = self.spanned(Node::new_member_expression(expr, property, false), start);
+                }
+                Some(Token::Punctuator('[')) => {
+                    let property = self.computed_property();
+                    expr = self.spanned(Node::new_member_expression(expr, property, true), start);
+                }
+                _ => return expr,
+            }
+        }
+    }
+    /// Parses the property of a computed member access such as `list[0]`, from `[` to `]`.
+    fn computed_property(&mut self) -> Option<Rc<Node>> {
+        assert!(self.t.next().is_some());
+        let property = self.assignment_expression();
+        match self.t.next() {
+            Some(Token::Punctuator(']')) => {}
+            _ => self.parse_error("unexpected-token"),
+        }
+        property
     }
     fn left_hand_side_expression(&mut self) -> Option<Rc<Node>> {
-        let expr = self.member_expression();
-        let t = match self.t.peek() {
-            Some(token) => token,
-            None => return expr,
-        };
-        match t {
-            Token::Punctuator(c) => {
-                if c == &'(' {
+        let start = self.start();
+        let mut expr = self.member_expression();
+        // Calls and property accesses can be chained, e.g. `fetch(url).then(f)`.
+        loop {
+            match self.t.peek() {
+                Some(Token::Punctuator('(')) => {
+                    assert!(self.t.next().is_some());
+                    let arguments = self.arguments();
+                    expr = self.spanned(Node::new_call_expression(expr, arguments), start);
+                }
+                Some(Token::Punctuator('.')) => {
                     assert!(self.t.next().is_some());
-                    return Node::new_call_expression(expr, self.arguments());
+                    let property = self.identifier();
+                    expr = self.spanned(Node::new_member_expression(expr, property, false), start);
+                }
+                Some(Token::Punctuator('[')) => {
+                    let property = self.computed_property();
+                    expr = self.spanned(Node::new_member_expression(expr, property, true), start);
                 }
-                expr
+                _ => return expr,
             }
-            _ => expr,
         }
     }
     fn arguments(&mut self) -> Vec<Option<Rc<Node>>> {
@@ -201,15 +367,24 @@ impl JsParser {
                         }
                         if c == &',' {
                             assert!(self.t.next().is_some());
+                        } else {
+                            // A punctuator can't start an argument, e.g. the `;` of `f(1;`.
+                            assert!(self.t.next().is_some());
+                            self.parse_error("unexpected-token");
+                            return arguments;
                         }
                     }
                     _ => arguments.push(self.assignment_expression()),
                 },
-                None => return arguments,
+                None => {
+                    self.parse_error("unexpected-end-of-input");
+                    return arguments;
+                }
             }
         }
     }
     fn additive_expression(&mut self) -> Option<Rc<Node>> {
+        let start = self.start();
         let left = self.left_hand_side_expression();
         let t = match self.t.peek() {
             Some(token) => token.clone(),
@@ -219,7 +394,8 @@ impl JsParser {
             Token::Punctuator(c) => match c {
                 '+' | '-' => {
                     assert!(self.t.next().is_some());
-                    Node::new_additive_expression(c, left, self.assignment_expression())
+                    let right = self.assignment_expression();
+                    self.spanned(Node::new_additive_expression(c, left, right), start)
                 }
                 _ => left,
             },
@@ -227,6 +403,7 @@ impl JsParser {
         }
     }
     fn assignment_expression(&mut self) -> Option<Rc<Node>> {
+        let start = self.start();
         let expr = self.additive_expression();
         let t = match self.t.peek() {
             Some(token) => token,
@@ -235,7 +412,8 @@ impl JsParser {
         match t {
             Token::Punctuator('=') => {
                 assert!(self.t.next().is_some());
-                Node::new_assignment_expression('=', expr, self.assignment_expression())
+                let right = self.assignment_expression();
+                self.spanned(Node::new_assignment_expression('=', expr, right), start)
             }
             _ => expr,
         }
@@ -254,23 +432,33 @@ impl JsParser {
         }
     }
     fn identifier(&mut self) -> Option<Rc<Node>> {
+        let start = self.start();
         let t = match self.t.next() {
             Some(token) => token,
-            None => return None,
+            None => {
+                self.parse_error("unexpected-end-of-input");
+                return None;
+            }
         };
         match t {
-            Token::Identifier(name) => Node::new_identifier(name),
-            _ => None,
+            Token::Identifier(name) => self.spanned(Node::new_identifier(name), start),
+            _ => {
+                self.parse_error("expected-identifier");
+                None
+            }
         }
     }
     fn variable_declaration(&mut self) -> Option<Rc<Node>> {
+        let start = self.start();
         let ident = self.identifier();
-        let declarator = Node::new_variable_declarator(ident, self.initializer());
+        let init = self.initializer();
+        let declarator = self.spanned(Node::new_variable_declarator(ident, init), start);
         let mut declarations = Vec::new();
         declarations.push(declarator);
         Node::new_variable_declaration(declarations)
     }
     fn statement(&mut self) -> Option<Rc<Node>> {
+        let start = self.start();
         let t = match self.t.peek() {
             Some(t) => t,
             None => return None,
@@ -284,7 +472,7 @@ impl JsParser {
                     assert!(self.t.next().is_some());
                     Node::new_return_statement(self.assignment_expression())
                 } else {
-                    None
+                    Node::new_expression_statement(self.assignment_expression())
                 }
             }
             _ => Node::new_expression_statement(self.assignment_expression()),
@@ -294,7 +482,7 @@ impl JsParser {
                 assert!(self.t.next().is_some());
             }
         }
-        node
+        self.spanned(node, start)
     }
     fn source_element(&mut self) -> Option<Rc<Node>> {
         let t = match self.t.peek() {
@@ -314,18 +502,27 @@ impl JsParser {
         }
     }
     fn function_declaration(&mut self) -> Option<Rc<Node>> {
+        // The `function` keyword has been consumed.
+        let start = self.t.span().start();
         let id = self.identifier();
         let params = self.parameter_list();
-        Node::new_function_declaration(id, params, self.function_body())
+        let body = self.function_body();
+        self.spanned(Node::new_function_declaration(id, params, body), start)
+    }
+    fn function_expression(&mut self) -> Option<Rc<Node>> {
+        let start = self.t.span().start();
+        let id = match self.t.peek() {
+            Some(Token::Identifier(_)) => self.identifier(),
+            _ => None,
+        };
+        let params = self.parameter_list();
+        let body = self.function_body();
+        self.spanned(Node::new_function_expression(id, params, body), start)
     }
     fn parameter_list(&mut self) -> Vec<Option<Rc<Node>>> {
         let mut params = Vec::new();
-        match self.t.next() {
-            Some(t) => match t {
-                Token::Punctuator(c) => assert!(c == '('),
-                _ => unimplemented!("function should have `(` but got {:?}", t),
-            },
-            None => unimplemented!("function should not have `(` but got None"),
+        if !self.expect_punctuator('(') {
+            return params;
         }
         loop {
             match self.t.peek() {
@@ -335,41 +532,41 @@ impl JsParser {
                             assert!(self.t.next().is_some());
                             return params;
                         }
-                        if c == &',' {
-                            assert!(self.t.next().is_some());
+                        let comma = c == &',';
+                        assert!(self.t.next().is_some());
+                        if !comma {
+                            self.parse_error("unexpected-token");
                         }
                     }
                     _ => {
                         params.push(self.identifier());
                     }
                 },
-                None => return params,
+                None => {
+                    self.parse_error("unexpected-end-of-input");
+                    return params;
+                }
             }
         }
     }
     fn function_body(&mut self) -> Option<Rc<Node>> {
-        match self.t.next() {
-            Some(t) => match t {
-                Token::Punctuator(c) => assert!(c == '{'),
-                _ => unimplemented!("function should have open curly bracket but got {:?}", t),
-            },
-            None => unimplemented!("function should not have open curly bracket but got None"),
+        let start = self.start();
+        if !self.expect_punctuator('{') {
+            return None;
         }
         let mut body = Vec::new();
         loop {
             match self.t.peek() {
-                Some(t) => match t {
-                    Token::Punctuator(c) => {
-                        if c == &'}' {
-                            assert!(self.t.next().is_some());
-                            return Node::new_block_statement(body);
-                        }
-                    }
-                    _ => {}
-                },
-                None => {}
+                Some(Token::Punctuator('}')) => {
+                    assert!(self.t.next().is_some());
+                    return self.spanned(Node::new_block_statement(body), start);
+                }
+                Some(_) => body.push(self.source_element()),
+                None => {
+                    self.parse_error("unexpected-end-of-input");
+                    return self.spanned(Node::new_block_statement(body), start);
+                }
             }
-            body.push(self.source_element());
         }
     }
     pub fn parse_ast(&mut self) -> Program {
@@ -407,8 +604,8 @@ mod tests {
         let mut parser = JsParser::new(lexer);
         let mut expected = Program::new();
         let mut body = Vec::new();
-        body.push(Rc::new(Node::ExpressionStatement(Some(Rc::new(
-            Node::NumericLiteral(42),
+        body.push(Rc::new(Node::new(NodeKind::ExpressionStatement(Some(
+            Rc::new(Node::new(NodeKind::NumericLiteral(42))),
         )))));
         expected.set_body(body);
         assert_eq!(expected, parser.parse_ast());
@@ -420,12 +617,12 @@ mod tests {
         let mut parser = JsParser::new(lexer);
         let mut expected = Program::new();
         let mut body = Vec::new();
-        body.push(Rc::new(Node::ExpressionStatement(Some(Rc::new(
-            Node::AdditiveExpression {
+        body.push(Rc::new(Node::new(NodeKind::ExpressionStatement(Some(
+            Rc::new(Node::new(NodeKind::AdditiveExpression {
                 operator: '+',
-                left: Some(Rc::new(Node::NumericLiteral(1))),
-                right: Some(Rc::new(Node::NumericLiteral(2))),
-            },
+                left: Some(Rc::new(Node::new(NodeKind::NumericLiteral(1)))),
+                right: Some(Rc::new(Node::new(NodeKind::NumericLiteral(2)))),
+            })),
         )))));
         expected.set_body(body);
         assert_eq!(expected, parser.parse_ast());
@@ -437,13 +634,15 @@ mod tests {
         let mut parser = JsParser::new(lexer);
         let mut expected = Program::new();
         let mut body = Vec::new();
-        body.push(Rc::new(Node::VariableDeclaration {
-            declarations: [Some(Rc::new(Node::VariableDeclarator {
-                id: Some(Rc::new(Node::Identifier("foo".to_string()))),
-                init: Some(Rc::new(Node::StringLiteral("bar".to_string()))),
-            }))]
+        body.push(Rc::new(Node::new(NodeKind::VariableDeclaration {
+            declarations: [Some(Rc::new(Node::new(NodeKind::VariableDeclarator {
+                id: Some(Rc::new(Node::new(NodeKind::Identifier("foo".to_string())))),
+                init: Some(Rc::new(Node::new(NodeKind::StringLiteral(
+                    "bar".to_string(),
+                )))),
+            })))]
             .to_vec(),
-        }));
+        })));
         expected.set_body(body);
         assert_eq!(expected, parser.parse_ast());
     }
@@ -454,24 +653,26 @@ mod tests {
         let mut parser = JsParser::new(lexer);
         let mut expected = Program::new();
         let mut body = Vec::new();
-        body.push(Rc::new(Node::VariableDeclaration {
-            declarations: [Some(Rc::new(Node::VariableDeclarator {
-                id: Some(Rc::new(Node::Identifier("foo".to_string()))),
-                init: Some(Rc::new(Node::NumericLiteral(42))),
-            }))]
+        body.push(Rc::new(Node::new(NodeKind::VariableDeclaration {
+            declarations: [Some(Rc::new(Node::new(NodeKind::VariableDeclarator {
+                id: Some(Rc::new(Node::new(NodeKind::Identifier("foo".to_string())))),
+                init: Some(Rc::new(Node::new(NodeKind::NumericLiteral(42)))),
+            })))]
             .to_vec(),
-        }));
-        body.push(Rc::new(Node::VariableDeclaration {
-            declarations: [Some(Rc::new(Node::VariableDeclarator {
-                id: Some(Rc::new(Node::Identifier("result".to_string()))),
-                init: Some(Rc::new(Node::AdditiveExpression {
+        })));
+        body.push(Rc::new(Node::new(NodeKind::VariableDeclaration {
+            declarations: [Some(Rc::new(Node::new(NodeKind::VariableDeclarator {
+                id: Some(Rc::new(Node::new(NodeKind::Identifier(
+                    "result".to_string(),
+                )))),
+                init: Some(Rc::new(Node::new(NodeKind::AdditiveExpression {
                     operator: '+',
-                    left: Some(Rc::new(Node::Identifier("foo".to_string()))),
-                    right: Some(Rc::new(Node::NumericLiteral(1))),
-                })),
-            }))]
+                    left: Some(Rc::new(Node::new(NodeKind::Identifier("foo".to_string())))),
+                    right: Some(Rc::new(Node::new(NodeKind::NumericLiteral(1)))),
+                }))),
+            })))]
             .to_vec(),
-        }));
+        })));
         expected.set_body(body);
         assert_eq!(expected, parser.parse_ast());
     }
@@ -482,16 +683,16 @@ mod tests {
         let mut parser = JsParser::new(lexer);
         let mut expected = Program::new();
         let mut body = Vec::new();
-        body.push(Rc::new(Node::FunctionDeclaration {
-            id: Some(Rc::new(Node::Identifier("foo".to_string()))),
+        body.push(Rc::new(Node::new(NodeKind::FunctionDeclaration {
+            id: Some(Rc::new(Node::new(NodeKind::Identifier("foo".to_string())))),
             params: [].to_vec(),
-            body: Some(Rc::new(Node::BlockStatement {
-                body: [Some(Rc::new(Node::ReturnStatement {
-                    argument: Some(Rc::new(Node::NumericLiteral(42))),
-                }))]
+            body: Some(Rc::new(Node::new(NodeKind::BlockStatement {
+                body: [Some(Rc::new(Node::new(NodeKind::ReturnStatement {
+                    argument: Some(Rc::new(Node::new(NodeKind::NumericLiteral(42)))),
+                })))]
                 .to_vec(),
-            })),
-        }));
+            }))),
+        })));
         expected.set_body(body);
         assert_eq!(expected, parser.parse_ast());
     }
@@ -502,24 +703,24 @@ mod tests {
         let mut parser = JsParser::new(lexer);
         let mut expected = Program::new();
         let mut body = Vec::new();
-        body.push(Rc::new(Node::FunctionDeclaration {
-            id: Some(Rc::new(Node::Identifier("foo".to_string()))),
+        body.push(Rc::new(Node::new(NodeKind::FunctionDeclaration {
+            id: Some(Rc::new(Node::new(NodeKind::Identifier("foo".to_string())))),
             params: [
-                Some(Rc::new(Node::Identifier("a".to_string()))),
-                Some(Rc::new(Node::Identifier("b".to_string()))),
+                Some(Rc::new(Node::new(NodeKind::Identifier("a".to_string())))),
+                Some(Rc::new(Node::new(NodeKind::Identifier("b".to_string())))),
             ]
             .to_vec(),
-            body: Some(Rc::new(Node::BlockStatement {
-                body: [Some(Rc::new(Node::ReturnStatement {
-                    argument: Some(Rc::new(Node::AdditiveExpression {
+            body: Some(Rc::new(Node::new(NodeKind::BlockStatement {
+                body: [Some(Rc::new(Node::new(NodeKind::ReturnStatement {
+                    argument: Some(Rc::new(Node::new(NodeKind::AdditiveExpression {
                         operator: '+',
-                        left: Some(Rc::new(Node::Identifier("a".to_string()))),
-                        right: Some(Rc::new(Node::Identifier("b".to_string()))),
-                    })),
-                }))]
+                        left: Some(Rc::new(Node::new(NodeKind::Identifier("a".to_string())))),
+                        right: Some(Rc::new(Node::new(NodeKind::Identifier("b".to_string())))),
+                    }))),
+                })))]
                 .to_vec(),
-            })),
-        }));
+            }))),
+        })));
         expected.set_body(body);
         assert_eq!(expected, parser.parse_ast());
     }
@@ -530,31 +731,164 @@ mod tests {
         let mut parser = JsParser::new(lexer);
         let mut expected = Program::new();
         let mut body = Vec::new();
-        body.push(Rc::new(Node::FunctionDeclaration {
-            id: Some(Rc::new(Node::Identifier("foo".to_string()))),
+        body.push(Rc::new(Node::new(NodeKind::FunctionDeclaration {
+            id: Some(Rc::new(Node::new(NodeKind::Identifier("foo".to_string())))),
             params: [].to_vec(),
-            body: Some(Rc::new(Node::BlockStatement {
-                body: [Some(Rc::new(Node::ReturnStatement {
-                    argument: Some(Rc::new(Node::NumericLiteral(42))),
-                }))]
+            body: Some(Rc::new(Node::new(NodeKind::BlockStatement {
+                body: [Some(Rc::new(Node::new(NodeKind::ReturnStatement {
+                    argument: Some(Rc::new(Node::new(NodeKind::NumericLiteral(42)))),
+                })))]
                 .to_vec(),
-            })),
-        }));
-        body.push(Rc::new(Node::VariableDeclaration {
-            declarations: [Some(Rc::new(Node::VariableDeclarator {
-                id: Some(Rc::new(Node::Identifier("result".to_string()))),
-                init: Some(Rc::new(Node::AdditiveExpression {
+            }))),
+        })));
+        body.push(Rc::new(Node::new(NodeKind::VariableDeclaration {
+            declarations: [Some(Rc::new(Node::new(NodeKind::VariableDeclarator {
+                id: Some(Rc::new(Node::new(NodeKind::Identifier(
+                    "result".to_string(),
+                )))),
+                init: Some(Rc::new(Node::new(NodeKind::AdditiveExpression {
                     operator: '+',
-                    left: Some(Rc::new(Node::CallExpression {
-                        callee: Some(Rc::new(Node::Identifier("foo".to_string()))),
+                    left: Some(Rc::new(Node::new(NodeKind::CallExpression {
+                        callee: Some(Rc::new(Node::new(NodeKind::Identifier("foo".to_string())))),
                         arguments: [].to_vec(),
-                    })),
-                    right: Some(Rc::new(Node::NumericLiteral(1))),
-                })),
-            }))]
+                    }))),
+                    right: Some(Rc::new(Node::new(NodeKind::NumericLiteral(1)))),
+                }))),
+            })))]
             .to_vec(),
-        }));
+        })));
+        expected.set_body(body);
+        assert_eq!(expected, parser.parse_ast());
+    }
+    #[test]
+    fn test_new_expression_and_function_expression() {
+        let input =
+            "var ws = new WebSocket(\"ws://localhost\"); ws.onmessage = function(e){};".to_string();
+        let lexer = JsLexer::new(input);
+        let mut parser = JsParser::new(lexer);
+        let mut expected = Program::new();
+        let body = [
+            Rc::new(Node::new(NodeKind::VariableDeclaration {
+                declarations: [Some(Rc::new(Node::new(NodeKind::VariableDeclarator {
+                    id: Some(Rc::new(Node::new(NodeKind::Identifier("ws".to_string())))),
+                    init: Some(Rc::new(Node::new(NodeKind::NewExpression {
+                        callee: Some(Rc::new(Node::new(NodeKind::Identifier(
+                            "WebSocket".to_string(),
+                        )))),
+                        arguments: [Some(Rc::new(Node::new(NodeKind::StringLiteral(
+                            "ws://localhost".to_string(),
+                        ))))]
+                        .to_vec(),
+                    }))),
+                })))]
+                .to_vec(),
+            })),
+            Rc::new(Node::new(NodeKind::ExpressionStatement(Some(Rc::new(
+                Node::new(NodeKind::AssignmentExpression {
+                    operator: '=',
+                    left: Some(Rc::new(Node::new(NodeKind::MemberExpression {
+                        object: Some(Rc::new(Node::new(NodeKind::Identifier("ws".to_string())))),
+                        property: Some(Rc::new(Node::new(NodeKind::Identifier(
+                            "onmessage".to_string(),
+                        )))),
+                        computed: false,
+                    }))),
+                    right: Some(Rc::new(Node::new(NodeKind::FunctionExpression {
+                        id: None,
+                        params: [Some(Rc::new(Node::new(NodeKind::Identifier(
+                            "e".to_string(),
+                        ))))]
+                        .to_vec(),
+                        body: Some(Rc::new(Node::new(NodeKind::BlockStatement {
+                            body: [].to_vec(),
+                        }))),
+                    }))),
+                }),
+            ))))),
+        ]
+        .to_vec();
+        expected.set_body(body);
+        assert_eq!(expected, parser.parse_ast());
+    }
+    #[test]
+    fn test_chained_call() {
+        let input = "fetch(url).then(f);".to_string();
+        let lexer = JsLexer::new(input);
+        let mut parser = JsParser::new(lexer);
+        let mut expected = Program::new();
+        let body = [Rc::new(Node::new(NodeKind::ExpressionStatement(Some(
+            Rc::new(Node::new(NodeKind::CallExpression {
+                callee: Some(Rc::new(Node::new(NodeKind::MemberExpression {
+                    object: Some(Rc::new(Node::new(NodeKind::CallExpression {
+                        callee: Some(Rc::new(Node::new(NodeKind::Identifier(
+                            "fetch".to_string(),
+                        )))),
+                        arguments: [Some(Rc::new(Node::new(NodeKind::Identifier(
+                            "url".to_string(),
+                        ))))]
+                        .to_vec(),
+                    }))),
+                    property: Some(Rc::new(Node::new(NodeKind::Identifier("then".to_string())))),
+                    computed: false,
+                }))),
+                arguments: [Some(Rc::new(Node::new(NodeKind::Identifier(
+                    "f".to_string(),
+                ))))]
+                .to_vec(),
+            })),
+        ))))]
+        .to_vec();
         expected.set_body(body);
         assert_eq!(expected, parser.parse_ast());
     }
+    #[test]
+    fn test_spans() {
+        let input = "var a = 1;\nfoo(a + 2);".to_string();
+        let lexer = JsLexer::new(input);
+        let mut parser = JsParser::new(lexer);
+        let program = parser.parse_ast();
+        let spans: Vec<(usize, usize, usize, usize)> = program
+            .body()
+            .iter()
+            .map(|node| {
+                let span = node.span();
+                (
+                    span.start().line(),
+                    span.start().column(),
+                    span.end().line(),
+                    span.end().column(),
+                )
+            })
+            .collect();
+        assert_eq!([(1, 1, 1, 11), (2, 1, 2, 12)].to_vec(), spans);
+        let call = match program.body()[1].kind() {
+            NodeKind::ExpressionStatement(Some(call)) => call.clone(),
+            kind => panic!("unexpected node {:?}", kind),
+        };
+        assert_eq!(SourcePosition::new(2, 1), call.span().start());
+        assert_eq!(SourcePosition::new(2, 11), call.span().end());
+        assert!(parser.errors().is_empty());
+    }
+    #[test]
+    fn test_parse_errors() {
+        let input = "var a = 1 # 2;\nfunction f(a b {".to_string();
+        let lexer = JsLexer::new(input);
+        let mut parser = JsParser::new(lexer);
+        parser.parse_ast();
+        let errors: Vec<(String, usize, usize)> = parser
+            .errors()
+            .iter()
+            .map(|e| (e.code(), e.span().start().line(), e.span().start().column()))
+            .collect();
+        assert_eq!(
+            [
+                ("unexpected-character".to_string(), 1, 11),
+                ("unexpected-token".to_string(), 2, 16),
+                ("unexpected-end-of-input".to_string(), 2, 16),
+                ("unexpected-end-of-input".to_string(), 2, 16),
+            ]
+            .to_vec(),
+            errors
+        );
+    }
 }
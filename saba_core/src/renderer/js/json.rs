@@ -0,0 +1,245 @@
+use crate::renderer::js::runtime::RuntimeValue;
+use alloc::string::String;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+
+/// Parses a JSON text into a runtime value.
+/// The runtime only has unsigned integers, strings and objects, so other numbers, `true`, `false`
+/// and `null` become strings, and arrays become objects with index keys and a `length`.
+/// # Parameters
+/// - `input`: The JSON text.
+/// # Returns
+/// - The value, or `None` if the text is not valid JSON.
+/// # References:
+/// - <https://www.rfc-editor.org/rfc/rfc8259>
+pub fn parse_json(input: &str) -> Option<RuntimeValue> {
+    let mut parser = JsonParser {
+        input: input.chars().collect(),
+        pos: 0,
+    };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if parser.pos != parser.input.len() {
+        return None;
+    }
+    Some(value)
+}
+
+struct JsonParser {
+    input: Vec<char>,
+    pos: usize,
+}
+impl JsonParser {
+    fn peek(&self) -> Option<char> {
+        self.input.get(self.pos).copied()
+    }
+    fn skip_whitespace(&mut self) {
+        while let Some(' ' | '\t' | '\n' | '\r') = self.peek() {
+            self.pos += 1;
+        }
+    }
+    fn expect(&mut self, c: char) -> Option<()> {
+        self.skip_whitespace();
+        if self.peek() != Some(c) {
+            return None;
+        }
+        self.pos += 1;
+        Some(())
+    }
+    fn value(&mut self) -> Option<RuntimeValue> {
+        self.skip_whitespace();
+        match self.peek()? {
+            '{' => self.object(),
+            '[' => self.array(),
+            '"' => Some(RuntimeValue::StringLiteral(self.string()?)),
+            '-' | '0'..='9' => self.number(),
+            _ => {
+                for literal in ["true", "false", "null"] {
+                    let end = self.pos + literal.len();
+                    if end <= self.input.len()
+                        && self.input[self.pos..end]
+                            .iter()
+                            .copied()
+                            .eq(literal.chars())
+                    {
+                        self.pos = end;
+                        return Some(RuntimeValue::StringLiteral(literal.to_string()));
+                    }
+                }
+                None
+            }
+        }
+    }
+    fn object(&mut self) -> Option<RuntimeValue> {
+        self.expect('{')?;
+        let mut properties = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.pos += 1;
+            return Some(RuntimeValue::Object(properties));
+        }
+        loop {
+            self.skip_whitespace();
+            let name = self.string()?;
+            self.expect(':')?;
+            let value = self.value()?;
+            properties.retain(|(n, _): &(String, RuntimeValue)| *n != name);
+            properties.push((name, value));
+            self.skip_whitespace();
+            match self.peek()? {
+                ',' => self.pos += 1,
+                '}' => {
+                    self.pos += 1;
+                    return Some(RuntimeValue::Object(properties));
+                }
+                _ => return None,
+            }
+        }
+    }
+    fn array(&mut self) -> Option<RuntimeValue> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.peek() != Some(']') {
+            loop {
+                let value = self.value()?;
+                items.push((items.len().to_string(), value));
+                self.skip_whitespace();
+                match self.peek()? {
+                    ',' => self.pos += 1,
+                    ']' => break,
+                    _ => return None,
+                }
+            }
+        }
+        self.pos += 1;
+        let length = items.len() as u64;
+        items.push(("length".to_string(), RuntimeValue::Number(length)));
+        Some(RuntimeValue::Object(items))
+    }
+    fn string(&mut self) -> Option<String> {
+        if self.peek() != Some('"') {
+            return None;
+        }
+        self.pos += 1;
+        let mut result = String::new();
+        loop {
+            let c = self.peek()?;
+            self.pos += 1;
+            match c {
+                '"' => return Some(result),
+                '\\' => {
+                    let escaped = self.peek()?;
+                    self.pos += 1;
+                    match escaped {
+                        '"' | '\\' | '/' => result.push(escaped),
+                        'b' => result.push('\u{8}'),
+                        'f' => result.push('\u{c}'),
+                        'n' => result.push('\n'),
+                        'r' => result.push('\r'),
+                        't' => result.push('\t'),
+                        'u' => {
+                            let code = self.hex4()?;
+                            if (0xd800..0xdc00).contains(&code) {
+                                // A high surrogate must be followed by an escaped low surrogate.
+                                if self.peek() != Some('\\') {
+                                    return None;
+                                }
+                                self.pos += 1;
+                                if self.peek() != Some('u') {
+                                    return None;
+                                }
+                                self.pos += 1;
+                                let low = self.hex4()?;
+                                let c = 0x10000
+                                    + ((code - 0xd800) << 10)
+                                    + (low.checked_sub(0xdc00)?);
+                                result.push(char::from_u32(c)?);
+                            } else {
+                                result.push(char::from_u32(code)?);
+                            }
+                        }
+                        _ => return None,
+                    }
+                }
+                _ => result.push(c),
+            }
+        }
+    }
+    fn hex4(&mut self) -> Option<u32> {
+        let mut code = 0;
+        for _ in 0..4 {
+            code = code * 16 + self.peek()?.to_digit(16)?;
+            self.pos += 1;
+        }
+        Some(code)
+    }
+    fn number(&mut self) -> Option<RuntimeValue> {
+        let start = self.pos;
+        while let Some('-' | '+' | '.' | 'e' | 'E' | '0'..='9') = self.peek() {
+            self.pos += 1;
+        }
+        let text: String = self.input[start..self.pos].iter().collect();
+        match text.parse::<u64>() {
+            Ok(n) => Some(RuntimeValue::Number(n)),
+            Err(_) => {
+                if text.chars().any(|c| c.is_ascii_digit()) {
+                    Some(RuntimeValue::StringLiteral(text))
+                } else {
+                    None
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_object() {
+        assert_eq!(
+            parse_json(r#" {"name": "saba", "stars": 42, "ok": true, "tags": ["a"]} "#),
+            Some(RuntimeValue::Object(
+                [
+                    (
+                        "name".to_string(),
+                        RuntimeValue::StringLiteral("saba".to_string())
+                    ),
+                    ("stars".to_string(), RuntimeValue::Number(42)),
+                    (
+                        "ok".to_string(),
+                        RuntimeValue::StringLiteral("true".to_string())
+                    ),
+                    (
+                        "tags".to_string(),
+                        RuntimeValue::Object(
+                            [
+                                (
+                                    "0".to_string(),
+                                    RuntimeValue::StringLiteral("a".to_string())
+                                ),
+                                ("length".to_string(), RuntimeValue::Number(1)),
+                            ]
+                            .to_vec()
+                        )
+                    ),
+                ]
+                .to_vec()
+            ))
+        );
+    }
+    #[test]
+    fn test_escapes() {
+        assert_eq!(
+            parse_json(r#""a\n\u00e9\ud83d\ude00""#),
+            Some(RuntimeValue::StringLiteral("a\né😀".to_string()))
+        );
+    }
+    #[test]
+    fn test_invalid() {
+        assert_eq!(parse_json("{\"a\":}"), None);
+        assert_eq!(parse_json("[1,2"), None);
+        assert_eq!(parse_json("1 2"), None);
+    }
+}
@@ -1,3 +1,4 @@
 pub mod ast;
+pub mod json;
 pub mod runtime;
 pub mod token;
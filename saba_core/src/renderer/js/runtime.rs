@@ -1,15 +1,76 @@
+use crate::error::Error;
+use crate::fetch::Fetcher;
+use crate::http::Header;
+use crate::http::HttpRequest;
+use crate::http::HttpResponse;
+use crate::renderer::diagnostics::ParseError;
+use crate::renderer::diagnostics::SourceLanguage;
+use crate::renderer::dom::api::append_child;
+use crate::renderer::dom::api::class_list_add;
+use crate::renderer::dom::api::class_list_contains;
+use crate::renderer::dom::api::class_list_remove;
+use crate::renderer::dom::api::class_list_toggle;
+use crate::renderer::dom::api::clone_node;
 use crate::renderer::dom::api::get_element_by_id;
+use crate::renderer::dom::api::get_elements_by_class_name;
+use crate::renderer::dom::api::get_elements_by_tag_name;
+use crate::renderer::dom::api::has_attribute;
+use crate::renderer::dom::api::insert_adjacent_html;
+use crate::renderer::dom::api::insert_before;
+use crate::renderer::dom::api::normalize;
+use crate::renderer::dom::api::query_selector;
+use crate::renderer::dom::api::query_selector_all;
+use crate::renderer::dom::api::remove_attribute;
+use crate::renderer::dom::api::remove_child;
+use crate::renderer::dom::api::replace_child;
+use crate::renderer::dom::api::set_attribute;
+use crate::renderer::dom::api::set_inner_html;
+use crate::renderer::dom::api::set_outer_html;
+use crate::renderer::dom::api::set_text_content;
+use crate::renderer::dom::api::text_content;
+use crate::renderer::dom::api::toggle_attribute;
+use crate::renderer::dom::document::Document;
+use crate::renderer::dom::event::add_event_listener;
+use crate::renderer::dom::event::dispatch_event;
+use crate::renderer::dom::event::remove_event_listener;
+use crate::renderer::dom::event::Event;
+use crate::renderer::dom::event::EventInterface;
+use crate::renderer::dom::event::EventListener;
+use crate::renderer::dom::node::Element;
+use crate::renderer::dom::node::Namespace;
 use crate::renderer::dom::node::Node as DomNode;
+use crate::renderer::dom::node::NodeId;
 use crate::renderer::dom::node::NodeKind as DomNodeKind;
+use crate::renderer::dom::traversal::FilterResult;
+use crate::renderer::dom::traversal::NodeIterator;
+use crate::renderer::dom::traversal::TreeWalker;
+use crate::renderer::dom::traversal::SHOW_ALL;
+use crate::renderer::dom::traversal::SHOW_COMMENT;
+use crate::renderer::dom::traversal::SHOW_DOCUMENT;
+use crate::renderer::dom::traversal::SHOW_DOCUMENT_FRAGMENT;
+use crate::renderer::dom::traversal::SHOW_DOCUMENT_TYPE;
+use crate::renderer::dom::traversal::SHOW_ELEMENT;
+use crate::renderer::dom::traversal::SHOW_TEXT;
+use crate::renderer::html::serializer::serialize_children;
+use crate::renderer::html::serializer::serialize_node;
 use crate::renderer::js::ast::Node;
+use crate::renderer::js::ast::NodeKind;
 use crate::renderer::js::ast::Program;
+use crate::renderer::js::json::parse_json;
+use crate::url::Url;
+use crate::websocket::Connector;
+use crate::websocket::ReadyState;
+use crate::websocket::WebSocket;
+use crate::websocket::WebSocketEvent;
+use alloc::collections::VecDeque;
 use alloc::format;
 use alloc::rc::Rc;
 use alloc::string::String;
 use alloc::string::ToString;
+use alloc::vec;
 use alloc::vec::Vec;
-use core::borrow::Borrow;
 use core::cell::RefCell;
+use core::fmt::Debug;
 use core::fmt::Display;
 use core::fmt::Formatter;
 use core::ops::Add;
@@ -23,9 +84,16 @@ pub enum RuntimeValue {
     Number(u64),
     StringLiteral(String),
     HtmlElement {
-        object: Rc<RefCell<DomNode>>,
+        object: NodeId,
         property: Option<String>,
     },
+    Function(Function),
+    HostObject {
+        kind: HostObjectKind,
+        id: usize,
+        property: Option<String>,
+    },
+    Object(Vec<(String, RuntimeValue)>),
 }
 impl Add<RuntimeValue> for RuntimeValue {
     type Output = RuntimeValue;
@@ -56,21 +124,68 @@ impl Display for RuntimeValue {
             } => {
                 format!("HtmlElement: {:#?}", object)
             }
+            RuntimeValue::Function(function) => format!("function {}", function.id),
+            RuntimeValue::HostObject { kind, .. } => format!("[object {:?}]", kind),
+            RuntimeValue::Object(_) => "[object Object]".to_string(),
         };
         write!(f, "{}", s)
     }
 }
 
+/// Enum representing the kinds of objects implemented by the browser.
+/// A host object refers to its state in the runtime by an index.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum HostObjectKind {
+    WebSocket,
+    Promise,
+    Response,
+    Headers,
+    XMLHttpRequest,
+    TreeWalker,
+    NodeIterator,
+    Event,
+}
+
 /// Struct representing a JavaScript function.
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Clone)]
 pub struct Function {
     id: String,
     params: Vec<Option<Rc<Node>>>,
     body: Option<Rc<Node>>,
+    /// The environment that a function expression was created in, which is the outer
+    /// environment of its calls. A function declaration has none, and its calls see the
+    /// environment of the caller.
+    env: Option<Rc<RefCell<Environment>>>,
 }
 impl Function {
     fn new(id: String, params: Vec<Option<Rc<Node>>>, body: Option<Rc<Node>>) -> Self {
-        Self { id, params, body }
+        Self {
+            id,
+            params,
+            body,
+            env: None,
+        }
+    }
+}
+impl PartialEq for Function {
+    fn eq(&self, other: &Self) -> bool {
+        let same_env = match (&self.env, &other.env) {
+            (Some(a), Some(b)) => Rc::ptr_eq(a, b),
+            (None, None) => true,
+            _ => false,
+        };
+        self.id == other.id && self.params == other.params && self.body == other.body && same_env
+    }
+}
+impl Eq for Function {}
+impl Debug for Function {
+    // The environment isn't printed, because it can contain the function itself.
+    fn fmt(&self, f: &mut Formatter) -> core::fmt::Result {
+        f.debug_struct("Function")
+            .field("id", &self.id)
+            .field("params", &self.params)
+            .field("body", &self.body)
+            .finish_non_exhaustive()
     }
 }
 
@@ -109,24 +224,364 @@ impl Environment {
                 return self.variables.push((name, value));
             }
         }
+        if let Some(env) = &self.outer {
+            env.borrow_mut().update_variable(name, value);
+        }
+    }
+}
+
+/// Struct representing a WebSocket object created by a script.
+#[derive(Debug, Clone)]
+struct JsWebSocket {
+    url: String,
+    socket: Option<Rc<RefCell<WebSocket>>>,
+    handlers: Vec<(String, Function)>,
+    pending_events: Vec<WebSocketEvent>,
+}
+
+static XHR_UNSENT: u64 = 0;
+static XHR_OPENED: u64 = 1;
+static XHR_DONE: u64 = 4;
+
+/// Enum representing the state of a promise.
+#[derive(Debug, Clone, PartialEq)]
+enum PromiseState {
+    Pending,
+    Fulfilled(Option<RuntimeValue>),
+    Rejected(Option<RuntimeValue>),
+}
+
+/// Type of a function that returns the current time in milliseconds.
+pub type Clock = fn() -> u64;
+
+/// Struct representing a timer set by `setTimeout`.
+/// # References:
+/// - <https://html.spec.whatwg.org/multipage/timers-and-user-prompts.html#timers>
+#[derive(Debug, Clone)]
+struct Timer {
+    id: u64,
+    /// The time in milliseconds after which the callback runs.
+    due: u64,
+    callback: Function,
+}
+
+/// Struct representing the callbacks registered by `then` and the promise they settle.
+#[derive(Debug, Clone)]
+struct PromiseReaction {
+    on_fulfilled: Option<Function>,
+    on_rejected: Option<Function>,
+    result: usize,
+}
+
+/// Struct representing a promise created by a script or a browser API.
+/// # References:
+/// - <https://tc39.es/ecma262/#sec-promise-objects>
+#[derive(Debug, Clone)]
+struct JsPromise {
+    state: PromiseState,
+    reactions: Vec<PromiseReaction>,
+}
+
+/// Struct representing an XMLHttpRequest object created by a script.
+#[derive(Debug, Clone, Default)]
+struct JsXmlHttpRequest {
+    method: String,
+    url: Option<String>,
+    request_headers: Vec<Header>,
+    body: String,
+    send_flag: bool,
+    ready_state: u64,
+    response: Option<HttpResponse>,
+    handlers: Vec<(String, Function)>,
+}
+
+/// Struct representing a TreeWalker created by a script, with its filter function.
+#[derive(Debug, Clone)]
+struct JsTreeWalker {
+    walker: TreeWalker,
+    filter: Option<Function>,
+}
+
+/// Struct representing a NodeIterator created by a script, with its filter function.
+#[derive(Debug, Clone)]
+struct JsNodeIterator {
+    iterator: NodeIterator,
+    filter: Option<Function>,
+}
+
+/// The properties of `NodeFilter`, which are the results of a filter and the bits of
+/// `whatToShow`.
+/// # References:
+/// - <https://dom.spec.whatwg.org/#interface-nodefilter>
+static NODE_FILTER_CONSTANTS: [(&str, u32); 10] = [
+    ("FILTER_ACCEPT", 1),
+    ("FILTER_REJECT", 2),
+    ("FILTER_SKIP", 3),
+    ("SHOW_ALL", SHOW_ALL),
+    ("SHOW_ELEMENT", SHOW_ELEMENT),
+    ("SHOW_TEXT", SHOW_TEXT),
+    ("SHOW_COMMENT", SHOW_COMMENT),
+    ("SHOW_DOCUMENT", SHOW_DOCUMENT),
+    ("SHOW_DOCUMENT_TYPE", SHOW_DOCUMENT_TYPE),
+    ("SHOW_DOCUMENT_FRAGMENT", SHOW_DOCUMENT_FRAGMENT),
+];
+
+/// Enum representing a task on the page's task queue.
+#[derive(Debug, Clone)]
+enum Task {
+    Fetch {
+        promise: usize,
+        request: HttpRequest,
+    },
+    SendXMLHttpRequest {
+        id: usize,
+    },
+    PromiseReaction {
+        reaction: PromiseReaction,
+        settled: PromiseState,
+    },
+}
+
+/// The methods of `document` that search the DOM tree, which elements also have.
+static QUERY_METHODS: [&str; 4] = [
+    "querySelector",
+    "querySelectorAll",
+    "getElementsByTagName",
+    "getElementsByClassName",
+];
+
+/// The methods of `EventTarget`, which `document` and elements have.
+static EVENT_TARGET_METHODS: [&str; 3] =
+    ["addEventListener", "removeEventListener", "dispatchEvent"];
+
+/// Returns the value of a boolean. There are no boolean values, so `true` and `false` are
+/// represented by the strings that the identifiers evaluate to.
+fn boolean(value: bool) -> RuntimeValue {
+    RuntimeValue::StringLiteral(if value { "true" } else { "false" }.to_string())
+}
+
+/// Converts an argument to a boolean, which is true for `true` and for numbers other than 0.
+fn to_boolean(value: Option<&RuntimeValue>) -> bool {
+    match value {
+        Some(RuntimeValue::Number(n)) => *n != 0,
+        Some(RuntimeValue::StringLiteral(s)) => s == "true",
+        _ => false,
+    }
+}
+
+/// Returns an array-like object of DOM nodes with `length`, like a JSON array. It is a snapshot,
+/// so it doesn't reflect later changes of the tree like a live `HTMLCollection`.
+fn node_list(nodes: Vec<NodeId>) -> RuntimeValue {
+    let length = nodes.len() as u64;
+    let mut items: Vec<(String, RuntimeValue)> = nodes
+        .into_iter()
+        .enumerate()
+        .map(|(i, node)| {
+            (
+                i.to_string(),
+                RuntimeValue::HtmlElement {
+                    object: node,
+                    property: None,
+                },
+            )
+        })
+        .collect();
+    items.push(("length".to_string(), RuntimeValue::Number(length)));
+    RuntimeValue::Object(items)
+}
+
+/// Struct representing a search for the nodes that values refer to. The environments of
+/// functions are searched too, each of them once, because a function can be stored in the
+/// environment that it was created in.
+#[derive(Default)]
+struct NodeCollector {
+    nodes: Vec<NodeId>,
+    visited: Vec<Rc<RefCell<Environment>>>,
+}
+impl NodeCollector {
+    /// Adds the nodes that `value` refers to, including the ones in its properties.
+    fn value(&mut self, value: Option<&RuntimeValue>) {
+        match value {
+            Some(RuntimeValue::HtmlElement { object, .. }) => self.nodes.push(*object),
+            Some(RuntimeValue::Function(function)) => self.function(function),
+            Some(RuntimeValue::Object(properties)) => {
+                for (_, value) in properties {
+                    self.value(Some(value));
+                }
+            }
+            _ => {}
+        }
+    }
+    fn function(&mut self, function: &Function) {
+        if let Some(env) = &function.env {
+            self.env(env);
+        }
+    }
+    fn reaction(&mut self, reaction: &PromiseReaction) {
+        for function in reaction.on_fulfilled.iter().chain(&reaction.on_rejected) {
+            self.function(function);
+        }
+    }
+    /// Adds the nodes that the variables of `env` and of its outer environments refer to.
+    fn env(&mut self, env: &Rc<RefCell<Environment>>) {
+        if self.visited.iter().any(|visited| Rc::ptr_eq(visited, env)) {
+            return;
+        }
+        self.visited.push(env.clone());
+        let env = env.borrow();
+        for (_, value) in env.variables.iter() {
+            self.value(value.as_ref());
+        }
+        if let Some(outer) = &env.outer {
+            self.env(outer);
+        }
     }
 }
 
 /// Struct representing a JavaScript runtime.
 #[derive(Debug, Clone)]
 pub struct JsRuntime {
-    dom_root: Rc<RefCell<DomNode>>,
+    document: Rc<RefCell<Document>>,
     env: Rc<RefCell<Environment>>,
     functions: Vec<Function>,
+    tasks: VecDeque<Task>,
+    timers: Vec<Timer>,
+    /// The ID of the last timer. IDs start at 1, so that 0 never refers to a timer.
+    last_timer_id: u64,
+    clock: Option<Clock>,
+    websockets: Vec<JsWebSocket>,
+    websocket_connector: Option<Connector>,
+    promises: Vec<JsPromise>,
+    responses: Vec<HttpResponse>,
+    xhrs: Vec<JsXmlHttpRequest>,
+    tree_walkers: Vec<JsTreeWalker>,
+    node_iterators: Vec<JsNodeIterator>,
+    /// The functions of event listeners. An `EventListener` refers to its callback by an index.
+    event_listeners: Vec<Function>,
+    /// The events that scripts can access. The slot of an event that the browser created is
+    /// freed when its dispatch finishes.
+    events: Vec<Option<Event>>,
+    fetcher: Option<Fetcher>,
+    /// The errors thrown while running the scripts, e.g. calling a function that doesn't exist.
+    errors: Vec<ParseError>,
 }
 impl JsRuntime {
-    pub fn new(dom_root: Rc<RefCell<DomNode>>) -> Self {
+    pub fn new(document: Rc<RefCell<Document>>) -> Self {
         Self {
-            dom_root,
+            document,
             functions: Vec::new(),
             env: Rc::new(RefCell::new(Environment::new(None))),
+            tasks: VecDeque::new(),
+            timers: Vec::new(),
+            last_timer_id: 0,
+            clock: None,
+            websockets: Vec::new(),
+            websocket_connector: None,
+            promises: Vec::new(),
+            responses: Vec::new(),
+            xhrs: Vec::new(),
+            tree_walkers: Vec::new(),
+            node_iterators: Vec::new(),
+            event_listeners: Vec::new(),
+            events: Vec::new(),
+            fetcher: None,
+            errors: Vec::new(),
+        }
+    }
+    /// Returns the `Document` node of the document that the scripts run in.
+    fn document_node(&self) -> NodeId {
+        self.document.borrow().node()
+    }
+    pub fn set_fetcher(&mut self, fetcher: Fetcher) {
+        self.fetcher = Some(fetcher);
+    }
+    pub fn set_websocket_connector(&mut self, connector: Connector) {
+        self.websocket_connector = Some(connector);
+    }
+    /// Sets the clock that timers use. Without a clock, the delay of a timer is ignored and its
+    /// callback runs at the next turn of the event loop.
+    pub fn set_clock(&mut self, clock: Clock) {
+        self.clock = Some(clock);
+    }
+    /// Returns the current time in milliseconds, which is always 0 without a clock.
+    fn now(&self) -> u64 {
+        match self.clock {
+            Some(clock) => clock(),
+            None => 0,
         }
     }
+    /// Returns the errors thrown while running the scripts. Spans are relative to the script
+    /// that threw the error.
+    pub fn errors(&self) -> Vec<ParseError> {
+        self.errors.clone()
+    }
+    /// Returns the nodes that the scripts can still refer to, which must not be freed. They are
+    /// searched for in the global environment, in the environments of the callbacks that can
+    /// still be called, such as timers and event listeners, and in the values of pending
+    /// promises and tasks. The environments of running calls are not searched, so this must be
+    /// called when no script is running.
+    pub fn referenced_nodes(&self) -> Vec<NodeId> {
+        let mut collector = NodeCollector::default();
+        collector.env(&self.env);
+        for timer in &self.timers {
+            collector.function(&timer.callback);
+        }
+        for listener in &self.event_listeners {
+            collector.function(listener);
+        }
+        let handlers = self.xhrs.iter().flat_map(|xhr| xhr.handlers.iter());
+        let handlers = handlers.chain(self.websockets.iter().flat_map(|ws| ws.handlers.iter()));
+        for (_, handler) in handlers {
+            collector.function(handler);
+        }
+        for promise in &self.promises {
+            if let PromiseState::Fulfilled(value) | PromiseState::Rejected(value) = &promise.state {
+                collector.value(value.as_ref());
+            }
+            for reaction in &promise.reactions {
+                collector.reaction(reaction);
+            }
+        }
+        for task in &self.tasks {
+            if let Task::PromiseReaction { reaction, settled } = task {
+                collector.reaction(reaction);
+                if let PromiseState::Fulfilled(value) | PromiseState::Rejected(value) = settled {
+                    collector.value(value.as_ref());
+                }
+            }
+        }
+        let filters = self.tree_walkers.iter().map(|walker| &walker.filter);
+        let filters = filters.chain(self.node_iterators.iter().map(|iterator| &iterator.filter));
+        for filter in filters.flatten() {
+            collector.function(filter);
+        }
+        let mut nodes = collector.nodes;
+        for walker in &self.tree_walkers {
+            nodes.push(walker.walker.root());
+            nodes.push(walker.walker.current_node());
+        }
+        for iterator in &self.node_iterators {
+            nodes.push(iterator.iterator.root());
+            nodes.push(iterator.iterator.reference_node());
+        }
+        for event in self.events.iter().flatten() {
+            nodes.extend(event.target());
+            nodes.extend(event.current_target());
+        }
+        nodes
+    }
+    /// Records a `TypeError`. The statement that threw it evaluates to `None`, like an uncaught
+    /// exception that stops the statement.
+    /// # References:
+    /// - <https://tc39.es/ecma262/#sec-native-error-types-used-in-this-standard-typeerror>
+    fn type_error(&mut self, node: &Node) -> Option<RuntimeValue> {
+        self.errors.push(ParseError::new(
+            SourceLanguage::JavaScript,
+            "type-error",
+            node.span(),
+        ));
+        None
+    }
     fn eval(
         &mut self,
         node: &Option<Rc<Node>>,
@@ -136,9 +591,9 @@ impl JsRuntime {
             Some(n) => n,
             None => return None,
         };
-        match node.borrow() {
-            Node::ExpressionStatement(expr) => return self.eval(&expr, env.clone()),
-            Node::AdditiveExpression {
+        match node.kind() {
+            NodeKind::ExpressionStatement(expr) => self.eval(expr, env.clone()),
+            NodeKind::AdditiveExpression {
                 operator,
                 left,
                 right,
@@ -159,7 +614,7 @@ impl JsRuntime {
                     None
                 }
             }
-            Node::AssignmentExpression {
+            NodeKind::AssignmentExpression {
                 operator,
                 left,
                 right,
@@ -168,83 +623,114 @@ impl JsRuntime {
                     return None;
                 }
                 if let Some(node) = left {
-                    if let Node::Identifier(id) = node.borrow() {
+                    if let NodeKind::Identifier(id) = node.kind() {
                         let new_value = self.eval(right, env.clone());
                         env.borrow_mut().update_variable(id.to_string(), new_value);
                         return None;
                     }
                 }
-                if let Some(RuntimeValue::HtmlElement { object, property }) =
-                    self.eval(left, env.clone())
+                let left_value = match left.as_deref().map(Node::kind) {
+                    Some(NodeKind::MemberExpression {
+                        object,
+                        property,
+                        computed,
+                    }) => {
+                        self.eval_member_expression(object, property, *computed, env.clone(), true)
+                    }
+                    _ => self.eval(left, env.clone()),
+                };
+                if let Some(RuntimeValue::HostObject {
+                    kind,
+                    id,
+                    property: Some(p),
+                }) = left_value
                 {
+                    let right_value = self.eval(right, env.clone());
+                    if kind == HostObjectKind::TreeWalker {
+                        self.set_tree_walker_property(id, &p, right_value);
+                        return None;
+                    }
+                    let handler = self.to_function(right_value);
+                    self.set_event_handler(kind, id, p, handler);
+                    return None;
+                }
+                if let Some(RuntimeValue::HtmlElement { object, property }) = left_value {
                     let right_value = match self.eval(right, env.clone()) {
                         Some(value) => value,
                         None => return None,
                     };
                     if let Some(p) = property {
-                        if p == "textContent" {
-                            object
-                                .borrow_mut()
-                                .set_first_child(Some(Rc::new(RefCell::new(DomNode::new(
-                                    DomNodeKind::Text(right_value.to_string()),
-                                )))));
+                        match p.as_str() {
+                            "textContent" => set_text_content(
+                                self.document.borrow_mut().dom_mut(),
+                                object,
+                                &right_value.to_string(),
+                            ),
+                            "innerHTML" => set_inner_html(
+                                &mut self.document.borrow_mut(),
+                                object,
+                                &right_value.to_string(),
+                            ),
+                            "outerHTML" => {
+                                // Errors are ignored because exceptions are not supported.
+                                let _ = set_outer_html(
+                                    &mut self.document.borrow_mut(),
+                                    object,
+                                    &right_value.to_string(),
+                                );
+                            }
+                            "id" | "className" => {
+                                let name = if p == "id" { "id" } else { "class" };
+                                let _ = set_attribute(
+                                    self.document.borrow_mut().dom_mut(),
+                                    object,
+                                    name,
+                                    &right_value.to_string(),
+                                );
+                            }
+                            _ => {}
                         }
                     }
                 }
                 None
             }
-            Node::MemberExpression { object, property } => {
-                let object_value = match self.eval(object, env.clone()) {
-                    Some(value) => value,
-                    None => return None,
-                };
-                let property_value = match self.eval(property, env.clone()) {
-                    Some(value) => value,
-                    None => return None,
-                };
-                if let RuntimeValue::HtmlElement { object, property } = object_value {
-                    assert!(property.is_none());
-                    return Some(RuntimeValue::HtmlElement {
-                        object,
-                        property: Some(property_value.to_string()),
-                    });
-                }
-                return Some(
-                    object_value + RuntimeValue::StringLiteral(".".to_string()) + property_value,
-                );
-            }
-            Node::NumericLiteral(value) => Some(RuntimeValue::Number(*value)),
-            Node::VariableDeclaration { declarations } => {
+            NodeKind::MemberExpression {
+                object,
+                property,
+                computed,
+            } => self.eval_member_expression(object, property, *computed, env, false),
+            NodeKind::NumericLiteral(value) => Some(RuntimeValue::Number(*value)),
+            NodeKind::VariableDeclaration { declarations } => {
                 for declaration in declarations {
                     self.eval(&declaration, env.clone());
                 }
                 None
             }
-            Node::VariableDeclarator { id, init } => {
+            NodeKind::VariableDeclarator { id, init } => {
                 if let Some(node) = id {
-                    if let Node::Identifier(id) = node.borrow() {
+                    if let NodeKind::Identifier(id) = node.kind() {
                         let init = self.eval(&init, env.clone());
                         env.borrow_mut().add_variable(id.to_string(), init);
                     }
                 }
                 None
             }
-            Node::Identifier(name) => match env.borrow_mut().get_variable(name.to_string()) {
+            NodeKind::Identifier(name) => match env.borrow_mut().get_variable(name.to_string()) {
                 Some(v) => Some(v),
                 None => Some(RuntimeValue::StringLiteral(name.to_string())),
             },
-            Node::StringLiteral(value) => Some(RuntimeValue::StringLiteral(value.to_string())),
-            Node::BlockStatement { body } => {
+            NodeKind::StringLiteral(value) => Some(RuntimeValue::StringLiteral(value.to_string())),
+            NodeKind::BlockStatement { body } => {
                 let mut result: Option<RuntimeValue> = None;
                 for stmt in body {
                     result = self.eval(&stmt, env.clone());
                 }
                 result
             }
-            Node::ReturnStatement { argument } => {
+            NodeKind::ReturnStatement { argument } => {
                 return self.eval(&argument, env.clone());
             }
-            Node::FunctionDeclaration { id, params, body } => {
+            NodeKind::FunctionDeclaration { id, params, body } => {
                 if let Some(RuntimeValue::StringLiteral(id)) = self.eval(&id, env.clone()) {
                     let cloned_body = match body {
                         Some(b) => Some(b.clone()),
@@ -255,7 +741,7 @@ impl JsRuntime {
                 };
                 None
             }
-            Node::CallExpression { callee, arguments } => {
+            NodeKind::CallExpression { callee, arguments } => {
                 let new_env = Rc::new(RefCell::new(Environment::new(Some(env))));
                 let callee_value = match self.eval(callee, new_env.clone()) {
                     Some(value) => value,
@@ -265,31 +751,286 @@ impl JsRuntime {
                 if api_result.0 {
                     return api_result.1;
                 }
-                let function = {
-                    let mut f: Option<Function> = None;
-                    for func in &self.functions {
-                        if callee_value == RuntimeValue::StringLiteral(func.id.to_string()) {
-                            f = Some(func.clone());
-                        }
-                    }
-                    match f {
+                let function = match callee_value {
+                    RuntimeValue::Function(f) => f,
+                    _ => match self.find_function(&callee_value.to_string()) {
                         Some(f) => f,
-                        None => panic!("Function {:?} does not exist.", callee),
-                    }
+                        None => return self.type_error(node),
+                    },
+                };
+                let mut values = Vec::new();
+                for item in arguments {
+                    values.push(self.eval(item, new_env.clone()));
+                }
+                self.call_function(&function, values, new_env)
+            }
+            NodeKind::NewExpression { callee, arguments } => {
+                let callee_value = self.eval(callee, env.clone())?;
+                if callee_value == RuntimeValue::StringLiteral("WebSocket".to_string()) {
+                    let url = self.eval(arguments.first()?, env.clone())?.to_string();
+                    return Some(self.open_websocket(url));
+                }
+                if callee_value == RuntimeValue::StringLiteral("XMLHttpRequest".to_string()) {
+                    self.xhrs.push(JsXmlHttpRequest::default());
+                    return Some(RuntimeValue::HostObject {
+                        kind: HostObjectKind::XMLHttpRequest,
+                        id: self.xhrs.len() - 1,
+                        property: None,
+                    });
+                }
+                // The event init dictionary isn't supported, because there are no object
+                // literals, so the event neither bubbles nor is cancelable.
+                if callee_value == RuntimeValue::StringLiteral("Event".to_string()) {
+                    let event_type = self.eval(arguments.first()?, env.clone())?.to_string();
+                    self.events
+                        .push(Some(Event::new(&event_type, false, false)));
+                    return Some(RuntimeValue::HostObject {
+                        kind: HostObjectKind::Event,
+                        id: self.events.len() - 1,
+                        property: None,
+                    });
+                }
+                None
+            }
+            NodeKind::FunctionExpression { id, params, body } => {
+                let name = match id.as_deref().map(Node::kind) {
+                    Some(NodeKind::Identifier(name)) => name.to_string(),
+                    _ => String::new(),
                 };
-                assert!(arguments.len() == function.params.len());
-                for (i, item) in arguments.iter().enumerate() {
-                    if let Some(RuntimeValue::StringLiteral(name)) =
-                        self.eval(&function.params[i], new_env.clone())
-                    {
-                        new_env
-                            .borrow_mut()
-                            .add_variable(name, self.eval(item, new_env.clone()));
+                Some(RuntimeValue::Function(Function {
+                    env: Some(env),
+                    ..Function::new(name, params.to_vec(), body.clone())
+                }))
+            }
+        }
+    }
+    fn find_function(&self, name: &str) -> Option<Function> {
+        let mut f: Option<Function> = None;
+        for func in &self.functions {
+            if func.id == name {
+                f = Some(func.clone());
+            }
+        }
+        f
+    }
+    /// Binds the arguments to the parameters in `env` and evaluates the function body. A function
+    /// expression is called in a new environment inside the one it was created in instead.
+    fn call_function(
+        &mut self,
+        function: &Function,
+        arguments: Vec<Option<RuntimeValue>>,
+        env: Rc<RefCell<Environment>>,
+    ) -> Option<RuntimeValue> {
+        let env = match &function.env {
+            Some(outer) => Rc::new(RefCell::new(Environment::new(Some(outer.clone())))),
+            None => env,
+        };
+        for (i, param) in function.params.iter().enumerate() {
+            if let Some(NodeKind::Identifier(name)) = param.as_deref().map(Node::kind) {
+                let value = arguments.get(i).cloned().flatten();
+                env.borrow_mut().add_variable(name.to_string(), value);
+            }
+        }
+        self.eval(&function.body, env)
+    }
+    /// Evaluates a member expression. The properties of an element or a host object that have
+    /// getters, such as `innerHTML`, are evaluated to their values unless the expression is
+    /// assigned to.
+    fn eval_member_expression(
+        &mut self,
+        object: &Option<Rc<Node>>,
+        property: &Option<Rc<Node>>,
+        computed: bool,
+        env: Rc<RefCell<Environment>>,
+        is_target: bool,
+    ) -> Option<RuntimeValue> {
+        let object_value = self.eval(object, env.clone())?;
+        // The property name after a dot is not looked up as a variable.
+        let property_value = match property.as_deref().map(Node::kind) {
+            Some(NodeKind::Identifier(name)) if !computed => {
+                RuntimeValue::StringLiteral(name.to_string())
+            }
+            _ => self.eval(property, env.clone())?,
+        };
+        match object_value {
+            RuntimeValue::HostObject {
+                kind,
+                id,
+                property: None,
+            } => {
+                if is_target {
+                    return Some(RuntimeValue::HostObject {
+                        kind,
+                        id,
+                        property: Some(property_value.to_string()),
+                    });
+                }
+                return self.host_object_property(kind, id, property_value.to_string());
+            }
+            RuntimeValue::Object(ref properties) => {
+                return properties
+                    .iter()
+                    .find(|(name, _)| *name == property_value.to_string())
+                    .map(|(_, value)| value.clone());
+            }
+            _ => {}
+        }
+        // The methods of `classList` are called on the element.
+        if let RuntimeValue::HtmlElement {
+            object,
+            property: Some(ref p),
+        } = object_value
+        {
+            if p == "classList" {
+                return Some(RuntimeValue::HtmlElement {
+                    object,
+                    property: Some(format!("classList.{}", property_value)),
+                });
+            }
+        }
+        if let RuntimeValue::HtmlElement { object, property } = object_value {
+            // A property of a property that isn't an object, such as `a.foo.bar`, is undefined.
+            if property.is_some() {
+                return None;
+            }
+            if !is_target {
+                let name = property_value.to_string();
+                if let Some(value) = self.node_tree_property(object, &name) {
+                    return value;
+                }
+                match name.as_str() {
+                    "textContent" => {
+                        return text_content(self.document.borrow().dom(), object)
+                            .map(RuntimeValue::StringLiteral);
                     }
+                    "innerHTML" => {
+                        let html = serialize_children(self.document.borrow().dom(), object);
+                        return Some(RuntimeValue::StringLiteral(html));
+                    }
+                    "outerHTML" => {
+                        let html = serialize_node(self.document.borrow().dom(), object);
+                        return Some(RuntimeValue::StringLiteral(html));
+                    }
+                    "id" | "className" => {
+                        let name = if property_value.to_string() == "id" {
+                            "id"
+                        } else {
+                            "class"
+                        };
+                        let value = self.document.borrow().dom()[object]
+                            .get_element()
+                            .and_then(|e| e.get_attribute(name))
+                            .unwrap_or_default();
+                        return Some(RuntimeValue::StringLiteral(value));
+                    }
+                    _ => {}
                 }
-                self.eval(&function.body.clone(), new_env.clone())
+            }
+            return Some(RuntimeValue::HtmlElement {
+                object,
+                property: Some(property_value.to_string()),
+            });
+        }
+        if object_value == RuntimeValue::StringLiteral("NodeFilter".to_string()) {
+            let name = property_value.to_string();
+            if let Some((_, value)) = NODE_FILTER_CONSTANTS.iter().find(|(n, _)| *n == name) {
+                return Some(RuntimeValue::Number(*value as u64));
+            }
+        }
+        if !is_target {
+            let name = property_value.to_string();
+            let value = match object_value.to_string().as_str() {
+                "document" => self.document_property(&name),
+                "location" => self.location_property(&name),
+                // The global scope is the window, so its properties are the global variables.
+                "window" => Some(
+                    self.env
+                        .borrow()
+                        .get_variable(name.clone())
+                        .unwrap_or(RuntimeValue::StringLiteral(name)),
+                ),
+                _ => None,
+            };
+            if value.is_some() {
+                return value;
             }
         }
+        Some(object_value + RuntimeValue::StringLiteral(".".to_string()) + property_value)
+    }
+    /// Returns a property of a node that follows the links of the tree, such as `parentNode`.
+    /// # Returns
+    /// - `None` if `name` is not such a property, or `Some(None)` if there is no such node.
+    /// # References:
+    /// - <https://dom.spec.whatwg.org/#interface-node>
+    fn node_tree_property(&self, node: NodeId, name: &str) -> Option<Option<RuntimeValue>> {
+        let document = self.document.borrow();
+        let dom = document.dom();
+        let related = match name {
+            "parentNode" => dom.parent(node),
+            "firstChild" => dom.first_child(node),
+            "lastChild" => dom.last_child(node),
+            "previousSibling" => dom.previous_sibling(node),
+            "nextSibling" => dom.next_sibling(node),
+            "childNodes" => return Some(Some(node_list(dom.children(node).collect()))),
+            _ => return None,
+        };
+        Some(related.map(|object| RuntimeValue::HtmlElement {
+            object,
+            property: None,
+        }))
+    }
+    /// Returns the value of a property of the document that is not a method.
+    /// # References:
+    /// - <https://dom.spec.whatwg.org/#interface-document>
+    fn document_property(&self, name: &str) -> Option<RuntimeValue> {
+        let document = self.document.borrow();
+        let value = match name {
+            "URL" | "documentURI" => document.url_string(),
+            "referrer" => document.referrer(),
+            "contentType" => document.content_type(),
+            "readyState" => document.ready_state().as_str().to_string(),
+            "compatMode" => document.compat_mode().to_string(),
+            "characterSet" | "charset" | "inputEncoding" => {
+                document.character_set().name().to_string()
+            }
+            "location" => "location".to_string(),
+            _ => return None,
+        };
+        Some(RuntimeValue::StringLiteral(value))
+    }
+    /// Returns the value of a property of the location of the document. The location is read
+    /// only, so navigating by assigning to it is not supported.
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/nav-history-apis.html#the-location-interface>
+    fn location_property(&self, name: &str) -> Option<RuntimeValue> {
+        let url = match self.document.borrow().url() {
+            Some(url) => url,
+            None => {
+                return match name {
+                    "href" => Some(RuntimeValue::StringLiteral("about:blank".to_string())),
+                    _ => None,
+                }
+            }
+        };
+        let port = match url.port().as_str() {
+            "80" => String::new(),
+            port => port.to_string(),
+        };
+        let value = match name {
+            "href" => url.serialize(),
+            "protocol" => "http:".to_string(),
+            "host" if port.is_empty() => url.host(),
+            "host" => format!("{}:{}", url.host(), port),
+            "hostname" => url.host(),
+            "port" => port,
+            "pathname" => format!("/{}", url.path()),
+            "search" if url.search_part().is_empty() => String::new(),
+            "search" => format!("?{}", url.search_part()),
+            "hash" => String::new(),
+            "origin" => url.origin(),
+            _ => return None,
+        };
+        Some(RuntimeValue::StringLiteral(value))
     }
     pub fn execute(&mut self, program: &Program) {
         for node in program.body() {
@@ -307,7 +1048,12 @@ impl JsRuntime {
                 Some(a) => a,
                 None => return (true, None),
             };
-            let target = match get_element_by_id(Some(self.dom_root.clone()), &arg.to_string()) {
+            let target = get_element_by_id(
+                self.document.borrow().dom(),
+                self.document_node(),
+                &arg.to_string(),
+            );
+            let target = match target {
                 Some(n) => n,
                 None => return (true, None),
             };
@@ -319,22 +1065,1283 @@ impl JsRuntime {
                 }),
             );
         }
+        if func == &RuntimeValue::StringLiteral("document.createElement".to_string())
+            || func == &RuntimeValue::StringLiteral("document.createTextNode".to_string())
+        {
+            let arg = match arguments.first() {
+                Some(arg) => self.eval(arg, env.clone()),
+                None => None,
+            };
+            let arg = arg.map(|a| a.to_string()).unwrap_or_default();
+            let kind = if func.to_string() == "document.createElement" {
+                DomNodeKind::Element(Element::new(&arg.to_ascii_lowercase(), Vec::new()))
+            } else {
+                DomNodeKind::Text(arg)
+            };
+            return (
+                true,
+                Some(RuntimeValue::HtmlElement {
+                    object: self
+                        .document
+                        .borrow_mut()
+                        .dom_mut()
+                        .alloc(DomNode::new(kind)),
+                    property: None,
+                }),
+            );
+        }
+        if func == &RuntimeValue::StringLiteral("document.createTreeWalker".to_string())
+            || func == &RuntimeValue::StringLiteral("document.createNodeIterator".to_string())
+        {
+            let mut values = Vec::new();
+            for arg in arguments {
+                values.push(self.eval(arg, env.clone()));
+            }
+            let root = match values.first() {
+                Some(Some(RuntimeValue::HtmlElement {
+                    object,
+                    property: None,
+                })) => *object,
+                _ => return (true, None),
+            };
+            let what_to_show = match values.get(1) {
+                Some(Some(RuntimeValue::Number(n))) => *n as u32,
+                _ => SHOW_ALL,
+            };
+            let filter = self.to_function(values.get(2).cloned().flatten());
+            if func.to_string() == "document.createTreeWalker" {
+                self.tree_walkers.push(JsTreeWalker {
+                    walker: TreeWalker::new(root, what_to_show),
+                    filter,
+                });
+                return (
+                    true,
+                    Some(RuntimeValue::HostObject {
+                        kind: HostObjectKind::TreeWalker,
+                        id: self.tree_walkers.len() - 1,
+                        property: None,
+                    }),
+                );
+            }
+            self.node_iterators.push(JsNodeIterator {
+                iterator: NodeIterator::new(root, what_to_show),
+                filter,
+            });
+            return (
+                true,
+                Some(RuntimeValue::HostObject {
+                    kind: HostObjectKind::NodeIterator,
+                    id: self.node_iterators.len() - 1,
+                    property: None,
+                }),
+            );
+        }
+        if let RuntimeValue::StringLiteral(name) = func {
+            // The queries of `document` search the whole tree, and its listeners are on the
+            // `Document` node.
+            if let Some(method) = name.strip_prefix("document.") {
+                if QUERY_METHODS.contains(&method) || EVENT_TARGET_METHODS.contains(&method) {
+                    let mut values = Vec::new();
+                    for arg in arguments {
+                        values.push(self.eval(arg, env.clone()));
+                    }
+                    return self.call_node_method(self.document_node(), method, values);
+                }
+            }
+        }
+        if func == &RuntimeValue::StringLiteral("setTimeout".to_string())
+            || func == &RuntimeValue::StringLiteral("clearTimeout".to_string())
+        {
+            let mut values = Vec::new();
+            for arg in arguments {
+                values.push(self.eval(arg, env.clone()));
+            }
+            if func.to_string() == "setTimeout" {
+                return (true, self.set_timeout(values));
+            }
+            if let Some(Some(RuntimeValue::Number(id))) = values.first() {
+                self.timers.retain(|timer| timer.id != *id);
+            }
+            return (true, None);
+        }
+        if func == &RuntimeValue::StringLiteral("fetch".to_string()) {
+            let arg = match arguments.first() {
+                Some(arg) => self.eval(arg, env.clone()),
+                None => None,
+            };
+            let url = arg.map(|a| a.to_string()).unwrap_or_default();
+            return (true, Some(self.start_fetch(&url)));
+        }
+        if let RuntimeValue::HtmlElement {
+            object,
+            property: Some(method),
+        } = func
+        {
+            let mut values = Vec::new();
+            for arg in arguments {
+                values.push(self.eval(arg, env.clone()));
+            }
+            return self.call_node_method(*object, method, values);
+        }
+        if let RuntimeValue::HostObject {
+            kind,
+            id,
+            property: Some(method),
+        } = func
+        {
+            let mut values = Vec::new();
+            for arg in arguments {
+                values.push(self.eval(arg, env.clone()));
+            }
+            return match kind {
+                HostObjectKind::WebSocket => self.call_websocket_method(*id, method, values),
+                HostObjectKind::Promise => self.call_promise_method(*id, method, values),
+                HostObjectKind::Response => self.call_response_method(*id, method),
+                HostObjectKind::Headers => self.call_headers_method(*id, method, values),
+                HostObjectKind::XMLHttpRequest => self.call_xhr_method(*id, method, values),
+                HostObjectKind::TreeWalker => self.call_tree_walker_method(*id, method),
+                HostObjectKind::NodeIterator => self.call_node_iterator_method(*id, method),
+                HostObjectKind::Event => self.call_event_method(*id, method),
+            };
+        }
         (false, None)
     }
+    /// Calls a method of a DOM node. Errors such as hierarchy errors are ignored, because
+    /// exceptions are not supported.
+    fn call_node_method(
+        &mut self,
+        object: NodeId,
+        method: &str,
+        values: Vec<Option<RuntimeValue>>,
+    ) -> (bool, Option<RuntimeValue>) {
+        let node_argument = |i: usize| match values.get(i) {
+            Some(Some(RuntimeValue::HtmlElement {
+                object,
+                property: None,
+            })) => Some(*object),
+            _ => None,
+        };
+        let document = self.document.clone();
+        let string_argument = |i: usize| match values.get(i) {
+            Some(Some(value)) => value.to_string(),
+            _ => String::new(),
+        };
+        if EVENT_TARGET_METHODS.contains(&method) {
+            return self.call_event_target_method(object, method, values);
+        }
+        // `toggleAttribute` and `classList.toggle` only force the result if `force` is given.
+        let force = values.get(1).map(|value| to_boolean(value.as_ref()));
+        let string_result = match method {
+            "getAttribute" => {
+                let document = document.borrow();
+                let dom = document.dom();
+                let name = string_argument(0);
+                let value = match dom[object].get_element() {
+                    Some(e) if e.namespace() == Namespace::Html => {
+                        e.get_attribute(&name.to_ascii_lowercase())
+                    }
+                    Some(e) => e.get_attribute(&name),
+                    None => None,
+                };
+                Some(value.map(RuntimeValue::StringLiteral))
+            }
+            "setAttribute" => {
+                let dom = &mut document.borrow_mut();
+                let _ = set_attribute(
+                    dom.dom_mut(),
+                    object,
+                    &string_argument(0),
+                    &string_argument(1),
+                );
+                Some(None)
+            }
+            "removeAttribute" => {
+                remove_attribute(document.borrow_mut().dom_mut(), object, &string_argument(0));
+                Some(None)
+            }
+            "toggleAttribute" => {
+                let dom = &mut document.borrow_mut();
+                let result = toggle_attribute(dom.dom_mut(), object, &string_argument(0), force);
+                Some(result.ok().map(boolean))
+            }
+            "hasAttribute" => {
+                let result = has_attribute(document.borrow().dom(), object, &string_argument(0));
+                Some(Some(boolean(result)))
+            }
+            "classList.add" | "classList.remove" => {
+                let tokens: Vec<String> = (0..values.len()).map(string_argument).collect();
+                let tokens: Vec<&str> = tokens.iter().map(String::as_str).collect();
+                let dom = &mut document.borrow_mut();
+                let _ = match method {
+                    "classList.add" => class_list_add(dom.dom_mut(), object, &tokens),
+                    _ => class_list_remove(dom.dom_mut(), object, &tokens),
+                };
+                Some(None)
+            }
+            "classList.toggle" => {
+                let dom = &mut document.borrow_mut();
+                let result = class_list_toggle(dom.dom_mut(), object, &string_argument(0), force);
+                Some(result.ok().map(boolean))
+            }
+            "classList.contains" => {
+                let result =
+                    class_list_contains(document.borrow().dom(), object, &string_argument(0));
+                Some(Some(boolean(result)))
+            }
+            _ => None,
+        };
+        if let Some(value) = string_result {
+            return (true, value);
+        }
+        let result = match method {
+            "appendChild" => node_argument(0)
+                .and_then(|node| append_child(document.borrow_mut().dom_mut(), object, node).ok()),
+            "insertBefore" => node_argument(0).and_then(|node| {
+                // A `null` child or any other value that is not a node appends the node.
+                insert_before(
+                    document.borrow_mut().dom_mut(),
+                    object,
+                    node,
+                    node_argument(1),
+                )
+                .ok()
+            }),
+            "removeChild" => node_argument(0).and_then(|child| {
+                self.pre_remove(object, child);
+                remove_child(document.borrow_mut().dom_mut(), object, child).ok()
+            }),
+            "replaceChild" => match (node_argument(0), node_argument(1)) {
+                (Some(node), Some(child)) => {
+                    self.pre_remove(object, child);
+                    replace_child(document.borrow_mut().dom_mut(), object, node, child).ok()
+                }
+                _ => None,
+            },
+            "cloneNode" => {
+                let deep = to_boolean(values.first().cloned().flatten().as_ref());
+                Some(clone_node(document.borrow_mut().dom_mut(), object, deep))
+            }
+            "normalize" => {
+                normalize(document.borrow_mut().dom_mut(), object);
+                None
+            }
+            "querySelector" => {
+                let selectors = values.first().cloned().flatten();
+                let selectors = selectors.map(|s| s.to_string()).unwrap_or_default();
+                query_selector(document.borrow().dom(), object, &selectors)
+                    .ok()
+                    .flatten()
+            }
+            "querySelectorAll" | "getElementsByTagName" | "getElementsByClassName" => {
+                let argument = values.first().cloned().flatten();
+                let argument = argument.map(|a| a.to_string()).unwrap_or_default();
+                let document = document.borrow();
+                let dom = document.dom();
+                let nodes = match method {
+                    "querySelectorAll" => {
+                        query_selector_all(dom, object, &argument).unwrap_or_default()
+                    }
+                    "getElementsByTagName" => get_elements_by_tag_name(dom, object, &argument),
+                    _ => get_elements_by_class_name(dom, object, &argument),
+                };
+                return (true, Some(node_list(nodes)));
+            }
+            "insertAdjacentHTML" => {
+                let strings: Vec<String> = values
+                    .iter()
+                    .take(2)
+                    .map(|v| v.as_ref().map(|v| v.to_string()).unwrap_or_default())
+                    .collect();
+                if let [position, markup] = strings.as_slice() {
+                    let _ =
+                        insert_adjacent_html(&mut document.borrow_mut(), object, position, markup);
+                }
+                None
+            }
+            _ => return (false, None),
+        };
+        (
+            true,
+            result.map(|node| RuntimeValue::HtmlElement {
+                object: node,
+                property: None,
+            }),
+        )
+    }
+    /// Calls a method of `EventTarget` on a DOM node.
+    /// # References:
+    /// - <https://dom.spec.whatwg.org/#interface-eventtarget>
+    fn call_event_target_method(
+        &mut self,
+        object: NodeId,
+        method: &str,
+        values: Vec<Option<RuntimeValue>>,
+    ) -> (bool, Option<RuntimeValue>) {
+        if method == "dispatchEvent" {
+            let id = match values.first() {
+                Some(Some(RuntimeValue::HostObject {
+                    kind: HostObjectKind::Event,
+                    id,
+                    property: None,
+                })) => *id,
+                _ => return (true, None),
+            };
+            // Dispatching an event that is being dispatched throws an `InvalidStateError`.
+            return match self.dispatch_event_object(object, id) {
+                Some(not_canceled) => (true, Some(boolean(not_canceled))),
+                None => (true, None),
+            };
+        }
+        let event_type = match values.first() {
+            Some(Some(value)) => value.to_string(),
+            _ => return (true, None),
+        };
+        let function = match self.to_function(values.get(1).cloned().flatten()) {
+            Some(function) => function,
+            None => return (true, None),
+        };
+        let capture = to_boolean(values.get(2).cloned().flatten().as_ref());
+        // The same function is the same callback, so that it can be removed.
+        let callback = match self.event_listeners.iter().position(|f| *f == function) {
+            Some(callback) => callback,
+            None => {
+                self.event_listeners.push(function);
+                self.event_listeners.len() - 1
+            }
+        };
+        let listener = EventListener::new(&event_type, callback, capture);
+        let mut document = self.document.borrow_mut();
+        if method == "addEventListener" {
+            add_event_listener(document.dom_mut(), object, listener);
+        } else {
+            remove_event_listener(document.dom_mut(), object, &listener);
+        }
+        (true, None)
+    }
+    /// Dispatches an event created by the browser, such as a click, to `target` and runs the
+    /// listeners that scripts added.
+    /// # Returns
+    /// - `false` if a listener canceled the event, otherwise `true`.
+    pub fn dispatch_event(&mut self, target: NodeId, event: &mut Event) -> bool {
+        self.events.push(Some(event.clone()));
+        let id = self.events.len() - 1;
+        let not_canceled = self.dispatch_event_object(target, id).unwrap_or(false);
+        // The slot is freed, so a listener that kept the event sees `undefined` properties. The
+        // freed slots at the end are removed, so that only the events that scripts created stay.
+        if let Some(dispatched) = self.events[id].take() {
+            *event = dispatched;
+        }
+        while let Some(None) = self.events.last() {
+            self.events.pop();
+        }
+        not_canceled
+    }
+    /// Dispatches the event of a host object. The listeners see the same event object, whose
+    /// state is kept in sync with the event being dispatched.
+    /// # Returns
+    /// - `false` if a listener canceled the event, otherwise `true`.
+    /// - `None` if the event doesn't exist or is already being dispatched.
+    /// # References:
+    /// - <https://dom.spec.whatwg.org/#dom-eventtarget-dispatchevent>
+    fn dispatch_event_object(&mut self, target: NodeId, id: usize) -> Option<bool> {
+        let mut event = self.events.get(id)?.clone()?;
+        if event.is_dispatching() {
+            return None;
+        }
+        let document = self.document.clone();
+        let not_canceled = dispatch_event(&document, target, &mut event, &mut |listener, event| {
+            let function = match self.event_listeners.get(listener.callback()) {
+                Some(function) => function.clone(),
+                None => return,
+            };
+            self.events[id] = Some(event.clone());
+            let env = Rc::new(RefCell::new(Environment::new(Some(self.env.clone()))));
+            let argument = RuntimeValue::HostObject {
+                kind: HostObjectKind::Event,
+                id,
+                property: None,
+            };
+            self.call_function(&function, vec![Some(argument)], env);
+            if let Some(changed) = &self.events[id] {
+                *event = changed.clone();
+            }
+        });
+        self.events[id] = Some(event);
+        Some(not_canceled)
+    }
+    /// # References:
+    /// - <https://dom.spec.whatwg.org/#interface-event>
+    /// - <https://w3c.github.io/uievents/#interface-mouseevent>
+    /// - <https://w3c.github.io/uievents/#interface-keyboardevent>
+    fn event_property(&self, id: usize, name: &str) -> Option<RuntimeValue> {
+        let event = self.events.get(id)?.as_ref()?;
+        let node = |node: Option<NodeId>| {
+            node.map(|node| RuntimeValue::HtmlElement {
+                object: node,
+                property: None,
+            })
+        };
+        let number = |n: i64| Some(RuntimeValue::Number(n.max(0) as u64));
+        match (name, event.interface()) {
+            ("type", _) => Some(RuntimeValue::StringLiteral(event.event_type())),
+            ("target", _) => node(event.target()),
+            ("currentTarget", _) => node(event.current_target()),
+            ("eventPhase", _) => Some(RuntimeValue::Number(event.phase().value())),
+            ("bubbles", _) => Some(boolean(event.bubbles())),
+            ("cancelable", _) => Some(boolean(event.cancelable())),
+            ("defaultPrevented", _) => Some(boolean(event.default_prevented())),
+            ("clientX", EventInterface::MouseEvent(e)) => number(e.client_x()),
+            ("clientY", EventInterface::MouseEvent(e)) => number(e.client_y()),
+            ("button", EventInterface::MouseEvent(e)) => Some(RuntimeValue::Number(e.button())),
+            ("key", EventInterface::KeyboardEvent(e)) => Some(RuntimeValue::StringLiteral(e.key())),
+            _ => None,
+        }
+    }
+    fn call_event_method(&mut self, id: usize, method: &str) -> (bool, Option<RuntimeValue>) {
+        let event = match self.events.get_mut(id) {
+            Some(Some(event)) => event,
+            _ => return (false, None),
+        };
+        match method {
+            "stopPropagation" => event.stop_propagation(),
+            "stopImmediatePropagation" => event.stop_immediate_propagation(),
+            "preventDefault" => event.prevent_default(),
+            _ => return (false, None),
+        }
+        (true, None)
+    }
+    fn to_function(&self, value: Option<RuntimeValue>) -> Option<Function> {
+        match value {
+            Some(RuntimeValue::Function(f)) => Some(f),
+            Some(RuntimeValue::StringLiteral(name)) => self.find_function(&name),
+            _ => None,
+        }
+    }
+    /// Returns the value of a host object property, or a reference to a method.
+    fn host_object_property(
+        &self,
+        kind: HostObjectKind,
+        id: usize,
+        name: String,
+    ) -> Option<RuntimeValue> {
+        let value = match kind {
+            HostObjectKind::WebSocket => self.websocket_property(id, &name),
+            HostObjectKind::Response => self.response_property(id, &name),
+            HostObjectKind::XMLHttpRequest => self.xhr_property(id, &name),
+            HostObjectKind::TreeWalker => self.tree_walker_property(id, &name),
+            HostObjectKind::NodeIterator => self.node_iterator_property(id, &name),
+            HostObjectKind::Event => self.event_property(id, &name),
+            HostObjectKind::Promise | HostObjectKind::Headers => None,
+        };
+        if value.is_some() {
+            return value;
+        }
+        Some(RuntimeValue::HostObject {
+            kind,
+            id,
+            property: Some(name),
+        })
+    }
+    fn set_event_handler(
+        &mut self,
+        kind: HostObjectKind,
+        id: usize,
+        name: String,
+        handler: Option<Function>,
+    ) {
+        let handlers = match kind {
+            HostObjectKind::WebSocket => match self.websockets.get_mut(id) {
+                Some(ws) => &mut ws.handlers,
+                None => return,
+            },
+            HostObjectKind::XMLHttpRequest => match self.xhrs.get_mut(id) {
+                Some(xhr) => &mut xhr.handlers,
+                None => return,
+            },
+            _ => return,
+        };
+        handlers.retain(|(n, _)| *n != name);
+        if let Some(handler) = handler {
+            handlers.push((name, handler));
+        }
+    }
+    /// Calls an event handler with an event object whose `type` is `event_type`.
+    /// # Returns
+    /// - `true` if the handler exists and was called.
+    fn call_event_handler(
+        &mut self,
+        handlers: &[(String, Function)],
+        event_type: &str,
+        properties: Vec<(String, RuntimeValue)>,
+    ) -> bool {
+        let handler_name = format!("on{}", event_type);
+        let handler = match handlers.iter().find(|(name, _)| *name == handler_name) {
+            Some((_, handler)) => handler.clone(),
+            None => return false,
+        };
+        let mut event = vec![(
+            "type".to_string(),
+            RuntimeValue::StringLiteral(event_type.to_string()),
+        )];
+        event.extend(properties);
+        let env = Rc::new(RefCell::new(Environment::new(Some(self.env.clone()))));
+        self.call_function(&handler, vec![Some(RuntimeValue::Object(event))], env);
+        true
+    }
+    /// Sets a timer that calls `callback` once after `delay` milliseconds.
+    /// # Returns
+    /// - The ID of the timer, which `clearTimeout` takes, or `None` if the callback is not a
+    ///   function.
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/timers-and-user-prompts.html#dom-settimeout>
+    fn set_timeout(&mut self, arguments: Vec<Option<RuntimeValue>>) -> Option<RuntimeValue> {
+        let mut arguments = arguments.into_iter();
+        let callback = self.to_function(arguments.next().flatten())?;
+        let delay = match arguments.next().flatten() {
+            Some(RuntimeValue::Number(delay)) => delay,
+            _ => 0,
+        };
+        self.last_timer_id += 1;
+        self.timers.push(Timer {
+            id: self.last_timer_id,
+            due: self.now().saturating_add(delay),
+            callback,
+        });
+        Some(RuntimeValue::Number(self.last_timer_id))
+    }
+    /// Runs the callbacks of the timers whose delay has passed, in the order of their due times.
+    /// A timer set by one of the callbacks runs at the next turn at the earliest.
+    /// # Returns
+    /// - `true` if a callback ran.
+    fn run_timers(&mut self) -> bool {
+        let now = self.now();
+        let mut due = Vec::new();
+        self.timers.retain(|timer| {
+            if timer.due > now {
+                return true;
+            }
+            due.push(timer.clone());
+            false
+        });
+        due.sort_by_key(|timer| (timer.due, timer.id));
+        for timer in &due {
+            let env = Rc::new(RefCell::new(Environment::new(Some(self.env.clone()))));
+            self.call_function(&timer.callback, Vec::new(), env);
+        }
+        !due.is_empty()
+    }
+    /// Runs the tasks queued by asynchronous APIs: timers, promise callbacks, network requests
+    /// made by scripts, and messages received by WebSockets.
+    /// # Returns
+    /// - `true` if a script callback ran and may have changed the DOM.
+    pub fn process_tasks(&mut self) -> bool {
+        let mut callback_ran = self.run_timers();
+        for id in 0..self.websockets.len() {
+            let mut events = core::mem::take(&mut self.websockets[id].pending_events);
+            if let Some(socket) = self.websockets[id].socket.clone() {
+                events.extend(socket.borrow_mut().poll());
+            }
+            for event in events {
+                if self.dispatch_websocket_event(id, event) {
+                    callback_ran = true;
+                }
+            }
+        }
+        // Tasks queued by callbacks, such as the reactions of chained promises, run in the same turn.
+        while let Some(task) = self.tasks.pop_front() {
+            if self.run_task(task) {
+                callback_ran = true;
+            }
+        }
+        callback_ran
+    }
+    fn run_task(&mut self, task: Task) -> bool {
+        match task {
+            Task::Fetch { promise, request } => {
+                match self.fetch_for_script(request) {
+                    Ok(response) => {
+                        self.responses.push(response);
+                        let value = RuntimeValue::HostObject {
+                            kind: HostObjectKind::Response,
+                            id: self.responses.len() - 1,
+                            property: None,
+                        };
+                        self.resolve_promise(promise, Some(value));
+                    }
+                    Err(_) => self.reject_promise(
+                        promise,
+                        Some(RuntimeValue::StringLiteral(
+                            "TypeError: Failed to fetch".to_string(),
+                        )),
+                    ),
+                }
+                false
+            }
+            Task::SendXMLHttpRequest { id } => self.run_xhr_send(id),
+            Task::PromiseReaction { reaction, settled } => {
+                let (handler, value, fulfilled) = match settled {
+                    PromiseState::Fulfilled(value) => (reaction.on_fulfilled, value, true),
+                    PromiseState::Rejected(reason) => (reaction.on_rejected, reason, false),
+                    PromiseState::Pending => return false,
+                };
+                match handler {
+                    Some(handler) => {
+                        let env = Rc::new(RefCell::new(Environment::new(Some(self.env.clone()))));
+                        let result = self.call_function(&handler, vec![value], env);
+                        self.resolve_promise(reaction.result, result);
+                        true
+                    }
+                    None => {
+                        if fulfilled {
+                            self.resolve_promise(reaction.result, value);
+                        } else {
+                            self.reject_promise(reaction.result, value);
+                        }
+                        false
+                    }
+                }
+            }
+        }
+    }
+    /// Sends a request on behalf of a script, applying the same-origin policy.
+    /// Cookies are only sent to the document's origin, and a cross-origin response is only
+    /// exposed when the server allows it with `Access-Control-Allow-Origin`.
+    /// # References:
+    /// - <https://fetch.spec.whatwg.org/#cors-check>
+    fn fetch_for_script(&self, request: HttpRequest) -> Result<HttpResponse, Error> {
+        let fetcher = match &self.fetcher {
+            Some(fetcher) => fetcher,
+            None => return Err(Error::Network("Network is not available".to_string())),
+        };
+        let origin = self.document.borrow().url().map(|url| url.origin());
+        let target = Url::new(request.url())
+            .parse()
+            .map_err(Error::UnexpectedInput)?;
+        let same_origin = Some(target.origin()) == origin;
+        let response = fetcher.fetch(request, same_origin)?;
+        let response_origin = Url::new(response.url()).parse().ok().map(|u| u.origin());
+        if response_origin != origin {
+            let allowed = response
+                .header_values("Access-Control-Allow-Origin")
+                .iter()
+                .any(|value| value == "*" || Some(value) == origin.as_ref());
+            if !allowed {
+                return Err(Error::Network(format!(
+                    "Cross-origin response from {} was blocked",
+                    response.url()
+                )));
+            }
+        }
+        Ok(response)
+    }
+    fn resolve_url(&self, url: &str) -> Option<String> {
+        match self.document.borrow().url() {
+            Some(base) => base.resolve(url).ok().map(|u| u.serialize()),
+            None => Url::new(url.to_string())
+                .parse()
+                .ok()
+                .map(|u| u.serialize()),
+        }
+    }
+    /// Starts a GET request and returns a promise for its response.
+    /// # References:
+    /// - <https://fetch.spec.whatwg.org/#fetch-method>
+    fn start_fetch(&mut self, url: &str) -> RuntimeValue {
+        let promise = self.new_promise();
+        match self.resolve_url(url) {
+            Some(url) => self.tasks.push_back(Task::Fetch {
+                promise,
+                request: HttpRequest::get(url),
+            }),
+            None => self.reject_promise(
+                promise,
+                Some(RuntimeValue::StringLiteral(format!(
+                    "TypeError: Invalid URL {}",
+                    url
+                ))),
+            ),
+        }
+        RuntimeValue::HostObject {
+            kind: HostObjectKind::Promise,
+            id: promise,
+            property: None,
+        }
+    }
+    fn new_promise(&mut self) -> usize {
+        self.promises.push(JsPromise {
+            state: PromiseState::Pending,
+            reactions: Vec::new(),
+        });
+        self.promises.len() - 1
+    }
+    /// Resolves a promise. Resolving with another promise makes it follow that promise.
+    /// # References:
+    /// - <https://tc39.es/ecma262/#sec-promise-resolve-functions>
+    fn resolve_promise(&mut self, id: usize, value: Option<RuntimeValue>) {
+        if let Some(RuntimeValue::HostObject {
+            kind: HostObjectKind::Promise,
+            id: inner,
+            property: None,
+        }) = value
+        {
+            if inner != id {
+                self.add_reaction(
+                    inner,
+                    PromiseReaction {
+                        on_fulfilled: None,
+                        on_rejected: None,
+                        result: id,
+                    },
+                );
+                return;
+            }
+        }
+        self.settle_promise(id, PromiseState::Fulfilled(value));
+    }
+    fn reject_promise(&mut self, id: usize, reason: Option<RuntimeValue>) {
+        self.settle_promise(id, PromiseState::Rejected(reason));
+    }
+    fn settle_promise(&mut self, id: usize, state: PromiseState) {
+        let promise = match self.promises.get_mut(id) {
+            Some(p) if p.state == PromiseState::Pending => p,
+            _ => return,
+        };
+        promise.state = state.clone();
+        for reaction in core::mem::take(&mut promise.reactions) {
+            self.tasks.push_back(Task::PromiseReaction {
+                reaction,
+                settled: state.clone(),
+            });
+        }
+    }
+    fn add_reaction(&mut self, id: usize, reaction: PromiseReaction) {
+        let promise = match self.promises.get_mut(id) {
+            Some(p) => p,
+            None => return,
+        };
+        if promise.state == PromiseState::Pending {
+            promise.reactions.push(reaction);
+        } else {
+            let settled = promise.state.clone();
+            self.tasks
+                .push_back(Task::PromiseReaction { reaction, settled });
+        }
+    }
+    /// # References:
+    /// - <https://tc39.es/ecma262/#sec-promise.prototype.then>
+    fn call_promise_method(
+        &mut self,
+        id: usize,
+        method: &str,
+        values: Vec<Option<RuntimeValue>>,
+    ) -> (bool, Option<RuntimeValue>) {
+        let mut values = values.into_iter();
+        let (on_fulfilled, on_rejected) = match method {
+            "then" => {
+                let on_fulfilled = self.to_function(values.next().flatten());
+                (on_fulfilled, self.to_function(values.next().flatten()))
+            }
+            "catch" => (None, self.to_function(values.next().flatten())),
+            _ => return (false, None),
+        };
+        let result = self.new_promise();
+        self.add_reaction(
+            id,
+            PromiseReaction {
+                on_fulfilled,
+                on_rejected,
+                result,
+            },
+        );
+        (
+            true,
+            Some(RuntimeValue::HostObject {
+                kind: HostObjectKind::Promise,
+                id: result,
+                property: None,
+            }),
+        )
+    }
+    /// # References:
+    /// - <https://fetch.spec.whatwg.org/#response-class>
+    fn response_property(&self, id: usize, name: &str) -> Option<RuntimeValue> {
+        let response = self.responses.get(id)?;
+        let status = response.status_code();
+        match name {
+            "status" => Some(RuntimeValue::Number(status as u64)),
+            "ok" => Some(RuntimeValue::StringLiteral(
+                (200..300).contains(&status).to_string(),
+            )),
+            "statusText" => Some(RuntimeValue::StringLiteral(response.reason())),
+            "url" => Some(RuntimeValue::StringLiteral(response.url())),
+            "headers" => Some(RuntimeValue::HostObject {
+                kind: HostObjectKind::Headers,
+                id,
+                property: None,
+            }),
+            _ => None,
+        }
+    }
+    fn call_response_method(&mut self, id: usize, method: &str) -> (bool, Option<RuntimeValue>) {
+        let body = match self.responses.get(id) {
+            Some(response) => response.body(),
+            None => return (true, None),
+        };
+        let promise = self.new_promise();
+        match method {
+            "text" => self.resolve_promise(promise, Some(RuntimeValue::StringLiteral(body))),
+            "json" => match parse_json(&body) {
+                Some(value) => self.resolve_promise(promise, Some(value)),
+                None => self.reject_promise(
+                    promise,
+                    Some(RuntimeValue::StringLiteral(
+                        "SyntaxError: Invalid JSON".to_string(),
+                    )),
+                ),
+            },
+            _ => return (false, None),
+        }
+        (
+            true,
+            Some(RuntimeValue::HostObject {
+                kind: HostObjectKind::Promise,
+                id: promise,
+                property: None,
+            }),
+        )
+    }
+    fn call_headers_method(
+        &mut self,
+        id: usize,
+        method: &str,
+        values: Vec<Option<RuntimeValue>>,
+    ) -> (bool, Option<RuntimeValue>) {
+        let response = match self.responses.get(id) {
+            Some(response) => response,
+            None => return (true, None),
+        };
+        let name = match values.first() {
+            Some(Some(name)) => name.to_string(),
+            _ => String::new(),
+        };
+        let header_values = response.header_values(&name);
+        match method {
+            "get" => {
+                if header_values.is_empty() {
+                    return (true, None);
+                }
+                (
+                    true,
+                    Some(RuntimeValue::StringLiteral(header_values.join(", "))),
+                )
+            }
+            "has" => (
+                true,
+                Some(RuntimeValue::StringLiteral(
+                    (!header_values.is_empty()).to_string(),
+                )),
+            ),
+            _ => (false, None),
+        }
+    }
+    /// Calls the filter function of a TreeWalker or a NodeIterator with `node`. A missing filter
+    /// or a result other than `FILTER_REJECT` and `FILTER_SKIP` accepts the node.
+    fn run_node_filter(&mut self, filter: &Option<Function>, node: NodeId) -> FilterResult {
+        let filter = match filter {
+            Some(f) => f,
+            None => return FilterResult::Accept,
+        };
+        let env = Rc::new(RefCell::new(Environment::new(Some(self.env.clone()))));
+        let argument = RuntimeValue::HtmlElement {
+            object: node,
+            property: None,
+        };
+        match self.call_function(filter, vec![Some(argument)], env) {
+            Some(RuntimeValue::Number(2)) => FilterResult::Reject,
+            Some(RuntimeValue::Number(3)) => FilterResult::Skip,
+            _ => FilterResult::Accept,
+        }
+    }
+    /// Runs the pre-removing steps of the NodeIterators before `child` is removed from `parent`.
+    fn pre_remove(&mut self, parent: NodeId, child: NodeId) {
+        let document = self.document.borrow();
+        let dom = document.dom();
+        if dom.parent(child) != Some(parent) {
+            return;
+        }
+        for js_iterator in self.node_iterators.iter_mut() {
+            js_iterator.iterator.pre_remove(dom, child);
+        }
+    }
+    fn tree_walker_property(&self, id: usize, name: &str) -> Option<RuntimeValue> {
+        let walker = &self.tree_walkers.get(id)?.walker;
+        let node = match name {
+            "root" => walker.root(),
+            "currentNode" => walker.current_node(),
+            "whatToShow" => return Some(RuntimeValue::Number(walker.what_to_show() as u64)),
+            _ => return None,
+        };
+        Some(RuntimeValue::HtmlElement {
+            object: node,
+            property: None,
+        })
+    }
+    fn set_tree_walker_property(&mut self, id: usize, name: &str, value: Option<RuntimeValue>) {
+        let walker = match self.tree_walkers.get_mut(id) {
+            Some(js_walker) => &mut js_walker.walker,
+            None => return,
+        };
+        if let (
+            "currentNode",
+            Some(RuntimeValue::HtmlElement {
+                object,
+                property: None,
+            }),
+        ) = (name, value)
+        {
+            walker.set_current_node(object);
+        }
+    }
+    fn call_tree_walker_method(&mut self, id: usize, method: &str) -> (bool, Option<RuntimeValue>) {
+        let mut js_walker = match self.tree_walkers.get(id) {
+            Some(js_walker) => js_walker.clone(),
+            None => return (false, None),
+        };
+        let filter = js_walker.filter.clone();
+        let document = self.document.clone();
+        let mut node_filter = |node: NodeId| self.run_node_filter(&filter, node);
+        let walker = &mut js_walker.walker;
+        let node = match method {
+            "parentNode" => walker.parent_node(&document, &mut node_filter),
+            "firstChild" => walker.first_child(&document, &mut node_filter),
+            "lastChild" => walker.last_child(&document, &mut node_filter),
+            "previousSibling" => walker.previous_sibling(&document, &mut node_filter),
+            "nextSibling" => walker.next_sibling(&document, &mut node_filter),
+            "previousNode" => walker.previous_node(&document, &mut node_filter),
+            "nextNode" => walker.next_node(&document, &mut node_filter),
+            _ => return (false, None),
+        };
+        if let Some(w) = self.tree_walkers.get_mut(id) {
+            w.walker = js_walker.walker;
+        }
+        (
+            true,
+            node.map(|node| RuntimeValue::HtmlElement {
+                object: node,
+                property: None,
+            }),
+        )
+    }
+    fn node_iterator_property(&self, id: usize, name: &str) -> Option<RuntimeValue> {
+        let iterator = &self.node_iterators.get(id)?.iterator;
+        let node = match name {
+            "root" => iterator.root(),
+            "referenceNode" => iterator.reference_node(),
+            "whatToShow" => return Some(RuntimeValue::Number(iterator.what_to_show() as u64)),
+            "pointerBeforeReferenceNode" => {
+                let before = iterator.pointer_before_reference_node();
+                return Some(RuntimeValue::StringLiteral(before.to_string()));
+            }
+            _ => return None,
+        };
+        Some(RuntimeValue::HtmlElement {
+            object: node,
+            property: None,
+        })
+    }
+    fn call_node_iterator_method(
+        &mut self,
+        id: usize,
+        method: &str,
+    ) -> (bool, Option<RuntimeValue>) {
+        let mut js_iterator = match self.node_iterators.get(id) {
+            Some(js_iterator) => js_iterator.clone(),
+            None => return (false, None),
+        };
+        let filter = js_iterator.filter.clone();
+        let document = self.document.clone();
+        let mut node_filter = |node: NodeId| self.run_node_filter(&filter, node);
+        let node = match method {
+            "nextNode" => js_iterator.iterator.next_node(&document, &mut node_filter),
+            "previousNode" => js_iterator
+                .iterator
+                .previous_node(&document, &mut node_filter),
+            // `detach()` does nothing.
+            "detach" => return (true, None),
+            _ => return (false, None),
+        };
+        if let Some(i) = self.node_iterators.get_mut(id) {
+            i.iterator = js_iterator.iterator;
+        }
+        (
+            true,
+            node.map(|node| RuntimeValue::HtmlElement {
+                object: node,
+                property: None,
+            }),
+        )
+    }
+    /// # References:
+    /// - <https://xhr.spec.whatwg.org/#interface-xmlhttprequest>
+    fn xhr_property(&self, id: usize, name: &str) -> Option<RuntimeValue> {
+        let xhr = self.xhrs.get(id)?;
+        let response = xhr.response.as_ref();
+        match name {
+            "readyState" => Some(RuntimeValue::Number(xhr.ready_state)),
+            "status" => Some(RuntimeValue::Number(
+                response.map(|r| r.status_code() as u64).unwrap_or(0),
+            )),
+            "statusText" => Some(RuntimeValue::StringLiteral(
+                response.map(|r| r.reason()).unwrap_or_default(),
+            )),
+            "responseText" | "response" => Some(RuntimeValue::StringLiteral(
+                response.map(|r| r.body()).unwrap_or_default(),
+            )),
+            "responseURL" => Some(RuntimeValue::StringLiteral(
+                response.map(|r| r.url()).unwrap_or_default(),
+            )),
+            _ => None,
+        }
+    }
+    fn call_xhr_method(
+        &mut self,
+        id: usize,
+        method: &str,
+        values: Vec<Option<RuntimeValue>>,
+    ) -> (bool, Option<RuntimeValue>) {
+        let arg = |i: usize| match values.get(i) {
+            Some(Some(value)) => value.to_string(),
+            _ => String::new(),
+        };
+        let resolved_url = self.resolve_url(&arg(1));
+        let xhr = match self.xhrs.get_mut(id) {
+            Some(xhr) => xhr,
+            None => return (true, None),
+        };
+        match method {
+            "open" => {
+                xhr.method = arg(0).to_ascii_uppercase();
+                xhr.url = resolved_url;
+                xhr.request_headers = Vec::new();
+                xhr.response = None;
+                xhr.send_flag = false;
+                xhr.ready_state = XHR_OPENED;
+            }
+            "setRequestHeader" => {
+                if xhr.ready_state == XHR_OPENED && !xhr.send_flag {
+                    xhr.request_headers.push(Header::new(arg(0), arg(1)));
+                }
+            }
+            "send" => {
+                if xhr.ready_state == XHR_OPENED && !xhr.send_flag {
+                    xhr.body = arg(0);
+                    xhr.send_flag = true;
+                    self.tasks.push_back(Task::SendXMLHttpRequest { id });
+                }
+            }
+            "abort" => {
+                xhr.send_flag = false;
+                xhr.response = None;
+                xhr.ready_state = XHR_UNSENT;
+            }
+            "getResponseHeader" => {
+                let values = match &xhr.response {
+                    Some(response) => response.header_values(&arg(0)),
+                    None => Vec::new(),
+                };
+                if values.is_empty() {
+                    return (true, None);
+                }
+                return (true, Some(RuntimeValue::StringLiteral(values.join(", "))));
+            }
+            "getAllResponseHeaders" => {
+                let mut all = String::new();
+                if let Some(response) = &xhr.response {
+                    for header in response.headers() {
+                        all.push_str(&format!(
+                            "{}: {}\r\n",
+                            header.name().to_ascii_lowercase(),
+                            header.value()
+                        ));
+                    }
+                }
+                return (true, Some(RuntimeValue::StringLiteral(all)));
+            }
+            _ => return (false, None),
+        }
+        (true, None)
+    }
+    /// Performs the request of an XMLHttpRequest and fires its events.
+    /// # References:
+    /// - <https://xhr.spec.whatwg.org/#the-send()-method>
+    fn run_xhr_send(&mut self, id: usize) -> bool {
+        let xhr = match self.xhrs.get(id) {
+            Some(xhr) if xhr.send_flag => xhr.clone(),
+            // The request was aborted or reopened before it ran.
+            _ => return false,
+        };
+        let result = match xhr.url {
+            Some(url) => {
+                let mut request = HttpRequest::new(&xhr.method, url);
+                for header in xhr.request_headers {
+                    request.add_header(header);
+                }
+                if xhr.method != "GET" && xhr.method != "HEAD" {
+                    request = request.with_body(xhr.body);
+                }
+                self.fetch_for_script(request)
+            }
+            None => Err(Error::UnexpectedInput("Invalid URL".to_string())),
+        };
+        let succeeded = result.is_ok();
+        if let Some(xhr) = self.xhrs.get_mut(id) {
+            xhr.response = result.ok();
+            xhr.send_flag = false;
+            xhr.ready_state = XHR_DONE;
+        }
+        let handlers = xhr.handlers;
+        let mut callback_ran = self.call_event_handler(&handlers, "readystatechange", Vec::new());
+        let event_type = if succeeded { "load" } else { "error" };
+        callback_ran |= self.call_event_handler(&handlers, event_type, Vec::new());
+        callback_ran |= self.call_event_handler(&handlers, "loadend", Vec::new());
+        callback_ran
+    }
+    /// Creates a WebSocket object and starts connecting to `url`.
+    /// A connection failure is reported to the script through `error` and `close` events.
+    /// # References:
+    /// - <https://websockets.spec.whatwg.org/#dom-websocket-websocket>
+    fn open_websocket(&mut self, url: String) -> RuntimeValue {
+        let socket = match self.websocket_connector {
+            Some(connect) => WebSocket::new(&url, connect).ok(),
+            None => None,
+        };
+        let pending_events = match socket {
+            Some(_) => Vec::new(),
+            None => vec![
+                WebSocketEvent::Error,
+                WebSocketEvent::Close {
+                    code: 1006,
+                    reason: String::new(),
+                    was_clean: false,
+                },
+            ],
+        };
+        self.websockets.push(JsWebSocket {
+            url,
+            socket: socket.map(|s| Rc::new(RefCell::new(s))),
+            handlers: Vec::new(),
+            pending_events,
+        });
+        RuntimeValue::HostObject {
+            kind: HostObjectKind::WebSocket,
+            id: self.websockets.len() - 1,
+            property: None,
+        }
+    }
+    fn websocket_property(&self, id: usize, name: &str) -> Option<RuntimeValue> {
+        let ws = self.websockets.get(id)?;
+        match name {
+            "url" => Some(RuntimeValue::StringLiteral(ws.url.clone())),
+            "readyState" => {
+                let state = match &ws.socket {
+                    Some(socket) => socket.borrow_mut().ready_state(),
+                    None => ReadyState::Closed,
+                };
+                Some(RuntimeValue::Number(state as u64))
+            }
+            _ => None,
+        }
+    }
+    fn call_websocket_method(
+        &mut self,
+        id: usize,
+        method: &str,
+        values: Vec<Option<RuntimeValue>>,
+    ) -> (bool, Option<RuntimeValue>) {
+        let socket = match self.websockets.get(id) {
+            Some(ws) => ws.socket.clone(),
+            None => return (true, None),
+        };
+        match method {
+            "send" => {
+                if let (Some(socket), Some(Some(data))) = (socket, values.first()) {
+                    let _ = socket.borrow_mut().send_text(&data.to_string());
+                }
+            }
+            "close" => {
+                let code = match values.first() {
+                    Some(Some(RuntimeValue::Number(code))) => *code as u16,
+                    _ => 1000,
+                };
+                let reason = match values.get(1) {
+                    Some(Some(reason)) => reason.to_string(),
+                    _ => String::new(),
+                };
+                if let Some(socket) = socket {
+                    let _ = socket.borrow_mut().close(code, &reason);
+                }
+            }
+            _ => return (false, None),
+        }
+        (true, None)
+    }
+    /// Calls the `on<type>` handler of a WebSocket object with an event object.
+    fn dispatch_websocket_event(&mut self, id: usize, event: WebSocketEvent) -> bool {
+        let (event_type, properties) = match event {
+            WebSocketEvent::Open => ("open", Vec::new()),
+            WebSocketEvent::Text(data) => (
+                "message",
+                vec![("data".to_string(), RuntimeValue::StringLiteral(data))],
+            ),
+            WebSocketEvent::Binary(data) => (
+                "message",
+                vec![(
+                    "data".to_string(),
+                    RuntimeValue::StringLiteral(String::from_utf8_lossy(&data).to_string()),
+                )],
+            ),
+            WebSocketEvent::Error => ("error", Vec::new()),
+            WebSocketEvent::Close {
+                code,
+                reason,
+                was_clean,
+            } => (
+                "close",
+                vec![
+                    ("code".to_string(), RuntimeValue::Number(code as u64)),
+                    ("reason".to_string(), RuntimeValue::StringLiteral(reason)),
+                    (
+                        "wasClean".to_string(),
+                        RuntimeValue::StringLiteral(was_clean.to_string()),
+                    ),
+                ],
+            ),
+        };
+        let handlers = self.websockets[id].handlers.clone();
+        self.call_event_handler(&handlers, event_type, properties)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::renderer::dom::api::get_target_element_node;
+    use crate::renderer::dom::event::KeyboardEvent;
+    use crate::renderer::dom::event::MouseEvent;
+    use crate::renderer::dom::node::ElementKind;
     use crate::renderer::dom::node::NodeKind as DomNodeKind;
+    use crate::renderer::html::parser::HtmlParser;
+    use crate::renderer::html::token::HtmlTokenizer;
     use crate::renderer::js::ast::JsParser;
     use crate::renderer::js::token::JsLexer;
+    use crate::websocket::tests::connect_echo_server;
+    use core::sync::atomic::AtomicU64;
+    use core::sync::atomic::Ordering;
     fn test_run(input: String, expected: Vec<Option<RuntimeValue>>) {
         let lexer = JsLexer::new(input);
         let mut parser = JsParser::new(lexer);
         let ast = parser.parse_ast();
-        let dom = Rc::new(RefCell::new(DomNode::new(DomNodeKind::Document)));
-        let mut runtime = JsRuntime::new(dom);
+        let mut runtime = JsRuntime::new(Rc::new(RefCell::new(Document::new())));
         let mut i = 0;
         for node in ast.body() {
             let result = runtime.eval(&Some(node.clone()), runtime.env.clone());
@@ -387,10 +2394,448 @@ mod tests {
         )
     }
     #[test]
+    fn test_call_undefined_function() {
+        let lexer = JsLexer::new("var a = 1; undefinedFn(); a = 2;".to_string());
+        let mut parser = JsParser::new(lexer);
+        let ast = parser.parse_ast();
+        let mut runtime = JsRuntime::new(Rc::new(RefCell::new(Document::new())));
+        runtime.execute(&ast);
+        assert_eq!(Some(RuntimeValue::Number(2)), variable(&runtime, "a"));
+        let errors = runtime.errors();
+        assert_eq!(1, errors.len());
+        assert_eq!("type-error", errors[0].code());
+        assert_eq!(12, errors[0].span().start().column());
+    }
+    #[test]
     fn test_local_variable() {
         test_run(
             "var a=42;function foo(){var a=1;return a;}foo()+a".to_string(),
             [None, None, Some(RuntimeValue::Number(43))].to_vec(),
         )
     }
+    #[test]
+    fn test_websocket_echo() {
+        let input = "var received = 0; var closed = 0; var ws = new WebSocket(\"ws://localhost:8080/echo\"); ws.onopen = function(e){ws.send(\"hello\");}; ws.onmessage = function(e){received = e.data; ws.close();}; ws.onclose = function(e){closed = e.code;}; var state = ws.readyState;".to_string();
+        let lexer = JsLexer::new(input);
+        let mut parser = JsParser::new(lexer);
+        let ast = parser.parse_ast();
+        let mut runtime = JsRuntime::new(Rc::new(RefCell::new(Document::new())));
+        runtime.set_websocket_connector(connect_echo_server);
+        runtime.execute(&ast);
+        // The handshake runs in the task queue, so the socket is connecting while the script runs.
+        assert_eq!(
+            Some(RuntimeValue::Number(0)),
+            runtime.env.borrow_mut().get_variable("state".to_string())
+        );
+        // The open event is delivered from the task queue, not during script execution.
+        assert_eq!(
+            Some(RuntimeValue::Number(0)),
+            runtime
+                .env
+                .borrow_mut()
+                .get_variable("received".to_string())
+        );
+        assert!(runtime.process_tasks());
+        assert!(runtime.process_tasks());
+        assert_eq!(
+            Some(RuntimeValue::StringLiteral("hello".to_string())),
+            runtime
+                .env
+                .borrow_mut()
+                .get_variable("received".to_string())
+        );
+        assert!(runtime.process_tasks());
+        assert_eq!(
+            Some(RuntimeValue::Number(1000)),
+            runtime.env.borrow_mut().get_variable("closed".to_string())
+        );
+        assert!(!runtime.process_tasks());
+    }
+    #[test]
+    fn test_websocket_connection_failure() {
+        let input = "var failed = 0; var ws = new WebSocket(\"ws://localhost\"); ws.onerror = function(e){failed = 1;};".to_string();
+        let lexer = JsLexer::new(input);
+        let mut parser = JsParser::new(lexer);
+        let ast = parser.parse_ast();
+        let mut runtime = JsRuntime::new(Rc::new(RefCell::new(Document::new())));
+        runtime.execute(&ast);
+        assert!(runtime.process_tasks());
+        assert_eq!(
+            Some(RuntimeValue::Number(1)),
+            runtime.env.borrow_mut().get_variable("failed".to_string())
+        );
+    }
+    #[test]
+    fn test_set_inner_html_and_insert_adjacent_html() {
+        let html = "<div id=\"target\"><p>old</p></div><span id=\"old\">x</span>".to_string();
+        let doc = HtmlParser::new(HtmlTokenizer::new(html)).construct_tree();
+        let document = doc.borrow().node();
+        let input = "var t = document.getElementById(\"target\"); t.innerHTML = \"<b>new</b>\"; t.insertAdjacentHTML(\"beforeend\", \"<i>1</i><i>2</i>\"); var o = document.getElementById(\"old\"); o.outerHTML = \"<u>y</u>\";".to_string();
+        let mut parser = JsParser::new(JsLexer::new(input));
+        let ast = parser.parse_ast();
+        let mut runtime = JsRuntime::new(doc.clone());
+        runtime.execute(&ast);
+        let doc = doc.borrow();
+        let dom = doc.dom();
+        let target = get_element_by_id(dom, document, "target").expect("failed to get the target");
+        let b = dom.first_child(target).expect("failed to get b");
+        assert_eq!(Some(ElementKind::B), dom[b].element_kind());
+        let i = dom.next_sibling(b).expect("failed to get i");
+        assert_eq!(Some(ElementKind::I), dom[i].element_kind());
+        let text = dom.first_child(i).expect("failed to get a text of i");
+        assert_eq!(DomNodeKind::Text("1".to_string()), dom[text].kind());
+        assert!(get_target_element_node(dom, document, ElementKind::P).is_none());
+        assert!(get_element_by_id(dom, document, "old").is_none());
+        assert!(get_target_element_node(dom, document, ElementKind::U).is_some());
+    }
+    #[test]
+    fn test_dom_mutation() {
+        let html = "<ul id=\"list\"><li id=\"a\">a</li><li id=\"b\">b</li></ul><p id=\"p\">x</p>"
+            .to_string();
+        let doc = HtmlParser::new(HtmlTokenizer::new(html)).construct_tree();
+        let document = doc.borrow().node();
+        let input = "var list = document.getElementById(\"list\"); var a = document.getElementById(\"a\"); var b = document.getElementById(\"b\"); var c = document.createElement(\"LI\"); c.textContent = \"c\"; list.appendChild(c); list.insertBefore(b, a); var copy = b.cloneNode(true); list.replaceChild(copy, a); list.removeChild(b); list.appendChild(list); var p = document.getElementById(\"p\"); p.appendChild(document.createTextNode(\"y\")); p.normalize(); p.textContent = p.textContent + \"z\";".to_string();
+        let mut parser = JsParser::new(JsLexer::new(input));
+        let ast = parser.parse_ast();
+        let mut runtime = JsRuntime::new(doc.clone());
+        runtime.execute(&ast);
+        let doc = doc.borrow();
+        let dom = doc.dom();
+        let list = get_element_by_id(dom, document, "list").expect("failed to get the list");
+        assert_eq!(
+            "<li id=\"b\">b</li><li>c</li>",
+            serialize_children(dom, list)
+        );
+        let p = get_element_by_id(dom, document, "p").expect("failed to get p");
+        assert_eq!("xyz", serialize_children(dom, p));
+        let text = dom.first_child(p).expect("failed to get a text");
+        assert!(dom.next_sibling(text).is_none());
+        assert_eq!(Some(text), dom.last_child(p));
+    }
+    #[test]
+    fn test_queries() {
+        let html = "<ul id=\"list\"><li class=\"x\">a</li><li>b</li><li class=\"x y\">c</li></ul><p class=\"y\">d</p>"
+            .to_string();
+        let doc = HtmlParser::new(HtmlTokenizer::new(html)).construct_tree();
+        let document = doc.borrow().node();
+        let input = "var items = document.querySelectorAll(\".x\"); var count = items.length; var i = 1; items[i].textContent = \"C\"; var list = document.querySelector(\"#list\"); var lis = list.getElementsByTagName(\"li\"); lis[1].textContent = \"B\"; var ys = document.getElementsByClassName(\"y\"); var ys_count = ys.length; list.querySelector(\"li\").textContent = \"A\";".to_string();
+        let mut parser = JsParser::new(JsLexer::new(input));
+        let ast = parser.parse_ast();
+        let mut runtime = JsRuntime::new(doc.clone());
+        runtime.execute(&ast);
+        assert_eq!(
+            Some(RuntimeValue::Number(2)),
+            runtime.env.borrow_mut().get_variable("count".to_string())
+        );
+        assert_eq!(
+            Some(RuntimeValue::Number(2)),
+            runtime
+                .env
+                .borrow_mut()
+                .get_variable("ys_count".to_string())
+        );
+        let doc = doc.borrow();
+        let dom = doc.dom();
+        let list = get_element_by_id(dom, document, "list").expect("failed to get the list");
+        assert_eq!(
+            "<li class=\"x\">A</li><li>B</li><li class=\"x y\">C</li>",
+            serialize_children(dom, list)
+        );
+    }
+    #[test]
+    fn test_attributes_and_class_list() {
+        let html = "<p id=\"target\" class=\"a\" title=\"x\"></p>".to_string();
+        let doc = HtmlParser::new(HtmlTokenizer::new(html)).construct_tree();
+        let document = doc.borrow().node();
+        let input = "var t = document.getElementById(\"target\"); t.setAttribute(\"Data-N\", \"1\"); var title = t.getAttribute(\"TITLE\"); t.removeAttribute(\"title\"); var has = t.hasAttribute(\"title\"); var hidden = t.toggleAttribute(\"hidden\"); t.classList.add(\"b\", \"c\"); t.classList.remove(\"a\"); var toggled = t.classList.toggle(\"c\"); var forced = t.classList.toggle(\"d\", true); var contains = t.classList.contains(\"b\"); var className = t.className; t.id = \"renamed\";".to_string();
+        let mut parser = JsParser::new(JsLexer::new(input));
+        let ast = parser.parse_ast();
+        let mut runtime = JsRuntime::new(doc.clone());
+        runtime.execute(&ast);
+        let variable = |name: &str| runtime.env.borrow_mut().get_variable(name.to_string());
+        let string = |s: &str| Some(RuntimeValue::StringLiteral(s.to_string()));
+        assert_eq!(string("x"), variable("title"));
+        assert_eq!(string("false"), variable("has"));
+        assert_eq!(string("true"), variable("hidden"));
+        assert_eq!(string("false"), variable("toggled"));
+        assert_eq!(string("true"), variable("forced"));
+        assert_eq!(string("true"), variable("contains"));
+        assert_eq!(string("b d"), variable("className"));
+        let doc = doc.borrow();
+        let dom = doc.dom();
+        let target = get_element_by_id(dom, document, "renamed").expect("failed to get the target");
+        assert_eq!(
+            "<p id=\"renamed\" class=\"b d\" data-n=\"1\" hidden=\"\"></p>",
+            serialize_node(dom, target)
+        );
+        assert!(dom[target].needs_style_recalc());
+    }
+    #[test]
+    fn test_tree_walker_and_node_iterator() {
+        let html = "<div id=\"root\"><p id=\"a\">1<b>2</b></p><p id=\"c\">3</p></div>".to_string();
+        let doc = HtmlParser::new(HtmlTokenizer::new(html)).construct_tree();
+        let document = doc.borrow().node();
+        let input = "var root = document.getElementById(\"root\"); var walker = document.createTreeWalker(root, NodeFilter.SHOW_TEXT); var first = walker.nextNode(); var second = walker.nextNode(); walker.currentNode = document.getElementById(\"c\"); var third = walker.firstChild(); var parent = walker.parentNode(); var skip = function(node) { return NodeFilter.FILTER_SKIP; }; var elements = document.createTreeWalker(root, NodeFilter.SHOW_ELEMENT, skip); var none = elements.firstChild(); var iterator = document.createNodeIterator(root, NodeFilter.SHOW_TEXT); iterator.nextNode(); iterator.nextNode(); root.removeChild(document.getElementById(\"a\")); var reference = iterator.referenceNode; var after = iterator.nextNode();".to_string();
+        let mut parser = JsParser::new(JsLexer::new(input));
+        let ast = parser.parse_ast();
+        let mut runtime = JsRuntime::new(doc.clone());
+        runtime.execute(&ast);
+        let node = |name: &str| match runtime.env.borrow_mut().get_variable(name.to_string()) {
+            Some(RuntimeValue::HtmlElement { object, .. }) => Some(object),
+            _ => None,
+        };
+        let doc = doc.borrow();
+        let dom = doc.dom();
+        let text = |name: &str| node(name).and_then(|n| text_content(dom, n));
+        assert_eq!(Some("2".to_string()), text("second"));
+        assert_eq!(Some("3".to_string()), text("third"));
+        assert!(node("parent").is_none());
+        assert!(node("none").is_none());
+        assert_eq!(Some("3".to_string()), text("after"));
+        let root = get_element_by_id(dom, document, "root").expect("failed to get the root");
+        // The iterator was on a removed node and moved to its parent.
+        assert_eq!(Some(root), node("reference"));
+        assert_eq!("<p id=\"c\">3</p>", serialize_children(dom, root));
+    }
+    #[test]
+    fn test_event_listeners() {
+        let html = "<div id=\"outer\"><p id=\"target\"></p></div>".to_string();
+        let doc = HtmlParser::new(HtmlTokenizer::new(html)).construct_tree();
+        let document = doc.borrow().node();
+        let input = "var log = \"\"; var outer = document.getElementById(\"outer\"); var t = document.getElementById(\"target\"); var capture = function(e) { log = log + \"capture:\" + e.eventPhase + \" \"; }; var bubble = function(e) { log = log + \"bubble:\" + e.currentTarget.id + \" \"; }; var cancel = function(e) { e.preventDefault(); }; var removed = function(e) { log = log + \"removed \"; }; document.addEventListener(\"click\", capture, true); outer.addEventListener(\"click\", bubble); t.addEventListener(\"click\", bubble); t.addEventListener(\"click\", cancel); t.addEventListener(\"click\", removed); t.removeEventListener(\"click\", removed); var key = \"\"; t.addEventListener(\"keydown\", function(e) { key = e.key; e.stopPropagation(); }); outer.addEventListener(\"keydown\", removed); var custom = new Event(\"custom\"); var target = \"\"; outer.addEventListener(\"custom\", function(e) { target = e.target; }); var result = t.dispatchEvent(custom); var before = target; outer.dispatchEvent(custom);".to_string();
+        let mut parser = JsParser::new(JsLexer::new(input));
+        let ast = parser.parse_ast();
+        let mut runtime = JsRuntime::new(doc.clone());
+        runtime.execute(&ast);
+        let string = |s: &str| Some(RuntimeValue::StringLiteral(s.to_string()));
+        let env = runtime.env.clone();
+        let variable = |name: &str| env.borrow_mut().get_variable(name.to_string());
+        // The event doesn't bubble, so the listener on the parent only runs when the event is
+        // dispatched to the parent itself.
+        assert_eq!(string(""), variable("before"));
+        assert_eq!(string("true"), variable("result"));
+        let target = get_element_by_id(doc.borrow().dom(), document, "target")
+            .expect("failed to get the target");
+        let outer = get_element_by_id(doc.borrow().dom(), document, "outer")
+            .expect("failed to get the outer element");
+        assert_eq!(
+            Some(RuntimeValue::HtmlElement {
+                object: outer,
+                property: None
+            }),
+            variable("target")
+        );
+        let mut click = Event::new_mouse_event("click", MouseEvent::new(1, 2, 0));
+        assert!(!runtime.dispatch_event(target, &mut click));
+        assert!(click.default_prevented());
+        assert_eq!(
+            string("capture:1 bubble:target bubble:outer "),
+            variable("log")
+        );
+        let mut keydown = Event::new_keyboard_event("keydown", KeyboardEvent::new("Enter"));
+        assert!(runtime.dispatch_event(target, &mut keydown));
+        assert_eq!(string("Enter"), variable("key"));
+        // The keydown listener stopped the event before it reached the removed listener.
+        assert_eq!(
+            string("capture:1 bubble:target bubble:outer "),
+            variable("log")
+        );
+    }
+    #[test]
+    fn test_node_tree_properties() {
+        let html = "<div id=\"outer\"><p id=\"a\">1</p><p id=\"x\">2</p></div>".to_string();
+        let doc = HtmlParser::new(HtmlTokenizer::new(html)).construct_tree();
+        let input = "var x = document.getElementById(\"x\"); var parent = x.parentNode.id; var before = x.parentNode.childNodes.length; var first = x.parentNode.firstChild.id; var next = x.parentNode.firstChild.nextSibling.id; var last = x.parentNode.lastChild.previousSibling.id; var missing = x.nextSibling; var chained = x.foo.bar; x.parentNode.removeChild(x); var after = document.getElementById(\"outer\").childNodes.length;".to_string();
+        let mut parser = JsParser::new(JsLexer::new(input));
+        let ast = parser.parse_ast();
+        let mut runtime = JsRuntime::new(doc.clone());
+        runtime.execute(&ast);
+        let string = |s: &str| Some(RuntimeValue::StringLiteral(s.to_string()));
+        assert_eq!(string("outer"), variable(&runtime, "parent"));
+        assert_eq!(Some(RuntimeValue::Number(2)), variable(&runtime, "before"));
+        assert_eq!(string("a"), variable(&runtime, "first"));
+        assert_eq!(string("x"), variable(&runtime, "next"));
+        assert_eq!(string("a"), variable(&runtime, "last"));
+        assert_eq!(None, variable(&runtime, "missing"));
+        assert_eq!(None, variable(&runtime, "chained"));
+        assert_eq!(Some(RuntimeValue::Number(1)), variable(&runtime, "after"));
+    }
+    #[test]
+    fn test_event_dispatch_state() {
+        let html = "<p id=\"target\"></p>".to_string();
+        let doc = HtmlParser::new(HtmlTokenizer::new(html)).construct_tree();
+        let document = doc.borrow().node();
+        let input = "var t = document.getElementById(\"target\"); var count = 0; t.addEventListener(\"custom\", function(e) { count = count + 1; t.dispatchEvent(e); }); var result = t.dispatchEvent(new Event(\"custom\")); var clicks = 0; t.addEventListener(\"click\", function(e) { clicks = clicks + 1; });".to_string();
+        let mut parser = JsParser::new(JsLexer::new(input));
+        let ast = parser.parse_ast();
+        let mut runtime = JsRuntime::new(doc.clone());
+        runtime.execute(&ast);
+        // Dispatching the event again from its own listener is rejected instead of recursing.
+        assert_eq!(Some(RuntimeValue::Number(1)), variable(&runtime, "count"));
+        assert_eq!(
+            Some(RuntimeValue::StringLiteral("true".to_string())),
+            variable(&runtime, "result")
+        );
+        assert_eq!(1, runtime.events.len());
+        let target = get_element_by_id(doc.borrow().dom(), document, "target")
+            .expect("failed to get the target");
+        for _ in 0..3 {
+            let mut click = Event::new_mouse_event("click", MouseEvent::new(1, 2, 0));
+            assert!(runtime.dispatch_event(target, &mut click));
+            assert!(!click.is_dispatching());
+        }
+        assert_eq!(Some(RuntimeValue::Number(3)), variable(&runtime, "clicks"));
+        // The events that the browser created don't stay in the runtime.
+        assert_eq!(1, runtime.events.len());
+    }
+    #[test]
+    fn test_get_inner_html_and_outer_html() {
+        let html = "<div id=\"target\"><p class=\"a\">1 &lt; 2</p></div>".to_string();
+        let doc = HtmlParser::new(HtmlTokenizer::new(html)).construct_tree();
+        let document = doc.borrow().node();
+        let input = "var t = document.getElementById(\"target\"); var inner = t.innerHTML; var outer = t.outerHTML; t.innerHTML = inner + \"<p>3</p>\";".to_string();
+        let mut parser = JsParser::new(JsLexer::new(input));
+        let ast = parser.parse_ast();
+        let mut runtime = JsRuntime::new(doc.clone());
+        runtime.execute(&ast);
+        assert_eq!(
+            Some(RuntimeValue::StringLiteral(
+                "<p class=\"a\">1 &lt; 2</p>".to_string()
+            )),
+            runtime.env.borrow_mut().get_variable("inner".to_string())
+        );
+        assert_eq!(
+            Some(RuntimeValue::StringLiteral(
+                "<div id=\"target\"><p class=\"a\">1 &lt; 2</p></div>".to_string()
+            )),
+            runtime.env.borrow_mut().get_variable("outer".to_string())
+        );
+        let doc = doc.borrow();
+        let dom = doc.dom();
+        let target = get_element_by_id(dom, document, "target").expect("failed to get the target");
+        assert_eq!(
+            "<p class=\"a\">1 &lt; 2</p><p>3</p>",
+            serialize_children(dom, target)
+        );
+    }
+    fn send(request: HttpRequest) -> Result<HttpResponse, Error> {
+        let raw = match request.url().as_str() {
+            "http://example.com/data" => "HTTP/1.1 200 OK\nContent-Type: text/plain\n\nhello",
+            "http://example.com/json" => {
+                "HTTP/1.1 200 OK\nContent-Type: application/json\n\n{\"name\": \"saba\"}"
+            }
+            "http://example.com/echo" => {
+                return HttpResponse::new(format!(
+                    "HTTP/1.1 200 OK\nContent-Type: text/plain\n\n{}:{}",
+                    request.method(),
+                    request.body()
+                ))
+            }
+            "http://open.example/data" => "HTTP/1.1 200 OK\nAccess-Control-Allow-Origin: *\n\nopen",
+            "http://closed.example/data" => "HTTP/1.1 200 OK\nContent-Type: text/plain\n\nsecret",
+            _ => "HTTP/1.1 404 Not Found\nContent-Type: text/plain\n\n",
+        };
+        HttpResponse::new(raw.to_string())
+    }
+    fn run_with_fetcher(input: &str) -> JsRuntime {
+        let lexer = JsLexer::new(input.to_string());
+        let mut parser = JsParser::new(lexer);
+        let ast = parser.parse_ast();
+        let mut document = Document::new();
+        document.set_url(
+            Url::new("http://example.com/index.html".to_string())
+                .parse()
+                .expect("failed to parse url"),
+        );
+        let mut runtime = JsRuntime::new(Rc::new(RefCell::new(document)));
+        runtime.set_fetcher(Fetcher::new(send));
+        runtime.execute(&ast);
+        runtime
+    }
+    fn variable(runtime: &JsRuntime, name: &str) -> Option<RuntimeValue> {
+        runtime.env.borrow_mut().get_variable(name.to_string())
+    }
+    static TEST_TIME: AtomicU64 = AtomicU64::new(0);
+    fn test_clock() -> u64 {
+        TEST_TIME.load(Ordering::Relaxed)
+    }
+    #[test]
+    fn test_set_timeout() {
+        let input = "var log = \"\"; function later(x) { return function() { log = log + x; }; } setTimeout(later(\"b\"), 20); setTimeout(later(\"a\"), 10); var c = setTimeout(later(\"c\"), 10); clearTimeout(c); setTimeout(function() { log = log + \"d\"; setTimeout(later(\"e\"), 0); }, 30);";
+        let ast = JsParser::new(JsLexer::new(input.to_string())).parse_ast();
+        let mut runtime = JsRuntime::new(Rc::new(RefCell::new(Document::new())));
+        runtime.set_clock(test_clock);
+        runtime.execute(&ast);
+        let log = |runtime: &JsRuntime| variable(runtime, "log").map(|log| log.to_string());
+        assert!(!runtime.process_tasks());
+        TEST_TIME.store(10, Ordering::Relaxed);
+        assert!(runtime.process_tasks());
+        assert_eq!(Some("a".to_string()), log(&runtime));
+        // The callbacks run in the order of their due times, and a timer set by a callback
+        // runs at the next turn.
+        TEST_TIME.store(30, Ordering::Relaxed);
+        assert!(runtime.process_tasks());
+        assert_eq!(Some("abd".to_string()), log(&runtime));
+        assert!(runtime.process_tasks());
+        assert_eq!(Some("abde".to_string()), log(&runtime));
+        assert!(!runtime.process_tasks());
+    }
+    #[test]
+    fn test_fetch_text() {
+        let mut runtime = run_with_fetcher(
+            "var result = 0; var status = 0; fetch(\"/data\").then(function(r){status = r.status; return r.text();}).then(function(t){result = t;});",
+        );
+        // Callbacks run asynchronously on the task queue.
+        assert_eq!(Some(RuntimeValue::Number(0)), variable(&runtime, "result"));
+        assert!(runtime.process_tasks());
+        assert_eq!(
+            Some(RuntimeValue::Number(200)),
+            variable(&runtime, "status")
+        );
+        assert_eq!(
+            Some(RuntimeValue::StringLiteral("hello".to_string())),
+            variable(&runtime, "result")
+        );
+    }
+    #[test]
+    fn test_fetch_json() {
+        let mut runtime = run_with_fetcher(
+            "var name = 0; fetch(\"json\").then(function(r){return r.json();}).then(function(data){name = data.name;});",
+        );
+        assert!(runtime.process_tasks());
+        assert_eq!(
+            Some(RuntimeValue::StringLiteral("saba".to_string())),
+            variable(&runtime, "name")
+        );
+    }
+    #[test]
+    fn test_fetch_same_origin_policy() {
+        let mut runtime = run_with_fetcher(
+            "var open = 0; var error = 0; fetch(\"http://open.example/data\").then(function(r){return r.text();}).then(function(t){open = t;}); fetch(\"http://closed.example/data\").then(function(r){error = 1;}).catch(function(e){error = e;});",
+        );
+        assert!(runtime.process_tasks());
+        assert_eq!(
+            Some(RuntimeValue::StringLiteral("open".to_string())),
+            variable(&runtime, "open")
+        );
+        assert_eq!(
+            Some(RuntimeValue::StringLiteral(
+                "TypeError: Failed to fetch".to_string()
+            )),
+            variable(&runtime, "error")
+        );
+    }
+    #[test]
+    fn test_xml_http_request() {
+        let mut runtime = run_with_fetcher(
+            "var result = 0; var state = 0; var xhr = new XMLHttpRequest(); xhr.open(\"POST\", \"/echo\"); xhr.setRequestHeader(\"Content-Type\", \"text/plain\"); xhr.onload = function(e){result = xhr.responseText; state = xhr.readyState;}; xhr.send(\"ping\");",
+        );
+        assert_eq!(Some(RuntimeValue::Number(0)), variable(&runtime, "result"));
+        assert!(runtime.process_tasks());
+        assert_eq!(
+            Some(RuntimeValue::StringLiteral("POST:ping".to_string())),
+            variable(&runtime, "result")
+        );
+        assert_eq!(Some(RuntimeValue::Number(4)), variable(&runtime, "state"));
+    }
 }
@@ -1,8 +1,13 @@
+use crate::renderer::diagnostics::LineIndex;
+use crate::renderer::diagnostics::ParseError;
+use crate::renderer::diagnostics::SourceLanguage;
+use crate::renderer::diagnostics::Span;
+use crate::renderer::diagnostics::SpannedTokenizer;
 use alloc::string::String;
 use alloc::string::ToString;
 use alloc::vec::Vec;
 
-static RESERVED_WORDS: [&str; 3] = ["var", "function", "return"];
+static RESERVED_WORDS: [&str; 4] = ["var", "function", "return", "new"];
 
 /// Enum representation of JavaScript tokens.
 #[derive(Debug, PartialEq, Clone, Eq)]
@@ -18,14 +23,31 @@ pub enum Token {
 pub struct JsLexer {
     pos: usize,
     input: Vec<char>,
+    line_index: LineIndex,
+    token_start: usize,
+    errors: Vec<ParseError>,
 }
 impl JsLexer {
     pub fn new(js: String) -> Self {
+        let input: Vec<char> = js.chars().collect();
         Self {
             pos: 0,
-            input: js.chars().collect(),
+            line_index: LineIndex::new(&input),
+            token_start: 0,
+            errors: Vec::new(),
+            input,
         }
     }
+    /// Returns the parse errors found so far.
+    pub fn errors(&self) -> Vec<ParseError> {
+        self.errors.clone()
+    }
+    /// Records a parse error between `start` and the current position.
+    fn parse_error(&mut self, code: &str, start: usize) {
+        let span = self.line_index.span(start, self.pos.min(self.input.len()));
+        self.errors
+            .push(ParseError::new(SourceLanguage::JavaScript, code, span));
+    }
     fn consume_number(&mut self) -> u64 {
         let mut num = 0;
         loop {
@@ -44,6 +66,15 @@ impl JsLexer {
         return num;
     }
     fn contains(&self, keyword: &str) -> bool {
+        if self.pos + keyword.len() > self.input.len() {
+            return false;
+        }
+        // A keyword must not be followed by an identifier character, e.g. `newValue`.
+        if let Some(c) = self.input.get(self.pos + keyword.len()) {
+            if c.is_ascii_alphanumeric() || *c == '_' || *c == '$' {
+                return false;
+            }
+        }
         for i in 0..keyword.len() {
             if keyword
                 .chars()
@@ -70,7 +101,10 @@ impl JsLexer {
             if self.pos >= self.input.len() {
                 return result;
             }
-            if self.input[self.pos].is_ascii_alphanumeric() || self.input[self.pos] == '$' {
+            if self.input[self.pos].is_ascii_alphanumeric()
+                || self.input[self.pos] == '$'
+                || self.input[self.pos] == '_'
+            {
                 result.push(self.input[self.pos]);
                 self.pos += 1;
             } else {
@@ -80,9 +114,11 @@ impl JsLexer {
     }
     fn consume_string(&mut self) -> String {
         let mut result = String::new();
+        let start = self.pos;
         self.pos += 1;
         loop {
             if self.pos >= self.input.len() {
+                self.parse_error("unterminated-string-literal", start);
                 return result;
             }
             if self.input[self.pos] == '"' {
@@ -97,33 +133,46 @@ impl JsLexer {
 impl Iterator for JsLexer {
     type Item = Token;
     fn next(&mut self) -> Option<Self::Item> {
-        if self.pos >= self.input.len() {
-            return None;
-        }
-        while self.input[self.pos] == ' ' || self.input[self.pos] == '\n' {
-            self.pos += 1;
+        loop {
+            while self.pos < self.input.len()
+                && matches!(self.input[self.pos], ' ' | '\n' | '\t' | '\r')
+            {
+                self.pos += 1;
+            }
             if self.pos >= self.input.len() {
                 return None;
             }
-        }
-        if let Some(keyword) = self.check_reserved_word() {
-            self.pos += keyword.len();
-            let token = Some(Token::Keyword(keyword));
-            return token;
-        }
-        let c = self.input[self.pos];
-        let token = match c {
-            '+' | '-' | ';' | '=' | '(' | ')' | '{' | '}' | ',' | '.' => {
-                let t = Token::Punctuator(c);
-                self.pos += 1;
-                t
+            self.token_start = self.pos;
+            if let Some(keyword) = self.check_reserved_word() {
+                self.pos += keyword.len();
+                let token = Some(Token::Keyword(keyword));
+                return token;
             }
-            '0'..='9' => Token::Number(self.consume_number()),
-            'a'..='z' | 'A'..='Z' | '_' | '$' => Token::Identifier(self.consume_identifier()),
-            '"' => Token::StringLiteral(self.consume_string()),
-            _ => unimplemented!("char {:?} is not supported yet", c),
-        };
-        Some(token)
+            let c = self.input[self.pos];
+            let token = match c {
+                '+' | '-' | ';' | '=' | '(' | ')' | '{' | '}' | '[' | ']' | ',' | '.' => {
+                    let t = Token::Punctuator(c);
+                    self.pos += 1;
+                    t
+                }
+                '0'..='9' => Token::Number(self.consume_number()),
+                'a'..='z' | 'A'..='Z' | '_' | '$' => Token::Identifier(self.consume_identifier()),
+                '"' => Token::StringLiteral(self.consume_string()),
+                _ => {
+                    // An unsupported character is reported and skipped.
+                    self.pos += 1;
+                    self.parse_error("unexpected-character", self.token_start);
+                    continue;
+                }
+            };
+            return Some(token);
+        }
+    }
+}
+
+impl SpannedTokenizer for JsLexer {
+    fn span(&self) -> Span {
+        self.line_index.span(self.token_start, self.pos)
     }
 }
 
@@ -206,6 +255,24 @@ mod tests {
         assert!(lexer.peek().is_none());
     }
     #[test]
+    fn test_new_keyword() {
+        let input = "var newValue = new WebSocket(url);".to_string();
+        let lexer = JsLexer::new(input);
+        let expected = [
+            Token::Keyword("var".to_string()),
+            Token::Identifier("newValue".to_string()),
+            Token::Punctuator('='),
+            Token::Keyword("new".to_string()),
+            Token::Identifier("WebSocket".to_string()),
+            Token::Punctuator('('),
+            Token::Identifier("url".to_string()),
+            Token::Punctuator(')'),
+            Token::Punctuator(';'),
+        ]
+        .to_vec();
+        assert_eq!(expected, lexer.collect::<Vec<Token>>());
+    }
+    #[test]
     fn test_add_local_variable_and_num() {
         let input = "function foo(){var a = 42; return a;} var result = foo() + 1;".to_string();
         let mut lexer = JsLexer::new(input).peekable();
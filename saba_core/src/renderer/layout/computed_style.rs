@@ -3,10 +3,9 @@ use crate::renderer::dom::node::ElementKind;
 use crate::renderer::dom::node::Node;
 use crate::renderer::dom::node::NodeKind;
 use alloc::format;
-use alloc::rc::Rc;
 use alloc::string::String;
 use alloc::string::ToString;
-use core::cell::RefCell;
+use core::str::FromStr;
 
 /// Struct representing a CSS property.
 #[derive(Debug, Clone, PartialEq)]
@@ -16,6 +15,8 @@ pub struct ComputedStyle {
     display: Option<DisplayType>,
     font_size: Option<FontSize>,
     text_decoration: Option<TextDecoration>,
+    white_space: Option<WhiteSpace>,
+    padding_left: Option<i64>,
     height: Option<f64>,
     width: Option<f64>,
 }
@@ -27,6 +28,8 @@ impl ComputedStyle {
             display: None,
             font_size: None,
             text_decoration: None,
+            white_space: None,
+            padding_left: None,
             height: None,
             width: None,
         }
@@ -62,6 +65,17 @@ impl ComputedStyle {
         self.text_decoration
             .expect("Failed to access CSS property: text_decoration.")
     }
+    pub fn set_white_space(&mut self, white_space: WhiteSpace) {
+        self.white_space = Some(white_space);
+    }
+    pub fn white_space(&self) -> WhiteSpace {
+        self.white_space
+            .expect("Failed to access CSS property: white_space.")
+    }
+    pub fn padding_left(&self) -> i64 {
+        self.padding_left
+            .expect("Failed to access CSS property: padding_left.")
+    }
     pub fn set_height(&mut self, height: f64) {
         self.height = Some(height);
     }
@@ -71,7 +85,7 @@ impl ComputedStyle {
     pub fn set_width(&mut self, width: f64) {
         self.width = Some(width);
     }
-    pub fn defaulting(&mut self, node: &Rc<RefCell<Node>>, parent_style: Option<ComputedStyle>) {
+    pub fn defaulting(&mut self, node: &Node, parent_style: Option<ComputedStyle>) {
         if let Some(parent_style) = parent_style {
             if self.background_color.is_none() && parent_style.background_color() != Color::white()
             {
@@ -88,6 +102,9 @@ impl ComputedStyle {
             {
                 self.text_decoration = Some(parent_style.text_decoration());
             }
+            if self.white_space.is_none() && parent_style.white_space() != WhiteSpace::Normal {
+                self.white_space = Some(parent_style.white_space());
+            }
         }
         if self.background_color.is_none() {
             self.background_color = Some(Color::white());
@@ -104,6 +121,12 @@ impl ComputedStyle {
         if self.text_decoration.is_none() {
             self.text_decoration = Some(TextDecoration::default(node));
         }
+        if self.white_space.is_none() {
+            self.white_space = Some(WhiteSpace::default(node));
+        }
+        if self.padding_left.is_none() {
+            self.padding_left = Some(default_padding_left(node));
+        }
         if self.height.is_none() {
             self.height = Some(0.0);
         }
@@ -215,11 +238,11 @@ pub enum FontSize {
     XXLarge,
 }
 impl FontSize {
-    fn default(node: &Rc<RefCell<Node>>) -> Self {
-        match &node.borrow().kind() {
+    fn default(node: &Node) -> Self {
+        match &node.kind() {
             NodeKind::Element(element) => match element.kind() {
-                ElementKind::H1 => FontSize::XXLarge,
-                ElementKind::H2 => FontSize::XLarge,
+                Some(ElementKind::H1) => FontSize::XXLarge,
+                Some(ElementKind::H2 | ElementKind::H3) => FontSize::XLarge,
                 _ => FontSize::Medium,
             },
             _ => FontSize::Medium,
@@ -232,26 +255,91 @@ impl FontSize {
 pub enum DisplayType {
     Block,
     Inline,
+    ListItem,
     DisplayNone,
 }
 impl DisplayType {
-    fn default(node: &Rc<RefCell<Node>>) -> Self {
-        match &node.borrow().kind() {
+    fn default(node: &Node) -> Self {
+        match &node.kind() {
             NodeKind::Document => DisplayType::Block,
             NodeKind::Element(e) => {
-                if e.is_block_element() {
-                    DisplayType::Block
-                } else {
-                    DisplayType::Inline
+                if e.kind() == Some(ElementKind::Input)
+                    && e.get_attribute("type")
+                        .is_some_and(|t| t.eq_ignore_ascii_case("hidden"))
+                {
+                    return DisplayType::DisplayNone;
+                }
+                let kind = match e.kind() {
+                    Some(kind) => kind,
+                    None => return DisplayType::Inline,
+                };
+                match kind {
+                    // The content of these elements is never rendered. Nested browsing contexts of
+                    // `iframe` are not supported.
+                    ElementKind::Head
+                    | ElementKind::Title
+                    | ElementKind::Base
+                    | ElementKind::Meta
+                    | ElementKind::Link
+                    | ElementKind::Style
+                    | ElementKind::Script
+                    | ElementKind::Colgroup
+                    | ElementKind::Col
+                    | ElementKind::Iframe
+                    | ElementKind::Noembed
+                    | ElementKind::Noframes => DisplayType::DisplayNone,
+                    // Line breaks are laid out as empty blocks, which start a new line.
+                    ElementKind::Html
+                    | ElementKind::Body
+                    | ElementKind::Article
+                    | ElementKind::Section
+                    | ElementKind::Nav
+                    | ElementKind::Aside
+                    | ElementKind::Header
+                    | ElementKind::Footer
+                    | ElementKind::Main
+                    | ElementKind::Address
+                    | ElementKind::H1
+                    | ElementKind::H2
+                    | ElementKind::H3
+                    | ElementKind::H4
+                    | ElementKind::H5
+                    | ElementKind::H6
+                    | ElementKind::P
+                    | ElementKind::Div
+                    | ElementKind::Hr
+                    | ElementKind::Pre
+                    | ElementKind::Blockquote
+                    | ElementKind::Ul
+                    | ElementKind::Ol
+                    | ElementKind::Dl
+                    | ElementKind::Dt
+                    | ElementKind::Dd
+                    | ElementKind::Br
+                    | ElementKind::Table
+                    | ElementKind::Caption
+                    | ElementKind::Thead
+                    | ElementKind::Tbody
+                    | ElementKind::Tfoot
+                    | ElementKind::Tr
+                    | ElementKind::Form
+                    | ElementKind::Xmp => DisplayType::Block,
+                    ElementKind::Li => DisplayType::ListItem,
+                    // Table cells are laid out side by side in their row.
+                    _ => DisplayType::Inline,
                 }
             }
             NodeKind::Text(_) => DisplayType::Inline,
+            NodeKind::Comment(_) | NodeKind::DocumentType { .. } | NodeKind::DocumentFragment => {
+                DisplayType::DisplayNone
+            }
         }
     }
     pub fn from_str(s: &str) -> Result<Self, Error> {
         match s {
             "block" => Ok(Self::Block),
             "inline" => Ok(Self::Inline),
+            "list-item" => Ok(Self::ListItem),
             "none" => Ok(Self::DisplayNone),
             _ => Err(Error::UnexpectedInput(format!(
                 "Display {:?} is not supported yet.",
@@ -268,13 +356,62 @@ pub enum TextDecoration {
     Underline,
 }
 impl TextDecoration {
-    fn default(node: &Rc<RefCell<Node>>) -> Self {
-        match &node.borrow().kind() {
+    fn default(node: &Node) -> Self {
+        match &node.kind() {
             NodeKind::Element(element) => match element.kind() {
-                ElementKind::A => TextDecoration::Underline,
+                Some(ElementKind::A | ElementKind::U) => TextDecoration::Underline,
                 _ => TextDecoration::None,
             },
             _ => TextDecoration::None,
         }
     }
 }
+
+/// Enum representing a white-space property.
+/// # References:
+/// - <https://drafts.csswg.org/css-text/#white-space-property>
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum WhiteSpace {
+    /// Sequences of white space collapse into a single space and lines wrap.
+    Normal,
+    /// White space and line breaks are preserved and lines never wrap.
+    Pre,
+}
+impl WhiteSpace {
+    fn default(node: &Node) -> Self {
+        match &node.kind() {
+            NodeKind::Element(element) => match element.kind() {
+                Some(ElementKind::Pre | ElementKind::Xmp | ElementKind::Textarea) => {
+                    WhiteSpace::Pre
+                }
+                _ => WhiteSpace::Normal,
+            },
+            _ => WhiteSpace::Normal,
+        }
+    }
+}
+impl FromStr for WhiteSpace {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "normal" => Ok(Self::Normal),
+            "pre" => Ok(Self::Pre),
+            _ => Err(Error::UnexpectedInput(format!(
+                "White space {:?} is not supported yet.",
+                s
+            ))),
+        }
+    }
+}
+
+/// Returns the default left padding of a node in pixels, which indents lists and quotations.
+/// The margins of `blockquote` in the user agent style sheet are approximated by padding.
+/// # References:
+/// - <https://html.spec.whatwg.org/multipage/rendering.html#lists>
+/// - <https://html.spec.whatwg.org/multipage/rendering.html#flow-content-3>
+fn default_padding_left(node: &Node) -> i64 {
+    match node.element_kind() {
+        Some(ElementKind::Ul | ElementKind::Ol | ElementKind::Blockquote | ElementKind::Dd) => 40,
+        _ => 0,
+    }
+}
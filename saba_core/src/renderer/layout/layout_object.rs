@@ -3,40 +3,38 @@ use crate::constants::{
     CHAR_HEIGHT_WITH_PADDING, CHAR_WIDTH, CONTENT_AREA_WIDTH, WINDOW_PADDING, WINDOW_WIDTH,
 };
 use crate::display_item::DisplayItem;
-use crate::renderer::css::cssom::{ComponentValue, Declaration, Selector, StyleSheet};
-use crate::renderer::dom::node::{Node, NodeKind};
-use crate::renderer::layout::computed_style::{ComputedStyle, DisplayType, FontSize};
-use alloc::rc::Rc;
-use alloc::rc::Weak;
+use crate::renderer::arena::Arena;
+use crate::renderer::arena::Id;
+use crate::renderer::css::cssom::{ComponentValue, CssParser, Declaration, Selector, StyleSheet};
+use crate::renderer::css::token::CssTokenizer;
+use crate::renderer::dom::form::display_text;
+use crate::renderer::dom::node::{Dom, ElementKind, Node, NodeId, NodeKind};
+use crate::renderer::layout::computed_style::{ComputedStyle, DisplayType, FontSize, WhiteSpace};
+use alloc::format;
 use alloc::string::{String, ToString};
 use alloc::vec;
 use alloc::vec::Vec;
-use core::cell::RefCell;
+use core::str::FromStr;
+
+/// The layout tree, whose objects are linked by `LayoutObjectId`s.
+pub type LayoutTree = Arena<LayoutObject>;
+/// The handle of a layout object in a `LayoutTree`.
+pub type LayoutObjectId = Id<LayoutObject>;
 
 /// Struct representing a layout object.
 #[derive(Debug, Clone)]
 pub struct LayoutObject {
     kind: LayoutObjectKind,
-    node: Rc<RefCell<Node>>,
-    first_child: Option<Rc<RefCell<LayoutObject>>>,
-    next_sibling: Option<Rc<RefCell<LayoutObject>>>,
-    parent: Weak<RefCell<LayoutObject>>,
+    node: NodeId,
     style: ComputedStyle,
     point: LayoutPoint,
     size: LayoutSize,
 }
 impl LayoutObject {
-    pub fn new(node: Rc<RefCell<Node>>, parent_obj: &Option<Rc<RefCell<LayoutObject>>>) -> Self {
-        let parent = match parent_obj {
-            Some(p) => Rc::downgrade(p),
-            None => Weak::new(),
-        };
+    pub fn new(node: NodeId) -> Self {
         Self {
             kind: LayoutObjectKind::Block,
-            node: node.clone(),
-            first_child: None,
-            next_sibling: None,
-            parent,
+            node,
             style: ComputedStyle::new(),
             point: LayoutPoint::new(0, 0),
             size: LayoutSize::new(0, 0),
@@ -45,23 +43,20 @@ impl LayoutObject {
     pub fn kind(&self) -> LayoutObjectKind {
         self.kind
     }
-    pub fn node_kind(&self) -> NodeKind {
-        self.node.borrow().kind().clone()
-    }
-    pub fn set_first_child(&mut self, first_child: Option<Rc<RefCell<LayoutObject>>>) {
-        self.first_child = first_child;
-    }
-    pub fn first_child(&self) -> Option<Rc<RefCell<LayoutObject>>> {
-        self.first_child.as_ref().cloned()
-    }
-    pub fn set_next_sibling(&mut self, next_sibling: Option<Rc<RefCell<LayoutObject>>>) {
-        self.next_sibling = next_sibling;
+    pub fn node(&self) -> NodeId {
+        self.node
     }
-    pub fn next_sibling(&self) -> Option<Rc<RefCell<LayoutObject>>> {
-        self.next_sibling.as_ref().cloned()
+    pub fn node_kind(&self, dom: &Dom) -> NodeKind {
+        dom[self.node].kind()
     }
-    pub fn parent(&self) -> Weak<RefCell<Self>> {
-        self.parent.clone()
+    /// Returns the text rendered inside a form control or an image, which have no layout
+    /// children. Images are not decoded, so their alternative text is shown instead.
+    pub fn replaced_text(&self, dom: &Dom) -> Option<String> {
+        if dom[self.node].element_kind() == Some(ElementKind::Img) {
+            let element = dom[self.node].get_element()?;
+            return Some(element.get_attribute("alt").unwrap_or_default());
+        }
+        display_text(dom, self.node)
     }
     pub fn style(&self) -> ComputedStyle {
         self.style.clone()
@@ -72,33 +67,20 @@ impl LayoutObject {
     pub fn size(&self) -> LayoutSize {
         self.size
     }
-    pub fn is_node_selected(&self, selector: &Selector) -> bool {
-        match &self.node_kind() {
-            NodeKind::Element(e) => match selector {
-                Selector::TypeSelector(type_name) => {
-                    if e.kind().to_string() == *type_name {
-                        return true;
-                    }
-                    false
-                }
-                Selector::ClassSelector(class_name) => {
-                    for attr in &e.attributes() {
-                        if attr.name() == "class" && attr.value() == *class_name {
-                            return true;
-                        }
-                    }
-                    false
-                }
-                Selector::IdSelector(id_name) => {
-                    for attr in &e.attributes() {
-                        if attr.name() == "id" && attr.value() == *id_name {
-                            return true;
-                        }
-                    }
-                    false
-                }
-                Selector::UnknownSelector => false,
-            },
+    /// Returns the position of the content box, where the children are laid out.
+    pub fn content_point(&self) -> LayoutPoint {
+        LayoutPoint::new(self.point.x() + self.style.padding_left(), self.point.y())
+    }
+    /// Returns the size of the content box, where the children are laid out.
+    pub fn content_size(&self) -> LayoutSize {
+        LayoutSize::new(
+            self.size.width() - self.style.padding_left(),
+            self.size.height(),
+        )
+    }
+    pub fn is_node_selected(&self, dom: &Dom, selector: &Selector) -> bool {
+        match dom[self.node].get_element() {
+            Some(e) => selector.matches(e),
             _ => false,
         }
     }
@@ -150,24 +132,29 @@ impl LayoutObject {
                         self.style.set_display(display_type);
                     }
                 }
+                "white-space" => {
+                    if let ComponentValue::Ident(value) = &declaration.value {
+                        if let Ok(white_space) = WhiteSpace::from_str(value) {
+                            self.style.set_white_space(white_space);
+                        }
+                    }
+                }
                 _ => {}
             }
         }
     }
-    pub fn defaulting_style(
-        &mut self,
-        node: &Rc<RefCell<Node>>,
-        parent_style: Option<ComputedStyle>,
-    ) {
+    pub fn defaulting_style(&mut self, node: &Node, parent_style: Option<ComputedStyle>) {
         self.style.defaulting(node, parent_style);
     }
-    pub fn update_kind(&mut self) {
-        match self.node_kind() {
+    pub fn update_kind(&mut self, dom: &Dom) {
+        match self.node_kind(dom) {
             NodeKind::Document => panic!("should not create a layout object for a Document"),
             NodeKind::Element(_) => {
                 let display = self.style.display();
                 match display {
-                    DisplayType::Block => self.kind = LayoutObjectKind::Block,
+                    DisplayType::Block | DisplayType::ListItem => {
+                        self.kind = LayoutObjectKind::Block
+                    }
                     DisplayType::Inline => self.kind = LayoutObjectKind::Inline,
                     DisplayType::DisplayNone => {
                         panic!("should not create a layout object for display:none")
@@ -175,54 +162,73 @@ impl LayoutObject {
                 }
             }
             NodeKind::Text(_) => self.kind = LayoutObjectKind::Text,
+            NodeKind::Comment(_) | NodeKind::DocumentType { .. } | NodeKind::DocumentFragment => {
+                panic!("should not create a layout object for a Comment or DocumentType")
+            }
         }
     }
-    pub fn compute_size(&mut self, parent_size: LayoutSize) {
+    /// Computes the size of this object from the kinds and sizes of its children.
+    /// # Parameters
+    /// - `dom`: The DOM tree of the node.
+    /// - `parent_size`: The size of the content box of the parent.
+    /// - `children`: The kinds and sizes of the children in order.
+    pub fn compute_size(
+        &mut self,
+        dom: &Dom,
+        parent_size: LayoutSize,
+        children: &[(LayoutObjectKind, LayoutSize)],
+    ) {
         let mut size = LayoutSize::new(0, 0);
         match self.kind() {
             LayoutObjectKind::Block => {
                 size.set_width(parent_size.width());
                 let mut height = 0;
-                let mut child = self.first_child();
                 let mut previous_child_kind = LayoutObjectKind::Block;
-                while child.is_some() {
-                    let c = match child {
-                        Some(c) => c,
-                        None => panic!("first child should exist"),
-                    };
+                for (kind, child_size) in children {
                     if previous_child_kind == LayoutObjectKind::Block
-                        || c.borrow().kind() == LayoutObjectKind::Block
+                        || *kind == LayoutObjectKind::Block
                     {
-                        height += c.borrow().size.height();
+                        height += child_size.height();
                     }
-                    previous_child_kind = c.borrow().kind();
-                    child = c.borrow().next_sibling();
+                    previous_child_kind = *kind;
+                }
+                // A thematic break takes a line, where a rule is painted.
+                if dom[self.node].element_kind() == Some(ElementKind::Hr) {
+                    height = CHAR_HEIGHT_WITH_PADDING;
                 }
                 size.set_height(height);
             }
             LayoutObjectKind::Inline => {
+                if let Some(text) = self.replaced_text(dom) {
+                    size.set_width(CHAR_WIDTH * (text.chars().count() as i64 + 2));
+                    size.set_height(CHAR_HEIGHT_WITH_PADDING);
+                    self.size = size;
+                    return;
+                }
                 let mut width = 0;
                 let mut height = 0;
-                let mut child = self.first_child();
-                while child.is_some() {
-                    let c = match child {
-                        Some(c) => c,
-                        None => panic!("first child should exist"),
-                    };
-                    width += c.borrow().size.width();
-                    height += c.borrow().size.height();
-                    child = c.borrow().next_sibling();
+                for (_, child_size) in children {
+                    width += child_size.width();
+                    height += child_size.height();
                 }
                 size.set_width(width);
                 size.set_height(height);
             }
             LayoutObjectKind::Text => {
-                if let NodeKind::Text(t) = self.node_kind() {
+                if let NodeKind::Text(t) = self.node_kind(dom) {
                     let ratio = match self.style.font_size() {
                         FontSize::Medium => 1,
                         FontSize::XLarge => 2,
                         FontSize::XXLarge => 3,
                     };
+                    if self.style.white_space() == WhiteSpace::Pre {
+                        let lines = preformatted_lines(&t);
+                        let columns = lines.iter().map(|l| l.chars().count()).max();
+                        size.set_width(CHAR_WIDTH * ratio * columns.unwrap_or(0) as i64);
+                        size.set_height(CHAR_HEIGHT_WITH_PADDING * ratio * lines.len() as i64);
+                        self.size = size;
+                        return;
+                    }
                     let width = CHAR_WIDTH * ratio * t.len() as i64;
                     if width > CONTENT_AREA_WIDTH {
                         size.set_width(CONTENT_AREA_WIDTH);
@@ -274,29 +280,89 @@ impl LayoutObject {
         }
         self.point = point;
     }
-    pub fn paint(&mut self) -> Vec<DisplayItem> {
+    pub fn paint(&self, dom: &Dom) -> Vec<DisplayItem> {
         if self.style.display() == DisplayType::DisplayNone {
             return vec![];
         }
         match self.kind {
             LayoutObjectKind::Block => {
-                if let NodeKind::Element(_e) = self.node_kind() {
-                    return vec![DisplayItem::Rect {
+                if let NodeKind::Element(_e) = self.node_kind(dom) {
+                    let mut v = vec![DisplayItem::Rect {
                         style: self.style(),
                         layout_point: self.point(),
                         layout_size: self.size(),
                     }];
+                    if dom[self.node].element_kind() == Some(ElementKind::Hr) {
+                        let mut style = self.style();
+                        style.set_background_color(
+                            Color::from_name("gray").unwrap_or(Color::black()),
+                        );
+                        v.push(DisplayItem::Rect {
+                            style,
+                            layout_point: LayoutPoint::new(
+                                self.point().x(),
+                                self.point().y() + self.size().height() / 2,
+                            ),
+                            layout_size: LayoutSize::new(self.size().width(), 2),
+                        });
+                    }
+                    if self.style.display() == DisplayType::ListItem {
+                        // The marker is painted in the padding of the list, left of the item.
+                        let marker = list_marker(dom, self.node);
+                        let x = self.point().x() - CHAR_WIDTH * (marker.len() as i64 + 1);
+                        v.push(DisplayItem::Text {
+                            text: marker,
+                            style: self.style(),
+                            layout_point: LayoutPoint::new(x.max(0), self.point().y()),
+                        });
+                    }
+                    return v;
+                }
+            }
+            LayoutObjectKind::Inline => {
+                if let Some(text) = self.replaced_text(dom) {
+                    let mut style = self.style();
+                    style.set_background_color(
+                        Color::from_name("lightgray").unwrap_or(Color::white()),
+                    );
+                    return vec![
+                        DisplayItem::Rect {
+                            style: style.clone(),
+                            layout_point: self.point(),
+                            layout_size: self.size(),
+                        },
+                        DisplayItem::Text {
+                            text,
+                            style,
+                            layout_point: LayoutPoint::new(
+                                self.point().x() + CHAR_WIDTH,
+                                self.point().y(),
+                            ),
+                        },
+                    ];
                 }
             }
-            LayoutObjectKind::Inline => {}
             LayoutObjectKind::Text => {
-                if let NodeKind::Text(t) = self.node_kind() {
+                if let NodeKind::Text(t) = self.node_kind(dom) {
                     let mut v = vec![];
                     let ratio = match self.style.font_size() {
                         FontSize::Medium => 1,
                         FontSize::XLarge => 2,
                         FontSize::XXLarge => 3,
                     };
+                    if self.style.white_space() == WhiteSpace::Pre {
+                        for (i, line) in preformatted_lines(&t).into_iter().enumerate() {
+                            v.push(DisplayItem::Text {
+                                text: line.to_string(),
+                                style: self.style(),
+                                layout_point: LayoutPoint::new(
+                                    self.point().x(),
+                                    self.point().y() + CHAR_HEIGHT_WITH_PADDING * ratio * i as i64,
+                                ),
+                            });
+                        }
+                        return v;
+                    }
                     let plain_text = t
                         .replace("\n", " ")
                         .split(' ')
@@ -388,38 +454,83 @@ impl LayoutSize {
 
 /// Create a LayoutObject
 /// # Parameters
+/// - `dom`: The DOM tree.
 /// - `node`: The node of the layout tree.
-/// - `parent_obj`: The parent LayoutObject.
+/// - `parent_style`: The computed style of the parent LayoutObject.
 /// - `cssom`: The CSSOM.
 /// # Returns
 /// - `LayoutObject`: A new LayoutObject, or `None` if the display property is 'none'.
 pub fn create_layout_object(
-    node: &Option<Rc<RefCell<Node>>>,
-    parent_obj: &Option<Rc<RefCell<LayoutObject>>>,
+    dom: &Dom,
+    node: NodeId,
+    parent_style: Option<ComputedStyle>,
     cssom: &StyleSheet,
-) -> Option<Rc<RefCell<LayoutObject>>> {
-    if let Some(n) = node {
-        let layout_object = Rc::new(RefCell::new(LayoutObject::new(n.clone(), parent_obj)));
-        for rule in &cssom.rules {
-            if layout_object.borrow().is_node_selected(&rule.selector) {
-                layout_object
-                    .borrow_mut()
-                    .cascading_style(rule.declarations.clone());
-            }
+) -> Option<LayoutObject> {
+    let mut layout_object = LayoutObject::new(node);
+    for rule in &cssom.rules {
+        if layout_object.is_node_selected(dom, &rule.selector) {
+            layout_object.cascading_style(rule.declarations.clone());
         }
-        let parent_style = if let Some(parent) = parent_obj {
-            Some(parent.borrow().style())
-        } else {
-            None
-        };
-        layout_object.borrow_mut().defaulting_style(n, parent_style);
-        if layout_object.borrow().style().display() == DisplayType::DisplayNone {
-            return None;
+    }
+    // The declarations of the style attribute come after the style sheets in the cascade.
+    let inline_style = dom[node]
+        .get_element()
+        .and_then(|e| e.get_attribute("style"));
+    if let Some(style) = inline_style {
+        let declarations = CssParser::new(CssTokenizer::new(style)).parse_declaration_list();
+        layout_object.cascading_style(declarations);
+    }
+    layout_object.defaulting_style(&dom[node], parent_style);
+    if layout_object.style().display() == DisplayType::DisplayNone {
+        return None;
+    }
+    // Whitespace between elements, such as the indentation of the source, is not rendered.
+    let collapsible = match dom[node].kind {
+        NodeKind::Text(ref t) => t.chars().all(|c| c.is_ascii_whitespace()),
+        _ => false,
+    };
+    if collapsible && layout_object.style().white_space() == WhiteSpace::Normal {
+        return None;
+    }
+    layout_object.update_kind(dom);
+    Some(layout_object)
+}
+
+/// Returns the lines of preformatted text. A line break at the end does not start a new line.
+fn preformatted_lines(text: &str) -> Vec<&str> {
+    text.strip_suffix('\n')
+        .unwrap_or(text)
+        .split('\n')
+        .collect()
+}
+
+/// Returns the marker of a list item, which is its ordinal in an `ol` and a bullet otherwise.
+/// # Parameters
+/// - `dom`: The DOM tree.
+/// - `node`: The list item.
+/// # References:
+/// - <https://html.spec.whatwg.org/multipage/grouping-content.html#ordinal-value>
+fn list_marker(dom: &Dom, node: NodeId) -> String {
+    let parent = match dom.parent(node) {
+        Some(p) => p,
+        None => return "*".to_string(),
+    };
+    let list = match dom[parent].get_element() {
+        Some(e) if e.kind() == Some(ElementKind::Ol) => e,
+        _ => return "*".to_string(),
+    };
+    let mut ordinal = list
+        .get_attribute("start")
+        .and_then(|s| s.trim().parse::<i64>().ok())
+        .unwrap_or(1);
+    let mut sibling = dom.previous_sibling(node);
+    while let Some(s) = sibling {
+        if dom[s].element_kind() == Some(ElementKind::Li) {
+            ordinal += 1;
         }
-        layout_object.borrow_mut().update_kind();
-        return Some(layout_object);
+        sibling = dom.previous_sibling(s);
     }
-    None
+    format!("{}.", ordinal)
 }
 
 /// Find the word wrap point.
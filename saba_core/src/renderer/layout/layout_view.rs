@@ -1,208 +1,157 @@
+use super::layout_object::LayoutObjectId;
 use super::layout_object::LayoutObjectKind;
 use super::layout_object::LayoutPoint;
 use super::layout_object::LayoutSize;
+use super::layout_object::LayoutTree;
 use crate::constants::CONTENT_AREA_WIDTH;
 use crate::display_item::DisplayItem;
 use crate::renderer::css::cssom::StyleSheet;
 use crate::renderer::dom::api::get_target_element_node;
+use crate::renderer::dom::node::Dom;
 use crate::renderer::dom::node::ElementKind;
-use crate::renderer::dom::node::Node;
+use crate::renderer::dom::node::NodeId;
 use crate::renderer::layout::layout_object::create_layout_object;
-use crate::renderer::layout::layout_object::LayoutObject;
-use alloc::rc::Rc;
+use alloc::vec;
 use alloc::vec::Vec;
-use core::cell::RefCell;
 
 /// Struct to represent a layout tree.
 #[derive(Debug, Clone)]
 pub struct LayoutView {
-    root: Option<Rc<RefCell<LayoutObject>>>,
+    tree: LayoutTree,
+    root: Option<LayoutObjectId>,
 }
 impl LayoutView {
-    pub fn new(root: Rc<RefCell<Node>>, cssom: &StyleSheet) -> Self {
-        let body_root = get_target_element_node(Some(root), ElementKind::Body);
-        let mut tree = Self {
-            root: build_layout_tree(&body_root, &None, cssom),
-        };
-        tree.update_layout();
-        tree
+    pub fn new(dom: &Dom, document: NodeId, cssom: &StyleSheet) -> Self {
+        let mut tree = LayoutTree::new();
+        let root = get_target_element_node(dom, document, ElementKind::Body)
+            .and_then(|body| build_layout_tree(dom, body, &mut tree, cssom));
+        let mut view = Self { tree, root };
+        view.update_layout(dom);
+        view
     }
-    pub fn root(&self) -> Option<Rc<RefCell<LayoutObject>>> {
-        self.root.clone()
+    pub fn root(&self) -> Option<LayoutObjectId> {
+        self.root
     }
-    fn update_layout(&mut self) {
-        Self::calculate_node_size(&self.root, LayoutSize::new(CONTENT_AREA_WIDTH, 0));
-        Self::calculate_node_position(
-            &self.root,
-            LayoutPoint::new(0, 0),
-            LayoutObjectKind::Block,
-            None,
-            None,
-        );
+    pub fn tree(&self) -> &LayoutTree {
+        &self.tree
     }
-    fn calculate_node_size(node: &Option<Rc<RefCell<LayoutObject>>>, parent_size: LayoutSize) {
-        if let Some(n) = node {
-            if n.borrow().kind() == LayoutObjectKind::Block {
-                n.borrow_mut().compute_size(parent_size);
-            }
-            let first_child = n.borrow().first_child();
-            Self::calculate_node_size(&first_child, n.borrow().size());
-            let next_sibling = n.borrow().next_sibling();
-            Self::calculate_node_size(&next_sibling, parent_size);
-            n.borrow_mut().compute_size(parent_size);
+    fn update_layout(&mut self, dom: &Dom) {
+        if let Some(root) = self.root {
+            self.calculate_node_size(dom, root);
+            self.calculate_node_position(root);
+        }
+    }
+    /// Returns the size of the content box of the parent of `id`, or of the content area for
+    /// the root.
+    fn parent_content_size(&self, id: LayoutObjectId) -> LayoutSize {
+        match self.tree.parent(id) {
+            Some(parent) => self.tree[parent].content_size(),
+            None => LayoutSize::new(CONTENT_AREA_WIDTH, 0),
+        }
+    }
+    fn calculate_node_size(&mut self, dom: &Dom, root: LayoutObjectId) {
+        // A block takes the width of its parent before its children are laid out in it.
+        let blocks: Vec<LayoutObjectId> = self
+            .tree
+            .inclusive_descendants(root)
+            .filter(|id| self.tree[*id].kind() == LayoutObjectKind::Block)
+            .collect();
+        for id in blocks {
+            let parent_size = self.parent_content_size(id);
+            self.tree[id].compute_size(dom, parent_size, &[]);
+        }
+        // Then the heights are computed from the leaves up.
+        let objects: Vec<LayoutObjectId> = self.tree.post_order(root).collect();
+        for id in objects {
+            let parent_size = self.parent_content_size(id);
+            let children: Vec<(LayoutObjectKind, LayoutSize)> = self
+                .tree
+                .children(id)
+                .map(|c| (self.tree[c].kind(), self.tree[c].size()))
+                .collect();
+            self.tree[id].compute_size(dom, parent_size, &children);
         }
     }
-    fn calculate_node_position(
-        node: &Option<Rc<RefCell<LayoutObject>>>,
-        parent_point: LayoutPoint,
-        previous_sibling_kind: LayoutObjectKind,
-        previous_sibling_position: Option<LayoutPoint>,
-        previous_sibling_size: Option<LayoutSize>,
-    ) {
-        if let Some(n) = node {
-            n.borrow_mut().compute_position(
+    fn calculate_node_position(&mut self, root: LayoutObjectId) {
+        let objects: Vec<LayoutObjectId> = self.tree.inclusive_descendants(root).collect();
+        for id in objects {
+            let parent_point = match self.tree.parent(id) {
+                Some(parent) => self.tree[parent].content_point(),
+                None => LayoutPoint::new(0, 0),
+            };
+            let (previous_sibling_kind, previous_sibling_point, previous_sibling_size) =
+                match self.tree.previous_sibling(id) {
+                    Some(s) => (
+                        self.tree[s].kind(),
+                        Some(self.tree[s].point()),
+                        Some(self.tree[s].size()),
+                    ),
+                    None => (LayoutObjectKind::Block, None, None),
+                };
+            self.tree[id].compute_position(
                 parent_point,
                 previous_sibling_kind,
-                previous_sibling_position,
+                previous_sibling_point,
                 previous_sibling_size,
             );
-            let first_child = n.borrow().first_child();
-            Self::calculate_node_position(
-                &first_child,
-                n.borrow().point(),
-                LayoutObjectKind::Block,
-                None,
-                None,
-            );
-            let next_sibling = n.borrow().next_sibling();
-            Self::calculate_node_position(
-                &next_sibling,
-                parent_point,
-                n.borrow().kind(),
-                Some(n.borrow().point()),
-                Some(n.borrow().size()),
-            );
         }
     }
-    fn paint_node(node: &Option<Rc<RefCell<LayoutObject>>>, display_items: &mut Vec<DisplayItem>) {
-        match node {
-            Some(n) => {
-                display_items.extend(n.borrow_mut().paint());
-                let first_child = n.borrow_mut().first_child();
-                Self::paint_node(&first_child, display_items);
-                let next_sibling = n.borrow().next_sibling();
-                Self::paint_node(&next_sibling, display_items);
+    pub fn paint(&self, dom: &Dom) -> Vec<DisplayItem> {
+        let mut display_items = Vec::new();
+        if let Some(root) = self.root {
+            for id in self.tree.inclusive_descendants(root) {
+                display_items.extend(self.tree[id].paint(dom));
             }
-            None => (),
         }
-    }
-    pub fn paint(&self) -> Vec<DisplayItem> {
-        let mut display_items = Vec::new();
-        Self::paint_node(&self.root, &mut display_items);
         display_items
     }
-    pub fn find_node_by_position(&self, position: (i64, i64)) -> Option<Rc<RefCell<LayoutObject>>> {
-        Self::find_node_by_position_internal(&self.root(), position)
-    }
-    fn find_node_by_position_internal(
-        node: &Option<Rc<RefCell<LayoutObject>>>,
-        position: (i64, i64),
-    ) -> Option<Rc<RefCell<LayoutObject>>> {
-        match node {
-            Some(n) => {
-                let first_child = n.borrow().first_child();
-                let result1 = Self::find_node_by_position_internal(&first_child, position);
-                if result1.is_some() {
-                    return result1;
-                }
-                let next_sibling = n.borrow().next_sibling();
-                let result2 = Self::find_node_by_position_internal(&next_sibling, position);
-                if result2.is_some() {
-                    return result2;
-                }
-                if n.borrow().point().x() <= position.0
-                    && position.0 <= (n.borrow().point().x() + n.borrow().size().width())
-                    && n.borrow().point().y() <= position.1
-                    && position.1 <= (n.borrow().point().y() + n.borrow().size().height())
-                {
-                    return Some(n.clone());
-                }
-                None
-            }
-            None => None,
-        }
+    /// Returns the innermost layout object at `position`.
+    pub fn find_node_by_position(&self, position: (i64, i64)) -> Option<LayoutObjectId> {
+        self.tree.post_order(self.root?).find(|id| {
+            let point = self.tree[*id].point();
+            let size = self.tree[*id].size();
+            point.x() <= position.0
+                && position.0 <= point.x() + size.width()
+                && point.y() <= position.1
+                && position.1 <= point.y() + size.height()
+        })
     }
 }
 
-/// Recursively Build a layout tree.
+/// Build a layout tree for the subtree of a DOM node.
 /// # Parameters
-/// - `node`: The node of the DOM tree.
-/// - `parent_obj`: The parent layout object.
+/// - `dom`: The DOM tree.
+/// - `root`: The root of the subtree.
+/// - `tree`: The layout tree where the layout objects are allocated.
 /// - `cssom`: The CSSOM.
 /// # Returns
-/// - The layout object of the tree.
+/// - The layout object of `root`, or `None` if it isn't rendered.
 fn build_layout_tree(
-    node: &Option<Rc<RefCell<Node>>>,
-    parent_obj: &Option<Rc<RefCell<LayoutObject>>>,
+    dom: &Dom,
+    root: NodeId,
+    tree: &mut LayoutTree,
     cssom: &StyleSheet,
-) -> Option<Rc<RefCell<LayoutObject>>> {
-    let mut target_node = node.clone();
-    let mut layout_object = create_layout_object(node, parent_obj, cssom);
-    while layout_object.is_none() {
-        if let Some(n) = target_node {
-            target_node = n.borrow().next_sibling().clone();
-            layout_object = create_layout_object(&target_node, parent_obj, cssom);
-        } else {
-            return layout_object;
+) -> Option<LayoutObjectId> {
+    let root_object = tree.alloc(create_layout_object(dom, root, None, cssom)?);
+    // The nodes whose children are still to be laid out, with their layout objects.
+    let mut stack = vec![(root, root_object)];
+    while let Some((node, object)) = stack.pop() {
+        // Form controls and images render their own content, so their DOM children are not laid out.
+        if tree[object].replaced_text(dom).is_some() {
+            continue;
         }
-    }
-    if let Some(n) = target_node {
-        let original_first_child = n.borrow().first_child();
-        let original_next_sibling = n.borrow().next_sibling();
-        let mut first_child = build_layout_tree(&original_first_child, &layout_object, cssom);
-        let mut next_sibling = build_layout_tree(&original_next_sibling, &None, cssom);
-        if first_child.is_none() && original_first_child.is_some() {
-            let mut original_dom_node = original_first_child
-                .expect("First child should exist.")
-                .borrow()
-                .next_sibling();
-            loop {
-                first_child = build_layout_tree(&original_dom_node, &layout_object, cssom);
-                if first_child.is_none() && original_dom_node.is_some() {
-                    original_dom_node = original_dom_node
-                        .expect("Next sibling should exist.")
-                        .borrow()
-                        .next_sibling();
-                    continue;
-                }
-                break;
+        let parent_style = tree[object].style();
+        for child in dom.children(node) {
+            if let Some(child_object) =
+                create_layout_object(dom, child, Some(parent_style.clone()), cssom)
+            {
+                let child_object = tree.alloc(child_object);
+                tree.append(object, child_object);
+                stack.push((child, child_object));
             }
         }
-        if next_sibling.is_none() && n.borrow().next_sibling().is_some() {
-            let mut original_dom_node = original_next_sibling
-                .expect("Next sibling should exist.")
-                .borrow()
-                .next_sibling();
-            loop {
-                next_sibling = build_layout_tree(&original_dom_node, &None, cssom);
-                if next_sibling.is_none() && original_dom_node.is_some() {
-                    original_dom_node = original_dom_node
-                        .expect("Next sibling should exist.")
-                        .borrow()
-                        .next_sibling();
-                    continue;
-                }
-                break;
-            }
-        }
-        let obj = match layout_object {
-            Some(ref obj) => obj,
-            None => panic!("Render object should exist here."),
-        };
-        obj.borrow_mut().set_first_child(first_child);
-        obj.borrow_mut().set_next_sibling(next_sibling);
     }
-    layout_object
+    Some(root_object)
 }
 
 #[cfg(test)]
@@ -211,83 +160,73 @@ mod tests {
     use crate::alloc::string::ToString;
     use crate::renderer::css::cssom::CssParser;
     use crate::renderer::css::token::CssTokenizer;
-    use crate::renderer::dom::api::get_style_content;
+    use crate::renderer::dom::api::get_style_contents;
     use crate::renderer::dom::node::{Element, NodeKind};
     use crate::renderer::html::parser::HtmlParser;
     use crate::renderer::html::token::HtmlTokenizer;
+    use crate::renderer::layout::computed_style::Color;
     use alloc::string::String;
     use alloc::vec::Vec;
-    fn create_layout_view(html: String) -> LayoutView {
+    fn create_layout_view(html: String) -> (Dom, LayoutView) {
         let t = HtmlTokenizer::new(html);
-        let window = HtmlParser::new(t).construct_tree();
-        let dom = window.borrow().document();
-        let style = get_style_content(dom.clone());
-        let css_tokenizer = CssTokenizer::new(style);
-        let cssom = CssParser::new(css_tokenizer).parse_stylesheet();
-        LayoutView::new(dom, &cssom)
+        let document = HtmlParser::new(t).construct_tree();
+        let dom = document.borrow().dom().clone();
+        let document = document.borrow().node();
+        let mut cssom = StyleSheet::new();
+        for style in get_style_contents(&dom, document) {
+            let css_tokenizer = CssTokenizer::new(style);
+            cssom.append(CssParser::new(css_tokenizer).parse_stylesheet());
+        }
+        let layout_view = LayoutView::new(&dom, document, &cssom);
+        (dom, layout_view)
     }
     #[test]
     fn test_empty() {
-        let layout_view = create_layout_view("".to_string());
-        assert_eq!(None, layout_view.root());
+        // The parser creates an empty body at the end of the input.
+        let (dom, layout_view) = create_layout_view("".to_string());
+        let tree = layout_view.tree();
+        let root = layout_view.root().expect("failed to get a root");
+        assert_eq!(
+            Some(ElementKind::Body),
+            dom[tree[root].node()].element_kind()
+        );
+        assert!(tree.first_child(root).is_none());
     }
     #[test]
     fn test_body() {
         let html = "<html><head><body></body></head></html>".to_string();
-        let layout_view = create_layout_view(html);
-        let root = layout_view.root;
-        assert!(root.is_some());
-        assert_eq!(
-            LayoutObjectKind::Block,
-            root.clone().expect("root should exist").borrow().kind()
-        );
+        let (dom, layout_view) = create_layout_view(html);
+        let tree = layout_view.tree();
+        let root = layout_view.root().expect("root should exist");
+        assert_eq!(LayoutObjectKind::Block, tree[root].kind());
         assert_eq!(
             NodeKind::Element(Element::new("body", Vec::new())),
-            root.clone()
-                .expect("root should exist")
-                .borrow()
-                .node_kind()
+            tree[root].node_kind(&dom)
         );
     }
     #[test]
     fn test_text() {
         let html = "<html><head><body>text</body></head></html>".to_string();
-        let layout_view = create_layout_view(html);
-        let root = layout_view.root;
-        assert!(root.is_some());
-        assert_eq!(
-            LayoutObjectKind::Block,
-            root.clone().expect("root should exist").borrow().kind()
-        );
+        let (dom, layout_view) = create_layout_view(html);
+        let tree = layout_view.tree();
+        let root = layout_view.root().expect("root should exist");
+        assert_eq!(LayoutObjectKind::Block, tree[root].kind());
         assert_eq!(
             NodeKind::Element(Element::new("body", Vec::new())),
-            root.clone()
-                .expect("root should exist")
-                .borrow()
-                .node_kind()
-        );
-        let text = root.expect("root should exist").borrow().first_child();
-        assert!(text.is_some());
-        assert_eq!(
-            LayoutObjectKind::Text,
-            text.clone()
-                .expect("text node should exist")
-                .borrow()
-                .kind()
+            tree[root].node_kind(&dom)
         );
+        let text = tree.first_child(root).expect("text node should exist");
+        assert_eq!(LayoutObjectKind::Text, tree[text].kind());
         assert_eq!(
             NodeKind::Text("text".to_string()),
-            text.clone()
-                .expect("text node should exist")
-                .borrow()
-                .node_kind()
+            tree[text].node_kind(&dom)
         );
     }
     #[test]
     fn test_display_none() {
         let html = "<html><head><style>body{display:none;}</style></head><body>text</body></html>"
             .to_string();
-        let layout_view = create_layout_view(html);
+        let (_, layout_view) = create_layout_view(html);
         assert_eq!(None, layout_view.root());
     }
     #[test]
@@ -295,33 +234,99 @@ mod tests {
         let html =
             r#"<html><head><style>.hidden{display:none;}</style></head><body><a class="hidden">link1</a><p></p><p class="hidden"><a>link2</a></p></body></html>"#
                 .to_string();
-        let layout_view = create_layout_view(html);
-        let root = layout_view.root();
-        assert!(root.is_some());
+        let (dom, layout_view) = create_layout_view(html);
+        let tree = layout_view.tree();
+        let root = layout_view.root().expect("root should exist");
         assert_eq!(
             NodeKind::Element(Element::new("body", Vec::new())),
-            root.clone()
-                .expect("root should exist")
-                .borrow()
-                .node_kind()
+            tree[root].node_kind(&dom)
+        );
+        let p = tree.first_child(root).expect("p node should exist");
+        assert_eq!(LayoutObjectKind::Block, tree[p].kind());
+        assert!(tree.first_child(p).is_none());
+        assert!(tree.next_sibling(p).is_none());
+    }
+    #[test]
+    fn test_unknown_elements() {
+        let html = "<html><head><style>span{display:block;}</style></head><body><div><span>a</span></div></body></html>".to_string();
+        let (dom, layout_view) = create_layout_view(html);
+        let tree = layout_view.tree();
+        let root = layout_view.root().expect("root should exist");
+        let div = tree.first_child(root).expect("div should exist");
+        assert_eq!(
+            NodeKind::Element(Element::new("div", Vec::new())),
+            tree[div].node_kind(&dom)
         );
-        let p = root.expect("root should exist").borrow().first_child();
-        assert!(p.is_some());
+        let span = tree.first_child(div).expect("span should exist");
+        assert_eq!(LayoutObjectKind::Block, tree[span].kind());
         assert_eq!(
-            LayoutObjectKind::Block,
-            p.clone().expect("p node should exist").borrow().kind()
+            NodeKind::Element(Element::new("span", Vec::new())),
+            tree[span].node_kind(&dom)
         );
-        assert!(p
-            .clone()
-            .expect("p node should exist")
-            .borrow()
-            .first_child()
-            .is_none());
-        assert!(p
-            .clone()
-            .expect("p node should exist")
-            .borrow()
-            .next_sibling()
-            .is_none());
+    }
+    #[test]
+    fn test_default_rendering() {
+        let html = "<ol start=3><li>a</li><li>b</li></ol><pre>x\n  y\n</pre><p>c<br>d</p><img alt=pic><script>e</script>".to_string();
+        let (dom, layout_view) = create_layout_view(html);
+        let texts: Vec<(String, i64, i64)> = layout_view
+            .paint(&dom)
+            .into_iter()
+            .filter_map(|item| match item {
+                DisplayItem::Text {
+                    text, layout_point, ..
+                } => Some((text, layout_point.x(), layout_point.y())),
+                _ => None,
+            })
+            .collect();
+        let position = |t: &str| {
+            texts
+                .iter()
+                .find(|(text, _, _)| text == t)
+                .map(|(_, x, y)| (*x, *y))
+                .expect(t)
+        };
+        assert_eq!((40, 0), position("a"));
+        assert_eq!((16, 0), position("3."));
+        assert_eq!((16, 20), position("4."));
+        assert_eq!((0, 40), position("x"));
+        assert_eq!((0, 60), position("  y"));
+        assert_eq!((0, 80), position("c"));
+        assert_eq!((0, 100), position("d"));
+        assert_eq!((8, 120), position("pic"));
+        assert!(texts.iter().all(|(text, _, _)| text != "e"));
+    }
+    #[test]
+    fn test_multiple_style_sheets_and_inline_style() {
+        let html = r#"<style>p{color:red;}</style><p class="x" style="color:green">a</p><p class="x">b</p><style type="text/less">p{display:none;}</style><style>.x{background-color:blue;}</style><span style="display:none">c</span>"#.to_string();
+        let (_, layout_view) = create_layout_view(html);
+        let tree = layout_view.tree();
+        let root = layout_view.root().expect("root should exist");
+        let first = tree.first_child(root).expect("first p should exist");
+        let second = tree.next_sibling(first).expect("second p should exist");
+        assert!(tree.next_sibling(second).is_none());
+        let style = tree[first].style();
+        assert_eq!(Color::from_name("green").ok(), Some(style.color()));
+        assert_eq!(
+            Color::from_name("blue").ok(),
+            Some(style.background_color())
+        );
+        let style = tree[second].style();
+        assert_eq!(Color::from_name("red").ok(), Some(style.color()));
+        assert_eq!(
+            Color::from_name("blue").ok(),
+            Some(style.background_color())
+        );
+    }
+    #[test]
+    fn test_deep_tree() {
+        // Layout doesn't recurse, so deeply nested markup doesn't overflow the stack.
+        let html = "<div>".repeat(5000) + "a";
+        let (dom, layout_view) = create_layout_view(html);
+        let tree = layout_view.tree();
+        let root = layout_view.root().expect("root should exist");
+        let text = tree.post_order(root).next().expect("text should exist");
+        assert_eq!(NodeKind::Text("a".to_string()), tree[text].node_kind(&dom));
+        assert_eq!(20, tree[text].size().height());
+        assert_eq!(Some(text), layout_view.find_node_by_position((1, 1)));
     }
 }
@@ -1,4 +1,6 @@
+pub mod arena;
 pub mod css;
+pub mod diagnostics;
 pub mod dom;
 pub mod html;
 pub mod js;
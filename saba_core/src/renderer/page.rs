@@ -1,23 +1,57 @@
 use super::css::cssom::CssParser;
 use super::css::token::CssTokenizer;
-use super::dom::api::get_style_content;
 use crate::browser::Browser;
 use crate::display_item::DisplayItem;
+use crate::encoding::decode;
+use crate::encoding::sniff_encoding;
+use crate::encoding::Encoding;
+use crate::fetch::Fetcher;
+use crate::http::HttpRequest;
 use crate::http::HttpResponse;
 use crate::renderer::css::cssom::StyleSheet;
-use crate::renderer::dom::api::get_js_content;
+use crate::renderer::diagnostics::ParseError;
+use crate::renderer::dom::api::child_text_content;
+use crate::renderer::dom::api::clear_style_invalidation;
+use crate::renderer::dom::api::collect_garbage;
+use crate::renderer::dom::api::get_style_sheet_owners;
+use crate::renderer::dom::api::get_target_element_node;
+use crate::renderer::dom::api::is_classic_script;
+use crate::renderer::dom::api::is_connected;
+use crate::renderer::dom::api::needs_style_recalc;
+use crate::renderer::dom::document::Document;
+use crate::renderer::dom::document::DocumentReadyState;
+use crate::renderer::dom::event::Event;
+use crate::renderer::dom::event::KeyboardEvent;
+use crate::renderer::dom::event::MouseEvent;
+use crate::renderer::dom::form::control_type;
+use crate::renderer::dom::form::control_value;
+use crate::renderer::dom::form::default_button;
+use crate::renderer::dom::form::form_owner;
+use crate::renderer::dom::form::is_submit_button;
+use crate::renderer::dom::form::is_text_field;
+use crate::renderer::dom::form::labeled_control;
+use crate::renderer::dom::form::radio_button_group;
+use crate::renderer::dom::form::submit_form;
+use crate::renderer::dom::metadata::base_url;
+use crate::renderer::dom::metadata::document_title;
+use crate::renderer::dom::metadata::meta_content;
+use crate::renderer::dom::metadata::refresh;
 use crate::renderer::dom::node::ElementKind;
-use crate::renderer::dom::node::NodeKind;
-use crate::renderer::dom::node::Window;
+use crate::renderer::dom::node::NodeId;
+use crate::renderer::dom::window::Window;
 use crate::renderer::html::parser::HtmlParser;
+use crate::renderer::html::serializer::serialize_node;
 use crate::renderer::html::token::HtmlTokenizer;
 use crate::renderer::js::ast::JsParser;
-use crate::renderer::js::runtime::JsRuntime;
+use crate::renderer::js::runtime::Clock;
 use crate::renderer::js::token::JsLexer;
 use crate::renderer::layout::layout_view::LayoutView;
+use crate::url::Url;
+use crate::websocket::Connector;
 use alloc::rc::Rc;
 use alloc::rc::Weak;
 use alloc::string::String;
+use alloc::string::ToString;
 use alloc::vec::Vec;
 use core::cell::RefCell;
 
@@ -25,89 +59,921 @@ use core::cell::RefCell;
 #[derive(Debug, Clone)]
 pub struct Page {
     browser: Weak<RefCell<Browser>>,
-    frame: Option<Rc<RefCell<Window>>>,
+    window: Option<Window>,
     style: Option<StyleSheet>,
     layout_view: Option<LayoutView>,
     display_items: Vec<DisplayItem>,
+    focused_element: Option<NodeId>,
+    /// The URL of the document that started the navigation to the next document.
+    referrer: Option<String>,
+    websocket_connector: Option<Connector>,
+    clock: Option<Clock>,
+    diagnostics: Vec<ParseError>,
+    /// The navigation scheduled by `<meta http-equiv="refresh">` and its delay in milliseconds.
+    scheduled_navigation: Option<(u64, HttpRequest)>,
 }
 impl Page {
     pub fn new() -> Self {
         Self {
             browser: Weak::new(),
-            frame: None,
+            window: None,
             style: None,
             layout_view: None,
             display_items: Vec::new(),
+            focused_element: None,
+            referrer: None,
+            websocket_connector: None,
+            clock: None,
+            diagnostics: Vec::new(),
+            scheduled_navigation: None,
         }
     }
     pub fn set_browser(&mut self, browser: Weak<RefCell<Browser>>) {
         self.browser = browser;
     }
-    pub fn receive_response(&mut self, response: HttpResponse) {
-        self.create_frame(response.body());
-        self.execute_js();
+    /// Sets the function that opens the transports of the page's WebSockets. The sockets are
+    /// polled from the UI loop, so the transport must return from `read` without waiting for
+    /// data. Without a connector, every WebSocket fails with `error` and `close` events.
+    pub fn set_websocket_connector(&mut self, connector: Connector) {
+        self.websocket_connector = Some(connector);
+    }
+    /// Sets the clock that the timers of the page's scripts use.
+    pub fn set_clock(&mut self, clock: Clock) {
+        self.clock = Some(clock);
+    }
+    pub fn receive_response(&mut self, url: String, response: HttpResponse) {
+        self.focused_element = None;
+        self.diagnostics = Vec::new();
+        let body = response.body_bytes();
+        let content_type = response.header_values("Content-Type").first().cloned();
+        let (encoding, _confidence) = sniff_encoding(&body, content_type.as_deref());
+        let mut document = Document::new();
+        if let Ok(url) = Url::new(url).parse() {
+            document.set_url(url);
+        }
+        if let Some(referrer) = self.referrer.take() {
+            document.set_referrer(referrer);
+        }
+        if let Some(content_type) = content_type.as_deref().and_then(mime_type_essence) {
+            document.set_content_type(content_type);
+        }
+        document.set_character_set(encoding);
+        self.create_frame(document, decode(&body, encoding));
+        self.schedule_refresh();
         self.set_layout_view();
         self.paint_tree();
     }
-    fn execute_js(&mut self) {
-        let dom = match &self.frame {
-            Some(frame) => frame.borrow().document(),
+    /// Returns the title of the document, which the UI shows in the title bar and the tab.
+    /// # Returns
+    /// - The text of the `title` element, or an empty string if there is none.
+    pub fn title(&self) -> String {
+        match &self.window {
+            Some(window) => {
+                let document = window.document().borrow();
+                document_title(document.dom(), document.node())
+            }
+            None => String::new(),
+        }
+    }
+    /// Returns the URL that relative URLs in the document are resolved against, which is the
+    /// `href` of the `base` element if any, and the document URL otherwise.
+    pub fn base_url(&self) -> Option<Url> {
+        let document = self.window.as_ref()?.document().borrow();
+        let url = document.url()?;
+        Some(base_url(document.dom(), document.node(), &url))
+    }
+    /// Returns the `content` of the `<meta name>` element with the given name, such as
+    /// `description`.
+    pub fn meta(&self, name: &str) -> Option<String> {
+        let document = self.window.as_ref()?.document().borrow();
+        meta_content(document.dom(), document.node(), name)
+    }
+    /// Returns the character encoding of the document, which is declared by the
+    /// `Content-Type` header or `<meta charset>`.
+    pub fn encoding(&self) -> Option<Encoding> {
+        let document = self.window.as_ref()?.document().borrow();
+        Some(document.character_set())
+    }
+    /// Returns the navigation scheduled by `<meta http-equiv="refresh">`, which the UI starts
+    /// after the delay.
+    /// # Returns
+    /// - The delay in milliseconds and the request, which reloads the page if the `content` has
+    ///   no URL.
+    pub fn scheduled_navigation(&self) -> Option<(u64, HttpRequest)> {
+        self.scheduled_navigation.clone()
+    }
+    fn schedule_refresh(&mut self) {
+        self.scheduled_navigation = None;
+        let refresh = self.window.as_ref().and_then(|window| {
+            let document = window.document().borrow();
+            refresh(document.dom(), document.node())
+        });
+        let refresh = match refresh {
+            Some(refresh) => refresh,
             None => return,
         };
-        let js = get_js_content(dom.clone());
+        let url = match (refresh.url(), self.base_url()) {
+            (Some(url), Some(base)) => match base.resolve(&url) {
+                Ok(url) => url.serialize(),
+                Err(_) => return,
+            },
+            (Some(url), None) => url,
+            (None, _) => match self.url() {
+                Some(url) => url.serialize(),
+                None => return,
+            },
+        };
+        let delay = refresh.delay().saturating_mul(1000);
+        self.scheduled_navigation = Some((delay, HttpRequest::get(url)));
+    }
+    /// Returns the URL of the document.
+    fn url(&self) -> Option<Url> {
+        self.window.as_ref()?.document().borrow().url()
+    }
+    /// Records the URL of the document as the referrer of the document that `request` navigates
+    /// to, and returns the request.
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/browsing-the-web.html#navigate>
+    fn navigate(&mut self, request: Option<HttpRequest>) -> Option<HttpRequest> {
+        if request.is_some() {
+            self.referrer = self.url().map(|url| url.serialize());
+        }
+        request
+    }
+    fn create_window(&self, document: Rc<RefCell<Document>>) -> Window {
+        let mut window = Window::new(document);
+        if let Some(fetcher) = self.fetcher() {
+            window.runtime_mut().set_fetcher(fetcher);
+        }
+        if let Some(connector) = self.websocket_connector {
+            window.runtime_mut().set_websocket_connector(connector);
+        }
+        if let Some(clock) = self.clock {
+            window.runtime_mut().set_clock(clock);
+        }
+        window
+    }
+    fn fetcher(&self) -> Option<Fetcher> {
+        let browser = self.browser.upgrade()?;
+        let fetcher = browser.borrow().fetcher();
+        fetcher
+    }
+    /// Fetches a style sheet or a script through the same fetcher as navigations.
+    /// # Parameters
+    /// - `url`: The URL of the resource, relative to the document URL.
+    /// # Returns
+    /// - The decoded body, or `None` if the URL is invalid or the fetch fails.
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/urls-and-fetching.html#encoding-parsing-a-url>
+    fn fetch_subresource(&self, url: &str) -> Option<String> {
+        let url = match self.base_url() {
+            Some(base) => base.resolve(url).ok()?.serialize(),
+            None => url.to_string(),
+        };
+        let response = self.fetcher()?.fetch(HttpRequest::get(url), true).ok()?;
+        if !(200..300).contains(&response.status_code()) {
+            return None;
+        }
+        let body = response.body_bytes();
+        let content_type = response.header_values("Content-Type").first().cloned();
+        let (encoding, _confidence) = sniff_encoding(&body, content_type.as_deref());
+        Some(decode(&body, encoding))
+    }
+    fn run_script(&mut self, js: String) {
         let lexer = JsLexer::new(js);
         let mut parser = JsParser::new(lexer);
         let ast = parser.parse_ast();
-        let mut runtime = JsRuntime::new(dom);
-        runtime.execute(&ast);
+        self.diagnostics.extend(parser.errors());
+        if let Some(window) = &mut self.window {
+            window.runtime_mut().execute(&ast);
+        }
+    }
+    /// Runs the pending tasks of the page's event loop, and restyles the document if a script
+    /// has changed an attribute that the style depends on.
+    /// # Returns
+    /// - `true` if the page needs to be repainted.
+    pub fn process_tasks(&mut self) -> bool {
+        let window = match &mut self.window {
+            Some(window) => window,
+            None => return false,
+        };
+        let ran = window.runtime_mut().process_tasks();
+        let needs_style_recalc = {
+            let document = window.document().borrow();
+            needs_style_recalc(document.dom(), document.node())
+        };
+        if !ran && !needs_style_recalc {
+            return false;
+        }
+        self.update_layout();
+        self.collect_garbage();
+        true
     }
-    fn create_frame(&mut self, html: String) {
+    /// Frees the nodes that neither the document nor the scripts can reach anymore, such as the
+    /// ones that `innerHTML` replaced. It runs when no script is running and the layout was
+    /// built from the current tree, so that nothing else refers to the freed nodes.
+    fn collect_garbage(&mut self) {
+        let window = match &self.window {
+            Some(window) => window,
+            None => return,
+        };
+        let mut roots = window.runtime().referenced_nodes();
+        roots.extend(self.focused_element);
+        let mut document = window.document().borrow_mut();
+        roots.push(document.node());
+        collect_garbage(document.dom_mut(), &roots);
+    }
+    /// Parses the document and runs its scripts. Every script runs in the same global environment,
+    /// so a script can use the variables and functions of the scripts before it.
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/scripting.html#prepare-the-script-element>
+    /// - <https://html.spec.whatwg.org/multipage/parsing.html#the-end>
+    fn create_frame(&mut self, document: Document, html: String) {
+        let document = Rc::new(RefCell::new(document));
         let html_tokenizer = HtmlTokenizer::new(html);
-        let frame = HtmlParser::new(html_tokenizer).construct_tree();
-        let dom = frame.borrow().document();
-        let style = get_style_content(dom);
-        let css_tokenizer = CssTokenizer::new(style);
-        let cssom = CssParser::new(css_tokenizer).parse_stylesheet();
-        self.frame = Some(frame);
+        let mut html_parser = HtmlParser::with_document(html_tokenizer, document.clone());
+        self.window = Some(self.create_window(document.clone()));
+        let mut async_scripts = Vec::new();
+        let mut deferred_scripts = Vec::new();
+        // The parser stops after each script element, so a parser-blocking script sees the
+        // document only up to itself.
+        while let Some(script) = html_parser.run_until_script() {
+            let (element, connected) = {
+                let document = document.borrow();
+                let element = match document.dom()[script].get_element() {
+                    Some(e) => e.clone(),
+                    None => continue,
+                };
+                (element, is_connected(document.dom(), script))
+            };
+            if !is_classic_script(&element) || !connected {
+                continue;
+            }
+            let src = match element.get_attribute("src") {
+                Some(src) => src,
+                None => {
+                    let js = child_text_content(document.borrow().dom(), script);
+                    self.run_script(js);
+                    continue;
+                }
+            };
+            if element.get_attribute("async").is_some() {
+                async_scripts.push(src);
+            } else if element.get_attribute("defer").is_some() {
+                deferred_scripts.push(src);
+            } else if let Some(js) = self.fetch_subresource(&src) {
+                self.run_script(js);
+            }
+        }
+        self.diagnostics.extend(html_parser.errors());
+        document
+            .borrow_mut()
+            .set_ready_state(DocumentReadyState::Interactive);
+        // Async scripts don't block the parser and run as soon as they are fetched. Fetches
+        // complete in order here, so they run right after parsing, before the deferred scripts,
+        // which run in document order.
+        for src in async_scripts.into_iter().chain(deferred_scripts) {
+            if let Some(js) = self.fetch_subresource(&src) {
+                self.run_script(js);
+            }
+        }
+        document
+            .borrow_mut()
+            .set_ready_state(DocumentReadyState::Complete);
+        // The style sheets are combined in tree order, so a later rule wins over an earlier one.
+        let mut cssom = StyleSheet::new();
+        let owners = {
+            let document = document.borrow();
+            get_style_sheet_owners(document.dom(), document.node())
+        };
+        for owner in owners {
+            let element = match document.borrow().dom()[owner].get_element() {
+                Some(e) => e.clone(),
+                None => continue,
+            };
+            let css = if element.kind() == Some(ElementKind::Link) {
+                match element
+                    .get_attribute("href")
+                    .and_then(|href| self.fetch_subresource(&href))
+                {
+                    Some(css) => css,
+                    None => continue,
+                }
+            } else {
+                child_text_content(document.borrow().dom(), owner)
+            };
+            let css_tokenizer = CssTokenizer::new(css);
+            let mut css_parser = CssParser::new(css_tokenizer);
+            cssom.append(css_parser.parse_stylesheet());
+            self.diagnostics.extend(css_parser.errors());
+        }
         self.style = Some(cssom);
     }
     fn set_layout_view(&mut self) {
-        let dom = match &self.frame {
-            Some(frame) => frame.borrow().document(),
+        let window = match &self.window {
+            Some(window) => window,
             None => return,
         };
-        let style = match self.style.clone() {
+        let style = match &self.style {
             Some(style) => style,
             None => return,
         };
-        let layout_view = LayoutView::new(dom, &style);
+        let mut document = window.document().borrow_mut();
+        let layout_view = LayoutView::new(document.dom(), document.node(), style);
         self.layout_view = Some(layout_view);
+        // The layout tree is built with the current style of every node.
+        let node = document.node();
+        clear_style_invalidation(document.dom_mut(), node);
     }
     fn paint_tree(&mut self) {
-        if let Some(layout_view) = &self.layout_view {
-            self.display_items = layout_view.paint();
+        if let (Some(window), Some(layout_view)) = (&self.window, &self.layout_view) {
+            self.display_items = layout_view.paint(window.document().borrow().dom());
         }
     }
+    /// Returns the parse errors of the HTML, CSS and JavaScript of the page, followed by the
+    /// errors that its scripts threw.
+    pub fn diagnostics(&self) -> Vec<ParseError> {
+        let mut diagnostics = self.diagnostics.clone();
+        if let Some(window) = &self.window {
+            diagnostics.extend(window.runtime().errors());
+        }
+        diagnostics
+    }
+    /// Serializes the current DOM tree of the page, including the changes made by scripts, to
+    /// save the page as an HTML file.
+    /// # Returns
+    /// - The HTML markup of the document, or `None` if no document is loaded.
+    pub fn save_as_html(&self) -> Option<String> {
+        let document = self.window.as_ref()?.document().borrow();
+        let html = serialize_node(document.dom(), document.node());
+        Some(html)
+    }
     pub fn display_items(&self) -> Vec<DisplayItem> {
         self.display_items.clone()
     }
     pub fn clear_display_items(&mut self) {
         self.display_items = Vec::new();
     }
-    pub fn clicked(&self, position: (i64, i64)) -> Option<String> {
-        let view = match &self.layout_view {
-            Some(v) => v,
-            None => return None,
+    pub fn focused_element(&self) -> Option<NodeId> {
+        self.focused_element
+    }
+    /// Dispatches an event to a node and runs the listeners that scripts added. The page is laid
+    /// out again if a listener may have changed the DOM.
+    /// # Returns
+    /// - `false` if a listener canceled the default action of the event, otherwise `true`.
+    fn dispatch_event(&mut self, target: NodeId, event: &mut Event) -> bool {
+        let window = match &mut self.window {
+            Some(window) => window,
+            None => return true,
+        };
+        let has_listeners = {
+            let document = window.document().borrow();
+            let dom = document.dom();
+            dom.inclusive_ancestors(target)
+                .any(|node| !dom[node].event_listeners().is_empty())
         };
-        if let Some(n) = view.find_node_by_position(position) {
-            if let Some(parent) = n.borrow().parent().upgrade() {
-                if let NodeKind::Element(e) = parent.borrow().node_kind() {
-                    if e.kind() == ElementKind::A {
-                        return e.get_attribute("href");
+        if !has_listeners {
+            return true;
+        }
+        let not_canceled = window.runtime_mut().dispatch_event(target, event);
+        self.update_layout();
+        not_canceled
+    }
+    /// Handles a click in the content area. A `click` event is dispatched to the clicked element,
+    /// and the default action, such as following a link, runs unless a listener cancels it.
+    /// # Parameters
+    /// - `position`: The clicked position relative to the content area.
+    /// # Returns
+    /// - The request to navigate to when a link or a submit button is clicked.
+    /// # References:
+    /// - <https://w3c.github.io/uievents/#event-type-click>
+    pub fn clicked(&mut self, position: (i64, i64)) -> Option<HttpRequest> {
+        self.collect_garbage();
+        let view = self.layout_view.as_ref()?;
+        let document = self.window.as_ref()?.document().clone();
+        let hit = view
+            .find_node_by_position(position)
+            .map(|id| view.tree()[id].node());
+        self.focused_element = None;
+        // A click on text is dispatched to the element that contains it.
+        let mut target = hit.and_then(|node| {
+            let document = document.borrow();
+            let dom = document.dom();
+            dom.inclusive_ancestors(node)
+                .find(|n| dom[*n].get_element().is_some())
+        });
+        if let Some(node) = target {
+            let (x, y) = position;
+            let mut event = Event::new_mouse_event("click", MouseEvent::new(x, y, 0));
+            if !self.dispatch_event(node, &mut event) {
+                return None;
+            }
+        }
+        while let Some(node) = target {
+            let element_kind = document.borrow().dom()[node].element_kind();
+            match element_kind {
+                Some(ElementKind::A) => {
+                    // An `a` element without `href` is a placeholder, which isn't a link.
+                    let href = document.borrow().dom()[node]
+                        .get_element()
+                        .and_then(|e| e.get_attribute("href"));
+                    if let Some(href) = href {
+                        let url = match self.base_url() {
+                            Some(base) => base.resolve(&href).ok()?.serialize(),
+                            None => href,
+                        };
+                        return self.navigate(Some(HttpRequest::get(url)));
+                    }
+                }
+                Some(ElementKind::Input) | Some(ElementKind::Button) => {
+                    return self.activate(node);
+                }
+                Some(ElementKind::Textarea) => {
+                    self.focused_element = Some(node);
+                    return None;
+                }
+                Some(ElementKind::Label) => {
+                    // Clicking a label acts on its control.
+                    // https://html.spec.whatwg.org/multipage/forms.html#the-label-element
+                    let control = labeled_control(document.borrow().dom(), node)?;
+                    let is_textarea = document.borrow().dom()[control].element_kind()
+                        == Some(ElementKind::Textarea);
+                    if is_textarea {
+                        self.focused_element = Some(control);
+                        return None;
                     }
+                    return self.activate(control);
                 }
+                _ => {}
             }
+            target = document.borrow().dom().parent(node);
         }
         None
     }
+    /// Runs the activation behavior of a button or an input element.
+    fn activate(&mut self, node: NodeId) -> Option<HttpRequest> {
+        let document = self.window.as_ref()?.document().clone();
+        if is_submit_button(document.borrow().dom(), node) {
+            let form = form_owner(document.borrow().dom(), node)?;
+            return self.submit(form, Some(node));
+        }
+        let control_type = control_type(document.borrow().dom(), node);
+        match control_type.as_str() {
+            "checkbox" => {
+                if let Some(e) = document.borrow_mut().dom_mut()[node].get_element_mut() {
+                    let checked = e.checked();
+                    e.set_checked(!checked);
+                }
+                self.update_layout();
+            }
+            "radio" => {
+                self.check_radio_button(node);
+                self.update_layout();
+            }
+            _ => {
+                if is_text_field(document.borrow().dom(), node) {
+                    self.focused_element = Some(node);
+                }
+            }
+        }
+        None
+    }
+    /// Checks a radio button and unchecks the others in its radio button group.
+    fn check_radio_button(&mut self, node: NodeId) {
+        let mut document = match &self.window {
+            Some(window) => window.document().borrow_mut(),
+            None => return,
+        };
+        let dom = document.dom_mut();
+        for n in radio_button_group(dom, node) {
+            if let Some(e) = dom[n].get_element_mut() {
+                e.set_checked(n == node);
+            }
+        }
+    }
+    /// Dispatches a `submit` event to a form, and submits it unless a listener cancels the event.
+    /// # Parameters
+    /// - `form`: The form element.
+    /// - `submitter`: The submit button that submits the form, if any.
+    /// # Returns
+    /// - The request to navigate to.
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/form-control-infrastructure.html#form-submission-algorithm>
+    fn submit(&mut self, form: NodeId, submitter: Option<NodeId>) -> Option<HttpRequest> {
+        let mut event = Event::new("submit", true, true);
+        if !self.dispatch_event(form, &mut event) {
+            return None;
+        }
+        let base_url = self.base_url()?;
+        let request = {
+            let document = self.window.as_ref()?.document().borrow();
+            submit_form(document.dom(), form, submitter, &base_url)
+        };
+        self.navigate(request)
+    }
+    /// Handles a typed key. A `keydown` event is dispatched to the focused element, or to the
+    /// body if nothing has focus, and the default action edits the focused text control unless a
+    /// listener cancels it.
+    /// # Parameters
+    /// - `c`: The typed character.
+    /// # Returns
+    /// - The request to navigate to when Enter submits a form.
+    /// # References:
+    /// - <https://w3c.github.io/uievents/#event-type-keydown>
+    pub fn key_pressed(&mut self, c: char) -> Option<HttpRequest> {
+        self.collect_garbage();
+        let document = self.window.as_ref()?.document().clone();
+        let target = self.focused_element.or_else(|| {
+            let document = document.borrow();
+            get_target_element_node(document.dom(), document.node(), ElementKind::Body)
+        });
+        let key = match c {
+            '\n' => "Enter".to_string(),
+            '\x7f' | '\x08' => "Backspace".to_string(),
+            c => c.to_string(),
+        };
+        let mut event = Event::new_keyboard_event("keydown", KeyboardEvent::new(&key));
+        if !self.dispatch_event(target?, &mut event) {
+            return None;
+        }
+        let node = self.focused_element?;
+        let is_textarea =
+            document.borrow().dom()[node].element_kind() == Some(ElementKind::Textarea);
+        if c == 0x0a as char && !is_textarea {
+            // Implicit submission submits the form with its default button as the submitter.
+            // https://html.spec.whatwg.org/multipage/form-control-infrastructure.html#implicit-submission
+            let (form, submitter) = {
+                let document = document.borrow();
+                let dom = document.dom();
+                let form = form_owner(dom, node)?;
+                (form, default_button(dom, form))
+            };
+            return self.submit(form, submitter);
+        }
+        let mut value = control_value(document.borrow().dom(), node);
+        if c == 0x7f as char || c == 0x08 as char {
+            value.pop();
+        } else {
+            value.push(c);
+        }
+        if let Some(e) = document.borrow_mut().dom_mut()[node].get_element_mut() {
+            e.set_value(value);
+        }
+        self.update_layout();
+        None
+    }
+    fn update_layout(&mut self) {
+        self.set_layout_view();
+        self.paint_tree();
+    }
+}
+
+/// Returns the essence of a MIME type, which is its type and subtype in lowercase without the
+/// parameters, such as `text/html` for `text/html; charset=utf-8`.
+/// # References:
+/// - <https://mimesniff.spec.whatwg.org/#mime-type-essence>
+fn mime_type_essence(mime_type: &str) -> Option<String> {
+    let essence = mime_type.split(';').next()?.trim();
+    if !essence.contains('/') {
+        return None;
+    }
+    Some(essence.to_ascii_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+    use crate::renderer::css::token::CssToken;
+    use crate::renderer::diagnostics::SourceLanguage;
+    use crate::renderer::dom::api::class_list_add;
+    use crate::renderer::dom::api::get_element_by_id;
+    use crate::renderer::html::serializer::serialize_children;
+    use crate::renderer::layout::computed_style::Color;
+    use alloc::format;
+    use alloc::rc::Rc;
+    fn send(request: HttpRequest) -> Result<HttpResponse, Error> {
+        let raw = match request.url().as_str() {
+            "http://example.com/css/main.css" => {
+                "HTTP/1.1 200 OK\nContent-Type: text/css\n\n#t { color: red; }"
+            }
+            "http://example.com/js/blocking.js" => {
+                "HTTP/1.1 200 OK\nContent-Type: text/javascript\n\norder = order + \"b\"; var c = document.getElementById(\"c\"); c.insertAdjacentHTML(\"beforeend\", \"<i></i>\");"
+            }
+            "http://example.com/js/async.js" => {
+                "HTTP/1.1 200 OK\nContent-Type: text/javascript\n\norder = order + \"a\";"
+            }
+            "http://example.com/js/ready-state.js" => {
+                "HTTP/1.1 200 OK\nContent-Type: text/javascript\n\nstates = states + \" \" + document.readyState; var log = document.getElementById(\"log\"); log.textContent = states;"
+            }
+            "http://example.com/js/defer.js" => {
+                "HTTP/1.1 200 OK\nContent-Type: text/javascript\n\norder = order + \"d\"; var log = document.getElementById(\"log\"); log.textContent = order;"
+            }
+            _ => "HTTP/1.1 404 Not Found\nContent-Type: text/plain\n\norder = order + \"404\";",
+        };
+        HttpResponse::new(raw.to_string())
+    }
+    fn load_with_subresources(html: &str) -> Rc<RefCell<Page>> {
+        let browser = Browser::new();
+        browser.borrow_mut().set_request_sender(send);
+        let page = browser.borrow().current_page();
+        let response = HttpResponse::new(format!(
+            "HTTP/1.1 200 OK\nContent-Type: text/html\n\n{}",
+            html
+        ))
+        .expect("failed to create a response");
+        page.borrow_mut()
+            .receive_response("http://example.com/index.html".to_string(), response);
+        page
+    }
+    fn load(html: &str) -> Page {
+        let mut page = Page::new();
+        let response = HttpResponse::new(format!(
+            "HTTP/1.1 200 OK\nContent-Type: text/html\n\n{}",
+            html
+        ))
+        .expect("failed to create a response");
+        page.receive_response("http://example.com/".to_string(), response);
+        page
+    }
+    #[test]
+    fn test_scripts_share_global_environment() {
+        let page = load(
+            r#"<p id="t">x</p><script>var message = "shared";</script><script type="module">var message = "module";</script><script>var t = document.getElementById("t"); t.textContent = message;</script><script type="text/plain">t.textContent = "data";</script>"#,
+        );
+        let window = page.window.as_ref().expect("failed to get a window");
+        let document = window.document().borrow();
+        let dom = document.dom();
+        let p = get_element_by_id(dom, document.node(), "t").expect("failed to get p");
+        assert_eq!("shared", child_text_content(dom, p));
+    }
+    #[test]
+    fn test_script_errors() {
+        let page = load(
+            r#"<p id="t">x</p><script>undefinedFn(); document.getElementById("t").textContent = "ran";</script>"#,
+        );
+        let window = page.window.as_ref().expect("failed to get a window");
+        let document = window.document().borrow();
+        let dom = document.dom();
+        let p = get_element_by_id(dom, document.node(), "t").expect("failed to get p");
+        assert_eq!("ran", child_text_content(dom, p));
+        let errors: Vec<String> = page
+            .diagnostics()
+            .iter()
+            .filter(|e| e.language() == SourceLanguage::JavaScript)
+            .map(|e| e.code())
+            .collect();
+        assert_eq!(["type-error".to_string()].to_vec(), errors);
+    }
+    #[test]
+    fn test_external_scripts() {
+        let page = load_with_subresources(
+            r#"<p id="log"></p><script>var order = "";</script><script defer src="js/defer.js"></script><script async src="/js/async.js"></script><div id="c"><script src="js/blocking.js"></script><p></p></div><script src="missing.js"></script><script>order = order + "i";</script>"#,
+        );
+        let window = page
+            .borrow()
+            .window
+            .clone()
+            .expect("failed to get a window");
+        let document = window.document().borrow();
+        let dom = document.dom();
+        let log = get_element_by_id(dom, document.node(), "log").expect("failed to get p");
+        // A failed fetch doesn't run anything. The async script runs before the deferred one after
+        // parsing.
+        assert_eq!("biad", child_text_content(dom, log));
+        // The blocking script runs before the rest of the document is parsed.
+        let c = get_element_by_id(dom, document.node(), "c").expect("failed to get div");
+        assert_eq!(
+            r#"<script src="js/blocking.js"></script><i></i><p></p>"#,
+            serialize_children(dom, c)
+        );
+    }
+    #[test]
+    fn test_ready_state() {
+        let page = load_with_subresources(
+            r#"<p id="log"></p><script>var states = document.readyState;</script><script defer src="js/ready-state.js"></script>"#,
+        );
+        let window = page
+            .borrow()
+            .window
+            .clone()
+            .expect("failed to get a window");
+        let document = window.document().borrow();
+        let log =
+            get_element_by_id(document.dom(), document.node(), "log").expect("failed to get p");
+        // An inline script runs while the document is parsed, and a deferred script after it.
+        assert_eq!(
+            "loading interactive",
+            child_text_content(document.dom(), log)
+        );
+        assert_eq!(DocumentReadyState::Complete, document.ready_state());
+    }
+    #[test]
+    fn test_document_properties() {
+        let mut page = Page::new();
+        page.referrer = Some("http://example.com/".to_string());
+        let response = HttpResponse::new(
+            r#"HTTP/1.1 200 OK
+Content-Type: Text/HTML; charset=windows-1252
+
+<p id="t"></p><script>var t = document.getElementById("t"); t.textContent = document.URL + " " + window.location.href + " " + location.host + " " + location.pathname + " " + location.search + " " + document.referrer + " " + document.characterSet + " " + document.contentType + " " + document.compatMode;</script>"#
+                .to_string(),
+        )
+        .expect("failed to create a response");
+        page.receive_response("http://example.com:8000/a/b?q=1".to_string(), response);
+        let window = page.window.as_ref().expect("failed to get a window");
+        let document = window.document().borrow();
+        let p = get_element_by_id(document.dom(), document.node(), "t").expect("failed to get p");
+        assert_eq!(
+            "http://example.com:8000/a/b?q=1 http://example.com:8000/a/b?q=1 example.com:8000 /a/b ?q=1 http://example.com/ windows-1252 text/html BackCompat",
+            child_text_content(document.dom(), p)
+        );
+        assert!(page.referrer.is_none());
+    }
+    #[test]
+    fn test_external_style_sheets() {
+        let page = load_with_subresources(
+            r#"<link rel="Alternate StyleSheet" href="css/main.css"><link rel="icon" href="missing.css"><style>#t { color: blue; }</style><p id="t">x</p>"#,
+        );
+        let style = page
+            .borrow()
+            .style
+            .clone()
+            .expect("failed to get the style");
+        // The linked style sheet comes before the style element in tree order.
+        assert_eq!(2, style.rules.len());
+        assert_eq!(
+            CssToken::Ident("red".to_string()),
+            style.rules[0].declarations[0].value
+        );
+        assert_eq!(
+            CssToken::Ident("blue".to_string()),
+            style.rules[1].declarations[0].value
+        );
+    }
+    #[test]
+    fn test_attribute_changes_restyle_the_page() {
+        let mut page = load("<style>.red { color: red; }</style><p id=t>x</p>");
+        let text_color = |page: &Page| {
+            page.display_items().iter().find_map(|item| match item {
+                DisplayItem::Text { text, style, .. } if text == "x" => Some(style.color()),
+                _ => None,
+            })
+        };
+        assert_eq!(Some(Color::black()), text_color(&page));
+        assert!(!page.process_tasks());
+        {
+            let window = page.window.as_ref().expect("failed to get a window");
+            let mut document = window.document().borrow_mut();
+            let node = document.node();
+            let p = get_element_by_id(document.dom(), node, "t").expect("failed to get p");
+            class_list_add(document.dom_mut(), p, &["red"]).expect("failed to add a class");
+        }
+        // The next frame restyles and lays out the document again.
+        assert!(page.process_tasks());
+        assert_eq!(
+            Some(Color::from_name("red").expect("failed to get red")),
+            text_color(&page)
+        );
+        assert!(!page.process_tasks());
+    }
+    #[test]
+    fn test_metadata() {
+        let page = load(
+            r#"<meta charset="windows-1252"><title>Top  page</title><base href="/docs/"><meta name="description" content="saba"><meta http-equiv="refresh" content="2; url=next.html">"#,
+        );
+        assert_eq!("Top page", page.title());
+        assert_eq!(
+            Some("http://example.com/docs/".to_string()),
+            page.base_url().map(|u| u.serialize())
+        );
+        assert_eq!(Some("saba".to_string()), page.meta("description"));
+        assert_eq!(Some(Encoding::Windows1252), page.encoding());
+        assert_eq!(
+            Some((
+                2000,
+                HttpRequest::get("http://example.com/docs/next.html".to_string())
+            )),
+            page.scheduled_navigation()
+        );
+        let page = load("<meta http-equiv=refresh content=0>");
+        assert_eq!("", page.title());
+        assert_eq!(
+            Some((0, HttpRequest::get("http://example.com/".to_string()))),
+            page.scheduled_navigation()
+        );
+    }
+    /// Returns a position inside the text that is painted as `text`.
+    fn text_position(page: &Page, text: &str) -> (i64, i64) {
+        page.display_items()
+            .iter()
+            .find_map(|item| match item {
+                DisplayItem::Text {
+                    text: t,
+                    layout_point,
+                    ..
+                } if t == text => Some((layout_point.x() + 1, layout_point.y() + 1)),
+                _ => None,
+            })
+            .expect("failed to find the text")
+    }
+    fn text_of(page: &Page, id: &str) -> String {
+        let window = page.window.as_ref().expect("failed to get a window");
+        let document = window.document().borrow();
+        let node = get_element_by_id(document.dom(), document.node(), id).expect("failed to get");
+        serialize_children(document.dom(), node)
+    }
+    #[test]
+    fn test_click_events() {
+        let html = r#"<a id="a" href="next.html">link</a><p id="log">-</p><script>document.addEventListener("click", function(e) { document.getElementById("log").textContent = e.type + " " + e.target.id + " " + e.eventPhase; });</script>"#;
+        let mut page = load(html);
+        let position = text_position(&page, "link");
+        assert_eq!(
+            Some(HttpRequest::get("http://example.com/next.html".to_string())),
+            page.clicked(position)
+        );
+        assert_eq!("click a 3", text_of(&page, "log"));
+        // Canceling the click stops the link from being followed.
+        let mut page = load(&format!(
+            r#"{}<script>document.getElementById("a").addEventListener("click", function(e) {{ e.preventDefault(); }});</script>"#,
+            html
+        ));
+        assert_eq!(None, page.clicked(position));
+        assert_eq!("click a 3", text_of(&page, "log"));
+    }
+    #[test]
+    fn test_replaced_nodes_are_freed() {
+        let mut page = load(
+            r#"<p id="log">-</p><script>var log = document.getElementById("log"); var n = 0; document.addEventListener("click", function(e) { n = n + 1; log.innerHTML = "<b>" + n + "</b> clicks"; });</script>"#,
+        );
+        let position = text_position(&page, "-");
+        let len = |page: &Page| {
+            let window = page.window.as_ref().expect("failed to get a window");
+            let len = window.document().borrow().dom().len();
+            len
+        };
+        page.clicked(position);
+        page.clicked(position);
+        let before = len(&page);
+        for _ in 0..50 {
+            page.clicked(position);
+        }
+        assert_eq!("<b>52</b> clicks", text_of(&page, "log"));
+        assert_eq!(before, len(&page));
+    }
+    #[test]
+    fn test_timer_keeps_detached_node() {
+        let mut page = load(
+            r#"<div id="box"><p id="p">kept</p></div><script>function detach() { var p = document.getElementById("p"); var box = document.getElementById("box"); box.innerHTML = "replaced"; setTimeout(function() { box.appendChild(p); }, 0); } detach();</script>"#,
+        );
+        // The click collects garbage before the timer runs, while only the timer's callback
+        // refers to the detached paragraph.
+        let position = text_position(&page, "replaced");
+        page.clicked(position);
+        assert!(page.process_tasks());
+        assert_eq!(r#"replaced<p id="p">kept</p>"#, text_of(&page, "box"));
+    }
+    #[test]
+    fn test_click_placeholder_link() {
+        let mut page =
+            load(r#"<input type="checkbox" id="c"><label for="c"><a name="x">toggle</a></label>"#);
+        let position = text_position(&page, "toggle");
+        assert_eq!(None, page.clicked(position));
+        let window = page.window.as_ref().expect("failed to get a window");
+        let document = window.document().borrow();
+        let c = get_element_by_id(document.dom(), document.node(), "c").expect("failed to get c");
+        // The click goes past the anchor to the label, which checks its control.
+        assert!(document.dom()[c]
+            .get_element()
+            .expect("failed to get an element")
+            .checked());
+    }
+    #[test]
+    fn test_submit_and_keydown_events() {
+        let html = r#"<body id="body"><form id="f" action="/search"><input id="q" name="q" value="x"></form><p id="log">-</p><script>var log = document.getElementById("log"); var f = document.getElementById("f"); f.addEventListener("submit", function(e) { log.textContent = e.type; e.preventDefault(); }); var q = document.getElementById("q"); q.addEventListener("keydown", function(e) { log.textContent = e.key; e.preventDefault(); }); document.getElementById("body").addEventListener("keydown", function(e) { log.textContent = "body " + e.key; });</script>"#;
+        let mut page = load(html);
+        let (form, input) = {
+            let window = page.window.as_ref().expect("failed to get a window");
+            let document = window.document().borrow();
+            let dom = document.dom();
+            (
+                get_element_by_id(dom, document.node(), "f").expect("failed to get the form"),
+                get_element_by_id(dom, document.node(), "q").expect("failed to get the input"),
+            )
+        };
+        assert_eq!(None, page.submit(form, None));
+        assert_eq!("submit", text_of(&page, "log"));
+        // Without focus, keys go to the body.
+        assert_eq!(None, page.key_pressed('a'));
+        assert_eq!("body a", text_of(&page, "log"));
+        // The listener on the input cancels the key, so the value doesn't change and Enter
+        // doesn't submit the form. The event still bubbles up to the body.
+        page.focused_element = Some(input);
+        assert_eq!(None, page.key_pressed('b'));
+        assert_eq!("body b", text_of(&page, "log"));
+        assert_eq!(None, page.key_pressed('\n'));
+        assert_eq!("body Enter", text_of(&page, "log"));
+        let window = page.window.as_ref().expect("failed to get a window");
+        assert_eq!("x", control_value(window.document().borrow().dom(), input));
+    }
 }
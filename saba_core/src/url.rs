@@ -10,6 +10,7 @@ pub struct Url {
     search_part: String,
 }
 
+use alloc::format;
 use alloc::string::ToString;
 use alloc::vec::Vec;
 impl Url {
@@ -102,6 +103,132 @@ impl Url {
     pub fn search_part(&self) -> String {
         self.search_part.clone()
     }
+    /// Returns a new URL with the same host, port and path but a different query.
+    pub fn with_search_part(&self, search_part: &str) -> Self {
+        let mut url = self.clone();
+        url.search_part = search_part.to_string();
+        url.url = url.serialize();
+        url
+    }
+    /// Serializes the parsed components back into an absolute URL string.
+    /// # References:
+    /// - <https://url.spec.whatwg.org/#concept-url-serializer>
+    pub fn serialize(&self) -> String {
+        let mut s = format!("http://{}/{}", self.authority(), self.path);
+        if !self.search_part.is_empty() {
+            s.push('?');
+            s.push_str(&self.search_part);
+        }
+        s
+    }
+    /// Returns the serialized origin, which is shared by URLs with the same scheme, host and port.
+    /// # References:
+    /// - <https://html.spec.whatwg.org/multipage/browsers.html#ascii-serialisation-of-an-origin>
+    pub fn origin(&self) -> String {
+        format!("http://{}", self.authority())
+    }
+    fn authority(&self) -> String {
+        if self.port == "80" || self.port.is_empty() {
+            self.host.clone()
+        } else {
+            format!("{}:{}", self.host, self.port)
+        }
+    }
+    /// Resolves a possibly relative URL string against this URL.
+    /// # Parameters
+    /// - `input`: An absolute URL, or a path relative to this URL.
+    /// # Returns
+    /// - The parsed absolute URL.
+    /// # References:
+    /// - <https://url.spec.whatwg.org/#concept-basic-url-parser>
+    pub fn resolve(&self, input: &str) -> Result<Self, String> {
+        let input = match input.trim().split_once('#') {
+            Some((before, _fragment)) => before,
+            None => input.trim(),
+        };
+        if has_scheme(input) {
+            return Url::new(input.to_string()).parse();
+        }
+        if let Some(rest) = input.strip_prefix("//") {
+            return Url::new(format!("http://{}", rest)).parse();
+        }
+        if input.is_empty() {
+            return Url::new(self.serialize()).parse();
+        }
+        if input.starts_with('?') {
+            return Url::new(format!(
+                "http://{}/{}{}",
+                self.authority(),
+                self.path,
+                input
+            ))
+            .parse();
+        }
+        let (path, search_part) = match input.split_once('?') {
+            Some((p, q)) => (p, Some(q)),
+            None => (input, None),
+        };
+        let joined = if let Some(absolute) = path.strip_prefix('/') {
+            absolute.to_string()
+        } else {
+            match self.path.rfind('/') {
+                Some(i) => format!("{}{}", &self.path[..i + 1], path),
+                None => path.to_string(),
+            }
+        };
+        let mut resolved = format!(
+            "http://{}/{}",
+            self.authority(),
+            remove_dot_segments(&joined)
+        );
+        if let Some(q) = search_part {
+            resolved.push('?');
+            resolved.push_str(q);
+        }
+        Url::new(resolved).parse()
+    }
+}
+
+/// Returns true if `input` starts with a scheme followed by `:`, which makes it an absolute URL.
+/// A `:` after the first `/`, `?` or `#`, such as in a query, doesn't start a scheme.
+/// # References:
+/// - <https://datatracker.ietf.org/doc/html/rfc3986#section-3.1>
+fn has_scheme(input: &str) -> bool {
+    let scheme = match input.split_once(':') {
+        Some((scheme, _)) => scheme,
+        None => return false,
+    };
+    let mut chars = scheme.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '-' || c == '.')
+}
+
+/// Removes `.` and `..` segments from a path without a leading slash.
+/// # Parameters
+/// - `path`: The path to normalize.
+/// # Returns
+/// - The normalized path.
+fn remove_dot_segments(path: &str) -> String {
+    let mut output: Vec<&str> = Vec::new();
+    let segments: Vec<&str> = path.split('/').collect();
+    for (i, segment) in segments.iter().enumerate() {
+        let is_last = i == segments.len() - 1;
+        match *segment {
+            "." => {
+                if is_last {
+                    output.push("");
+                }
+            }
+            ".." => {
+                output.pop();
+                if is_last {
+                    output.push("");
+                }
+            }
+            s => output.push(s),
+        }
+    }
+    output.join("/")
 }
 
 #[cfg(test)]
@@ -168,6 +295,63 @@ mod tests {
         assert_eq!(expected, Url::new(url).parse());
     }
     #[test]
+    fn test_resolve_relative() {
+        let base = Url::new("http://example.com:8888/dir/index.html?a=1".to_string())
+            .parse()
+            .expect("failed to parse base url");
+        let cases = [
+            ("submit", "http://example.com:8888/dir/submit"),
+            ("/submit?q=1", "http://example.com:8888/submit?q=1"),
+            ("../up.html", "http://example.com:8888/up.html"),
+            ("./here/", "http://example.com:8888/dir/here/"),
+            ("?b=2", "http://example.com:8888/dir/index.html?b=2"),
+            ("", "http://example.com:8888/dir/index.html?a=1"),
+            ("http://other.com/x", "http://other.com/x"),
+            ("//other.com/y", "http://other.com/y"),
+            (
+                "/login?next=http://example.com/a",
+                "http://example.com:8888/login?next=http://example.com/a",
+            ),
+            ("a/b?c=d:e", "http://example.com:8888/dir/a/b?c=d:e"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(
+                base.resolve(input).map(|u| u.serialize()),
+                Ok(expected.to_string())
+            );
+        }
+    }
+    #[test]
+    fn test_has_scheme() {
+        assert!(has_scheme("http://example.com"));
+        assert!(has_scheme("view-source+x.1:a"));
+        assert!(!has_scheme("/login?next=http://example.com"));
+        assert!(!has_scheme("1http://example.com"));
+        assert!(!has_scheme("?q=http://example.com"));
+        assert!(!has_scheme("next.html"));
+    }
+    #[test]
+    fn test_origin() {
+        let url = Url::new("http://example.com:80/a/b?c".to_string())
+            .parse()
+            .expect("failed to parse");
+        assert_eq!(url.origin(), "http://example.com".to_string());
+        let url = Url::new("http://example.com:8000/".to_string())
+            .parse()
+            .expect("failed to parse");
+        assert_eq!(url.origin(), "http://example.com:8000".to_string());
+    }
+    #[test]
+    fn test_with_search_part() {
+        let url = Url::new("http://example.com/search?old=1".to_string())
+            .parse()
+            .expect("failed to parse url");
+        assert_eq!(
+            url.with_search_part("q=saba").serialize(),
+            "http://example.com/search?q=saba".to_string()
+        );
+    }
+    #[test]
     fn test_no_scheme() {
         let url = "example.com".to_string();
         let expected = Err("Only HTTP scheme is supported.".to_string());
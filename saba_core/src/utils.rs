@@ -1,32 +1,22 @@
-use crate::renderer::dom::node::Node;
+use crate::renderer::dom::node::Dom;
+use crate::renderer::dom::node::NodeId;
 use alloc::format;
-use alloc::rc::Rc;
 use alloc::string::String;
-use core::cell::RefCell;
 
 /// Converts DOM tree to a string for debugging.
 /// # Parameters
+/// - `dom`: The nodes of the document.
 /// - `root`: Root node of the DOM tree.
 /// # Returns
 /// - `String`: A string representation of the DOM tree.
-pub fn convert_dom_to_string(root: &Option<Rc<RefCell<Node>>>) -> String {
+pub fn convert_dom_to_string(dom: &Dom, root: NodeId) -> String {
     let mut result = String::from("\n");
-    convert_dom_to_string_internal(root, 0, &mut result);
-    result
-}
-fn convert_dom_to_string_internal(
-    node: &Option<Rc<RefCell<Node>>>,
-    depth: usize,
-    result: &mut String,
-) {
-    match node {
-        Some(n) => {
-            result.push_str(&" ".repeat(depth));
-            result.push_str(&format!("{:?}", n.borrow().kind()));
-            result.push('\n');
-            convert_dom_to_string_internal(&n.borrow().first_child(), depth + 1, result);
-            convert_dom_to_string_internal(&n.borrow().next_sibling(), depth, result);
-        }
-        None => (),
+    let root_depth = dom.ancestors(root).count();
+    for n in dom.inclusive_descendants(root) {
+        let depth = dom.ancestors(n).count() - root_depth;
+        result.push_str(&" ".repeat(depth));
+        result.push_str(&format!("{:?}", dom[n].kind));
+        result.push('\n');
     }
+    result
 }
@@ -0,0 +1,866 @@
+use crate::error::Error;
+use crate::url::Url;
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use core::fmt::Debug;
+use core::fmt::Formatter;
+use core::sync::atomic::AtomicU32;
+use core::sync::atomic::Ordering;
+
+/// The GUID appended to `Sec-WebSocket-Key` to compute `Sec-WebSocket-Accept`.
+static WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+/// The largest payload sent in a single frame. Longer messages are fragmented.
+const MAX_FRAME_PAYLOAD: usize = 4096;
+const READ_BUFFER_SIZE: usize = 4096;
+static RANDOM_STATE: AtomicU32 = AtomicU32::new(0x2545_f491);
+
+/// Trait representing a byte stream the WebSocket protocol runs on, such as a TCP connection.
+pub trait Transport {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error>;
+    /// Reads the bytes that are available without waiting for more. Returns 0 when nothing has
+    /// arrived yet and an error once the connection is closed.
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error>;
+}
+
+/// Type of a function that opens a transport to the given host and port.
+pub type Connector = fn(String, u16) -> Result<Box<dyn Transport>, Error>;
+
+/// Enum representing the state of a WebSocket connection.
+/// # References:
+/// - <https://websockets.spec.whatwg.org/#dom-websocket-readystate>
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ReadyState {
+    Connecting = 0,
+    Open = 1,
+    Closing = 2,
+    Closed = 3,
+}
+
+/// Enum representing the frame opcodes.
+/// # References:
+/// - <https://www.rfc-editor.org/rfc/rfc6455#section-5.2>
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Opcode {
+    Continuation,
+    Text,
+    Binary,
+    Close,
+    Ping,
+    Pong,
+}
+impl Opcode {
+    fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0x0 => Some(Opcode::Continuation),
+            0x1 => Some(Opcode::Text),
+            0x2 => Some(Opcode::Binary),
+            0x8 => Some(Opcode::Close),
+            0x9 => Some(Opcode::Ping),
+            0xa => Some(Opcode::Pong),
+            _ => None,
+        }
+    }
+    fn to_u8(self) -> u8 {
+        match self {
+            Opcode::Continuation => 0x0,
+            Opcode::Text => 0x1,
+            Opcode::Binary => 0x2,
+            Opcode::Close => 0x8,
+            Opcode::Ping => 0x9,
+            Opcode::Pong => 0xa,
+        }
+    }
+    fn is_control(self) -> bool {
+        matches!(self, Opcode::Close | Opcode::Ping | Opcode::Pong)
+    }
+}
+
+/// Struct representing a WebSocket frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub fin: bool,
+    pub opcode: Opcode,
+    pub payload: Vec<u8>,
+}
+
+/// Enum representing an event observed on a WebSocket connection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WebSocketEvent {
+    Open,
+    Text(String),
+    Binary(Vec<u8>),
+    Error,
+    Close {
+        code: u16,
+        reason: String,
+        was_clean: bool,
+    },
+}
+
+/// Returns a pseudo-random 32-bit number for masking keys and handshake nonces.
+fn next_random() -> u32 {
+    let mut x = RANDOM_STATE.load(Ordering::Relaxed);
+    x ^= x << 13;
+    x ^= x >> 17;
+    x ^= x << 5;
+    RANDOM_STATE.store(x, Ordering::Relaxed);
+    x
+}
+
+/// Encodes bytes with the standard base64 alphabet.
+/// # Parameters
+/// - `input`: The bytes to encode.
+/// # Returns
+/// - The padded base64 string.
+fn base64_encode(input: &[u8]) -> String {
+    let table = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut result = String::new();
+    for chunk in input.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = ((b[0] as u32) << 16) | ((b[1] as u32) << 8) | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                result.push(table[((n >> (18 - 6 * i)) & 0x3f) as usize] as char);
+            } else {
+                result.push('=');
+            }
+        }
+    }
+    result
+}
+
+/// Computes the SHA-1 digest used by the opening handshake.
+/// # Parameters
+/// - `input`: The message.
+/// # Returns
+/// - The 20-byte digest.
+/// # References:
+/// - <https://www.rfc-editor.org/rfc/rfc3174>
+fn sha1(input: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0];
+    let mut message = input.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((input.len() as u64) * 8).to_be_bytes());
+    for block in message.chunks(64) {
+        let mut w = [0u32; 80];
+        for i in 0..16 {
+            w[i] = u32::from_be_bytes([
+                block[i * 4],
+                block[i * 4 + 1],
+                block[i * 4 + 2],
+                block[i * 4 + 3],
+            ]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5a827999),
+                20..=39 => (b ^ c ^ d, 0x6ed9eba1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8f1bbcdc),
+                _ => (b ^ c ^ d, 0xca62c1d6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        h[0] = h[0].wrapping_add(a);
+        h[1] = h[1].wrapping_add(b);
+        h[2] = h[2].wrapping_add(c);
+        h[3] = h[3].wrapping_add(d);
+        h[4] = h[4].wrapping_add(e);
+    }
+    let mut digest = [0u8; 20];
+    for (i, word) in h.iter().enumerate() {
+        digest[i * 4..i * 4 + 4].copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
+/// Computes the `Sec-WebSocket-Accept` value expected for a `Sec-WebSocket-Key`.
+/// # Parameters
+/// - `key`: The base64-encoded nonce sent by the client.
+/// # Returns
+/// - The value the server must answer with.
+/// # References:
+/// - <https://www.rfc-editor.org/rfc/rfc6455#section-4.2.2>
+pub fn accept_key(key: &str) -> String {
+    let mut input = String::from(key);
+    input.push_str(WEBSOCKET_GUID);
+    base64_encode(&sha1(input.as_bytes()))
+}
+
+/// Encodes a frame.
+/// # Parameters
+/// - `frame`: The frame to encode.
+/// - `mask`: The masking key. Every frame sent by a client must be masked.
+/// # Returns
+/// - The bytes on the wire.
+/// # References:
+/// - <https://www.rfc-editor.org/rfc/rfc6455#section-5.2>
+pub fn encode_frame(frame: &Frame, mask: Option<[u8; 4]>) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let fin = if frame.fin { 0x80 } else { 0 };
+    bytes.push(fin | frame.opcode.to_u8());
+    let mask_bit = if mask.is_some() { 0x80 } else { 0 };
+    let len = frame.payload.len();
+    if len < 126 {
+        bytes.push(mask_bit | len as u8);
+    } else if len <= 0xffff {
+        bytes.push(mask_bit | 126);
+        bytes.extend_from_slice(&(len as u16).to_be_bytes());
+    } else {
+        bytes.push(mask_bit | 127);
+        bytes.extend_from_slice(&(len as u64).to_be_bytes());
+    }
+    match mask {
+        Some(key) => {
+            bytes.extend_from_slice(&key);
+            for (i, b) in frame.payload.iter().enumerate() {
+                bytes.push(b ^ key[i % 4]);
+            }
+        }
+        None => bytes.extend_from_slice(&frame.payload),
+    }
+    bytes
+}
+
+/// Decodes one frame from the beginning of a buffer.
+/// # Parameters
+/// - `buf`: The received bytes.
+/// # Returns
+/// - `Ok(None)` if the buffer does not hold a complete frame yet, or the frame, whether it was
+///   masked, and the number of bytes it occupied.
+pub fn decode_frame(buf: &[u8]) -> Result<Option<(Frame, bool, usize)>, Error> {
+    if buf.len() < 2 {
+        return Ok(None);
+    }
+    if buf[0] & 0x70 != 0 {
+        return Err(Error::Network(
+            "WebSocket frame uses reserved bits".to_string(),
+        ));
+    }
+    let opcode = match Opcode::from_u8(buf[0] & 0x0f) {
+        Some(opcode) => opcode,
+        None => {
+            return Err(Error::Network(format!(
+                "unknown WebSocket opcode {:#x}",
+                buf[0] & 0x0f
+            )))
+        }
+    };
+    let fin = buf[0] & 0x80 != 0;
+    let masked = buf[1] & 0x80 != 0;
+    let mut pos = 2;
+    let len = match buf[1] & 0x7f {
+        126 => {
+            if buf.len() < 4 {
+                return Ok(None);
+            }
+            pos = 4;
+            u16::from_be_bytes([buf[2], buf[3]]) as usize
+        }
+        127 => {
+            if buf.len() < 10 {
+                return Ok(None);
+            }
+            pos = 10;
+            let mut len_bytes = [0u8; 8];
+            len_bytes.copy_from_slice(&buf[2..10]);
+            u64::from_be_bytes(len_bytes) as usize
+        }
+        n => n as usize,
+    };
+    if opcode.is_control() && (!fin || len > 125) {
+        return Err(Error::Network(
+            "WebSocket control frame must not be fragmented".to_string(),
+        ));
+    }
+    let mut key = [0u8; 4];
+    if masked {
+        if buf.len() < pos + 4 {
+            return Ok(None);
+        }
+        key.copy_from_slice(&buf[pos..pos + 4]);
+        pos += 4;
+    }
+    if buf.len() < pos + len {
+        return Ok(None);
+    }
+    let mut payload = buf[pos..pos + len].to_vec();
+    if masked {
+        for (i, b) in payload.iter_mut().enumerate() {
+            *b ^= key[i % 4];
+        }
+    }
+    Ok(Some((
+        Frame {
+            fin,
+            opcode,
+            payload,
+        },
+        masked,
+        pos + len,
+    )))
+}
+
+/// Struct representing a client-side WebSocket connection.
+/// # References:
+/// - <https://www.rfc-editor.org/rfc/rfc6455>
+pub struct WebSocket {
+    url: String,
+    /// The URL of the handshake request, whose scheme is `http`.
+    http_url: Url,
+    port: u16,
+    connect: Connector,
+    state: ReadyState,
+    /// The transport, which is opened by the first `poll`.
+    transport: Option<Box<dyn Transport>>,
+    /// The `Sec-WebSocket-Key` sent in the handshake request.
+    key: String,
+    buffer: Vec<u8>,
+    message: Option<(Opcode, Vec<u8>)>,
+    events: Vec<WebSocketEvent>,
+}
+impl Debug for WebSocket {
+    fn fmt(&self, f: &mut Formatter) -> core::fmt::Result {
+        f.debug_struct("WebSocket")
+            .field("url", &self.url)
+            .field("state", &self.state)
+            .finish()
+    }
+}
+impl WebSocket {
+    /// Creates a connection in the `Connecting` state. The transport is opened and the opening
+    /// handshake runs in `poll`, so creating a WebSocket doesn't wait for the network.
+    /// # Parameters
+    /// - `url`: A `ws://` URL.
+    /// - `connect`: The function that opens the underlying transport.
+    /// # Returns
+    /// - The connection, or an error if the URL is invalid.
+    /// # References:
+    /// - <https://websockets.spec.whatwg.org/#dom-websocket-websocket>
+    pub fn new(url: &str, connect: Connector) -> Result<Self, Error> {
+        let http_url = match url.strip_prefix("ws://") {
+            Some(rest) => format!("http://{}", rest),
+            None if url.starts_with("http://") => url.to_string(),
+            None => {
+                return Err(Error::UnexpectedInput(format!(
+                    "Unsupported WebSocket URL {}",
+                    url
+                )))
+            }
+        };
+        let http_url = Url::new(http_url).parse().map_err(Error::UnexpectedInput)?;
+        let port = http_url
+            .port()
+            .parse::<u16>()
+            .map_err(|_| Error::UnexpectedInput(format!("Invalid port {}", http_url.port())))?;
+        Ok(Self {
+            url: url.to_string(),
+            http_url,
+            port,
+            connect,
+            state: ReadyState::Connecting,
+            transport: None,
+            key: String::new(),
+            buffer: Vec::new(),
+            message: None,
+            events: Vec::new(),
+        })
+    }
+    /// Runs the opening handshake as far as the received bytes allow. The transport is opened
+    /// and the request is sent on the first call, and the connection is open once the whole
+    /// response has arrived.
+    /// # References:
+    /// - <https://www.rfc-editor.org/rfc/rfc6455#section-4.1>
+    fn handshake(&mut self) -> Result<(), Error> {
+        if self.transport.is_none() {
+            self.transport = Some((self.connect)(self.http_url.host(), self.port)?);
+            let mut nonce = Vec::new();
+            for _ in 0..4 {
+                nonce.extend_from_slice(&next_random().to_be_bytes());
+            }
+            self.key = base64_encode(&nonce);
+            let url = &self.http_url;
+            let mut target = format!("/{}", url.path());
+            if !url.search_part().is_empty() {
+                target.push('?');
+                target.push_str(&url.search_part());
+            }
+            let request = format!(
+                "GET {} HTTP/1.1\r\nHost: {}:{}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: {}\r\nSec-WebSocket-Version: 13\r\n\r\n",
+                target,
+                url.host(),
+                url.port(),
+                self.key
+            );
+            self.write_all(request.as_bytes())?;
+        }
+        self.read_available()?;
+        let header_end = match self.buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+            Some(i) => i,
+            None => return Ok(()),
+        };
+        let head = String::from_utf8_lossy(&self.buffer[..header_end]).to_string();
+        self.buffer.drain(..header_end + 4);
+        let mut lines = head.split("\r\n");
+        let status = lines.next().unwrap_or_default();
+        if status.split(' ').nth(1) != Some("101") {
+            return Err(Error::Network(format!(
+                "WebSocket upgrade was refused: {}",
+                status
+            )));
+        }
+        let mut upgrade = false;
+        let mut connection = false;
+        let mut accept = false;
+        for line in lines {
+            let (name, value) = match line.split_once(':') {
+                Some((n, v)) => (n.trim().to_ascii_lowercase(), v.trim()),
+                None => continue,
+            };
+            match name.as_str() {
+                "upgrade" => upgrade = value.eq_ignore_ascii_case("websocket"),
+                "connection" => {
+                    connection = value
+                        .split(',')
+                        .any(|token| token.trim().eq_ignore_ascii_case("upgrade"))
+                }
+                "sec-websocket-accept" => accept = value == accept_key(&self.key),
+                _ => {}
+            }
+        }
+        if !(upgrade && connection && accept) {
+            return Err(Error::Network(
+                "Invalid WebSocket handshake response".to_string(),
+            ));
+        }
+        self.state = ReadyState::Open;
+        self.events.push(WebSocketEvent::Open);
+        Ok(())
+    }
+    pub fn url(&self) -> String {
+        self.url.clone()
+    }
+    pub fn ready_state(&self) -> ReadyState {
+        self.state
+    }
+    fn transport(&mut self) -> Result<&mut Box<dyn Transport>, Error> {
+        self.transport
+            .as_mut()
+            .ok_or(Error::Network("WebSocket is not connected".to_string()))
+    }
+    fn write_all(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        let mut written = 0;
+        while written < bytes.len() {
+            let n = self.transport()?.write(&bytes[written..])?;
+            if n == 0 {
+                return Err(Error::Network("Failed to write to transport".to_string()));
+            }
+            written += n;
+        }
+        Ok(())
+    }
+    /// Reads the bytes that have arrived without waiting for more.
+    fn read_available(&mut self) -> Result<(), Error> {
+        let mut buf = [0u8; READ_BUFFER_SIZE];
+        loop {
+            let n = self.transport()?.read(&mut buf)?;
+            self.buffer.extend_from_slice(&buf[..n]);
+            // A full buffer means more bytes may be waiting.
+            if n < READ_BUFFER_SIZE {
+                return Ok(());
+            }
+        }
+    }
+    fn send_frame(&mut self, opcode: Opcode, fin: bool, payload: &[u8]) -> Result<(), Error> {
+        let frame = Frame {
+            fin,
+            opcode,
+            payload: payload.to_vec(),
+        };
+        let bytes = encode_frame(&frame, Some(next_random().to_be_bytes()));
+        self.write_all(&bytes)
+    }
+    /// Sends a message, fragmenting it into several frames when it is long.
+    fn send_message(&mut self, opcode: Opcode, payload: &[u8]) -> Result<(), Error> {
+        if self.state != ReadyState::Open {
+            return Err(Error::Network("WebSocket is not open".to_string()));
+        }
+        if payload.is_empty() {
+            return self.send_frame(opcode, true, payload);
+        }
+        let chunks: Vec<&[u8]> = payload.chunks(MAX_FRAME_PAYLOAD).collect();
+        for (i, chunk) in chunks.iter().enumerate() {
+            let op = if i == 0 { opcode } else { Opcode::Continuation };
+            self.send_frame(op, i == chunks.len() - 1, chunk)?;
+        }
+        Ok(())
+    }
+    pub fn send_text(&mut self, text: &str) -> Result<(), Error> {
+        self.send_message(Opcode::Text, text.as_bytes())
+    }
+    pub fn send_binary(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.send_message(Opcode::Binary, data)
+    }
+    pub fn ping(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.send_frame(Opcode::Ping, true, data)
+    }
+    /// Starts the closing handshake.
+    /// # Parameters
+    /// - `code`: The status code, e.g. 1000 for a normal closure.
+    /// - `reason`: A short UTF-8 reason.
+    /// # References:
+    /// - <https://www.rfc-editor.org/rfc/rfc6455#section-7.1.2>
+    pub fn close(&mut self, code: u16, reason: &str) -> Result<(), Error> {
+        if self.state == ReadyState::Connecting {
+            // https://websockets.spec.whatwg.org/#dom-websocket-close
+            self.fail(1006);
+            return Ok(());
+        }
+        if self.state != ReadyState::Open {
+            return Ok(());
+        }
+        let mut payload = code.to_be_bytes().to_vec();
+        payload.extend_from_slice(reason.as_bytes());
+        self.send_frame(Opcode::Close, true, &payload)?;
+        self.state = ReadyState::Closing;
+        Ok(())
+    }
+    /// Fails the connection after a protocol violation by the server.
+    fn fail(&mut self, code: u16) {
+        if self.state == ReadyState::Open {
+            let _ = self.send_frame(Opcode::Close, true, &code.to_be_bytes());
+        }
+        self.state = ReadyState::Closed;
+        self.events.push(WebSocketEvent::Error);
+        self.events.push(WebSocketEvent::Close {
+            code,
+            reason: String::new(),
+            was_clean: false,
+        });
+    }
+    fn handle_frame(&mut self, frame: Frame) {
+        match frame.opcode {
+            Opcode::Ping => {
+                if self.state == ReadyState::Open {
+                    let _ = self.send_frame(Opcode::Pong, true, &frame.payload);
+                }
+            }
+            Opcode::Pong => {}
+            Opcode::Close => {
+                let (code, reason) = if frame.payload.len() >= 2 {
+                    (
+                        u16::from_be_bytes([frame.payload[0], frame.payload[1]]),
+                        String::from_utf8_lossy(&frame.payload[2..]).to_string(),
+                    )
+                } else {
+                    (1005, String::new())
+                };
+                if self.state == ReadyState::Open {
+                    // Echo the close frame to complete the closing handshake.
+                    let _ = self.send_frame(Opcode::Close, true, &frame.payload);
+                }
+                self.state = ReadyState::Closed;
+                self.events.push(WebSocketEvent::Close {
+                    code,
+                    reason,
+                    was_clean: true,
+                });
+            }
+            Opcode::Text | Opcode::Binary => {
+                if self.message.is_some() {
+                    return self.fail(1002);
+                }
+                self.message = Some((frame.opcode, frame.payload));
+                if frame.fin {
+                    self.finish_message();
+                }
+            }
+            Opcode::Continuation => match self.message.as_mut() {
+                Some((_, data)) => {
+                    data.extend_from_slice(&frame.payload);
+                    if frame.fin {
+                        self.finish_message();
+                    }
+                }
+                None => self.fail(1002),
+            },
+        }
+    }
+    fn finish_message(&mut self) {
+        let (opcode, data) = match self.message.take() {
+            Some(m) => m,
+            None => return,
+        };
+        if opcode == Opcode::Binary {
+            self.events.push(WebSocketEvent::Binary(data));
+            return;
+        }
+        match String::from_utf8(data) {
+            Ok(text) => self.events.push(WebSocketEvent::Text(text)),
+            Err(_) => self.fail(1007),
+        }
+    }
+    /// Reads the available bytes and returns the events they produced.
+    /// # Returns
+    /// - The events in the order they happened.
+    pub fn poll(&mut self) -> Vec<WebSocketEvent> {
+        match self.state {
+            ReadyState::Connecting => {
+                if self.handshake().is_err() {
+                    self.fail(1006);
+                }
+            }
+            ReadyState::Open | ReadyState::Closing => {
+                if self.read_available().is_err() {
+                    // The connection was closed without the closing handshake.
+                    self.fail(1006);
+                }
+            }
+            ReadyState::Closed => {}
+        }
+        while self.state == ReadyState::Open || self.state == ReadyState::Closing {
+            match decode_frame(&self.buffer) {
+                Ok(Some((frame, masked, used))) => {
+                    self.buffer.drain(..used);
+                    if masked {
+                        // A server must not mask the frames it sends.
+                        self.fail(1002);
+                        break;
+                    }
+                    self.handle_frame(frame);
+                }
+                Ok(None) => break,
+                Err(_) => {
+                    self.fail(1002);
+                    break;
+                }
+            }
+        }
+        core::mem::take(&mut self.events)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use alloc::vec;
+
+    /// An in-memory echo server used as a stand-in for a real WebSocket server.
+    #[derive(Default)]
+    pub struct EchoServer {
+        handshake_done: bool,
+        received: Vec<u8>,
+        outgoing: Vec<u8>,
+    }
+    impl EchoServer {
+        fn reply(&mut self, opcode: Opcode, fin: bool, payload: Vec<u8>) {
+            let frame = Frame {
+                fin,
+                opcode,
+                payload,
+            };
+            self.outgoing.extend(encode_frame(&frame, None));
+        }
+    }
+    impl Transport for EchoServer {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+            self.received.extend_from_slice(buf);
+            if !self.handshake_done {
+                let request = String::from_utf8_lossy(&self.received).to_string();
+                if !request.ends_with("\r\n\r\n") {
+                    return Ok(buf.len());
+                }
+                let key = request
+                    .split("\r\n")
+                    .find_map(|l| l.strip_prefix("Sec-WebSocket-Key: "))
+                    .unwrap_or_default()
+                    .to_string();
+                self.outgoing.extend(
+                    format!(
+                        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+                        accept_key(&key)
+                    )
+                    .bytes(),
+                );
+                self.handshake_done = true;
+                self.received.clear();
+                return Ok(buf.len());
+            }
+            while let Ok(Some((frame, masked, used))) = decode_frame(&self.received) {
+                assert!(masked, "client frames must be masked");
+                self.received.drain(..used);
+                match frame.opcode {
+                    Opcode::Ping => self.reply(Opcode::Pong, true, frame.payload),
+                    Opcode::Pong => {}
+                    _ => self.reply(frame.opcode, frame.fin, frame.payload),
+                }
+            }
+            Ok(buf.len())
+        }
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+            let n = core::cmp::min(buf.len(), self.outgoing.len());
+            buf[..n].copy_from_slice(&self.outgoing[..n]);
+            self.outgoing.drain(..n);
+            Ok(n)
+        }
+    }
+    pub fn connect_echo_server(_host: String, _port: u16) -> Result<Box<dyn Transport>, Error> {
+        Ok(Box::<EchoServer>::default())
+    }
+    /// A server that accepts the connection but never sends anything.
+    struct SilentServer;
+    impl Transport for SilentServer {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+            Ok(buf.len())
+        }
+        fn read(&mut self, _buf: &mut [u8]) -> Result<usize, Error> {
+            Ok(0)
+        }
+    }
+    fn connect_silent_server(_host: String, _port: u16) -> Result<Box<dyn Transport>, Error> {
+        Ok(Box::new(SilentServer))
+    }
+    fn refuse_connection(_host: String, _port: u16) -> Result<Box<dyn Transport>, Error> {
+        Err(Error::Network("Connection refused".to_string()))
+    }
+
+    #[test]
+    fn test_accept_key() {
+        // The example from RFC 6455 section 1.3.
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=".to_string()
+        );
+    }
+    #[test]
+    fn test_base64() {
+        assert_eq!(base64_encode(b""), "".to_string());
+        assert_eq!(base64_encode(b"f"), "Zg==".to_string());
+        assert_eq!(base64_encode(b"fo"), "Zm8=".to_string());
+        assert_eq!(base64_encode(b"foobar"), "Zm9vYmFy".to_string());
+    }
+    #[test]
+    fn test_frame_roundtrip() {
+        let frame = Frame {
+            fin: true,
+            opcode: Opcode::Text,
+            payload: vec![b'a'; 300],
+        };
+        let bytes = encode_frame(&frame, Some([1, 2, 3, 4]));
+        assert_eq!(bytes[1], 0x80 | 126);
+        assert_eq!(decode_frame(&bytes[..10]), Ok(None));
+        assert_eq!(decode_frame(&bytes), Ok(Some((frame, true, bytes.len()))));
+    }
+    #[test]
+    fn test_echo() {
+        let mut ws = WebSocket::new("ws://localhost:8080/echo", connect_echo_server)
+            .expect("failed to create a WebSocket");
+        assert_eq!(ws.ready_state(), ReadyState::Connecting);
+        assert_eq!(ws.poll(), vec![WebSocketEvent::Open]);
+        assert_eq!(ws.ready_state(), ReadyState::Open);
+        ws.send_text("hello").expect("failed to send");
+        ws.ping(b"p").expect("failed to ping");
+        let long = "x".repeat(MAX_FRAME_PAYLOAD * 2 + 1);
+        ws.send_text(&long).expect("failed to send");
+        assert_eq!(
+            ws.poll(),
+            vec![
+                WebSocketEvent::Text("hello".to_string()),
+                WebSocketEvent::Text(long)
+            ]
+        );
+        ws.close(1000, "bye").expect("failed to close");
+        assert_eq!(ws.ready_state(), ReadyState::Closing);
+        assert_eq!(
+            ws.poll(),
+            vec![WebSocketEvent::Close {
+                code: 1000,
+                reason: "bye".to_string(),
+                was_clean: true
+            }]
+        );
+        assert_eq!(ws.ready_state(), ReadyState::Closed);
+    }
+    #[test]
+    fn test_masked_server_frame_fails_connection() {
+        let mut ws = WebSocket::new("ws://localhost/", connect_echo_server)
+            .expect("failed to create a WebSocket");
+        assert_eq!(ws.poll(), vec![WebSocketEvent::Open]);
+        let frame = Frame {
+            fin: true,
+            opcode: Opcode::Text,
+            payload: b"x".to_vec(),
+        };
+        ws.buffer.extend(encode_frame(&frame, Some([9, 9, 9, 9])));
+        let events = ws.poll();
+        assert_eq!(events[0], WebSocketEvent::Error);
+        assert_eq!(ws.ready_state(), ReadyState::Closed);
+    }
+    #[test]
+    fn test_handshake_waits_for_response() {
+        let mut ws = WebSocket::new("ws://localhost:8080/", connect_silent_server)
+            .expect("failed to create a WebSocket");
+        // Nothing has arrived, so polling returns without blocking and the connection stays in
+        // the connecting state.
+        for _ in 0..3 {
+            assert_eq!(ws.poll(), Vec::new());
+            assert_eq!(ws.ready_state(), ReadyState::Connecting);
+        }
+        assert!(ws.send_text("early").is_err());
+        ws.close(1000, "").expect("failed to close");
+        assert_eq!(ws.ready_state(), ReadyState::Closed);
+        assert_eq!(
+            ws.poll(),
+            vec![
+                WebSocketEvent::Error,
+                WebSocketEvent::Close {
+                    code: 1006,
+                    reason: String::new(),
+                    was_clean: false
+                }
+            ]
+        );
+    }
+    #[test]
+    fn test_connection_failure() {
+        let mut ws = WebSocket::new("ws://localhost:8080/", refuse_connection)
+            .expect("failed to create a WebSocket");
+        assert_eq!(ws.ready_state(), ReadyState::Connecting);
+        assert_eq!(
+            ws.poll(),
+            vec![
+                WebSocketEvent::Error,
+                WebSocketEvent::Close {
+                    code: 1006,
+                    reason: String::new(),
+                    was_clean: false
+                }
+            ]
+        );
+        assert_eq!(ws.ready_state(), ReadyState::Closed);
+        assert!(WebSocket::new("wss://localhost/", refuse_connection).is_err());
+    }
+}
@@ -0,0 +1,614 @@
+//! Runs the tokenizer and tree construction tests of html5lib-tests against `HtmlTokenizer` and
+//! `HtmlParser`, and reports the pass/fail counts of each fixture file.
+//! - <https://github.com/html5lib/html5lib-tests>
+//!
+//! The number of passing tests of each file must not drop below the one in
+//! `tests/html5lib/baseline.txt`. Run `cargo test --test html5lib -- --nocapture` to see the
+//! report.
+
+use saba_core::renderer::dom::document::Document;
+use saba_core::renderer::dom::node::Dom;
+use saba_core::renderer::dom::node::Element;
+use saba_core::renderer::dom::node::Namespace;
+use saba_core::renderer::dom::node::Node;
+use saba_core::renderer::dom::node::NodeId;
+use saba_core::renderer::dom::node::NodeKind;
+use saba_core::renderer::html::parser::HtmlParser;
+use saba_core::renderer::html::token::HtmlToken;
+use saba_core::renderer::html::token::HtmlTokenizer;
+use saba_core::renderer::html::token::State;
+use saba_core::renderer::js::json::parse_json;
+use saba_core::renderer::js::runtime::RuntimeValue;
+use std::collections::HashMap;
+use std::fs;
+use std::panic;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Struct representing the results of the tests in a fixture file.
+struct Report {
+    file: String,
+    passed: usize,
+    /// The descriptions of the failed tests and what went wrong.
+    failures: Vec<String>,
+    /// The number of tests that use features this harness doesn't support.
+    skipped: usize,
+}
+impl Report {
+    fn new(file: &Path) -> Self {
+        Self {
+            file: file
+                .strip_prefix(env!("CARGO_MANIFEST_DIR"))
+                .unwrap_or(file)
+                .display()
+                .to_string(),
+            passed: 0,
+            failures: Vec::new(),
+            skipped: 0,
+        }
+    }
+    /// Records a test that panicked or whose fixture couldn't be read.
+    fn fail(&mut self, description: &str, reason: &str) {
+        self.failures
+            .push(format!("{}\n--- {}", description, reason));
+    }
+    fn record(&mut self, description: &str, expected: &str, actual: &str) {
+        if expected == actual {
+            self.passed += 1;
+        } else {
+            self.failures.push(format!(
+                "{}\n--- expected\n{}\n--- actual\n{}",
+                description, expected, actual
+            ));
+        }
+    }
+}
+
+/// Returns the fixture files with `extension` in `tests/html5lib/<dir>`.
+fn fixture_files(dir: &str, extension: &str) -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("html5lib")
+        .join(dir);
+    let mut files: Vec<PathBuf> = fs::read_dir(&dir)
+        .unwrap_or_else(|e| panic!("failed to read {}: {}", dir.display(), e))
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|e| e == extension))
+        .collect();
+    files.sort();
+    files
+}
+
+/// Reads the number of tests of each fixture file that are known to pass. Each line of
+/// `tests/html5lib/baseline.txt` has a file path and a number.
+fn baseline() -> HashMap<String, usize> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("html5lib")
+        .join("baseline.txt");
+    let source = fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("failed to read {}: {}", path.display(), e));
+    source
+        .lines()
+        .filter_map(|line| line.split_once(' '))
+        .map(|(file, passed)| {
+            let passed = passed.trim().parse().expect("invalid baseline");
+            (file.to_string(), passed)
+        })
+        .collect()
+}
+
+/// Prints the reports, and fails if fewer tests of a file pass than the baseline says.
+fn check(reports: &[Report]) {
+    let baseline = baseline();
+    let mut regressions = Vec::new();
+    for report in reports {
+        let expected = baseline.get(&report.file).copied().unwrap_or(0);
+        println!(
+            "{}: {} passed, {} failed, {} skipped (baseline {})",
+            report.file,
+            report.passed,
+            report.failures.len(),
+            report.skipped,
+            expected
+        );
+        for failure in &report.failures {
+            println!("{}\n", failure);
+        }
+        if report.passed < expected {
+            regressions.push(format!(
+                "{}: {} passed, but the baseline is {}",
+                report.file, report.passed, expected
+            ));
+        } else if report.passed > expected {
+            println!(
+                "{}: more tests pass than the baseline, which can be raised to {}",
+                report.file, report.passed
+            );
+        }
+    }
+    assert!(regressions.is_empty(), "{}", regressions.join("\n"));
+}
+
+/// Runs a test, and returns `None` if it panicked.
+fn catch<T>(f: impl FnOnce() -> T + panic::UnwindSafe) -> Option<T> {
+    panic::catch_unwind(f).ok()
+}
+
+/// Returns the value of the key `name` of a JSON object.
+fn field<'a>(value: &'a RuntimeValue, name: &str) -> Option<&'a RuntimeValue> {
+    match value {
+        RuntimeValue::Object(properties) => properties
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value),
+        _ => None,
+    }
+}
+
+/// Returns the items of a JSON array, which `parse_json` represents as an object with index keys
+/// and a `length`.
+fn items(value: &RuntimeValue) -> Vec<RuntimeValue> {
+    match value {
+        RuntimeValue::Object(properties) => properties
+            .iter()
+            .filter(|(key, _)| key != "length")
+            .map(|(_, value)| value.clone())
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Returns the string of a JSON value. `parse_json` represents `null`, `true` and `false` as
+/// strings too, so the string "null" in a fixture can't be told apart from `null`.
+fn string(value: &RuntimeValue) -> String {
+    match value {
+        RuntimeValue::StringLiteral(s) => s.clone(),
+        _ => value.to_string(),
+    }
+}
+
+/// Decodes the `\uXXXX` escapes of a test with `doubleEscaped`, or returns `None` if the string
+/// has a lone surrogate, which a Rust string can't have.
+fn unescape(s: &str) -> Option<String> {
+    let mut result = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('u') => {
+                let hex: String = chars.by_ref().take(4).collect();
+                result.push(char::from_u32(u32::from_str_radix(&hex, 16).ok()?)?);
+            }
+            Some(c) => {
+                result.push('\\');
+                result.push(c);
+            }
+            None => result.push('\\'),
+        }
+    }
+    Some(result)
+}
+
+/// Formats a token in the output format of the tokenizer tests, with the attributes sorted.
+fn format_token(name: &str, fields: &[String]) -> String {
+    format!("[{:?}, {}]", name, fields.join(", "))
+}
+
+/// Formats the expected tokens of a tokenizer test, joining adjacent character tokens. The
+/// `"ParseError"` items of the older format, which has no error codes, are ignored.
+fn expected_tokens(output: &RuntimeValue, double_escaped: bool) -> Option<Vec<String>> {
+    let text = |value: &RuntimeValue| {
+        let s = string(value);
+        match double_escaped {
+            true => unescape(&s),
+            false => Some(s),
+        }
+    };
+    let mut tokens = Vec::new();
+    let mut characters = String::new();
+    for token in items(output) {
+        if let RuntimeValue::StringLiteral(_) = token {
+            continue;
+        }
+        let token = items(&token);
+        let kind = string(&token[0]);
+        if kind == "Character" {
+            characters.push_str(&text(&token[1])?);
+            continue;
+        }
+        if !characters.is_empty() {
+            tokens.push(format_token("Character", &[format!("{:?}", characters)]));
+            characters.clear();
+        }
+        let fields = match kind.as_str() {
+            "DOCTYPE" => {
+                let mut fields = Vec::new();
+                for value in &token[1..4] {
+                    let s = text(value)?;
+                    fields.push(match s.as_str() {
+                        "null" => "null".to_string(),
+                        _ => format!("{:?}", s),
+                    });
+                }
+                fields.push(string(&token[4]));
+                fields
+            }
+            "StartTag" => {
+                let mut attributes = Vec::new();
+                if let RuntimeValue::Object(properties) = &token[2] {
+                    for (name, value) in properties {
+                        let name = match double_escaped {
+                            true => unescape(name)?,
+                            false => name.clone(),
+                        };
+                        attributes.push(format!("{:?}: {:?}", name, text(value)?));
+                    }
+                }
+                attributes.sort();
+                let mut fields = vec![
+                    format!("{:?}", text(&token[1])?),
+                    format!("{{{}}}", attributes.join(", ")),
+                ];
+                if token.len() > 3 && string(&token[3]) == "true" {
+                    fields.push("true".to_string());
+                }
+                fields
+            }
+            "EndTag" | "Comment" => vec![format!("{:?}", text(&token[1])?)],
+            _ => return None,
+        };
+        tokens.push(format_token(&kind, &fields));
+    }
+    if !characters.is_empty() {
+        tokens.push(format_token("Character", &[format!("{:?}", characters)]));
+    }
+    Some(tokens)
+}
+
+/// Runs the tokenizer and formats the tokens in the same way as `expected_tokens`. The error
+/// codes are added if `with_errors` is true.
+fn actual_tokens(mut tokenizer: HtmlTokenizer, with_errors: bool) -> Vec<String> {
+    let quote = |value: &Option<String>| match value {
+        Some(s) => format!("{:?}", s),
+        None => "null".to_string(),
+    };
+    let mut tokens = Vec::new();
+    let mut characters = String::new();
+    for token in tokenizer.by_ref() {
+        if let HtmlToken::Char(c) = token {
+            characters.push(c);
+            continue;
+        }
+        if !characters.is_empty() {
+            tokens.push(format_token("Character", &[format!("{:?}", characters)]));
+            characters.clear();
+        }
+        match token {
+            HtmlToken::Doctype {
+                name,
+                public_id,
+                system_id,
+                force_quirks,
+            } => tokens.push(format_token(
+                "DOCTYPE",
+                &[
+                    quote(&name),
+                    quote(&public_id),
+                    quote(&system_id),
+                    (!force_quirks).to_string(),
+                ],
+            )),
+            HtmlToken::StartTag {
+                tag,
+                self_closing,
+                attributes,
+            } => {
+                let mut attributes: Vec<String> = attributes
+                    .iter()
+                    .map(|a| format!("{:?}: {:?}", a.name(), a.value()))
+                    .collect();
+                attributes.sort();
+                let mut fields = vec![
+                    format!("{:?}", tag),
+                    format!("{{{}}}", attributes.join(", ")),
+                ];
+                if self_closing {
+                    fields.push("true".to_string());
+                }
+                tokens.push(format_token("StartTag", &fields));
+            }
+            HtmlToken::EndTag { tag } => {
+                tokens.push(format_token("EndTag", &[format!("{:?}", tag)]))
+            }
+            HtmlToken::Comment(data) => {
+                tokens.push(format_token("Comment", &[format!("{:?}", data)]))
+            }
+            HtmlToken::Char(_) => {}
+            HtmlToken::Eof => break,
+        }
+    }
+    if !characters.is_empty() {
+        tokens.push(format_token("Character", &[format!("{:?}", characters)]));
+    }
+    if with_errors {
+        let mut errors: Vec<String> = tokenizer.errors().iter().map(|e| e.code()).collect();
+        errors.sort();
+        tokens.push(format!("errors: {:?}", errors));
+    }
+    tokens
+}
+
+/// Runs the tests of a tokenizer fixture file, whose format is described in
+/// <https://github.com/html5lib/html5lib-tests/tree/master/tokenizer>.
+fn run_tokenizer_file(path: &Path) -> Report {
+    let mut report = Report::new(path);
+    let source = fs::read_to_string(path).expect("failed to read a fixture file");
+    let json = match parse_json(&source) {
+        Some(json) => json,
+        None => {
+            report.fail("the whole file", "failed to parse the JSON");
+            return report;
+        }
+    };
+    let tests = field(&json, "tests").map(items).unwrap_or_default();
+    for test in tests {
+        let description = field(&test, "description").map(string).unwrap_or_default();
+        let double_escaped = field(&test, "doubleEscaped").is_some_and(|v| string(v) == "true");
+        let input = field(&test, "input").map(string).unwrap_or_default();
+        let input = match double_escaped {
+            true => unescape(&input),
+            false => Some(input),
+        };
+        let output = field(&test, "output").and_then(|o| expected_tokens(o, double_escaped));
+        let (input, mut expected) = match (input, output) {
+            (Some(input), Some(output)) => (input, output),
+            _ => {
+                report.skipped += 1;
+                continue;
+            }
+        };
+        // The older format marks errors with "ParseError" in the output, without their codes,
+        // so the errors are only compared when the test has the `errors` field.
+        let with_errors = field(&test, "errors").is_some();
+        if with_errors {
+            let mut errors: Vec<String> = field(&test, "errors")
+                .map(items)
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|e| field(e, "code").map(string))
+                .collect();
+            errors.sort();
+            expected.push(format!("errors: {:?}", errors));
+        }
+        let states = match field(&test, "initialStates") {
+            Some(states) => items(states).iter().map(string).collect(),
+            None => vec!["Data state".to_string()],
+        };
+        for state_name in states {
+            let state = match state_name.as_str() {
+                "Data state" => State::Data,
+                "PLAINTEXT state" => State::Plaintext,
+                "RCDATA state" => State::Rcdata,
+                "RAWTEXT state" => State::Rawtext,
+                "Script data state" => State::ScriptData,
+                "CDATA section state" => State::CdataSection,
+                _ => {
+                    report.skipped += 1;
+                    continue;
+                }
+            };
+            let description = format!("{} ({})", description, state_name);
+            let input = input.clone();
+            let last_start_tag = field(&test, "lastStartTag").map(string);
+            let actual = catch(move || {
+                let mut tokenizer = HtmlTokenizer::new(input);
+                tokenizer.switch_to(state);
+                tokenizer.set_last_start_tag(last_start_tag);
+                actual_tokens(tokenizer, with_errors)
+            });
+            match actual {
+                Some(actual) => {
+                    report.record(&description, &expected.join("\n"), &actual.join("\n"))
+                }
+                None => report.fail(&description, "panicked"),
+            }
+        }
+    }
+    report
+}
+
+/// Struct representing a test in the tree construction format.
+/// # References:
+/// - <https://github.com/html5lib/html5lib-tests/tree/master/tree-construction>
+#[derive(Default)]
+struct TreeTest {
+    data: String,
+    fragment_context: Option<String>,
+    script_on: bool,
+    document: String,
+}
+
+/// Splits a `.dat` file into tests. Each section starts with a `#name` line, and a test starts
+/// with `#data` at the beginning of the file or after a blank line.
+fn parse_dat(source: &str) -> Vec<TreeTest> {
+    let mut tests = Vec::new();
+    let mut sections: Vec<(String, Vec<String>)> = Vec::new();
+    let mut previous = "";
+    for line in source.lines() {
+        let is_section = matches!(
+            line,
+            "#data"
+                | "#errors"
+                | "#new-errors"
+                | "#document-fragment"
+                | "#script-on"
+                | "#script-off"
+                | "#document"
+        );
+        if line == "#data" && previous.is_empty() && !sections.is_empty() {
+            tests.push(tree_test(&sections));
+            sections.clear();
+        }
+        if is_section {
+            sections.push((line.to_string(), Vec::new()));
+        } else if let Some((_, lines)) = sections.last_mut() {
+            lines.push(line.to_string());
+        }
+        previous = line;
+    }
+    if !sections.is_empty() {
+        tests.push(tree_test(&sections));
+    }
+    tests
+}
+
+fn tree_test(sections: &[(String, Vec<String>)]) -> TreeTest {
+    let mut test = TreeTest::default();
+    for (name, lines) in sections {
+        let text = lines.join("\n");
+        match name.as_str() {
+            "#data" => test.data = text,
+            "#document-fragment" => test.fragment_context = Some(text.trim().to_string()),
+            "#script-on" => test.script_on = true,
+            "#document" => test.document = text.trim_end_matches('\n').to_string(),
+            _ => {}
+        }
+    }
+    test
+}
+
+/// Dumps a node in the format of the tree construction tests.
+fn dump(dom: &Dom, node: NodeId, depth: usize, result: &mut Vec<String>) {
+    let indent = "  ".repeat(depth);
+    match dom[node].kind() {
+        NodeKind::DocumentType {
+            name,
+            public_id,
+            system_id,
+        } => {
+            if public_id.is_empty() && system_id.is_empty() {
+                result.push(format!("| {}<!DOCTYPE {}>", indent, name));
+            } else {
+                result.push(format!(
+                    "| {}<!DOCTYPE {} \"{}\" \"{}\">",
+                    indent, name, public_id, system_id
+                ));
+            }
+        }
+        NodeKind::Element(e) => {
+            let prefix = match e.namespace() {
+                Namespace::Html => "",
+                Namespace::Svg => "svg ",
+                Namespace::MathMl => "math ",
+            };
+            result.push(format!("| {}<{}{}>", indent, prefix, e.local_name()));
+            let mut attributes: Vec<String> = e
+                .attributes()
+                .iter()
+                .map(|a| {
+                    let name = a.name();
+                    let name = match name.split_once(':') {
+                        Some((ns @ ("xlink" | "xml" | "xmlns"), local))
+                            if e.namespace() != Namespace::Html =>
+                        {
+                            format!("{} {}", ns, local)
+                        }
+                        _ => name,
+                    };
+                    format!("| {}  {}=\"{}\"", indent, name, a.value())
+                })
+                .collect();
+            attributes.sort();
+            result.extend(attributes);
+            if let Some(contents) = dom[node].template_contents() {
+                result.push(format!("| {}  content", indent));
+                dump_children(dom, contents, depth + 2, result);
+            }
+        }
+        NodeKind::Text(text) => result.push(format!("| {}\"{}\"", indent, text)),
+        NodeKind::Comment(data) => result.push(format!("| {}<!-- {} -->", indent, data)),
+        NodeKind::Document | NodeKind::DocumentFragment => {}
+    }
+    dump_children(dom, node, depth + 1, result);
+}
+
+fn dump_children(dom: &Dom, node: NodeId, depth: usize, result: &mut Vec<String>) {
+    for child in dom.children(node) {
+        dump(dom, child, depth, result);
+    }
+}
+
+/// Parses a document, or a fragment in the context element given as `svg path`, `math mi` or
+/// `div`, and dumps the nodes.
+fn tree_construction_result(data: &str, fragment_context: Option<String>) -> Vec<String> {
+    let mut result = Vec::new();
+    match fragment_context {
+        Some(ref context) => {
+            let (namespace, local_name) = match context.split_once(' ') {
+                Some(("svg", name)) => (Namespace::Svg, name),
+                Some(("math", name)) => (Namespace::MathMl, name),
+                _ => (Namespace::Html, context.as_str()),
+            };
+            let mut document = Document::new();
+            let context = document
+                .dom_mut()
+                .alloc(Node::new(NodeKind::Element(Element::new_ns(
+                    namespace,
+                    local_name,
+                    Vec::new(),
+                ))));
+            for node in HtmlParser::parse_fragment(&mut document, context, data) {
+                dump(document.dom(), node, 0, &mut result);
+            }
+        }
+        None => {
+            let document = HtmlParser::new(HtmlTokenizer::new(data.to_string())).construct_tree();
+            let document = document.borrow();
+            dump_children(document.dom(), document.node(), 0, &mut result);
+        }
+    }
+    result
+}
+
+/// Runs the tests of a tree construction fixture file.
+fn run_tree_construction_file(path: &Path) -> Report {
+    let mut report = Report::new(path);
+    let source = fs::read_to_string(path).expect("failed to read a fixture file");
+    for test in parse_dat(&source) {
+        // The parser runs with scripting disabled.
+        if test.script_on {
+            report.skipped += 1;
+            continue;
+        }
+        let data = test.data.clone();
+        let fragment_context = test.fragment_context.clone();
+        let result = catch(move || tree_construction_result(&data, fragment_context));
+        match result {
+            Some(result) => report.record(&test.data, &test.document, &result.join("\n")),
+            None => report.fail(&test.data, "panicked"),
+        }
+    }
+    report
+}
+
+#[test]
+fn test_tokenizer() {
+    let reports: Vec<Report> = fixture_files("tokenizer", "test")
+        .iter()
+        .map(|path| run_tokenizer_file(path))
+        .collect();
+    check(&reports);
+}
+
+#[test]
+fn test_tree_construction() {
+    let reports: Vec<Report> = fixture_files("tree-construction", "dat")
+        .iter()
+        .map(|path| run_tree_construction_file(path))
+        .collect();
+    check(&reports);
+}
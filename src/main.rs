@@ -4,13 +4,13 @@ extern crate alloc;
 use crate::alloc::string::ToString;
 use alloc::format;
 use alloc::rc::Rc;
-use alloc::string::String;
 use core::cell::RefCell;
 use core::panic::Location;
 use net_wasabi::http::HttpClient;
 use noli::*;
 use saba_core::browser::Browser;
 use saba_core::error::Error;
+use saba_core::http::HttpRequest;
 use saba_core::http::HttpResponse;
 use saba_core::url::Url;
 use ui_wasabi::app::WasabiUI;
@@ -38,12 +38,14 @@ fn main() -> u64 {
 entry_point!(main);
 
 /// URL handling function
+/// Redirects and cookies are handled by the browser's fetcher, which calls this function for
+/// each request.
 /// # Parameters
-/// - url: The URL.
+/// - request: The request to send.
 /// # Returns
 /// - A response to the URL request.
-fn handle_url(url: String) -> Result<HttpResponse, Error> {
-    let parsed_url = match Url::new(url.to_string()).parse() {
+fn handle_url(request: HttpRequest) -> Result<HttpResponse, Error> {
+    let parsed_url = match Url::new(request.url()).parse() {
         Ok(url) => url,
         Err(e) => {
             return Err(Error::UnexpectedInput(format!(
@@ -53,43 +55,42 @@ fn handle_url(url: String) -> Result<HttpResponse, Error> {
         }
     };
     let client = HttpClient::new();
-    let response = match client.get(
-        parsed_url.host(),
-        parsed_url.port().parse::<u16>().expect(&format!(
-            "port number should be u16 but got {}",
-            parsed_url.port()
-        )),
-        parsed_url.path(),
-    ) {
-        Ok(res) => {
-            if res.status_code() == 302 {
-                let location = match res.header_value("Location") {
-                    Ok(value) => value,
-                    Err(_) => return Ok(res),
-                };
-                let redirect_parsed_url = Url::new(location);
-                let redirect_res = match client.get(
-                    redirect_parsed_url.host(),
-                    redirect_parsed_url.port().parse::<u16>().expect(&format!(
-                        "port number should be u16 but got {}",
-                        parsed_url.port()
-                    )),
-                    redirect_parsed_url.path(),
-                ) {
-                    Ok(res) => res,
-                    Err(e) => return Err(Error::Network(format!("{:?}", e))),
-                };
-                redirect_res
-            } else {
-                res
-            }
-        }
-        Err(e) => {
-            return Err(Error::Network(format!(
-                "failed to get http response: {:?}",
-                e
-            )))
-        }
-    };
-    Ok(response)
+    match send_request(&client, &parsed_url, &request) {
+        Ok(res) => Ok(res),
+        Err(e) => Err(Error::Network(format!(
+            "failed to get http response: {:?}",
+            e
+        ))),
+    }
+}
+
+/// Sends a request to the server of the parsed URL.
+/// # Parameters
+/// - client: The HTTP client.
+/// - url: The parsed destination URL.
+/// - request: The request to send.
+/// # Returns
+/// - A response from the server.
+fn send_request(
+    client: &HttpClient,
+    url: &Url,
+    request: &HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let port = url
+        .port()
+        .parse::<u16>()
+        .expect(&format!("port number should be u16 but got {}", url.port()));
+    let mut path = url.path();
+    if !url.search_part().is_empty() {
+        path.push('?');
+        path.push_str(&url.search_part());
+    }
+    client.send(
+        &request.method(),
+        url.host(),
+        port,
+        path,
+        request.headers(),
+        request.body(),
+    )
 }
@@ -16,6 +16,7 @@ use saba_core::browser::Browser;
 use saba_core::constants::*;
 use saba_core::display_item::DisplayItem;
 use saba_core::error::Error;
+use saba_core::http::HttpRequest;
 use saba_core::http::HttpResponse;
 use saba_core::renderer::layout::computed_style::FontSize;
 use saba_core::renderer::layout::computed_style::TextDecoration;
@@ -27,8 +28,12 @@ pub struct WasabiUI {
     input_url: String,
     input_mode: InputMode,
     window: Window,
+    /// The name that the window was created with, which is shown in its title bar.
+    window_title: String,
     cursor: Cursor,
     window_position: (i64, i64),
+    /// The time in milliseconds to start the navigation scheduled by the page, and its request.
+    scheduled_navigation: Option<(u64, HttpRequest)>,
 }
 impl WasabiUI {
     pub fn new(browser: Rc<RefCell<Browser>>, xy: (i64, i64), url: String) -> Self {
@@ -45,8 +50,10 @@ impl WasabiUI {
                 WINDOW_HEIGHT,
             )
             .unwrap(),
+            window_title: "saba".to_string(),
             cursor: Cursor::new(),
             window_position: xy,
+            scheduled_navigation: None,
         }
     }
     fn setup_toolbar(&mut self) -> OsResult<()> {
@@ -63,10 +70,17 @@ impl WasabiUI {
         )?;
         self.window
             .draw_string(BLACK, 5, 5, "Address:", StringSize::Medium, false)?;
+        self.window.fill_rect(
+            WHITE,
+            70,
+            2,
+            WINDOW_WIDTH - TAB_LABEL_WIDTH - 74,
+            2 + ADDRESS_BAR_HEIGHT,
+        )?;
+        self.window
+            .draw_line(GRAY, 70, 2, WINDOW_WIDTH - TAB_LABEL_WIDTH - 4, 2)?;
         self.window
-            .fill_rect(WHITE, 70, 2, WINDOW_WIDTH - 74, 2 + ADDRESS_BAR_HEIGHT)?;
-        self.window.draw_line(GRAY, 70, 2, WINDOW_WIDTH - 4, 2)?;
-        self.window.draw_line(BLACK, 71, 3, WINDOW_WIDTH - 5, 3)?;
+            .draw_line(BLACK, 71, 3, WINDOW_WIDTH - TAB_LABEL_WIDTH - 5, 3)?;
         self.window
             .draw_line(GRAY, 71, 3, 71, 1 + ADDRESS_BAR_HEIGHT)?;
         Ok(())
@@ -83,30 +97,56 @@ impl WasabiUI {
     }
     pub fn start(
         &mut self,
-        handle_url: fn(String) -> Result<HttpResponse, Error>,
+        handle_url: fn(HttpRequest) -> Result<HttpResponse, Error>,
     ) -> Result<(), Error> {
+        self.browser.borrow_mut().set_request_sender(handle_url);
+        self.browser
+            .borrow()
+            .current_page()
+            .borrow_mut()
+            .set_clock(Api::get_time_ms);
         self.setup()?;
-        self.run_app(handle_url)?;
+        self.run_app()?;
         Ok(())
     }
-    fn run_app(
-        &mut self,
-        handle_url: fn(String) -> Result<HttpResponse, Error>,
-    ) -> Result<(), Error> {
+    fn run_app(&mut self) -> Result<(), Error> {
         if self.input_url != "".to_string() {
             self.update_address_bar()?;
-            self.start_navigation(handle_url, self.input_url.clone())?;
+            self.start_navigation(HttpRequest::get(self.input_url.clone()))?;
         }
         loop {
-            self.handle_key_input(handle_url)?;
-            self.handle_mouse_input(handle_url)?;
+            self.handle_key_input()?;
+            self.handle_mouse_input()?;
+            self.process_page_tasks()?;
+            self.process_scheduled_navigation()?;
         }
         Ok(())
     }
-    fn handle_mouse_input(
-        &mut self,
-        handle_url: fn(String) -> Result<HttpResponse, Error>,
-    ) -> Result<(), Error> {
+    fn process_page_tasks(&mut self) -> Result<(), Error> {
+        let page = self.browser.borrow().current_page();
+        let needs_repaint = page.borrow_mut().process_tasks();
+        if needs_repaint {
+            self.clear_content_area()?;
+            self.update_ui()?;
+        }
+        Ok(())
+    }
+    fn process_scheduled_navigation(&mut self) -> Result<(), Error> {
+        let due = self
+            .scheduled_navigation
+            .as_ref()
+            .is_some_and(|(time, _)| Api::get_time_ms() >= *time);
+        if !due {
+            return Ok(());
+        }
+        if let Some((_, request)) = self.scheduled_navigation.take() {
+            self.input_url = request.url();
+            self.update_address_bar()?;
+            self.start_navigation(request)?;
+        }
+        Ok(())
+    }
+    fn handle_mouse_input(&mut self) -> Result<(), Error> {
         if let Some(MouseEvent { button, position }) = Api::get_mouse_cursor_info() {
             self.window.flush_area(self.cursor.rect());
             self.cursor.set_position(position.x, position.y);
@@ -141,27 +181,46 @@ impl WasabiUI {
                 );
                 let page = self.browser.borrow().current_page();
                 let next_destination = page.borrow_mut().clicked(position_in_content_area);
-                if let Some(url) = next_destination {
-                    self.input_url = url.clone();
-                    self.update_address_bar()?;
-                    self.start_navigation(handle_url, url)?;
+                match next_destination {
+                    Some(request) => {
+                        self.input_url = request.url();
+                        self.update_address_bar()?;
+                        self.start_navigation(request)?;
+                    }
+                    None => {
+                        self.clear_content_area()?;
+                        self.update_ui()?;
+                    }
                 }
             }
         }
         Ok(())
     }
-    fn handle_key_input(
-        &mut self,
-        handle_url: fn(String) -> Result<HttpResponse, Error>,
-    ) -> Result<(), Error> {
+    fn handle_key_input(&mut self) -> Result<(), Error> {
         match self.input_mode {
             InputMode::Normal => {
-                let _ = Api::read_key();
+                if let Some(c) = Api::read_key() {
+                    let page = self.browser.borrow().current_page();
+                    // Keys are dispatched to the page even without a focused text control, so
+                    // that scripts can listen to them.
+                    let next_destination = page.borrow_mut().key_pressed(c);
+                    match next_destination {
+                        Some(request) => {
+                            self.input_url = request.url();
+                            self.update_address_bar()?;
+                            self.start_navigation(request)?;
+                        }
+                        None => {
+                            self.clear_content_area()?;
+                            self.update_ui()?;
+                        }
+                    }
+                }
             }
             InputMode::Editing => {
                 if let Some(c) = Api::read_key() {
                     if c == 0x0a as char {
-                        self.start_navigation(handle_url, self.input_url.clone())?;
+                        self.start_navigation(HttpRequest::get(self.input_url.clone()))?;
                         self.input_url = String::new();
                         self.input_mode = InputMode::Normal;
                     } else if c == 0x7f as char || c == 0x08 as char {
@@ -178,7 +237,13 @@ impl WasabiUI {
     fn update_address_bar(&mut self) -> Result<(), Error> {
         if self
             .window
-            .fill_rect(WHITE, 72, 4, WINDOW_WIDTH - 76, ADDRESS_BAR_HEIGHT - 2)
+            .fill_rect(
+                WHITE,
+                72,
+                4,
+                WINDOW_WIDTH - TAB_LABEL_WIDTH - 76,
+                ADDRESS_BAR_HEIGHT - 2,
+            )
             .is_err()
         {
             return Err(Error::InvalidUI(
@@ -208,7 +273,13 @@ impl WasabiUI {
     fn clear_address_bar(&mut self) -> Result<(), Error> {
         if self
             .window
-            .fill_rect(WHITE, 72, 4, WINDOW_WIDTH - 76, ADDRESS_BAR_HEIGHT - 2)
+            .fill_rect(
+                WHITE,
+                72,
+                4,
+                WINDOW_WIDTH - TAB_LABEL_WIDTH - 76,
+                ADDRESS_BAR_HEIGHT - 2,
+            )
             .is_err()
         {
             return Err(Error::InvalidUI(
@@ -226,16 +297,21 @@ impl WasabiUI {
         );
         Ok(())
     }
-    fn start_navigation(
-        &mut self,
-        handle_url: fn(String) -> Result<HttpResponse, Error>,
-        destination: String,
-    ) -> Result<(), Error> {
+    fn start_navigation(&mut self, request: HttpRequest) -> Result<(), Error> {
         self.clear_content_area()?;
-        match handle_url(destination) {
+        let fetcher = match self.browser.borrow().fetcher() {
+            Some(fetcher) => fetcher,
+            None => return Err(Error::Other("request sender is not set".to_string())),
+        };
+        match fetcher.fetch(request, true) {
             Ok(response) => {
                 let page = self.browser.borrow().current_page();
-                page.borrow_mut().receive_response(response);
+                page.borrow_mut().receive_response(response.url(), response);
+                // A new navigation cancels the one scheduled by the previous page.
+                self.scheduled_navigation = page
+                    .borrow()
+                    .scheduled_navigation()
+                    .map(|(delay, request)| (Api::get_time_ms().saturating_add(delay), request));
             }
             Err(e) => {
                 return Err(e);
@@ -264,6 +340,7 @@ impl WasabiUI {
         Ok(())
     }
     fn update_ui(&mut self) -> Result<(), Error> {
+        self.update_window_title()?;
         let display_items = self
             .browser
             .borrow()
@@ -314,9 +391,88 @@ impl WasabiUI {
                 _ => {}
             }
         }
+        self.update_tab_label()?;
         self.window.flush();
         Ok(())
     }
+    /// Shows the title of the document in the title bar of the window. A WasabiOS window draws its
+    /// name only when it's created, so the window is created again with the new title, and the
+    /// toolbar is drawn again.
+    fn update_window_title(&mut self) -> Result<(), Error> {
+        let page = self.browser.borrow().current_page();
+        let title = page.borrow().title();
+        let title = if title.is_empty() {
+            "saba".to_string()
+        } else {
+            format!("{} - saba", title)
+        };
+        if title == self.window_title {
+            return Ok(());
+        }
+        self.window = match Window::new(
+            title.clone(),
+            WHITE,
+            self.window_position.0,
+            self.window_position.1,
+            WINDOW_WIDTH,
+            WINDOW_HEIGHT,
+        ) {
+            Ok(window) => window,
+            Err(error) => {
+                return Err(Error::InvalidUI(format!(
+                    "failed to create a window with error: {:#?}",
+                    error
+                )))
+            }
+        };
+        self.window_title = title;
+        self.setup()?;
+        self.update_address_bar()
+    }
+    /// Shows the title of the document in the tab label at the right of the toolbar. A document
+    /// without a title is labeled with its URL.
+    fn update_tab_label(&mut self) -> Result<(), Error> {
+        let page = self.browser.borrow().current_page();
+        let mut label = page.borrow().title();
+        if label.is_empty() {
+            label = page
+                .borrow()
+                .base_url()
+                .map(|url| url.serialize())
+                .unwrap_or("saba".to_string());
+        }
+        // The label is cut to fit in the tab.
+        let max_chars = ((TAB_LABEL_WIDTH - 12) / CHAR_WIDTH) as usize;
+        if label.chars().count() > max_chars {
+            label = label.chars().take(max_chars - 2).collect::<String>() + "..";
+        }
+        let x = WINDOW_WIDTH - TAB_LABEL_WIDTH;
+        if self
+            .window
+            .fill_rect(LIGHTGRAY, x, 2, TAB_LABEL_WIDTH - 4, ADDRESS_BAR_HEIGHT)
+            .is_err()
+            || self
+                .window
+                .draw_line(GRAY, x, 2, x, 2 + ADDRESS_BAR_HEIGHT)
+                .is_err()
+            || self
+                .window
+                .draw_string(BLACK, x + 6, 6, &label, StringSize::Medium, false)
+                .is_err()
+        {
+            return Err(Error::InvalidUI("failed to update a tab label".to_string()));
+        }
+        self.window.flush_area(
+            Rect::new(
+                self.window_position.0,
+                self.window_position.1 + TITLE_BAR_HEIGHT,
+                WINDOW_WIDTH,
+                TITLE_BAR_HEIGHT,
+            )
+            .expect("failed to create a rect for the tab label"),
+        );
+        Ok(())
+    }
 }
 
 /// Convert a `FontSize` to a `StringSize`.